@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Username/password pair used to connect to an authenticated cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Credentials {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Superuser both Scylla and Cassandra create once `PasswordAuthenticator` is enabled.
+    pub fn default_superuser() -> Self {
+        Self::new("cassandra", "cassandra")
+    }
+}
+
+/// Quotes `value` as a CQL string literal.
+pub fn cql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Quotes `name` as a CQL identifier.
+pub fn cql_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// `CREATE ROLE` statement for a login role with the given credentials.
+pub fn create_login_role(credentials: &Credentials, superuser: bool) -> String {
+    format!(
+        "CREATE ROLE IF NOT EXISTS {} WITH PASSWORD = {} AND LOGIN = true AND SUPERUSER = {}",
+        cql_identifier(&credentials.username),
+        cql_string(&credentials.password),
+        superuser
+    )
+}
+
+/// CQL permission granted to a role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    All,
+    Create,
+    Alter,
+    Drop,
+    Select,
+    Modify,
+    Authorize,
+    Describe,
+    Execute,
+}
+
+impl Permission {
+    pub fn as_cql(&self) -> &'static str {
+        match self {
+            Permission::All => "ALL PERMISSIONS",
+            Permission::Create => "CREATE",
+            Permission::Alter => "ALTER",
+            Permission::Drop => "DROP",
+            Permission::Select => "SELECT",
+            Permission::Modify => "MODIFY",
+            Permission::Authorize => "AUTHORIZE",
+            Permission::Describe => "DESCRIBE",
+            Permission::Execute => "EXECUTE",
+        }
+    }
+}
+
+/// Resource a permission applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    AllKeyspaces,
+    Keyspace(String),
+    Table { keyspace: String, table: String },
+    AllRoles,
+    Role(String),
+}
+
+impl Resource {
+    pub fn as_cql(&self) -> String {
+        match self {
+            Resource::AllKeyspaces => "ALL KEYSPACES".to_string(),
+            Resource::Keyspace(keyspace) => format!("KEYSPACE {}", cql_identifier(keyspace)),
+            Resource::Table { keyspace, table } => format!(
+                "TABLE {}.{}",
+                cql_identifier(keyspace),
+                cql_identifier(table)
+            ),
+            Resource::AllRoles => "ALL ROLES".to_string(),
+            Resource::Role(role) => format!("ROLE {}", cql_identifier(role)),
+        }
+    }
+}
+
+/// Login role created during bring-up together with its grants.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleSpec {
+    pub credentials: Credentials,
+    pub superuser: bool,
+    pub grants: Vec<(Permission, Resource)>,
+}
+
+impl RoleSpec {
+    pub fn new(credentials: Credentials) -> Self {
+        RoleSpec {
+            credentials,
+            superuser: false,
+            grants: vec![],
+        }
+    }
+
+    pub fn grant(mut self, permission: Permission, resource: Resource) -> Self {
+        self.grants.push((permission, resource));
+        self
+    }
+
+    /// Statements creating the role and applying its grants, in execution order.
+    pub fn statements(&self) -> Vec<String> {
+        let role = cql_identifier(&self.credentials.username);
+        let mut statements = vec![create_login_role(&self.credentials, self.superuser)];
+        statements.extend(self.grants.iter().map(|(permission, resource)| {
+            format!(
+                "GRANT {} ON {} TO {}",
+                permission.as_cql(),
+                resource.as_cql(),
+                role
+            )
+        }));
+        statements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_login_role_escapes_values() {
+        let credentials = Credentials::new("tester", "it's secret");
+        assert_eq!(
+            create_login_role(&credentials, false),
+            "CREATE ROLE IF NOT EXISTS \"tester\" WITH PASSWORD = 'it''s secret' \
+             AND LOGIN = true AND SUPERUSER = false"
+        );
+    }
+
+    #[test]
+    fn test_role_spec_statements() {
+        let role = RoleSpec::new(Credentials::new("reader", "pw"))
+            .grant(Permission::Select, Resource::Keyspace("ks".to_string()))
+            .grant(
+                Permission::Modify,
+                Resource::Table {
+                    keyspace: "ks".to_string(),
+                    table: "t".to_string(),
+                },
+            );
+        assert_eq!(
+            role.statements(),
+            vec![
+                "CREATE ROLE IF NOT EXISTS \"reader\" WITH PASSWORD = 'pw' \
+                 AND LOGIN = true AND SUPERUSER = false"
+                    .to_string(),
+                "GRANT SELECT ON KEYSPACE \"ks\" TO \"reader\"".to_string(),
+                "GRANT MODIFY ON TABLE \"ks\".\"t\" TO \"reader\"".to_string(),
+            ]
+        );
+    }
+}
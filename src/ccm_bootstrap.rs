@@ -0,0 +1,158 @@
+use crate::ccm_cli::LoggedCmd;
+use crate::ccm_runner::CcmRunner;
+use crate::host_capabilities::find_executable;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+/// scylla-ccm installed by default, pinned to a release tag so that bootstrapped
+/// environments don't change under the tests as scylla-ccm's default branch moves; see
+/// [`CcmBootstrap::with_package`] to install another ref. Virtualenvs bootstrapped with
+/// another spec are upgraded once the pin changes.
+pub const SCYLLA_CCM_PACKAGE: &str = "git+https://github.com/scylladb/scylla-ccm.git@v2.0.5";
+
+/// File in the virtualenv recording which package spec was installed into it.
+const INSTALLED_MARKER: &str = ".ccm-binding-package";
+
+/// Opt-in installation of scylla-ccm into a virtualenv, for hosts that have python but no
+/// ccm, e.g. bare CI images.
+#[derive(Debug, Clone)]
+pub struct CcmBootstrap {
+    pub venv_dir: PathBuf,
+    /// Interpreter creating the virtualenv, `python3` from `PATH` by default.
+    pub python: String,
+    /// pip requirement spec installed into the virtualenv.
+    pub package: String,
+}
+
+impl CcmBootstrap {
+    /// Bootstrap into `<install_directory>/.ccm-venv`.
+    pub fn new(install_directory: impl AsRef<Path>) -> Self {
+        CcmBootstrap {
+            venv_dir: install_directory.as_ref().join(".ccm-venv"),
+            python: "python3".to_string(),
+            package: SCYLLA_CCM_PACKAGE.to_string(),
+        }
+    }
+
+    pub fn with_python(mut self, python: impl Into<String>) -> Self {
+        self.python = python.into();
+        self
+    }
+
+    /// Installs `package` instead of [`SCYLLA_CCM_PACKAGE`], e.g.
+    /// `git+https://github.com/scylladb/scylla-ccm.git@<commit>` to pin a known-good commit.
+    pub fn with_package(mut self, package: impl Into<String>) -> Self {
+        self.package = package.into();
+        self
+    }
+
+    pub fn venv_python(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.venv_dir.join("Scripts").join("python.exe")
+        } else {
+            self.venv_dir.join("bin").join("python")
+        }
+    }
+
+    /// ccm script installed into the virtualenv; on Windows ccm ships it as `ccm.py`.
+    pub fn venv_ccm(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.venv_dir.join("Scripts").join("ccm.py")
+        } else {
+            self.venv_dir.join("bin").join("ccm")
+        }
+    }
+
+    /// Whether the virtualenv already has `package` installed.
+    pub async fn is_installed(&self) -> bool {
+        tokio::fs::read_to_string(self.venv_dir.join(INSTALLED_MARKER))
+            .await
+            .is_ok_and(|installed| installed.trim() == self.package)
+    }
+
+    /// Creates the virtualenv and pip-installs `package` into it, unless it is already
+    /// installed. A virtualenv holding a different package spec is upgraded in place.
+    pub async fn install(&self, logged_cmd: &LoggedCmd) -> Result<(), IoError> {
+        if self.is_installed().await {
+            return Ok(());
+        }
+        let venv_dir = self.venv_dir.to_string_lossy();
+        if !self.venv_python().exists() {
+            logged_cmd
+                .run_command(&self.python, &["-m", "venv", &venv_dir], None)
+                .await?;
+        }
+        let venv_python = self.venv_python();
+        logged_cmd
+            .run_command(
+                &venv_python.to_string_lossy(),
+                &["-m", "pip", "install", "--upgrade", &self.package],
+                None,
+            )
+            .await?;
+        if !logged_cmd.is_dry_run() {
+            tokio::fs::write(self.venv_dir.join(INSTALLED_MARKER), &self.package).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns `runner` unchanged if its ccm command is available, otherwise installs ccm
+    /// and returns `runner` invoking the virtualenv's ccm through its interpreter.
+    pub async fn runner(&self, runner: CcmRunner) -> Result<CcmRunner, IoError> {
+        if program_available(&runner.program) {
+            return Ok(runner);
+        }
+        self.install(runner.logged_cmd()).await?;
+        Ok(runner.with_command(
+            self.venv_python().to_string_lossy(),
+            [self.venv_ccm().to_string_lossy()],
+        ))
+    }
+}
+
+fn program_available(program: &str) -> bool {
+    if program.contains('/') {
+        Path::new(program).is_file()
+    } else {
+        find_executable(program).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_runner_installs_missing_ccm() {
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_bootstrap");
+        let logged_cmd = Arc::new(LoggedCmd::new());
+        logged_cmd.set_dry_run(true);
+        let bootstrap =
+            CcmBootstrap::new(&install_directory).with_package("scylla-ccm @ file:///src/ccm");
+
+        let present = CcmRunner::new(logged_cmd.clone(), "/tmp/ccm").with_command("sh", ["ccm"]);
+        let runner = bootstrap.runner(present).await.unwrap();
+        assert_eq!(runner.program, "sh");
+        assert!(logged_cmd.recorded_commands().is_empty());
+
+        let missing = CcmRunner::new(logged_cmd.clone(), "/tmp/ccm")
+            .with_command("/nonexistent/ccm", Vec::<String>::new());
+        let runner = bootstrap.runner(missing).await.unwrap();
+        let venv_python = bootstrap.venv_python().to_string_lossy().into_owned();
+        assert_eq!(runner.program, venv_python);
+        assert_eq!(
+            runner.leading_args,
+            [bootstrap.venv_ccm().to_string_lossy().into_owned()]
+        );
+        let recorded = logged_cmd.recorded_lines();
+        assert_eq!(
+            recorded,
+            [
+                format!("python3 -m venv {}", bootstrap.venv_dir.display()),
+                format!("{venv_python} -m pip install --upgrade scylla-ccm @ file:///src/ccm"),
+            ]
+        );
+        assert!(!bootstrap.is_installed().await);
+    }
+}
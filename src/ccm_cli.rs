@@ -1,19 +1,299 @@
-use std::collections::HashMap;
+use futures::future::BoxFuture;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::io::Error;
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+#[cfg(windows)]
+use std::os::windows::process::ExitStatusExt;
+use std::path::PathBuf;
 use std::process::{ExitStatus, Stdio};
 use std::sync::Arc;
-use std::sync::atomic::AtomicI32;
+use std::sync::atomic::{AtomicBool, AtomicI32};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::Command;
-use tokio::runtime::Runtime;
-use tokio::sync::Mutex;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
-pub(crate) struct LoggedCmd {
-    log_file: String,
-    file: Option<Arc<Mutex<File>>>,
+pub struct LoggedCmd {
+    sink: Arc<dyn LogSink>,
+    format: LogFormat,
     run_id: AtomicI32,
+    /// Bounds the number of commands running at once, see
+    /// [`LoggedCmd::set_max_concurrent_commands`].
+    limit: Option<Arc<Semaphore>>,
+    dry_run: AtomicBool,
+    recorded: std::sync::Mutex<Vec<RecordedCommand>>,
+    /// Shared with the output readers, which outlive a borrow of the logger.
+    redactions: Arc<std::sync::RwLock<Redactions>>,
+    default_env: std::sync::RwLock<HashMap<String, String>>,
+}
+
+/// Text replaced by `***` in every log entry, see [`LoggedCmd::redact`].
+#[derive(Default)]
+struct Redactions {
+    /// Longest first, so a secret containing another one is masked whole.
+    values: Vec<String>,
+    #[cfg(feature = "redaction-regex")]
+    patterns: Vec<regex::Regex>,
+}
+
+impl Redactions {
+    fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for value in self.values.iter() {
+            if text.contains(value.as_str()) {
+                text = Cow::Owned(text.replace(value.as_str(), "***"));
+            }
+        }
+        #[cfg(feature = "redaction-regex")]
+        for pattern in self.patterns.iter() {
+            if let Cow::Owned(replaced) = pattern.replace_all(&text, "***") {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+}
+
+/// Command captured instead of being run, see [`LoggedCmd::set_dry_run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCommand {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+}
+
+/// Size limit for the log file, see [`LoggedCmd::set_log_file_with_rotation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRotation {
+    /// Size in bytes after which the log is rotated.
+    pub max_size: u64,
+    /// Rotated files kept as `<log>.1` (newest) to `<log>.<keep>`; with 0 the log is
+    /// truncated instead.
+    pub keep: usize,
+}
+
+/// Destination of log entries, see [`LoggedCmd::set_log_sink`].
+pub trait LogSink: Send + Sync {
+    /// Writes one formatted entry, including its trailing newline. Sinks handle their own
+    /// write errors; logging never fails a command.
+    fn write<'a>(&'a self, entry: &'a str) -> BoxFuture<'a, ()>;
+
+    /// Persists buffered entries, called by [`LoggedCmd::close`].
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// Entries the sink still holds, for sinks that keep them in memory.
+    fn entries(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Log file, optionally rotated, see [`LoggedCmd::set_log_file`].
+pub struct FileSink {
+    writer: Mutex<LogWriter>,
+}
+
+impl FileSink {
+    pub async fn open(path: String, rotation: Option<LogRotation>) -> Result<Self, Error> {
+        Ok(FileSink {
+            writer: Mutex::new(LogWriter::open(path, rotation).await?),
+        })
+    }
+}
+
+impl LogSink for FileSink {
+    fn write<'a>(&'a self, entry: &'a str) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            self.writer.lock().await.write(entry.as_bytes()).await.ok();
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Err(e) = self.writer.lock().await.file.sync_all().await {
+                eprintln!("Failed to sync file: {}", e);
+            }
+        })
+    }
+}
+
+/// Entries kept in memory by the default sink.
+pub const DEFAULT_MEMORY_LOG_ENTRIES: usize = 10_000;
+
+/// Keeps the last `capacity` entries in memory; the sink used until a log file is set.
+pub struct MemorySink {
+    capacity: usize,
+    entries: std::sync::Mutex<VecDeque<String>>,
+}
+
+impl MemorySink {
+    pub fn new(capacity: usize) -> Self {
+        MemorySink {
+            capacity,
+            entries: std::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Default for MemorySink {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_LOG_ENTRIES)
+    }
+}
+
+impl LogSink for MemorySink {
+    fn write<'a>(&'a self, entry: &'a str) -> BoxFuture<'a, ()> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        if self.capacity > 0 {
+            entries.push_back(entry.to_string());
+        }
+        Box::pin(async {})
+    }
+
+    fn entries(&self) -> Vec<String> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Writes entries to the process stderr, e.g. to watch commands live in CI output.
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn write<'a>(&'a self, entry: &'a str) -> BoxFuture<'a, ()> {
+        eprint!("{}", entry);
+        Box::pin(async {})
+    }
+}
+
+/// Emits every entry, output lines included, as a `tracing` event.
+#[cfg(feature = "tracing")]
+pub struct TracingSink;
+
+#[cfg(feature = "tracing")]
+impl LogSink for TracingSink {
+    fn write<'a>(&'a self, entry: &'a str) -> BoxFuture<'a, ()> {
+        tracing::info!(target: "ccm_binding::log", "{}", entry.trim_end());
+        Box::pin(async {})
+    }
+}
+
+/// Log file handle that rotates or truncates the file once it outgrows its limit.
+struct LogWriter {
+    path: String,
+    file: File,
+    size: u64,
+    rotation: Option<LogRotation>,
+}
+
+impl LogWriter {
+    async fn open(path: String, rotation: Option<LogRotation>) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let size = file.metadata().await?.len();
+        Ok(LogWriter {
+            path,
+            file,
+            size,
+            rotation,
+        })
+    }
+
+    async fn write(&mut self, entry: &[u8]) -> Result<(), Error> {
+        if let Some(rotation) = self.rotation
+            && self.size > 0
+            && self.size + entry.len() as u64 > rotation.max_size
+        {
+            self.rotate(rotation).await?;
+        }
+        self.file.write_all(entry).await?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+
+    async fn rotate(&mut self, rotation: LogRotation) -> Result<(), Error> {
+        self.file.sync_all().await?;
+        if rotation.keep == 0 {
+            self.file.set_len(0).await?;
+        } else {
+            for index in (1..rotation.keep).rev() {
+                let from = format!("{}.{}", self.path, index);
+                if tokio::fs::try_exists(&from).await? {
+                    tokio::fs::rename(&from, format!("{}.{}", self.path, index + 1)).await?;
+                }
+            }
+            tokio::fs::rename(&self.path, format!("{}.1", self.path)).await?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Layout of log file entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Fixed-width `started[1]      -> ccm ...` lines.
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp_ms`, `event`, `run_id` and `message`.
+    Json,
+}
+
+impl LogFormat {
+    /// Format selected by `CCM_BINDING_LOG_FORMAT` (`text` or `json`), [`LogFormat::Text`]
+    /// when unset or unrecognized.
+    pub fn from_env() -> Self {
+        match std::env::var("CCM_BINDING_LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+
+    fn entry(&self, event: &str, run_id: Option<i32>, message: &str) -> String {
+        match self {
+            LogFormat::Text => {
+                let tag = match run_id {
+                    Some(run_id) => format!("{}[{}]", event, run_id),
+                    None => event.to_string(),
+                };
+                format!("{:15} -> {}\n", tag, message)
+            }
+            LogFormat::Json => {
+                let timestamp_ms = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_millis());
+                let mut entry = serde_json::json!({
+                    "timestamp_ms": timestamp_ms,
+                    "event": event,
+                    "message": message,
+                });
+                if let Some(run_id) = run_id {
+                    entry["run_id"] = run_id.into();
+                }
+                format!("{}\n", entry)
+            }
+        }
+    }
 }
 
 #[macro_export]
@@ -26,208 +306,909 @@ macro_rules! run_options {
     };
 }
 
+/// Outcome of [`LoggedCmd::run_command`]: exit status and the full captured output.
+#[derive(Debug, Clone)]
+pub struct CommandResult {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandResult {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct RunOptions {
     pub env: HashMap<String, String>,
     pub allow_failure: Option<bool>,
+    /// Kills the command if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Reruns the command when it fails in a way the policy considers transient.
+    pub retry: Option<RetryPolicy>,
+    /// Kills the command once the token is cancelled.
+    pub cancel: Option<CancellationToken>,
+    /// Receives every stdout line as soon as it is read, e.g. to watch for
+    /// "Bootstrap complete" while the command is still running.
+    pub stdout_lines: Option<UnboundedSender<String>>,
+    /// Same as `stdout_lines`, for stderr.
+    pub stderr_lines: Option<UnboundedSender<String>>,
+    /// Directory to run the command in instead of the current one.
+    pub cwd: Option<PathBuf>,
+    /// Runs the command with elevated privileges, e.g. for iptables rules in partition tests.
+    pub escalation: Option<Escalation>,
+    /// Exit code the command must end with, e.g. 2 for ccm rejecting an unknown node; any
+    /// other code, 0 included, fails with [`UnexpectedExitCode`].
+    pub expect_exit_code: Option<i32>,
+}
+
+/// Quotes `word` for a POSIX shell, leaving plain words as they are; use it for values
+/// interpolated into [`LoggedCmd::run_shell`] scripts.
+pub fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Command running `command` with `args`. On Windows batch files, e.g. a `ccm.cmd` wrapper
+/// from a checkout, can only be started through `cmd /C`.
+#[cfg(windows)]
+fn platform_command(command: &str, args: &[&str]) -> Command {
+    let resolved = crate::host_capabilities::find_executable(command);
+    let extension = resolved
+        .as_ref()
+        .and_then(|path| path.extension())
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let mut cmd = match (extension.as_deref(), &resolved) {
+        (Some("bat" | "cmd"), Some(path)) => {
+            let mut cmd = Command::new("cmd");
+            cmd.arg("/C").arg(path);
+            cmd
+        }
+        _ => Command::new(command),
+    };
+    cmd.args(args);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn platform_command(command: &str, args: &[&str]) -> Command {
+    let mut cmd = Command::new(command);
+    cmd.args(args);
+    cmd
+}
+
+/// How [`RunOptions::escalation`] elevates a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Escalation {
+    /// `sudo -n`: fails instead of prompting for a password, and keeps the variables
+    /// from [`RunOptions::env`] that sudo would otherwise drop.
+    Sudo,
+    /// Arbitrary prefix such as `["doas"]`, run as-is.
+    Prefix(Vec<String>),
+}
+
+impl Escalation {
+    /// Prefix placed before the command.
+    pub(crate) fn prefix(&self, env: &HashMap<String, String>) -> Vec<String> {
+        match self {
+            Escalation::Sudo => {
+                let mut prefix = vec!["sudo".to_string(), "-n".to_string()];
+                if !env.is_empty() {
+                    let mut keys: Vec<&str> = env.keys().map(String::as_str).collect();
+                    keys.sort();
+                    prefix.push(format!("--preserve-env={}", keys.join(",")));
+                }
+                prefix
+            }
+            Escalation::Prefix(prefix) => prefix.clone(),
+        }
+    }
+}
+
+/// Longest delay between retries unless set with [`RetryPolicy::with_max_backoff`].
+pub const DEFAULT_MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Retries a failed command with exponential backoff; every attempt is logged under
+/// the run id of the first one.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts allowed after the first one.
+    pub retries: u32,
+    /// Delay before the first retry, doubled before each following one.
+    pub backoff: Duration,
+    /// Upper bound for the doubled delay.
+    pub max_backoff: Duration,
+    /// Output fragments marking a failure as transient; every failure is retried when empty.
+    pub retry_on: Vec<String>,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        RetryPolicy {
+            retries,
+            backoff,
+            max_backoff: DEFAULT_MAX_RETRY_BACKOFF,
+            retry_on: vec![],
+        }
+    }
+
+    /// Caps the delay between attempts at `max_backoff`.
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Delay before retry number `retry`, counted from zero.
+    fn delay(&self, retry: u32) -> Duration {
+        2u32.checked_pow(retry)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    /// Only retries failures whose stdout or stderr contains `fragment` (or other
+    /// fragments added the same way).
+    pub fn retry_on(mut self, fragment: impl Into<String>) -> Self {
+        self.retry_on.push(fragment.into());
+        self
+    }
+
+    /// Known-flaky ccm failures: interrupted downloads and JMX not accepting connections yet.
+    pub fn ccm_transient() -> Self {
+        Self::new(3, Duration::from_secs(1))
+            .retry_on("urlopen error")
+            .retry_on("Connection reset by peer")
+            .retry_on("Temporary failure in name resolution")
+            .retry_on("Failed to connect to")
+            .retry_on("Connection refused")
+    }
+
+    fn is_transient(&self, result: &CommandResult) -> bool {
+        self.retry_on.is_empty()
+            || self.retry_on.iter().any(|fragment| {
+                result.stdout.contains(fragment.as_str())
+                    || result.stderr.contains(fragment.as_str())
+            })
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{command} timed out after {timeout:?}")]
+pub struct CommandTimeout {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl From<CommandTimeout> for Error {
+    fn from(e: CommandTimeout) -> Self {
+        Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{command} ended with {status} instead of exit code {expected}: {stderr}")]
+pub struct UnexpectedExitCode {
+    pub command: String,
+    pub expected: i32,
+    pub status: ExitStatus,
+    pub stderr: String,
+}
+
+impl From<UnexpectedExitCode> for Error {
+    fn from(e: UnexpectedExitCode) -> Self {
+        Error::other(e)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{command} was cancelled")]
+pub struct CommandCancelled {
+    pub command: String,
+}
+
+impl From<CommandCancelled> for Error {
+    fn from(e: CommandCancelled) -> Self {
+        Error::new(io::ErrorKind::Interrupted, e)
+    }
+}
+
+impl Default for LoggedCmd {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LoggedCmd {
     pub fn new() -> Self {
         LoggedCmd {
-            log_file: "".to_string(),
-            file: None,
+            sink: Arc::new(MemorySink::default()),
+            format: LogFormat::from_env(),
             run_id: AtomicI32::new(1),
+            limit: None,
+            dry_run: AtomicBool::new(false),
+            recorded: std::sync::Mutex::new(vec![]),
+            redactions: Arc::new(std::sync::RwLock::new(Redactions::default())),
+            default_env: std::sync::RwLock::new(HashMap::new()),
         }
     }
 
     pub async fn set_log_file(&mut self, file_name: String) -> Result<(), Error> {
-        self.log_file = file_name;
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(self.log_file.as_str())
-            .await?;
-        self.file = Some(Arc::new(Mutex::new(file)));
+        self.open_log_file(file_name, None).await
+    }
+
+    /// Same as [`set_log_file`](LoggedCmd::set_log_file), keeping the log under
+    /// `rotation.max_size` bytes by rotating or truncating it.
+    pub async fn set_log_file_with_rotation(
+        &mut self,
+        file_name: String,
+        rotation: LogRotation,
+    ) -> Result<(), Error> {
+        self.open_log_file(file_name, Some(rotation)).await
+    }
+
+    async fn open_log_file(
+        &mut self,
+        file_name: String,
+        rotation: Option<LogRotation>,
+    ) -> Result<(), Error> {
+        self.sink = Arc::new(FileSink::open(file_name, rotation).await?);
+        Ok(())
+    }
+
+    /// Sends log entries to `sink` instead of the current one, which is a [`MemorySink`]
+    /// until a log file is set.
+    pub fn set_log_sink(&mut self, sink: Arc<dyn LogSink>) {
+        self.sink = sink;
+    }
+
+    /// Entries held by the current sink, see [`LogSink::entries`].
+    pub fn log_entries(&self) -> Vec<String> {
+        self.sink.entries()
+    }
+
+    /// Runs at most `limit` commands at once; further ones wait, logged as `queued[run_id]`.
+    /// Keeps parallel cluster operations from forking dozens of ccm processes on small hosts.
+    pub fn set_max_concurrent_commands(&mut self, limit: usize) {
+        self.limit = Some(Arc::new(Semaphore::new(limit)));
+    }
+
+    /// In dry-run mode commands are recorded, logged as `dry-run[run_id]` and reported as
+    /// successful with empty output instead of being spawned. Takes `&self` so it can be
+    /// switched on a logger already shared with a cluster, e.g. to unit-test cluster logic
+    /// without ccm installed.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run
+            .store(dry_run, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Commands recorded in dry-run mode, oldest first.
+    pub fn recorded_commands(&self) -> Vec<RecordedCommand> {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    /// [`recorded_commands`](LoggedCmd::recorded_commands) as `command args...` lines.
+    #[cfg(test)]
+    pub(crate) fn recorded_lines(&self) -> Vec<String> {
+        self.recorded_commands()
+            .into_iter()
+            .map(|command| format!("{} {}", command.command, command.args.join(" ")))
+            .collect()
+    }
+
+    pub fn set_log_format(&mut self, format: LogFormat) {
+        self.format = format;
+    }
+
+    /// Environment applied to every command, beneath the per-call [`RunOptions::env`] which
+    /// wins on conflicts, e.g. `JAVA_HOME` or proxy settings. Takes `&self` so it can be set
+    /// on a logger already shared with a cluster.
+    pub fn set_default_env(&self, env: HashMap<String, String>) {
+        *self.default_env.write().unwrap() = env;
+    }
+
+    pub fn default_env(&self) -> HashMap<String, String> {
+        self.default_env.read().unwrap().clone()
+    }
+
+    /// `opts` with the default environment merged beneath its own.
+    fn with_default_env(&self, opts: Option<RunOptions>) -> RunOptions {
+        let mut opts = opts.unwrap_or_default();
+        let default_env = self.default_env.read().unwrap();
+        for (key, value) in default_env.iter() {
+            opts.env.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        opts
+    }
+
+    /// Masks `value`, e.g. a password passed to cqlsh or a keystore passphrase, as `***`
+    /// in every log entry and tracing event from now on, command output included. Takes
+    /// `&self` so secrets can be added to a logger already shared with a cluster.
+    pub fn redact(&self, value: impl Into<String>) {
+        let value = value.into();
+        if value.is_empty() {
+            return;
+        }
+        let mut redactions = self.redactions.write().unwrap();
+        if !redactions.values.contains(&value) {
+            redactions.values.push(value);
+            redactions
+                .values
+                .sort_by_key(|value| std::cmp::Reverse(value.len()));
+        }
+    }
+
+    /// Masks every match of `pattern`, e.g. `password=\S+`, like [`redact`](LoggedCmd::redact).
+    #[cfg(feature = "redaction-regex")]
+    pub fn redact_pattern(&self, pattern: &str) -> Result<(), regex::Error> {
+        let pattern = regex::Regex::new(pattern)?;
+        self.redactions.write().unwrap().patterns.push(pattern);
         Ok(())
     }
 
+    /// `text` with redacted values masked.
+    pub fn redacted<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.redactions.read().unwrap().apply(text)
+    }
+
+    /// Writes a free-form event line to the log file, laid out like command events.
+    pub async fn log_event(&self, tag: &str, message: &str) {
+        let message = self.redacted(message).into_owned();
+        #[cfg(feature = "tracing")]
+        tracing::info!(event = tag, message);
+        self.write_entry(self.format.entry(tag, None, &message))
+            .await;
+    }
+
+    async fn log_run_event(&self, event: &str, run_id: i32, message: &str) {
+        let message = self.redacted(message).into_owned();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(event, run_id, message);
+        self.write_entry(self.format.entry(event, Some(run_id), &message))
+            .await;
+    }
+
+    async fn write_entry(&self, entry: String) {
+        self.sink.write(&entry).await;
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(command = %command, args = %self.redacted(&args.join(" ")))
+        )
+    )]
     pub async fn run_command(
         &self,
         command: &str,
         args: &[&str],
         opts: Option<RunOptions>,
-    ) -> Result<ExitStatus, Error> {
+    ) -> Result<CommandResult, Error> {
         let run_id = self
             .run_id
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        let mut cmd = Command::new(command);
-        cmd.args(args).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let opts = self.with_default_env(opts);
+        let mut env: Vec<(&String, &String)> = opts.env.iter().collect();
+        env.sort();
+        for (key, value) in env {
+            self.log_run_event("env", run_id, &format!("{}={}", key, value))
+                .await;
+        }
 
-        let writer = self.file.as_ref().unwrap();
-        let mut allow_failure = false;
+        if let Some(cwd) = &opts.cwd {
+            self.log_run_event("cwd", run_id, &cwd.display().to_string())
+                .await;
+        }
+        let invocation = self.escalate(run_id, command, args, &opts).await;
+        let escalated: Vec<&str>;
+        let (command, args) = match &invocation {
+            Some(invocation) => {
+                escalated = invocation.iter().map(String::as_str).collect();
+                (escalated[0], &escalated[1..])
+            }
+            None => (command, args),
+        };
 
-        if let Some(opts) = opts {
-            if let Some(allow) = opts.allow_failure {
-                allow_failure = allow;
+        let mut retries = 0;
+        loop {
+            let result = self.run_attempt(run_id, command, args, &opts).await?;
+            match opts.expect_exit_code {
+                Some(expected) if result.status.code() == Some(expected) => return Ok(result),
+                None if result.success() => return Ok(result),
+                _ => {}
             }
-            if !opts.env.is_empty() {
-                cmd.envs(opts.env.clone());
-                for (key, value) in opts.env {
-                    writer
-                        .lock()
-                        .await
-                        .write_all(
-                            format!("{:15} -> {}={}\n", format!("env[{}]", run_id), key, value)
-                                .as_bytes(),
-                        )
-                        .await
-                        .ok();
+            if let Some(policy) = &opts.retry
+                && !result.success()
+                && retries < policy.retries
+                && policy.is_transient(&result)
+            {
+                let delay = policy.delay(retries);
+                retries += 1;
+                self.log_run_event(
+                    "retry",
+                    run_id,
+                    &format!("attempt {} in {:?}", retries + 1, delay),
+                )
+                .await;
+                if let Some(token) = &opts.cancel {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = token.cancelled() => {
+                            return Err(self.cancelled(run_id, command, args).await.into());
+                        }
+                    }
+                } else {
+                    tokio::time::sleep(delay).await;
                 }
+                continue;
             }
+            if opts.allow_failure.unwrap_or(false) {
+                return Ok(result);
+            }
+            if let Some(expected) = opts.expect_exit_code {
+                return Err(UnexpectedExitCode {
+                    command: format!("{} {}", command, args.join(" ")),
+                    expected,
+                    status: result.status,
+                    stderr: result.stderr,
+                }
+                .into());
+            }
+            return Err(io::Error::other(format!(
+                "Command failed with status: {}",
+                result.status
+            )));
         }
+    }
 
-        let mut child = cmd.spawn()?;
-        writer
-            .lock()
-            .await
-            .write_all(
-                format!(
-                    "{:15} -> {} {}\n",
-                    format!("started[{}]", run_id),
-                    command,
-                    args.join(" ")
-                )
-                .as_bytes(),
-            )
-            .await
-            .ok();
-
-        let stdout_task = tokio::spawn(Self::stream_reader(
-            child.stdout.take().expect("Failed to capture stdout"),
-            self.file.as_ref().unwrap().clone(),
-            format!("{:15} -> ", format!("stdout[{}]", run_id)),
-        ));
-        let stderr_task = tokio::spawn(Self::stream_reader(
-            child.stderr.take().expect("Failed to capture stderr"),
-            self.file.as_ref().unwrap().clone(),
-            format!("{:15} -> ", format!("stderr[{}]", run_id)),
-        ));
+    /// Runs the command once, logging it under `run_id`; a failed exit status is not an error.
+    async fn run_attempt(
+        &self,
+        run_id: i32,
+        command: &str,
+        args: &[&str],
+        opts: &RunOptions,
+    ) -> Result<CommandResult, Error> {
+        if self.is_dry_run() {
+            self.record_dry_run(run_id, command, args, opts).await;
+            return Ok(CommandResult {
+                status: ExitStatus::from_raw(0),
+                stdout: String::new(),
+                stderr: String::new(),
+            });
+        }
+        let _permit = match &self.limit {
+            None => None,
+            Some(limit) => match limit.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    self.log_run_event("queued", run_id, "waiting for a free command slot")
+                        .await;
+                    Some(limit.clone().acquire_owned().await.map_err(Error::other)?)
+                }
+            },
+        };
+        let (mut child, stdout_task, stderr_task) = self
+            .spawn_logged(run_id, command, args, opts, false)
+            .await?;
 
-        let status = child.wait().await;
-        let _ = tokio::join!(stdout_task, stderr_task);
+        let wait = async {
+            match opts.timeout {
+                None => Ok(child.wait().await),
+                Some(timeout) => tokio::time::timeout(timeout, child.wait()).await,
+            }
+        };
+        let cancelled = async {
+            match &opts.cancel {
+                Some(token) => token.cancelled().await,
+                None => std::future::pending().await,
+            }
+        };
+        let outcome = tokio::select! {
+            outcome = wait => Some(outcome),
+            _ = cancelled => None,
+        };
+        let status = match outcome {
+            Some(Ok(status)) => status,
+            interrupted => {
+                child.kill().await.ok();
+                // Grandchildren may keep the pipes open, so don't wait for the readers.
+                stdout_task.abort();
+                stderr_task.abort();
+                return Err(match (interrupted, opts.timeout) {
+                    (Some(Err(_)), Some(timeout)) => {
+                        self.log_run_event(
+                            "timeout",
+                            run_id,
+                            &format!("killed after {:?}", timeout),
+                        )
+                        .await;
+                        CommandTimeout {
+                            command: format!("{} {}", command, args.join(" ")),
+                            timeout,
+                        }
+                        .into()
+                    }
+                    _ => self.cancelled(run_id, command, args).await.into(),
+                });
+            }
+        };
+        let (stdout, stderr) = tokio::join!(stdout_task, stderr_task);
+        let stdout = stdout.unwrap_or_default();
+        let stderr = stderr.unwrap_or_default();
         match status {
             Ok(status) => {
-                match status.code() {
-                    Some(code) => {
-                        writer
-                            .lock()
-                            .await
-                            .write_all(
-                                format!(
-                                    "{:15} -> status = {}\n",
-                                    format!("exited[{}]", run_id),
-                                    code
-                                )
-                                .as_bytes(),
-                            )
-                            .await
-                            .ok();
-                    }
-                    None => {
-                        writer
-                            .lock()
-                            .await
-                            .write_all(
-                                format!(
-                                    "{:15} -> status = unknown\n",
-                                    format!("exited[{}]", run_id)
-                                )
-                                .as_bytes(),
-                            )
-                            .await
-                            .ok();
-                    }
-                }
-                if !allow_failure && !status.success() {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("Command failed with status: {}", status),
-                    ));
-                }
-                Ok(status)
+                let code = status
+                    .code()
+                    .map_or("unknown".to_string(), |code| code.to_string());
+                self.log_run_event("exited", run_id, &format!("status = {}", code))
+                    .await;
+                Ok(CommandResult {
+                    status,
+                    stdout,
+                    stderr,
+                })
             }
             Err(e) => {
-                writer
-                    .lock()
-                    .await
-                    .write_all(
-                        format!(
-                            "{:15} -> failed to wait on child process: = {}\n",
-                            format!("exited[{}]", run_id),
-                            e
-                        )
-                        .as_bytes(),
-                    )
-                    .await
-                    .ok();
+                self.log_run_event(
+                    "exited",
+                    run_id,
+                    &format!("failed to wait on child process: = {}", e),
+                )
+                .await;
                 Err(e)
             }
         }
     }
 
-    async fn stream_reader<T>(stream: T, writer: Arc<Mutex<File>>, prefix: String)
+    /// Runs `script` with `sh -c` (`cmd /C` on Windows), e.g.
+    /// `nodetool tablestats ks | grep 'Space used'`, so simple filters and redirections don't
+    /// need post-processing in Rust. The script is logged whole; quote interpolated values
+    /// with [`shell_quote`]. As in any `sh` script, a pipeline's status is that of its last
+    /// command.
+    pub async fn run_shell(
+        &self,
+        script: &str,
+        opts: Option<RunOptions>,
+    ) -> Result<CommandResult, Error> {
+        if cfg!(windows) {
+            self.run_command("cmd", &["/C", script], opts).await
+        } else {
+            self.run_command("sh", &["-c", script], opts).await
+        }
+    }
+
+    /// Full invocation, program first, when `opts` asks for escalation; logged as
+    /// `escalated[run_id]`.
+    async fn escalate(
+        &self,
+        run_id: i32,
+        command: &str,
+        args: &[&str],
+        opts: &RunOptions,
+    ) -> Option<Vec<String>> {
+        let escalation = opts.escalation.as_ref()?;
+        let mut invocation = escalation.prefix(&opts.env);
+        self.log_run_event(
+            "escalated",
+            run_id,
+            &format!(
+                "{} {} via {}",
+                command,
+                args.join(" "),
+                invocation.join(" ")
+            ),
+        )
+        .await;
+        invocation.push(command.to_string());
+        invocation.extend(args.iter().map(|arg| arg.to_string()));
+        Some(invocation)
+    }
+
+    async fn record_dry_run(&self, run_id: i32, command: &str, args: &[&str], opts: &RunOptions) {
+        self.recorded.lock().unwrap().push(RecordedCommand {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: opts.env.clone(),
+            cwd: opts.cwd.clone(),
+        });
+        self.log_run_event(
+            "dry-run",
+            run_id,
+            &format!("{} {}", command, args.join(" ")),
+        )
+        .await;
+    }
+
+    /// Spawns the command, logs it as `started[run_id]` and starts copying its output to
+    /// the log; the readers return everything they read. With `process_group` the command
+    /// leads a process group of its own, so that its children can be killed along with it.
+    async fn spawn_logged(
+        &self,
+        run_id: i32,
+        command: &str,
+        args: &[&str],
+        opts: &RunOptions,
+        process_group: bool,
+    ) -> Result<(Child, JoinHandle<String>, JoinHandle<String>), Error> {
+        let mut cmd = platform_command(command, args);
+        // Dropping the caller's future must not leave ccm running against a half-built cluster.
+        cmd.envs(&opts.env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &opts.cwd {
+            cmd.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        if process_group {
+            cmd.process_group(0);
+        }
+        let mut child = cmd.spawn()?;
+        self.log_run_event(
+            "started",
+            run_id,
+            &format!("{} {}", command, args.join(" ")),
+        )
+        .await;
+
+        let stdout_task = tokio::spawn(Self::stream_reader(
+            child.stdout.take().expect("Failed to capture stdout"),
+            self.sink.clone(),
+            self.redactions.clone(),
+            self.format,
+            "stdout",
+            run_id,
+            opts.stdout_lines.clone(),
+        ));
+        let stderr_task = tokio::spawn(Self::stream_reader(
+            child.stderr.take().expect("Failed to capture stderr"),
+            self.sink.clone(),
+            self.redactions.clone(),
+            self.format,
+            "stderr",
+            run_id,
+            opts.stderr_lines.clone(),
+        ));
+        Ok((child, stdout_task, stderr_task))
+    }
+
+    /// Starts the command and returns without waiting for it, e.g. for a stress run or a
+    /// proxy that must run alongside the test body. Output is logged and captured like for
+    /// [`run_command`](LoggedCmd::run_command), and `env`, `cwd`, the line channels and
+    /// `allow_failure` (applied by [`ProcessHandle::wait`]) are honored; the process does
+    /// not take a concurrency slot and is killed when the handle is dropped.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(command = %command, args = %self.redacted(&args.join(" ")))
+        )
+    )]
+    pub async fn spawn_background(
+        &self,
+        command: &str,
+        args: &[&str],
+        opts: Option<RunOptions>,
+    ) -> Result<ProcessHandle, Error> {
+        let run_id = self
+            .run_id
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let opts = self.with_default_env(opts);
+        let invocation = self.escalate(run_id, command, args, &opts).await;
+        let escalated: Vec<&str>;
+        let (command, args) = match &invocation {
+            Some(invocation) => {
+                escalated = invocation.iter().map(String::as_str).collect();
+                (escalated[0], &escalated[1..])
+            }
+            None => (command, args),
+        };
+        let mut handle = ProcessHandle {
+            command: format!("{} {}", command, args.join(" ")),
+            run_id,
+            child: None,
+            readers: None,
+            sink: self.sink.clone(),
+            format: self.format,
+            allow_failure: opts.allow_failure.unwrap_or(false),
+        };
+        if self.is_dry_run() {
+            self.record_dry_run(run_id, command, args, &opts).await;
+            return Ok(handle);
+        }
+        let (child, stdout_task, stderr_task) = self
+            .spawn_logged(run_id, command, args, &opts, true)
+            .await?;
+        handle.child = Some(child);
+        handle.readers = Some((stdout_task, stderr_task));
+        Ok(handle)
+    }
+
+    async fn cancelled(&self, run_id: i32, command: &str, args: &[&str]) -> CommandCancelled {
+        self.log_run_event("cancelled", run_id, "killed on request")
+            .await;
+        CommandCancelled {
+            command: format!("{} {}", command, args.join(" ")),
+        }
+    }
+
+    /// Copies `stream` to the log, and to `lines` if given, line by line and returns
+    /// everything it read.
+    async fn stream_reader<T>(
+        stream: T,
+        sink: Arc<dyn LogSink>,
+        redactions: Arc<std::sync::RwLock<Redactions>>,
+        format: LogFormat,
+        event: &'static str,
+        run_id: i32,
+        lines_sender: Option<UnboundedSender<String>>,
+    ) -> String
     where
         T: tokio::io::AsyncRead + Unpin + Send + 'static,
     {
         let reader = BufReader::new(stream);
         let mut lines = reader.lines();
+        let mut captured = String::new();
 
         while let Some(line) = tokio::select! {
             line = lines.next_line() => line.unwrap_or(None),
         } {
-            let _ = writer
-                .lock()
-                .await
-                .write_all(format!("{} {}\n", prefix, line).as_bytes())
+            let redacted = redactions.read().unwrap().apply(&line).into_owned();
+            // The text layout has always put an extra space before output lines.
+            let message = match format {
+                LogFormat::Text => format!(" {}", redacted),
+                LogFormat::Json => redacted,
+            };
+            sink.write(&format.entry(event, Some(run_id), &message))
                 .await;
+            captured.push_str(&line);
+            captured.push('\n');
+            if let Some(sender) = &lines_sender {
+                // The receiver may have stopped listening; the output is still captured.
+                sender.send(line).ok();
+            }
         }
+        captured
     }
 
-    fn drop(&mut self) {
-        if let Some(file) = self.file.take() {
-            Runtime::new().unwrap().block_on(async {
-                if let Err(e) = file.lock().await.sync_all().await {
-                    eprintln!("Failed to sync file: {}", e);
-                }
-            });
-        }
+    /// Flushes the log sink and detaches it from the logger, which falls back to a fresh
+    /// [`MemorySink`].
+    pub async fn close(&mut self) {
+        let sink = std::mem::replace(&mut self.sink, Arc::new(MemorySink::default()));
+        sink.flush().await;
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let mut runner = LoggedCmd::new();
-    runner
-        .set_log_file("command_log.txt".to_string())
-        .await
-        .expect("Failed to set log file");
+/// How long [`ProcessHandle::kill`] waits for output still buffered in the pipes.
+const KILLED_OUTPUT_GRACE: Duration = Duration::from_secs(1);
 
-    if let Err(e) = runner
-        .run_command("ls", &["-l", "/nonexistent_path"], None)
-        .await
-    {
-        eprintln!("Failed to run command: {}", e);
+/// Command started with [`LoggedCmd::spawn_background`]. In dry-run mode there is no
+/// process and the handle reports a successful exit with empty output.
+pub struct ProcessHandle {
+    command: String,
+    run_id: i32,
+    child: Option<Child>,
+    readers: Option<(JoinHandle<String>, JoinHandle<String>)>,
+    sink: Arc<dyn LogSink>,
+    format: LogFormat,
+    allow_failure: bool,
+}
+
+impl ProcessHandle {
+    pub fn command(&self) -> &str {
+        &self.command
     }
 
-    let mut env_vars: HashMap<String, String> = HashMap::new();
-    env_vars.insert("GREETING".to_string(), "Hello".to_string());
+    /// OS process id, `None` once the process has been reaped or in dry-run mode.
+    pub fn id(&self) -> Option<u32> {
+        self.child.as_ref().and_then(Child::id)
+    }
 
-    if let Err(e) = runner
-        .run_command("printenv", &["GREETING"], run_options!(env = env_vars))
-        .await
-    {
-        eprintln!("Failed to run command: {}", e);
+    /// Whether the process is still running, without waiting for it.
+    pub fn is_running(&mut self) -> Result<bool, Error> {
+        match self.child.as_mut() {
+            Some(child) => Ok(child.try_wait()?.is_none()),
+            None => Ok(false),
+        }
+    }
+
+    /// Waits for the process to exit. A failed exit status is an error unless the
+    /// command was started with `allow_failure`.
+    pub async fn wait(mut self) -> Result<CommandResult, Error> {
+        let status = match self.child.as_mut() {
+            Some(child) => child.wait().await?,
+            None => ExitStatus::from_raw(0),
+        };
+        let result = self.finish(status, None).await;
+        if result.success() || self.allow_failure {
+            Ok(result)
+        } else {
+            Err(io::Error::other(format!(
+                "Command failed with status: {}",
+                result.status
+            )))
+        }
+    }
+
+    /// Kills the process and everything it started, e.g. the server behind a wrapper
+    /// script, and returns what it printed; the killed status is not an error.
+    pub async fn kill(mut self) -> Result<CommandResult, Error> {
+        let status = match self.child.as_mut() {
+            Some(child) => {
+                // The process leads its own group, see `spawn_logged`.
+                #[cfg(unix)]
+                if let Some(pid) = child.id() {
+                    Command::new("kill")
+                        .args(["-KILL", "--", &format!("-{}", pid)])
+                        .status()
+                        .await
+                        .ok();
+                }
+                child.kill().await?;
+                child.wait().await?
+            }
+            None => ExitStatus::from_raw(0),
+        };
+        self.log("killed", "killed on request").await;
+        Ok(self.finish(status, Some(KILLED_OUTPUT_GRACE)).await)
+    }
+
+    /// Collects the output, waiting at most `grace` for it if given since grandchildren
+    /// may keep the pipes open, and logs the exit.
+    async fn finish(&mut self, status: ExitStatus, grace: Option<Duration>) -> CommandResult {
+        let (stdout, stderr) = match self.readers.take() {
+            Some((stdout_task, stderr_task)) => {
+                let (stdout_abort, stderr_abort) =
+                    (stdout_task.abort_handle(), stderr_task.abort_handle());
+                let readers = async {
+                    let (stdout, stderr) = tokio::join!(stdout_task, stderr_task);
+                    (stdout.unwrap_or_default(), stderr.unwrap_or_default())
+                };
+                match grace {
+                    None => readers.await,
+                    Some(grace) => {
+                        tokio::time::timeout(grace, readers)
+                            .await
+                            .unwrap_or_else(|_| {
+                                stdout_abort.abort();
+                                stderr_abort.abort();
+                                Default::default()
+                            })
+                    }
+                }
+            }
+            None => Default::default(),
+        };
+        let code = status
+            .code()
+            .map_or("unknown".to_string(), |code| code.to_string());
+        self.log("exited", &format!("status = {}", code)).await;
+        CommandResult {
+            status,
+            stdout,
+            stderr,
+        }
+    }
+
+    async fn log(&self, event: &str, message: &str) {
+        if self.child.is_some() {
+            self.sink
+                .write(&self.format.entry(event, Some(self.run_id), message))
+                .await;
+        }
     }
 }
 
@@ -285,6 +1266,538 @@ mod tests {
         fs::remove_file(log_file).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_run_command_captures_output() {
+        let log_file = "/tmp/test_log_capture.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let result = runner
+            .run_command(
+                "sh",
+                &["-c", "echo out; echo err >&2; exit 3"],
+                run_options!(allow_failure = Some(true)),
+            )
+            .await
+            .unwrap();
+        assert!(!result.success());
+        assert_eq!(result.status.code(), Some(3));
+        assert_eq!(result.stdout, "out\n");
+        assert_eq!(result.stderr, "err\n");
+
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_command_timeout() {
+        let log_file = "/tmp/test_log_timeout.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let err = runner
+            .run_command(
+                "sleep",
+                &["5"],
+                run_options!(timeout = Some(Duration::from_millis(100))),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(err.get_ref().unwrap().is::<CommandTimeout>());
+
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        assert!(log_contents.ends_with("timeout[1]      -> killed after 100ms\n"));
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_command_retries_transient_failures() {
+        let log_file = "/tmp/test_log_retry.txt";
+        let marker = "/tmp/test_log_retry.marker";
+        fs::remove_file(log_file).await.ok();
+        fs::remove_file(marker).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let script =
+            format!("[ -e {marker} ] && exit 0; touch {marker}; echo Connection refused; exit 1");
+        let policy = RetryPolicy::new(2, Duration::from_millis(10)).retry_on("Connection refused");
+        runner
+            .run_command(
+                "sh",
+                &["-c", &script],
+                run_options!(retry = Some(policy.clone())),
+            )
+            .await
+            .unwrap();
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        assert!(log_contents.contains("retry[1]        -> attempt 2 in 10ms\n"));
+        assert!(log_contents.ends_with("exited[1]       -> status = 0\n"));
+
+        runner
+            .run_command(
+                "sh",
+                &["-c", "echo disk full; exit 1"],
+                run_options!(retry = Some(policy)),
+            )
+            .await
+            .unwrap_err();
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        assert!(!log_contents.contains("retry[2]"));
+
+        fs::remove_file(log_file).await.unwrap();
+        fs::remove_file(marker).await.unwrap();
+    }
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        let policy = RetryPolicy::new(u32::MAX, Duration::from_secs(1));
+        assert_eq!(policy.delay(0), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(6), DEFAULT_MAX_RETRY_BACKOFF);
+        assert_eq!(policy.delay(40), DEFAULT_MAX_RETRY_BACKOFF);
+        assert_eq!(policy.delay(u32::MAX), DEFAULT_MAX_RETRY_BACKOFF);
+        let policy = policy.with_max_backoff(Duration::from_secs(5));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_run_command_cancellation() {
+        let log_file = "/tmp/test_log_cancel.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        let err = runner
+            .run_command("sleep", &["5"], run_options!(cancel = Some(token)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(err.get_ref().unwrap().is::<CommandCancelled>());
+
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        assert!(log_contents.ends_with("cancelled[1]    -> killed on request\n"));
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_json_log_format() {
+        let log_file = "/tmp/test_log_json.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner.set_log_format(LogFormat::Json);
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        runner
+            .run_command("echo", &["Test Json"], None)
+            .await
+            .unwrap();
+
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        let entries: Vec<serde_json::Value> = log_contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let events: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|e| (e["event"].as_str().unwrap(), e["message"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("started", "echo Test Json"),
+                ("stdout", "Test Json"),
+                ("exited", "status = 0")
+            ]
+        );
+        assert!(
+            entries
+                .iter()
+                .all(|e| e["run_id"] == 1 && e["timestamp_ms"].as_u64().unwrap() > 0)
+        );
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_log_rotation() {
+        let log_file = "/tmp/test_log_rotation.txt";
+        for suffix in ["", ".1", ".2", ".3"] {
+            fs::remove_file(format!("{log_file}{suffix}")).await.ok();
+        }
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file_with_rotation(
+                log_file.to_string(),
+                LogRotation {
+                    max_size: 40,
+                    keep: 2,
+                },
+            )
+            .await
+            .expect("Failed to set log file");
+
+        for i in 0..4 {
+            runner.log_event("event", &format!("entry {i}")).await;
+        }
+
+        assert_eq!(
+            fs::read_to_string(log_file).await.unwrap(),
+            "event           -> entry 3\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{log_file}.1")).await.unwrap(),
+            "event           -> entry 2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{log_file}.2")).await.unwrap(),
+            "event           -> entry 1\n"
+        );
+        assert!(!fs::try_exists(format!("{log_file}.3")).await.unwrap());
+        for suffix in ["", ".1", ".2"] {
+            fs::remove_file(format!("{log_file}{suffix}"))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_command_streams_lines() {
+        let log_file = "/tmp/test_log_stream.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let args = ["-c", "echo Starting; echo Bootstrap complete; sleep 5"];
+        let command = runner.run_command(
+            "sh",
+            &args,
+            run_options!(
+                stdout_lines = Some(sender),
+                timeout = Some(Duration::from_secs(10))
+            ),
+        );
+        let watcher = async {
+            while let Some(line) = receiver.recv().await {
+                if line == "Bootstrap complete" {
+                    return true;
+                }
+            }
+            false
+        };
+        tokio::select! {
+            _ = command => panic!("command finished before its output was seen"),
+            seen = watcher => assert!(seen),
+        }
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_commands() {
+        let log_file = "/tmp/test_log_limit.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner.set_max_concurrent_commands(1);
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let started = std::time::Instant::now();
+        let (first, second) = tokio::join!(
+            runner.run_command("sleep", &["0.2"], None),
+            runner.run_command("sleep", &["0.2"], None),
+        );
+        first.unwrap();
+        second.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(400));
+
+        let log_contents = fs::read_to_string(log_file).await.unwrap();
+        assert!(log_contents.contains("queued[2]       -> waiting for a free command slot\n"));
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_command_in_cwd() {
+        let log_file = "/tmp/test_log_cwd.txt";
+        fs::remove_file(log_file).await.ok();
+        let mut runner = LoggedCmd::new();
+        runner
+            .set_log_file(log_file.to_string())
+            .await
+            .expect("Failed to set log file");
+
+        let result = runner
+            .run_command("pwd", &[], run_options!(cwd = Some(PathBuf::from("/"))))
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "/\n");
+        fs::remove_file(log_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_records_commands() {
+        let runner = LoggedCmd::new();
+        runner.set_dry_run(true);
+
+        let mut env_vars: HashMap<String, String> = HashMap::new();
+        env_vars.insert("TEST_ENV".to_string(), "1".to_string());
+        let result = runner
+            .run_command(
+                "ccm",
+                &["start", "test"],
+                run_options!(env = env_vars.clone()),
+            )
+            .await
+            .unwrap();
+        assert!(result.success());
+        assert_eq!(
+            runner.recorded_commands(),
+            vec![RecordedCommand {
+                command: "ccm".to_string(),
+                args: vec!["start".to_string(), "test".to_string()],
+                env: env_vars,
+                cwd: None,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_sinks() {
+        // No log file: entries go to the default in-memory sink.
+        let runner = LoggedCmd::new();
+        runner.run_command("echo", &["hello"], None).await.unwrap();
+        let entries = runner.log_entries();
+        assert!(entries[0].starts_with("started[1]      -> echo hello"));
+        assert_eq!(entries[1], "stdout[1]       ->  hello\n");
+
+        let sink = Arc::new(MemorySink::new(2));
+        let mut runner = LoggedCmd::new();
+        runner.set_log_sink(sink.clone());
+        runner.log_event("one", "1").await;
+        runner.log_event("two", "2").await;
+        runner.log_event("three", "3").await;
+        assert_eq!(
+            sink.entries(),
+            ["two             -> 2\n", "three           -> 3\n"]
+        );
+        runner.close().await;
+        runner.log_event("four", "4").await;
+        assert_eq!(sink.entries().len(), 2);
+        assert_eq!(runner.log_entries(), ["four            -> 4\n"]);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_background() {
+        let runner = LoggedCmd::new();
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut handle = runner
+            .spawn_background(
+                "sh",
+                &["-c", "echo ready; sleep 30"],
+                run_options!(stdout_lines = Some(sender)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap(), "ready");
+        assert!(handle.is_running().unwrap());
+        assert!(handle.id().is_some());
+
+        let result = handle.kill().await.unwrap();
+        assert!(!result.success());
+        assert_eq!(result.stdout, "ready\n");
+        let entries = runner.log_entries();
+        assert!(entries.iter().any(|entry| entry.starts_with("killed[1]")));
+
+        let handle = runner
+            .spawn_background("sh", &["-c", "exit 3"], None)
+            .await
+            .unwrap();
+        assert!(handle.wait().await.is_err());
+        let handle = runner
+            .spawn_background(
+                "sh",
+                &["-c", "exit 3"],
+                run_options!(allow_failure = Some(true)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(handle.wait().await.unwrap().status.code(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_expect_exit_code() {
+        let runner = LoggedCmd::new();
+        let result = runner
+            .run_command(
+                "sh",
+                &["-c", "exit 2"],
+                run_options!(expect_exit_code = Some(2)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.status.code(), Some(2));
+
+        let err = runner
+            .run_command(
+                "sh",
+                &["-c", "echo 'no such node' >&2"],
+                run_options!(expect_exit_code = Some(2)),
+            )
+            .await
+            .unwrap_err();
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<UnexpectedExitCode>()
+            .unwrap();
+        assert_eq!(err.status.code(), Some(0));
+        assert_eq!(
+            err.to_string(),
+            "sh -c echo 'no such node' >&2 ended with exit status: 0 instead of exit code 2: no such node\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_escalation() {
+        let runner = LoggedCmd::new();
+        runner.set_dry_run(true);
+        let env = HashMap::from([("XTABLES_LOCKFILE".to_string(), "/tmp/xt.lock".to_string())]);
+        runner
+            .run_command(
+                "iptables",
+                &["-L"],
+                run_options!(env = env, escalation = Some(Escalation::Sudo)),
+            )
+            .await
+            .unwrap();
+        runner
+            .run_command(
+                "ip",
+                &["addr"],
+                run_options!(escalation = Some(Escalation::Prefix(vec!["doas".to_string()]))),
+            )
+            .await
+            .unwrap();
+        let recorded = runner.recorded_lines();
+        assert_eq!(
+            recorded,
+            [
+                "sudo -n --preserve-env=XTABLES_LOCKFILE iptables -L",
+                "doas ip addr"
+            ]
+        );
+        assert!(
+            runner
+                .log_entries()
+                .contains(&"escalated[2]    -> ip addr via doas\n".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redaction() {
+        let runner = LoggedCmd::new();
+        runner.redact("s3cret");
+        runner.redact("");
+        let result = runner
+            .run_command("echo", &["-p", "s3cret"], None)
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "-p s3cret\n");
+        runner.log_event("auth", "password s3cret set").await;
+        assert_eq!(
+            runner.log_entries(),
+            [
+                "started[1]      -> echo -p ***\n",
+                "stdout[1]       ->  -p ***\n",
+                "exited[1]       -> status = 0\n",
+                "auth            -> password *** set\n",
+            ]
+        );
+    }
+
+    #[cfg(feature = "redaction-regex")]
+    #[tokio::test]
+    async fn test_redaction_pattern() {
+        let runner = LoggedCmd::new();
+        runner.redact_pattern(r"pass:\S+").unwrap();
+        runner
+            .log_event("tls", "openssl -passout pass:ccm-binding")
+            .await;
+        assert_eq!(
+            runner.log_entries(),
+            ["tls             -> openssl -passout ***\n"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_env() {
+        let runner = LoggedCmd::new();
+        runner.set_default_env(HashMap::from([
+            ("JAVA_HOME".to_string(), "/opt/jdk11".to_string()),
+            ("SCYLLA_EXT_OPTS".to_string(), "--smp 1".to_string()),
+        ]));
+        let result = runner
+            .run_command(
+                "sh",
+                &["-c", "echo $JAVA_HOME $SCYLLA_EXT_OPTS"],
+                run_options!(
+                    env = HashMap::from([("SCYLLA_EXT_OPTS".to_string(), "--smp 2".to_string())])
+                ),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.stdout, "/opt/jdk11 --smp 2\n");
+        assert_eq!(
+            runner.log_entries()[0],
+            "env[1]          -> JAVA_HOME=/opt/jdk11\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_shell() {
+        let runner = LoggedCmd::new();
+        let keyspace = "it's";
+        let script = format!(
+            "printf 'a\\nb\\n%s\\n' {} | grep -v a > /dev/stdout",
+            shell_quote(keyspace)
+        );
+        let result = runner.run_shell(&script, None).await.unwrap();
+        assert_eq!(result.stdout, "b\nit's\n");
+        assert_eq!(
+            runner.log_entries()[0],
+            format!("started[1]      -> sh -c {}\n", script)
+        );
+        assert!(runner.run_shell("exit 4", None).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_run_command_with_env() {
         let log_file = "/tmp/test_log_env.txt";
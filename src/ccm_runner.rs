@@ -0,0 +1,441 @@
+use crate::ccm_cli::{CommandResult, LoggedCmd, RunOptions};
+use crate::executor::CommandExecutor;
+use crate::host_capabilities::find_executable;
+use crate::run_options;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Environment variable overriding the ccm command, e.g. `python3 /opt/scylla-ccm/ccm`.
+pub const CCM_COMMAND_ENV: &str = "CCM_BINDING_CCM";
+
+/// Runners that passed [`CcmRunner::preflight`] in this process, keyed by command and flavour.
+static PREFLIGHT_PASSED: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Cluster subcommands ccm dispatches on; any other first argument names a node.
+const CLUSTER_COMMANDS: &[&str] = &[
+    "create",
+    "add",
+    "list",
+    "switch",
+    "status",
+    "remove",
+    "clear",
+    "liveset",
+    "start",
+    "stop",
+    "flush",
+    "compact",
+    "stress",
+    "updateconf",
+    "updatedseconf",
+    "updatelog4j",
+    "cli",
+    "setdir",
+    "bulkload",
+    "setlog",
+    "scrub",
+    "verify",
+    "invalidatecache",
+    "checklogerror",
+    "showlastlog",
+    "jconsole",
+    "setworkload",
+];
+
+/// Cluster subcommands that only read state, e.g. `ccm status`. Their results may be
+/// cached, see [`CcmRunner::with_query_cache`].
+const CLUSTER_QUERY_COMMANDS: &[&str] = &["list", "status", "liveset"];
+
+/// Node subcommands that only read state, e.g. `ccm node_1_1 show`.
+const NODE_QUERY_COMMANDS: &[&str] = &["show"];
+
+/// A reasonable TTL for [`CcmRunner::with_query_cache`] when polling status.
+pub const DEFAULT_QUERY_CACHE_TTL: Duration = Duration::from_secs(1);
+
+type QueryKey = (Vec<String>, BTreeMap<String, String>);
+type QueryResults = HashMap<QueryKey, (Instant, CommandResult)>;
+
+/// Cached query results per config dir, shared by every runner using it so that a node
+/// start through one runner invalidates `ccm status` cached by another.
+static QUERY_CACHE: LazyLock<Mutex<HashMap<String, QueryResults>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Error)]
+pub enum CcmPreflightError {
+    #[error("ccm not found: could not run {program}: {source}")]
+    NotFound { program: String, source: IoError },
+    #[error("ccm is not usable: `{command}` failed: {stderr}")]
+    Broken { command: String, stderr: String },
+    #[error("scylla-ccm required: {program} does not support --scylla")]
+    ScyllaUnsupported { program: String },
+}
+
+impl From<CcmPreflightError> for IoError {
+    fn from(e: CcmPreflightError) -> Self {
+        let kind = match &e {
+            CcmPreflightError::NotFound { .. } => ErrorKind::NotFound,
+            CcmPreflightError::Broken { .. } => ErrorKind::Other,
+            CcmPreflightError::ScyllaUnsupported { .. } => ErrorKind::Unsupported,
+        };
+        IoError::new(kind, e)
+    }
+}
+
+/// How ccm is invoked: the executable, arguments placed before every subcommand, flags
+/// appended to every invocation, and the `--config-dir` clusters live in.
+#[derive(Clone)]
+pub struct CcmRunner {
+    /// Executable to run, `ccm` from `PATH` by default.
+    pub program: String,
+    /// Arguments placed before the subcommand, e.g. the script path when `program` is an
+    /// interpreter.
+    pub leading_args: Vec<String>,
+    /// Flags appended to every invocation after `--config-dir`.
+    pub global_args: Vec<String>,
+    pub config_dir: String,
+    /// How long query results are reused, `None` (the default) to always run queries.
+    pub query_cache_ttl: Option<Duration>,
+    logged_cmd: Arc<LoggedCmd>,
+    executor: Arc<dyn CommandExecutor>,
+}
+
+impl CcmRunner {
+    /// Runner for `config_dir`, invoking the command from [`CCM_COMMAND_ENV`] if set and
+    /// `ccm` otherwise.
+    pub fn new(logged_cmd: Arc<LoggedCmd>, config_dir: impl Into<String>) -> Self {
+        let mut command = std::env::var(CCM_COMMAND_ENV)
+            .ok()
+            .map(|command| {
+                command
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|command| !command.is_empty())
+            .unwrap_or_else(|| vec!["ccm".to_string()]);
+        let program = command.remove(0);
+        CcmRunner {
+            program,
+            leading_args: command,
+            global_args: vec![],
+            config_dir: config_dir.into(),
+            query_cache_ttl: None,
+            executor: logged_cmd.clone(),
+            logged_cmd,
+        }
+    }
+
+    /// Uses `program` with `leading_args` instead of the default command, e.g.
+    /// `with_command("python3", ["/opt/scylla-ccm/ccm"])` for a checkout that is not on `PATH`.
+    pub fn with_command<I, S>(mut self, program: impl Into<String>, leading_args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.program = program.into();
+        self.leading_args = leading_args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_global_args<I, S>(mut self, global_args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.global_args = global_args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Reuses results of read-only subcommands (`list`, `status`, `liveset` and node
+    /// `show`) for `ttl`, or never with `None`, the default. Any other subcommand run in
+    /// the same config dir drops all cached results, so a status poll after `start` always
+    /// sees the new state.
+    pub fn with_query_cache(mut self, ttl: Option<Duration>) -> Self {
+        self.query_cache_ttl = ttl;
+        self
+    }
+
+    /// Drops cached query results for this config dir, e.g. after changing the cluster
+    /// outside of ccm.
+    pub fn invalidate_query_cache(&self) {
+        QUERY_CACHE.lock().unwrap().remove(&self.config_dir);
+    }
+
+    /// Runs ccm through `executor`, e.g. an [`SshExecutor`](crate::executor::SshExecutor),
+    /// instead of locally through the logger.
+    pub fn with_executor(mut self, executor: Arc<dyn CommandExecutor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
+    pub fn logged_cmd(&self) -> &Arc<LoggedCmd> {
+        &self.logged_cmd
+    }
+
+    pub fn executor(&self) -> &Arc<dyn CommandExecutor> {
+        &self.executor
+    }
+
+    /// Full argument list for the ccm subcommand `args`, without the program itself.
+    pub fn command_args<'a>(&'a self, args: &[&'a str]) -> Vec<&'a str> {
+        let mut full: Vec<&str> = self.leading_args.iter().map(String::as_str).collect();
+        full.extend(args);
+        full.extend(["--config-dir", &self.config_dir]);
+        full.extend(self.global_args.iter().map(String::as_str));
+        full
+    }
+
+    /// Checks that ccm runs (`ccm list`) and, for Scylla clusters, that it is scylla-ccm
+    /// (`ccm create --help` mentions `--scylla`); the resolved executable is logged as a
+    /// `ccm` event. Passing checks are remembered for the rest of the process, and
+    /// nothing is checked in dry-run mode.
+    pub async fn preflight(&self, scylla: bool) -> Result<(), CcmPreflightError> {
+        if self.logged_cmd.is_dry_run() {
+            return Ok(());
+        }
+        let key = format!(
+            "{} {} {}",
+            self.program,
+            self.leading_args.join(" "),
+            scylla
+        );
+        if PREFLIGHT_PASSED.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+
+        let program = find_executable(&self.program)
+            .map_or(self.program.clone(), |path| path.display().to_string());
+        self.logged_cmd
+            .log_event(
+                "ccm",
+                &format!("using {} {}", program, self.leading_args.join(" ")),
+            )
+            .await;
+        let list = self
+            .run(&["list"], run_options!(allow_failure = Some(true)))
+            .await
+            .map_err(|source| CcmPreflightError::NotFound {
+                program: self.program.clone(),
+                source,
+            })?;
+        if !list.success() {
+            return Err(CcmPreflightError::Broken {
+                command: format!("{} list", self.program),
+                stderr: list.stderr.trim().to_string(),
+            });
+        }
+        if scylla {
+            let help = self
+                .run(
+                    &["create", "--help"],
+                    run_options!(allow_failure = Some(true)),
+                )
+                .await
+                .map_err(|source| CcmPreflightError::NotFound {
+                    program: self.program.clone(),
+                    source,
+                })?;
+            if !help.stdout.contains("--scylla") {
+                return Err(CcmPreflightError::ScyllaUnsupported {
+                    program: self.program.clone(),
+                });
+            }
+        }
+        PREFLIGHT_PASSED.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    /// Runs the ccm subcommand `args`, e.g. `["start", "my_cluster"]`. Queries may be
+    /// answered from the cache, see [`with_query_cache`](CcmRunner::with_query_cache).
+    pub async fn run(
+        &self,
+        args: &[&str],
+        opts: Option<RunOptions>,
+    ) -> Result<CommandResult, IoError> {
+        let Some(key) = self.query_key(args, opts.as_ref()) else {
+            self.invalidate_query_cache();
+            return self
+                .executor
+                .run_command(&self.program, &self.command_args(args), opts)
+                .await;
+        };
+        if let Some(result) = self.cached(&key) {
+            return Ok(result);
+        }
+        let result = self
+            .executor
+            .run_command(&self.program, &self.command_args(args), opts)
+            .await?;
+        if result.success() {
+            QUERY_CACHE
+                .lock()
+                .unwrap()
+                .entry(self.config_dir.clone())
+                .or_default()
+                .insert(key, (Instant::now(), result.clone()));
+        }
+        Ok(result)
+    }
+
+    /// Cache key for `args` if it is a cacheable query. Commands streaming their output
+    /// and dry runs always run.
+    fn query_key(&self, args: &[&str], opts: Option<&RunOptions>) -> Option<QueryKey> {
+        self.query_cache_ttl?;
+        if !is_query(args) || self.logged_cmd.is_dry_run() {
+            return None;
+        }
+        let env = match opts {
+            Some(opts) if opts.stdout_lines.is_some() || opts.stderr_lines.is_some() => {
+                return None;
+            }
+            Some(opts) => opts.env.clone().into_iter().collect(),
+            None => BTreeMap::new(),
+        };
+        let mut command = vec![self.program.clone()];
+        command.extend(self.command_args(args).into_iter().map(str::to_string));
+        Some((command, env))
+    }
+
+    fn cached(&self, key: &QueryKey) -> Option<CommandResult> {
+        let ttl = self.query_cache_ttl?;
+        let cache = QUERY_CACHE.lock().unwrap();
+        let (cached_at, result) = cache.get(&self.config_dir)?.get(key)?;
+        (cached_at.elapsed() < ttl).then(|| result.clone())
+    }
+}
+
+/// Whether `args` is a read-only subcommand: a cluster query as the first argument or a
+/// node query right after the node name.
+fn is_query(args: &[&str]) -> bool {
+    match args {
+        [command, ..] if CLUSTER_COMMANDS.contains(command) => {
+            CLUSTER_QUERY_COMMANDS.contains(command)
+        }
+        [node, command, ..] => !node.starts_with('-') && NODE_QUERY_COMMANDS.contains(command),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_preflight_reports_missing_ccm() {
+        let log_file = std::env::temp_dir().join("ccm_binding_test_preflight.log");
+        let mut logged_cmd = LoggedCmd::new();
+        logged_cmd
+            .set_log_file(log_file.to_string_lossy().into_owned())
+            .await
+            .unwrap();
+        let runner = CcmRunner::new(Arc::new(logged_cmd), "/tmp/ccm")
+            .with_command("/nonexistent/ccm", Vec::<String>::new());
+
+        let err = runner.preflight(true).await.unwrap_err();
+        assert!(matches!(err, CcmPreflightError::NotFound { .. }));
+        assert!(
+            err.to_string()
+                .starts_with("ccm not found: could not run /nonexistent/ccm")
+        );
+        assert_eq!(IoError::from(err).kind(), ErrorKind::NotFound);
+
+        let runner = runner.with_command("false", Vec::<String>::new());
+        let err = runner.preflight(true).await.unwrap_err();
+        assert!(matches!(err, CcmPreflightError::Broken { .. }));
+        tokio::fs::remove_file(&log_file).await.ok();
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let config_dir = std::env::temp_dir().join("ccm_binding_test_query_cache");
+        let config_dir = config_dir.to_string_lossy().into_owned();
+        let counter = format!("{config_dir}.count");
+        tokio::fs::remove_file(&counter).await.ok();
+        // Every invocation appends a line to `counter`, so its length is the run count.
+        let mut logged_cmd = LoggedCmd::new();
+        logged_cmd
+            .set_log_file(format!("{config_dir}.log"))
+            .await
+            .unwrap();
+        let runner = CcmRunner::new(Arc::new(logged_cmd), config_dir.clone())
+            .with_command(
+                "sh",
+                ["-c", &format!("echo run >> {counter}; echo $0"), "sh"],
+            )
+            .with_query_cache(Some(DEFAULT_QUERY_CACHE_TTL));
+        let runs = || async {
+            tokio::fs::read_to_string(&counter)
+                .await
+                .unwrap()
+                .lines()
+                .count()
+        };
+
+        let first = runner.run(&["status"], None).await.unwrap();
+        let second = runner.run(&["status"], None).await.unwrap();
+        assert_eq!(first.stdout, second.stdout);
+        assert_eq!(runs().await, 1);
+
+        runner.run(&["node_1_1", "show"], None).await.unwrap();
+        runner.run(&["node_1_1", "show"], None).await.unwrap();
+        assert_eq!(runs().await, 2);
+
+        // Query names in argument position are not queries.
+        runner.run(&["remove", "status"], None).await.unwrap();
+        runner.run(&["remove", "status"], None).await.unwrap();
+        assert_eq!(runs().await, 4);
+
+        runner.run(&["start", "test"], None).await.unwrap();
+        runner.run(&["status"], None).await.unwrap();
+        assert_eq!(runs().await, 6);
+
+        let uncached = runner.clone().with_query_cache(None);
+        uncached.run(&["status"], None).await.unwrap();
+        assert_eq!(runs().await, 7);
+        tokio::fs::remove_file(&counter).await.ok();
+        tokio::fs::remove_file(format!("{config_dir}.log"))
+            .await
+            .ok();
+    }
+
+    #[test]
+    fn test_is_query() {
+        assert!(is_query(&["status"]));
+        assert!(is_query(&["liveset"]));
+        assert!(is_query(&["node_1_1", "show"]));
+        assert!(!is_query(&["remove", "status"]));
+        assert!(!is_query(&["stop", "show"]));
+        assert!(!is_query(&["node_1_1", "start"]));
+        assert!(!is_query(&["node_1_1", "status"]));
+        assert!(!is_query(&[]));
+        assert!(!is_query(&["show"]));
+        assert!(
+            CcmRunner::new(Arc::new(LoggedCmd::new()), "/tmp/ccm")
+                .query_cache_ttl
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_command_args() {
+        let runner = CcmRunner::new(Arc::new(LoggedCmd::new()), "/tmp/ccm")
+            .with_command("python3", ["/opt/scylla-ccm/ccm"])
+            .with_global_args(["--quiet"]);
+        assert_eq!(runner.program, "python3");
+        assert_eq!(
+            runner.command_args(&["start", "test"]),
+            [
+                "/opt/scylla-ccm/ccm",
+                "start",
+                "test",
+                "--config-dir",
+                "/tmp/ccm",
+                "--quiet"
+            ]
+        );
+    }
+}
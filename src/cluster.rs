@@ -1,14 +1,52 @@
-use crate::ccm_cli::{LoggedCmd, RunOptions};
+use crate::auth::{self, Credentials, RoleSpec};
+use crate::ccm_bootstrap::CcmBootstrap;
+use crate::ccm_cli::{Escalation, LoggedCmd, RunOptions, shell_quote};
+use crate::ccm_runner::CcmRunner;
+use crate::cluster_builder::ClusterBuilder;
 use crate::cluster_config::ScyllaConfig;
+use crate::config_schema::{self, AuditMode, ConfigAudit, ConfigSchema};
+#[cfg(feature = "config-yaml")]
+use crate::config_template::ConfigTemplate;
+use crate::executor::CommandExecutor;
+use crate::faults::{Firewall, FirewallBackend, FirewallRule, Verdict};
+use crate::health::{self, HealthReport, NodeHealthReport};
+use crate::hooks::LifecycleHooks;
+use crate::host_capabilities::{self, Capability};
+use crate::host_limits::{HostLease, HostLimits};
+use crate::host_resources::{CapacityPolicy, HostResources, InsufficientResources};
+use crate::hosts::HostsFile;
+use crate::ip_range::{self, IpRangeAllocator, ipv6_range, normalize_ip_prefix};
+use crate::ip_strategy::{FixedPrefix, IpAllocation, IpRequest, IpStrategy, SniffedLoopback};
+use crate::jvm_options::{self, JvmEdit, JvmFile};
+use crate::log_tail::LogFollower;
+use crate::loopback;
+use crate::netns::NetworkNamespace;
+use crate::node_builder::NodeBuilder;
+use crate::nodetool::{self, CompactionStats, PeerInfo, TableStats};
+use crate::ports::{self, NodePorts, PortAllocator};
+use crate::proxy::NodeProxy;
 use crate::run_options;
-use std::collections::{HashMap, HashSet};
+use crate::saved_state::{SavedCluster, SavedNode};
+use crate::scylla_bench::{BenchSummary, BenchWorkload};
+use crate::stress::{StressProfile, StressSummary};
+use crate::test_context;
+use crate::tls::{self, CertificateAuthority, InternodeEncryption, NodeCertificate};
+use crate::tools::{self, SstableLoaderOptions};
+use crate::version::{self, Version};
+use crate::watchdog::CrashWatchdog;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::io::Error as IoError;
 use std::io::ErrorKind::DirectoryNotEmpty;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
 use thiserror::Error;
-use tokio::fs::{File, metadata};
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::fs::metadata;
+use tokio::net::TcpStream;
 use tokio::sync::RwLock;
 
 pub enum NodeStatus {
@@ -16,30 +54,293 @@ pub enum NodeStatus {
     DELETED,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NodeStartOption {
     NOWAIT,
     WaitOtherNotice,
     WaitForBinaryProto,
+    /// Gives up on a node whose start, waits included, takes longer than this: ccm is
+    /// killed, the server it may have launched is stopped with `ccm <node> stop
+    /// --not-gently` and the start fails with [`NodeStartTimeout`]. Without it the waits
+    /// last as long as ccm's own defaults.
+    Timeout(Duration),
+}
+
+/// A node start exceeded [`NodeStartOption::Timeout`]; carried by an `IoError` of kind
+/// `TimedOut`.
+#[derive(Debug, Error)]
+#[error("{node} did not start within {timeout:?}")]
+pub struct NodeStartTimeout {
+    pub node: String,
+    pub timeout: Duration,
+}
+
+/// Partitioner set on cluster creation through `ccm create -p`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Partitioner {
+    #[default]
+    Murmur3,
+    ByteOrdered,
+    Random,
+}
+
+impl Partitioner {
+    /// Class name understood by both Scylla and Cassandra.
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            Partitioner::Murmur3 => "org.apache.cassandra.dht.Murmur3Partitioner",
+            Partitioner::ByteOrdered => "org.apache.cassandra.dht.ByteOrderedPartitioner",
+            Partitioner::Random => "org.apache.cassandra.dht.RandomPartitioner",
+        }
+    }
+}
+
+/// How node addresses are laid out under the cluster IP prefix, see
+/// [`Cluster::set_ip_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpLayout {
+    /// Nodes get consecutive addresses in creation order: `.1`, `.2`, ...
+    #[default]
+    Sequential,
+    /// Every datacenter/rack pair gets a `/27` block of its own in order of appearance,
+    /// `.1`-`.31`, `.33`-`.63` and so on, so that addresses tell where a node lives. Fits
+    /// up to 8 racks of 31 nodes; IPv4 only.
+    SubnetPerRack,
+}
+
+/// What [`Cluster::destroy`] does with the cluster, see [`Cluster::set_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClusterPolicy {
+    /// Stops and removes the cluster.
+    #[default]
+    Destroy,
+    /// Leaves the cluster running, its state saved, for later processes to reuse through
+    /// [`Cluster::ensure`].
+    KeepAlive,
+}
+
+/// How [`Cluster::destroy`] takes the cluster down, see [`Cluster::set_destroy_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DestroyMode {
+    /// `ccm stop`, then `ccm remove`.
+    #[default]
+    Stop,
+    /// Drains every node first, so that memtables are flushed and nothing is replayed.
+    Graceful,
+    /// Kills the servers (`ccm stop --not-gently`) instead of waiting for a clean shutdown.
+    Fast,
+    /// Stops the cluster and takes it out of ccm, but moves its directory, data and logs
+    /// included, to `<install_directory>/kept/<test label>/<name>` (`kept/<name>` outside
+    /// of tests) for post-mortem investigation.
+    KeepData,
+}
+
+/// What [`Cluster::init_or_attach`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitOutcome {
+    /// No cluster of that name existed; it was created.
+    Created,
+    /// The cluster existed and was left as it is.
+    Attached,
+}
+
+/// What [`Cluster::scale`] did to a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleAction {
+    Add,
+    Decommission,
+}
+
+/// Step of a [`Cluster::scale`], reported once the node is done.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScaleProgress {
+    pub action: ScaleAction,
+    pub node: String,
+    /// Nodes added or decommissioned so far, this one included.
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Block of addresses a rack got under [`IpLayout::SubnetPerRack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RackSubnet {
+    pub datacenter_id: i32,
+    /// `None` for nodes added without a rack.
+    pub rack: Option<String>,
+    /// Block in CIDR notation, e.g. `127.0.3.32/27`.
+    pub network: String,
+}
+
+/// How data is distributed, set on cluster creation with [`Cluster::set_replication_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplicationMode {
+    /// Token ring with `num_tokens` vnodes per node; tablets are disabled where they would
+    /// otherwise be the default.
+    Vnodes { num_tokens: u32 },
+    /// Scylla tablets for new keyspaces.
+    Tablets,
+}
+
+impl ReplicationMode {
+    /// Node config selecting this mode in the form the given server version expects.
+    pub fn config(&self, scylla: bool, ccm_version: &str) -> Result<ScyllaConfig, IoError> {
+        let version = Version::parse(ccm_version);
+        let at_least = |open_source: Version, enterprise: Version| {
+            version.is_none_or(|version| version.at_least(open_source, enterprise))
+        };
+        let tablets_mode = at_least(Version::new(2025, 1, 0), Version::new(2025, 1, 0));
+        let enable_tablets = at_least(Version::new(6, 0, 0), Version::new(2024, 2, 0));
+        let entries = match self {
+            ReplicationMode::Vnodes { num_tokens } => {
+                let mut entries = format!("num_tokens:{}", num_tokens);
+                if scylla && tablets_mode {
+                    entries.push_str(" tablets_mode_for_new_keyspaces:disabled");
+                } else if scylla && enable_tablets {
+                    entries.push_str(" enable_tablets:false");
+                }
+                entries
+            }
+            ReplicationMode::Tablets if !scylla => {
+                return Err(IoError::new(
+                    std::io::ErrorKind::Unsupported,
+                    "tablets are only available on Scylla",
+                ));
+            }
+            ReplicationMode::Tablets if tablets_mode => {
+                "tablets_mode_for_new_keyspaces:enabled".to_string()
+            }
+            ReplicationMode::Tablets if enable_tablets => "enable_tablets:true".to_string(),
+            ReplicationMode::Tablets
+                if at_least(Version::new(5, 4, 0), Version::new(2024, 2, 0)) =>
+            {
+                "experimental_features:[tablets,consistent-topology-changes]".to_string()
+            }
+            ReplicationMode::Tablets => {
+                return Err(IoError::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("Scylla {} does not support tablets", ccm_version),
+                ));
+            }
+        };
+        ScyllaConfig::from_flat_string(&entries)
+            .map_err(|e| IoError::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Whether the servers of a cluster listed by [`Cluster::list`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterState {
+    Running,
+    /// Some nodes run, others don't, e.g. after a node crashed.
+    PartiallyRunning,
+    Stopped,
+}
+
+/// Cluster found in a ccm config directory by [`Cluster::list`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterInfo {
+    pub name: String,
+    /// Version from `cluster.conf`, if ccm recorded one.
+    pub version: Option<String>,
+    pub ip_prefix: Option<String>,
+    /// Node names, sorted.
+    pub nodes: Vec<String>,
+    pub state: ClusterState,
+    /// Whether ccm commands without a cluster name act on it.
+    pub current: bool,
+    /// Tags from the cluster's state file, see [`Cluster::tags`].
+    pub tags: HashMap<String, String>,
+}
+
+/// Version ccm recorded in a `cluster.conf`.
+fn cluster_conf_version(conf: &str) -> Option<String> {
+    ["scylla_version", "cassandra_version", "version"]
+        .iter()
+        .find_map(|key| ip_range::conf_value(conf, key))
+        .map(str::to_string)
+}
+
+/// Outcome of [`Node::reload_config`], as top-level config keys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigReload {
+    /// Keys the running server picked up.
+    pub reloaded: Vec<String>,
+    /// Keys that only take effect after the node is restarted.
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigReload {
+    pub fn restart_required(&self) -> bool {
+        !self.restart_required.is_empty()
+    }
 }
 
 #[derive(Debug, Error)]
 #[error("Multiple errors occurred: {0:?}")]
-struct AggregatedError(Vec<String>);
+pub struct AggregatedError(pub Vec<String>);
 
-pub(crate) struct Node {
+pub struct Node {
     pub name: String,
     pub datacenter_id: i32,
     pub node_id: i32,
+    /// Rack passed to `ccm add`, see [`Cluster::add_node_in_rack`]; `None` keeps ccm's default.
+    pub rack: Option<String>,
+    /// Version installed on this node instead of the cluster's, through `ccm setdir`.
+    pub version: Option<String>,
+    /// Added as a seed (`ccm add --seeds`), see
+    /// [`DatacenterSpec::seeds`](crate::cluster_builder::DatacenterSpec::seeds).
+    pub seed: bool,
     pub status: NodeStatus,
     pub scylla: bool,
     pub smp: i32,
     pub memory: i32,
     pub config: ScyllaConfig,
+    /// Extra environment merged into every ccm invocation for this node.
+    pub env: HashMap<String, String>,
+    /// Free-form metadata, saved in the cluster's state file, see [`Cluster::tags`].
+    pub tags: HashMap<String, String>,
+    /// Edits applied to the node's JVM files right after `ccm add`, see [`Node::edit_jvm_file`].
+    pub jvm_edits: Vec<(JvmFile, JvmEdit)>,
+    /// Certificate issued by the cluster CA, written to the node `conf` directory.
+    pub tls_certificate: Option<NodeCertificate>,
+    /// Checks keys of every config pushed to the node, see [`Cluster::set_config_audit`].
+    pub config_audit: Option<ConfigAudit>,
+    /// Ports passed to `ccm add`, see [`Cluster::allocate_ports`].
+    pub ports: NodePorts,
+    /// Used by [`start`](Node::start) without options, see
+    /// [`Cluster::set_default_start_options`].
+    pub default_start_options: Vec<NodeStartOption>,
+    /// Address the cluster assigned to the node; ccm needs it to override its ports.
+    address: Option<String>,
+    /// Shared with the cluster, see [`Cluster::set_firewall`].
+    firewall: Arc<Firewall>,
+    /// Name resolving to `address`, see [`Cluster::map_hostnames`].
+    hostname: Option<String>,
+    /// Forwarder in front of the CQL port, see [`Cluster::start_proxies`].
+    proxy: Option<Arc<NodeProxy>>,
+    /// Second address of a dual-stack node, see [`Cluster::enable_dual_stack`].
+    ipv6_address: Option<String>,
+    /// Whether ccm has to bind the node to `address` explicitly instead of deriving it from
+    /// the node number, see [`Cluster::use_host_addresses`] and [`IpLayout`].
+    host_address: bool,
+    /// Top-level keys written since the last start, see [`Node::reload_config`].
+    unapplied_config_keys: Mutex<BTreeSet<String>>,
+    /// Set by stops and cleared by starts, so that a [`CrashWatchdog`] tells them from crashes.
+    stop_requested: AtomicBool,
+    /// Shared with the cluster, see [`Cluster::add_hooks`].
+    hooks: Vec<Arc<dyn LifecycleHooks>>,
+    ccm: CcmRunner,
     logged_cmd: Arc<LoggedCmd>,
-    install_directory: String,
+    cluster_name: String,
 }
 
 impl Node {
+    /// Node of cluster `cluster_name` ccm keeps in config directory `config_dir`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         datacenter_id: i32,
         node_id: i32,
@@ -48,32 +349,257 @@ impl Node {
         memory: i32,
         config: ScyllaConfig,
         logged_cmd: Arc<LoggedCmd>,
-        install_directory: String,
+        config_dir: String,
+        cluster_name: String,
     ) -> Self {
         Node {
             name: format!("node_{}_{}", datacenter_id, node_id),
             datacenter_id,
             node_id,
+            rack: None,
+            version: None,
+            seed: false,
             status: NodeStatus::ACTIVE,
             scylla,
             smp,
             memory: { if memory != 0 { memory } else { 512 * smp } },
             config,
+            env: HashMap::new(),
+            tags: HashMap::new(),
+            jvm_edits: vec![],
+            tls_certificate: None,
+            config_audit: None,
+            ports: NodePorts::default(),
+            default_start_options: vec![],
+            address: None,
+            hostname: None,
+            proxy: None,
+            ipv6_address: None,
+            firewall: Arc::new(Firewall::new(FirewallBackend::default(), &cluster_name)),
+            host_address: false,
+            unapplied_config_keys: Mutex::new(BTreeSet::new()),
+            stop_requested: AtomicBool::new(false),
+            hooks: vec![],
+            ccm: CcmRunner::new(logged_cmd.clone(), config_dir),
             logged_cmd,
-            install_directory,
+            cluster_name,
+        }
+    }
+
+    /// Whether the server runs, going by its pid file, looked up on the machine ccm runs
+    /// the node on.
+    pub(crate) async fn process_running(&self) -> Result<bool, IoError> {
+        ip_range::node_process_running(&self.directory(), Some(self.ccm.executor().as_ref())).await
+    }
+
+    /// Whether the server was last stopped through the crate rather than started.
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.stop_requested.load(Ordering::SeqCst)
+    }
+
+    /// Directory ccm keeps the node in: `<config dir>/<cluster>/<node>`.
+    pub fn directory(&self) -> PathBuf {
+        PathBuf::from(&self.ccm.config_dir)
+            .join(&self.cluster_name)
+            .join(&self.name)
+    }
+
+    /// Follows the server output ccm redirects into the node `logs` directory
+    /// (`system.log`, and `startup-*-stdout/stderr.log` for Cassandra).
+    pub fn attach_output(&self, from_start: bool) -> LogFollower {
+        LogFollower::follow(self.directory().join("logs"), from_start)
+    }
+
+    /// Queues an edit of `jvm.options`/`cassandra-env.sh`; queued edits are applied during
+    /// [`init`](Node::init), before the first start. Only meaningful for Cassandra nodes.
+    pub fn edit_jvm_file(&mut self, file: JvmFile, edit: JvmEdit) {
+        self.jvm_edits.push((file, edit));
+    }
+
+    /// Applies queued JVM file edits to an already initialized node.
+    pub async fn apply_jvm_edits(&self) -> Result<(), IoError> {
+        let conf = self.directory().join("conf");
+        for (file, edit) in self.jvm_edits.iter() {
+            jvm_options::edit_file(&conf.join(file.file_name()), std::slice::from_ref(edit))
+                .await?;
         }
+        Ok(())
+    }
+
+    /// Sets an environment variable passed to every ccm invocation for this node.
+    /// `SCYLLA_EXT_OPTS` is computed from smp/memory and cannot be overridden here.
+    pub fn set_env(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.env.insert(key.into(), value.into());
     }
 
     fn jmx_port(&self) -> i32 {
-        7000 + self.datacenter_id * 100 + self.node_id
+        match self.ports.jmx {
+            Some(port) => port.into(),
+            None => 7000 + self.datacenter_id * 100 + self.node_id,
+        }
     }
 
     fn debug_port(&self) -> i32 {
-        2000 + self.datacenter_id * 100 + self.node_id
+        match self.ports.debug {
+            Some(port) => port.into(),
+            None => 2000 + self.datacenter_id * 100 + self.node_id,
+        }
+    }
+
+    /// Address ccm binds the node to: the cluster IP prefix followed by the node's position
+    /// in creation order, e.g. to point a driver at it. `None` for nodes not created
+    /// through [`Cluster::add_node`].
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.address.as_deref()?.parse().ok()
+    }
+
+    /// DNS name of the node, e.g. for TLS hostname verification or SNI; only set once
+    /// [`Cluster::map_hostnames`] was called.
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// Proxy in front of the node's CQL port, to delay, drop or reset driver connections;
+    /// only set once [`Cluster::start_proxies`] was called.
+    pub fn proxy(&self) -> Option<&Arc<NodeProxy>> {
+        self.proxy.as_ref()
+    }
+
+    /// IPv6 address of a dual-stack node, see [`Cluster::enable_dual_stack`].
+    pub fn ipv6(&self) -> Option<Ipv6Addr> {
+        self.ipv6_address.as_deref()?.parse().ok()
+    }
+
+    /// `ip:port` of the node, see [`ip`](Node::ip).
+    pub fn socket_address(&self, port: u16) -> Option<SocketAddr> {
+        Some(SocketAddr::new(self.ip()?, port))
+    }
+
+    /// Makes connections to `port` of the node fail the way `verdict` says, e.g. time out
+    /// with [`Verdict::Drop`], until [`unblock_port`](Node::unblock_port). The node keeps
+    /// running; only packets towards the port are affected. Fails with `Unsupported` if the
+    /// host lacks the tool of the firewall backend.
+    pub async fn block_port(&self, port: u16, verdict: Verdict) -> Result<(), IoError> {
+        let ip = self.ip().ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} has no address", self.name),
+            )
+        })?;
+        let rule = FirewallRule {
+            source: None,
+            destination: ip.to_string(),
+            port: Some(port),
+            verdict,
+        };
+        host_capabilities::require_unless_dry_run(
+            &self.logged_cmd,
+            self.firewall.backend.capability(),
+        )?;
+        self.firewall.add(self.ccm.executor().as_ref(), rule).await
+    }
+
+    /// Removes the rules [`block_port`](Node::block_port) installed for `port`.
+    pub async fn unblock_port(&self, port: u16) -> Result<(), IoError> {
+        let Some(ip) = self.ip().map(|ip| ip.to_string()) else {
+            return Ok(());
+        };
+        for rule in self.firewall.rules() {
+            if rule.source.is_none() && rule.destination == ip && rule.port == Some(port) {
+                self.firewall
+                    .remove(self.ccm.executor().as_ref(), &rule)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// CQL port the node listens on.
+    pub fn native_port(&self) -> u16 {
+        self.ports.native.unwrap_or(Cluster::CQL_PORT)
+    }
+
+    /// Internode port the node listens on.
+    pub fn storage_port(&self) -> u16 {
+        self.ports.storage.unwrap_or(Cluster::STORAGE_PORT)
+    }
+
+    /// Waits until both the CQL and the storage port of the node accept connections; the
+    /// error names the node and the first port that stayed closed past `timeout`.
+    pub async fn wait_for_ports(&self, timeout: Duration) -> Result<(), IoError> {
+        let addresses =
+            [self.native_port(), self.storage_port()].map(|port| self.socket_address(port));
+        let deadline = tokio::time::Instant::now() + timeout;
+        for address in addresses {
+            let address = address.ok_or_else(|| {
+                IoError::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} has no address", self.name),
+                )
+            })?;
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            wait_for_port(&address.to_string(), remaining)
+                .await
+                .map_err(|e| {
+                    IoError::new(e.kind(), format!("{} never came up: {}", self.name, e))
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Whether both the CQL and the storage port accept connections, probed by `bash` run
+    /// through `executor`, e.g. inside the network namespace the node runs in, which
+    /// [`wait_for_ports`](Node::wait_for_ports) can't reach from the host.
+    pub async fn ports_open_through(&self, executor: &dyn CommandExecutor) -> bool {
+        let probes: Option<Vec<String>> = [self.native_port(), self.storage_port()]
+            .into_iter()
+            .map(|port| {
+                let address = self.socket_address(port)?;
+                Some(format!(": </dev/tcp/{}/{}", address.ip(), address.port()))
+            })
+            .collect();
+        let Some(probes) = probes else {
+            return false;
+        };
+        executor
+            .run_command(
+                "bash",
+                &["-c", &probes.join(" && ")],
+                run_options!(allow_failure = Some(true)),
+            )
+            .await
+            .is_ok_and(|result| result.success())
+    }
+
+    /// `address:port` interface argument for `ccm add`. ccm splits it on `:`, so ports
+    /// can't be overridden on IPv6 addresses.
+    fn interface(&self, port: u16) -> Result<String, IoError> {
+        match &self.address {
+            Some(address) if !address.contains(':') => Ok(format!("{}:{}", address, port)),
+            Some(_) => Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "ccm can't override ports of IPv6 nodes",
+            )),
+            None => Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} has no address to bind its ports to, add it with Cluster::add_node",
+                    self.name
+                ),
+            )),
+        }
+    }
+
+    /// Moves the node's address and config from `old_prefix` to `new_prefix`.
+    fn readdress(&mut self, old_prefix: &str, new_prefix: &str) {
+        readdress_config(&mut self.config, old_prefix, new_prefix);
+        if let Some(address) = &self.address {
+            self.address = Some(replace_ip_prefix(address, old_prefix, new_prefix));
+        }
     }
 
     fn get_ccm_env(&self) -> HashMap<String, String> {
-        let mut env: HashMap<String, String> = HashMap::new();
+        let mut env: HashMap<String, String> = self.env.clone();
         env.insert(
             "SCYLLA_EXT_OPTS".to_string(),
             format!("--smp={} --memory={}M", self.smp, self.memory),
@@ -81,10 +607,21 @@ impl Node {
         env
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
     pub async fn init(&self) -> Result<(), IoError> {
         let datacenter = format!("dc{}", self.datacenter_id);
         let jmx_port = self.jmx_port().to_string();
         let debug_port = self.debug_port().to_string();
+        let binary_itf = self
+            .ports
+            .native
+            .map(|port| self.interface(port))
+            .transpose()?;
+        let storage_itf = self
+            .ports
+            .storage
+            .map(|port| self.interface(port))
+            .transpose()?;
         let mut args: Vec<&str> = vec![
             "add",
             &self.name,
@@ -94,321 +631,5796 @@ impl Node {
             &jmx_port,
             "--remote-debug-port",
             &debug_port,
-            "--config-dir",
-            &self.install_directory,
         ];
+        if self.host_address
+            && let Some(address) = &self.address
+        {
+            args.extend(["--itfs", address]);
+        }
+        if let Some(rack) = &self.rack {
+            args.extend(["--rack", rack]);
+        }
+        if self.seed {
+            args.push("--seeds");
+        }
+        if let Some(binary_itf) = &binary_itf {
+            args.extend(["--binary-itf", binary_itf]);
+        }
+        if let Some(storage_itf) = &storage_itf {
+            args.extend(["--storage-itf", storage_itf]);
+        }
         if self.scylla {
             args.push("--scylla");
         }
 
-        self.logged_cmd
-            .run_command("ccm", &args, run_options!(env = self.get_ccm_env()))
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await?;
+        if let Some(version) = &self.version {
+            self.ccm
+                .run(
+                    &[&self.name, "setdir", "-v", version],
+                    run_options!(env = self.get_ccm_env()),
+                )
+                .await?;
+        }
+        self.apply_jvm_edits().await?;
+        self.push_config(&self.config).await
+    }
+
+    /// Merges `config` into the node config and writes it to the node's config file.
+    /// Takes effect on the next start unless the server reloads it live.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn update_config(&mut self, config: &ScyllaConfig) -> Result<(), IoError> {
+        self.push_config(config).await?;
+        self.config.merge(config);
+        Ok(())
+    }
+
+    async fn push_config(&self, config: &ScyllaConfig) -> Result<(), IoError> {
+        if let Some(audit) = &self.config_audit
+            && let Err(unknown) = audit.schema.validate(&self.name, config)
+        {
+            match audit.mode {
+                AuditMode::Strict => return Err(unknown.into()),
+                AuditMode::Warn => {
+                    self.logged_cmd
+                        .log_event("warning", &unknown.to_string())
+                        .await
+                }
+            }
+        }
+        let entries = config.to_flat_entries();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut args: Vec<&str> = vec![&self.name, "updateconf"];
+        args.extend(entries.iter().map(String::as_str));
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
             .await?;
+        if let ScyllaConfig::Map(map) = config {
+            self.unapplied_config_keys
+                .lock()
+                .unwrap()
+                .extend(map.keys().cloned());
+        }
         Ok(())
     }
 
+    /// Applies configs written by [`update_config`](Node::update_config) since the last start
+    /// to the running server: SIGHUP for Scylla, `nodetool reloadseeds` for Cassandra.
+    /// Keys the server cannot reload are reported and stay pending until the next start.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn reload_config(&self) -> Result<ConfigReload, IoError> {
+        let (reloaded, restart_required): (Vec<String>, Vec<String>) = self
+            .unapplied_config_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .partition(|key| config_schema::is_live_updatable(self.scylla, key));
+        if !reloaded.is_empty() {
+            if self.scylla {
+                let pid = self.pid().await?;
+                self.ccm
+                    .executor()
+                    .run_command("kill", &["-HUP", &pid], None)
+                    .await?;
+            } else {
+                let args = [&self.name, "nodetool", "reloadseeds"];
+                self.ccm
+                    .run(&args, run_options!(env = self.get_ccm_env()))
+                    .await?;
+            }
+            let mut unapplied = self.unapplied_config_keys.lock().unwrap();
+            for key in reloaded.iter() {
+                unapplied.remove(key);
+            }
+        }
+        Ok(ConfigReload {
+            reloaded,
+            restart_required,
+        })
+    }
+
+    /// Server process id, from the pid file ccm writes into the node directory.
+    async fn pid(&self) -> Result<String, IoError> {
+        let pid = tokio::fs::read_to_string(self.directory().join("cassandra.pid")).await?;
+        Ok(pid.trim().to_string())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
     pub async fn start(&self, opts: Option<&[NodeStartOption]>) -> Result<(), IoError> {
-        let mut args = vec!["start", &self.name, "--config-dir", &self.install_directory];
-        for opt in opts.unwrap_or(&[]) {
+        let mut args = vec!["start", &self.name];
+        let mut timeout = None;
+        for opt in opts.unwrap_or(&self.default_start_options) {
             match opt {
                 NodeStartOption::NOWAIT => args.push("--no-wait"),
                 NodeStartOption::WaitOtherNotice => args.push("--wait-other-notice"),
                 NodeStartOption::WaitForBinaryProto => args.push("--wait-for-binary-proto"),
+                NodeStartOption::Timeout(limit) => timeout = Some(*limit),
             }
         }
 
-        self.logged_cmd
-            .run_command("ccm", &args, run_options!(env = self.get_ccm_env()))
+        for hook in self.hooks.iter() {
+            hook.on_before_node_start(self).await?;
+        }
+        self.stop_requested.store(false, Ordering::SeqCst);
+        let started = self
+            .ccm
+            .run(
+                &args,
+                run_options!(env = self.get_ccm_env(), timeout = timeout),
+            )
+            .await;
+        match (started, timeout) {
+            (Err(e), Some(timeout)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                self.kill_after_start_timeout().await;
+                return Err(IoError::new(
+                    std::io::ErrorKind::TimedOut,
+                    NodeStartTimeout {
+                        node: self.name.clone(),
+                        timeout,
+                    },
+                ));
+            }
+            (started, _) => started?,
+        };
+        self.unapplied_config_keys.lock().unwrap().clear();
+        for hook in self.hooks.iter() {
+            hook.on_after_node_start(self).await?;
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn stop(&self) -> Result<(), IoError> {
+        self.run_stop_hooks().await;
+        self.stop_requested.store(true, Ordering::SeqCst);
+        let args = [&self.name, "stop"];
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await?;
+        Ok(())
+    }
+
+    /// Kills the server a timed out start may have left behind, only logging failures.
+    async fn kill_after_start_timeout(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        let args = [&self.name, "stop", "--not-gently"];
+        if let Err(e) = self
+            .ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await
+        {
+            self.logged_cmd
+                .log_event(
+                    "warning",
+                    &format!(
+                        "stopping {} after its start timed out failed: {}",
+                        self.name, e
+                    ),
+                )
+                .await;
+        }
+    }
+
+    /// Runs the [`on_node_stop`](LifecycleHooks::on_node_stop) hooks, only logging failures.
+    async fn run_stop_hooks(&self) {
+        for hook in self.hooks.iter() {
+            if let Err(e) = hook.on_node_stop(self).await {
+                self.logged_cmd
+                    .log_event(
+                        "warning",
+                        &format!("stop hook of {} failed: {}", self.name, e),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Runs CQL statements through `ccm <node> cqlsh -x`, optionally authenticating.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn cqlsh(&self, cql: &str, credentials: Option<&Credentials>) -> Result<(), IoError> {
+        let mut args: Vec<&str> = vec![&self.name, "cqlsh", "-x", cql];
+        if let Some(credentials) = credentials {
+            args.extend(["-u", &credentials.username, "-p", &credentials.password]);
+        }
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await?;
+        Ok(())
+    }
+
+    /// Runs `nodetool` with `args` on the running node and returns what it printed.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn nodetool(&self, args: &[&str]) -> Result<String, IoError> {
+        let mut ccm_args = vec![self.name.as_str(), "nodetool"];
+        ccm_args.extend(args);
+        let result = self
+            .ccm
+            .run(&ccm_args, run_options!(env = self.get_ccm_env()))
+            .await?;
+        Ok(result.stdout)
+    }
+
+    /// Metrics of `keyspace.table` on the running node (`nodetool tablestats`).
+    pub async fn table_stats(&self, keyspace: &str, table: &str) -> Result<TableStats, IoError> {
+        let output = self
+            .nodetool(&["tablestats", &format!("{}.{}", keyspace, table)])
+            .await?;
+        nodetool::parse_table_stats(&output).ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!("no stats of {}.{} in: {}", keyspace, table, output.trim()),
+            )
+        })
+    }
+
+    /// Pending and running compactions of the running node (`nodetool compactionstats`).
+    pub async fn compaction_stats(&self) -> Result<CompactionStats, IoError> {
+        let output = self.nodetool(&["compactionstats"]).await?;
+        nodetool::parse_compaction_stats(&output).ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected compactionstats output: {}", output.trim()),
+            )
+        })
+    }
+
+    /// Snapshots every table of the running node under `tag` (`nodetool snapshot -t`).
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn snapshot(&self, tag: &str) -> Result<(), IoError> {
+        let args = [&self.name, "nodetool", "snapshot", "-t", tag];
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
             .await?;
         Ok(())
     }
 
+    /// Puts the SSTables of snapshot `tag` back in place of the current ones, table by
+    /// table, and empties the commit log so that newer writes are not replayed over them on
+    /// start. Tables without the snapshot are left alone. The node must be stopped; returns
+    /// the number of tables restored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn restore_snapshot(&self, tag: &str) -> Result<usize, IoError> {
+        let directory = self.directory();
+        if self.process_running().await? {
+            return Err(IoError::new(
+                std::io::ErrorKind::ResourceBusy,
+                format!("stop {} before restoring snapshot {}", self.name, tag),
+            ));
+        }
+        let mut tables = vec![];
+        let mut keyspaces = tokio::fs::read_dir(directory.join("data")).await?;
+        while let Some(keyspace) = keyspaces.next_entry().await? {
+            if !keyspace.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(keyspace.path()).await?;
+            while let Some(table) = entries.next_entry().await? {
+                if table.path().join("snapshots").join(tag).is_dir() {
+                    tables.push(table.path());
+                }
+            }
+        }
+        if tables.is_empty() {
+            return Err(IoError::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} has no snapshot {}", self.name, tag),
+            ));
+        }
+
+        for table in tables.iter() {
+            for file in list_files(table).await? {
+                tokio::fs::remove_file(file).await?;
+            }
+            let snapshot = table.join("snapshots").join(tag);
+            for file in list_files(&snapshot).await? {
+                let name = file.file_name().unwrap();
+                // Written by the snapshot itself, not part of the table.
+                if name == "manifest.json" || name == "schema.cql" {
+                    continue;
+                }
+                // SSTables never change, so linking is as good as copying and much faster.
+                if tokio::fs::hard_link(&file, table.join(name)).await.is_err() {
+                    tokio::fs::copy(&file, table.join(name)).await?;
+                }
+            }
+        }
+        match list_files(&directory.join("commitlogs")).await {
+            Ok(files) => {
+                for file in files {
+                    tokio::fs::remove_file(file).await?;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.logged_cmd
+            .log_event(
+                "restore",
+                &format!(
+                    "{}: {} tables from snapshot {}",
+                    self.name,
+                    tables.len(),
+                    tag
+                ),
+            )
+            .await;
+        Ok(tables.len())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
     pub async fn delete(&mut self) -> Result<(), IoError> {
-        let args = ["remove", &self.name];
-        self.logged_cmd.run_command("ccm", &args, None).await?;
+        let args = [&self.name, "remove"];
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await?;
         self.status = NodeStatus::DELETED;
         Ok(())
     }
 
+    /// Streams the node's data to the rest of the cluster and takes it out of the ring
+    /// (`ccm <node> decommission`), then removes it like [`delete`](Node::delete).
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(node = %self.name)))]
+    pub async fn decommission(&mut self) -> Result<(), IoError> {
+        self.run_stop_hooks().await;
+        self.stop_requested.store(true, Ordering::SeqCst);
+        let args = [&self.name, "decommission"];
+        self.ccm
+            .run(&args, run_options!(env = self.get_ccm_env()))
+            .await?;
+        self.delete().await
+    }
+
     fn mark_deleted(&mut self) {
         self.status = NodeStatus::DELETED;
     }
 }
 
 /// Represents a cluster instance managed by CCM.
-pub(crate) struct Cluster {
+pub struct Cluster {
     pub name: String,
     pub scylla: bool,
     pub version: String,
     pub ip_prefix: String,
     pub install_directory: String,
+    /// Name of the test that created the cluster, see [`test_context`].
+    pub test_name: Option<String>,
+    /// See [`Cluster::tags`].
+    tags: BTreeMap<String, String>,
     nodes: Vec<Arc<RwLock<Node>>>,
     destroyed: bool,
+    sniffed_ip_prefix: bool,
     pub default_node_smp: i32,
     pub default_node_memory: i32,
     pub default_node_config: Option<ScyllaConfig>,
+    /// Per-datacenter defaults layered over `default_node_config`, keyed by datacenter id.
+    pub dc_default_configs: HashMap<i32, ScyllaConfig>,
+    /// See [`Cluster::set_dc_seeds`].
+    dc_seeds: HashMap<i32, usize>,
+    pub default_node_env: HashMap<String, String>,
+    /// CA created by the TLS helpers; drivers should trust `certificate_authority.cert`.
+    pub certificate_authority: Option<CertificateAuthority>,
+    /// Role created once password authentication is up, see [`Cluster::enable_password_auth`].
+    pub auth_test_role: Option<Credentials>,
+    password_auth: bool,
+    /// Roles created during bring-up once authentication is ready.
+    pub auth_roles: Vec<RoleSpec>,
+    /// Partitioner passed to `ccm create`; `None` keeps the server default (Murmur3).
+    pub partitioner: Option<Partitioner>,
+    /// Audit inherited by nodes added later, see [`Cluster::set_config_audit`].
+    pub config_audit: Option<ConfigAudit>,
+    /// Vnodes or tablets, applied to every node config by [`init`](Cluster::init).
+    pub replication_mode: Option<ReplicationMode>,
+    /// Adds missing loopback aliases for node addresses on [`init`](Cluster::init), see
+    /// [`Cluster::set_loopback_aliases`].
+    pub loopback_escalation: Option<Escalation>,
+    /// How long [`start`](Cluster::start) waits for node ports to open, see
+    /// [`Cluster::set_port_check_timeout`].
+    pub port_check_timeout: Option<Duration>,
+    /// See [`Cluster::set_policy`].
+    pub policy: ClusterPolicy,
+    /// See [`Cluster::set_default_start_options`].
+    pub default_start_options: Vec<NodeStartOption>,
+    /// See [`Cluster::set_destroy_mode`].
+    pub destroy_mode: DestroyMode,
+    /// See [`Cluster::set_health_check_cql`].
+    pub health_check_cql: bool,
+    /// See [`Cluster::set_capacity_policy`].
+    pub capacity_policy: CapacityPolicy,
+    /// Ports reserved by `resolve_port_collisions`, released by
+    /// [`destroy`](Cluster::destroy) or once the cluster is dropped.
+    reserved_ports: Mutex<Vec<u16>>,
+    /// Pid of the detached process, on the executor's machine, tearing the cluster down once
+    /// its TTL runs out, see [`Cluster::set_ttl`].
+    ttl_reaper: Option<u32>,
+    /// Share of the [`HostLimits`], if any, given up by [`destroy`](Cluster::destroy).
+    host_lease: Option<HostLease>,
+    /// See [`Cluster::add_hooks`].
+    hooks: Vec<Arc<dyn LifecycleHooks>>,
+    /// Aliases added by `init`, removed again by [`destroy`](Cluster::destroy).
+    loopback_aliases: Mutex<Vec<String>>,
+    /// Addresses nodes are bound to instead of the IP prefix, see
+    /// [`Cluster::use_host_addresses`].
+    host_addresses: Vec<IpAddr>,
+    /// See [`Cluster::set_ip_layout`].
+    pub ip_layout: IpLayout,
+    /// IPv6 prefix of a dual-stack cluster, see [`Cluster::enable_dual_stack`].
+    ipv6_prefix: Option<String>,
+    sniffed_ipv6_prefix: bool,
+    /// Number appended to the IP prefix for every node in creation order.
+    address_suffixes: Vec<u32>,
+    /// Datacenter and rack of every block under [`IpLayout::SubnetPerRack`], in block order.
+    rack_blocks: Vec<(i32, Option<String>)>,
+    /// Namespace the cluster runs in, see [`Cluster::isolate_network`].
+    network_namespace: Option<Arc<NetworkNamespace>>,
+    /// Picked the addresses in `ip_allocation`, see [`Cluster::with_ip_strategy`].
+    ip_strategy: Arc<dyn IpStrategy>,
+    /// Returned to `ip_strategy` once the cluster no longer uses it.
+    ip_allocation: Option<IpAllocation>,
+    /// Domain node hostnames live in, see [`Cluster::map_hostnames`].
+    hostname_domain: Option<String>,
+    /// Where node hostnames are registered.
+    hosts_file: Option<HostsFile>,
+    /// Rules injecting network faults, see [`Cluster::partition`] and
+    /// [`Node::block_port`].
+    pub firewall: Arc<Firewall>,
+    ccm: CcmRunner,
     logged_cmd: Arc<LoggedCmd>,
 }
 
-#[cfg(test)]
 impl Drop for Cluster {
     fn drop(&mut self) {
+        #[cfg(test)]
         if !self.destroyed {
             self.destroyed = true;
             tokio::runtime::Runtime::new()
                 .unwrap()
                 .block_on(async { self.destroy().await.ok() });
         }
+        // Needs no ccm call, so dropped clusters never keep ports from others in the process.
+        self.release_reserved_ports();
     }
 }
 
 impl Cluster {
-    pub(crate) fn set_default_node_memory(&mut self, memory: i32) {
+    pub fn set_default_node_memory(&mut self, memory: i32) {
         self.default_node_memory = memory;
     }
 
-    pub(crate) fn set_default_node_smp(&mut self, smp: i32) {
+    pub fn set_default_node_smp(&mut self, smp: i32) {
         self.default_node_smp = smp;
     }
 
-    pub(crate) fn set_default_node_config(&mut self, config: ScyllaConfig) {
-        self.default_node_config = config.into();
+    /// Options [`start`](Cluster::start) uses when called with `None`, e.g. to always wait
+    /// for the binary protocol. Applies to the nodes of the cluster too, those added later
+    /// included, when they are started on their own.
+    pub async fn set_default_start_options(&mut self, options: &[NodeStartOption]) {
+        self.default_start_options = options.to_vec();
+        for node in self.nodes.iter() {
+            node.write().await.default_start_options = options.to_vec();
+        }
     }
 
-    async fn sniff_ip_prefix() -> Result<String, IoError> {
-        let mut used_ips = HashSet::new();
-        let file = File::open("/proc/net/tcp").await?;
-        let mut lines = BufReader::new(file).lines();
-        while let Some(line) = lines.next_line().await? {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if let Some(ip_hex) = parts.get(1) {
-                let ip_port: Vec<&str> = ip_hex.split(':').collect();
-                if let Some(ip_hex) = ip_port.get(0) {
-                    if let Some(ip) = u32::from_str_radix(ip_hex, 16).ok() {
-                        used_ips.insert(format!(
-                            "{}.{}.{}.",
-                            ip & 0xFF,
-                            (ip >> 8) & 0xFF,
-                            (ip >> 16) & 0xFF,
-                        ));
-                    }
-                }
-            }
+    /// Registers `hooks` on the cluster and its nodes, those added later included; hooks
+    /// run in the order they were added.
+    pub async fn add_hooks(&mut self, hooks: Arc<dyn LifecycleHooks>) {
+        self.hooks.push(hooks.clone());
+        for node in self.nodes.iter() {
+            node.write().await.hooks.push(hooks.clone());
         }
+    }
 
-        for a in 1..=255 {
-            for b in 1..=255 {
-                let ip_prefix = format!("127.{}.{}.", a, b);
-                if !used_ips.contains(&ip_prefix) {
-                    return Ok(ip_prefix);
-                }
-            }
-        }
-        Err(IoError::from_raw_os_error(1))
+    /// Free-form metadata for orchestration layers, e.g. owner or ticket, saved in the state
+    /// file, reported by [`Cluster::list`] and attached to the tracing spans of the cluster.
+    pub fn tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
     }
 
-    pub async fn get_free_node_id(&self, datacenter_id: i32) -> i32 {
-        'outer: for node_id in 1..=255 {
-            for node in self.nodes.iter() {
-                let node = node.read().await;
-                if node.datacenter_id == datacenter_id {
-                    if node.node_id == node_id {
-                        continue 'outer;
-                    }
-                }
-            }
-            return node_id;
-        }
-        256
+    /// Sets tag `key` of the cluster, records it in the cluster log and saves it to the
+    /// [`state_file`](Cluster::state_file).
+    pub async fn set_tag(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), IoError> {
+        let (key, value) = (key.into(), value.into());
+        self.logged_cmd
+            .log_event("tag", &format!("{}={}", key, value))
+            .await;
+        self.tags.insert(key, value);
+        self.save_state().await?;
+        Ok(())
     }
 
-    pub(crate) async fn add_node(&mut self, datacenter_id: Option<i32>) -> &Arc<RwLock<Node>> {
-        let dc = datacenter_id.unwrap_or(1);
-        let node = Node::new(
-            dc,
-            self.get_free_node_id(dc).await,
-            self.scylla,
-            self.default_node_smp,
-            self.default_node_memory,
-            self.default_node_config.clone().unwrap_or_default(),
-            self.logged_cmd.clone(),
-            self.install_directory.clone(),
-        );
-        self.nodes.push(Arc::new(RwLock::new(node)));
-        self.nodes.last().clone().unwrap()
+    pub fn set_default_node_config(&mut self, config: ScyllaConfig) {
+        self.default_node_config = config.into();
     }
 
-    const DEFAULT_MEMORY: i32 = 512;
-    const DEFAULT_SMP: i32 = 1;
+    /// Sets the partitioner used by [`init`](Cluster::init) when creating the cluster.
+    pub fn set_partitioner(&mut self, partitioner: Partitioner) {
+        self.partitioner = Some(partitioner);
+    }
 
-    pub(crate) async fn new(
-        name: String,
-        version: String,
-        ip_prefix: Option<&str>,
-        number_of_nodes: Vec<i32>,
-        install_directory: String,
-        scylla: bool,
-    ) -> Result<Self, IoError> {
-        let mut ip_prefix = match ip_prefix {
-            Some(v) => v.to_string(),
-            None => Self::sniff_ip_prefix().await?,
-        };
-        if !ip_prefix.ends_with(".") {
-            ip_prefix = format!("{}.", ip_prefix);
-        }
+    /// Manages `lo0` aliases for node addresses through `escalation`, or not at all with
+    /// `None`. Enabled with `sudo -n` by default on macOS, where only `127.0.0.1` exists on
+    /// loopback. [`init`](Cluster::init) adds the aliases missing for the current nodes and
+    /// [`destroy`](Cluster::destroy) removes the ones it added.
+    pub fn set_loopback_aliases(&mut self, escalation: Option<Escalation>) {
+        self.loopback_escalation = escalation;
+    }
 
-        match metadata(install_directory.as_str()).await {
-            Ok(mt) => {
-                if !mt.is_dir() {
-                    return Err(IoError::new(
-                        DirectoryNotEmpty,
-                        format!("{install_directory} already exists and it is not a dictionary"),
-                    ));
-                }
-            }
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::NotFound => {
-                    tokio::fs::create_dir_all(install_directory.as_str()).await?;
-                }
-                _ => {
-                    return Err(e.into());
-                }
-            },
-        }
+    /// Bounds how long [`start`](Cluster::start) waits for the CQL and storage ports of
+    /// every node to accept connections, 60s by default; `None` trusts ccm and skips the
+    /// check.
+    pub fn set_port_check_timeout(&mut self, timeout: Option<Duration>) {
+        self.port_check_timeout = timeout;
+    }
 
-        let mut lcmd = LoggedCmd::new();
-        lcmd.set_log_file(format!("{install_directory}/{name}.ccm.log"))
+    /// What [`init`](Cluster::init) does when the smp, memory and disk space of the nodes
+    /// exceed what this host has available, instead of nodes running out of memory later;
+    /// only warns by default. Not checked in dry-run mode.
+    pub fn set_capacity_policy(&mut self, policy: CapacityPolicy) {
+        self.capacity_policy = policy;
+    }
+
+    /// With [`ClusterPolicy::KeepAlive`], [`destroy`](Cluster::destroy) leaves the cluster
+    /// running.
+    pub fn set_policy(&mut self, policy: ClusterPolicy) {
+        self.policy = policy;
+    }
+
+    /// How [`destroy`](Cluster::destroy) stops and removes the cluster, e.g.
+    /// [`DestroyMode::KeepData`] in CI to look at the nodes of failed tests afterwards.
+    pub fn set_destroy_mode(&mut self, mode: DestroyMode) {
+        self.destroy_mode = mode;
+    }
+
+    /// Makes [`wait_healthy`](Cluster::wait_healthy) also run `SELECT now()` through cqlsh
+    /// on every node, off by default since cqlsh takes a while to start.
+    pub fn set_health_check_cql(&mut self, check: bool) {
+        self.health_check_cql = check;
+    }
+
+    /// Tears the cluster down `ttl` from now, whole seconds, unless it is destroyed first;
+    /// `None` cancels a previous TTL. The teardown runs in a process detached through the
+    /// executor, on the machine the nodes run on, so it also happens when the test process
+    /// is killed before cleanup, e.g. SIGKILLed by a CI timeout; it removes what
+    /// [`destroy`](Cluster::destroy) removes, and the IP prefix lock goes away with the test
+    /// process. A [`KeepAlive`](ClusterPolicy::KeepAlive) cluster is torn down as well.
+    pub async fn set_ttl(&mut self, ttl: Option<Duration>) -> Result<(), IoError> {
+        self.cancel_ttl().await;
+        let Some(ttl) = ttl else {
+            return Ok(());
+        };
+        if self.logged_cmd.is_dry_run() {
+            self.logged_cmd
+                .log_event(
+                    "ttl",
+                    &format!("{} would be torn down in {:?}", self.name, ttl),
+                )
+                .await;
+            return Ok(());
+        }
+        let ccm = |args: &[&str]| {
+            std::iter::once(self.ccm.program.as_str())
+                .chain(self.ccm.command_args(args))
+                .map(shell_quote)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let leftovers: Vec<String> = self
+            .leftover_paths()
+            .iter()
+            .map(|path| shell_quote(&path.to_string_lossy()))
+            .collect();
+        let pending = shell_quote(&self.ttl_pending_file().to_string_lossy());
+        let script = format!(
+            "sleep {}; rm -f {}; {}; {}; rm -rf {}",
+            ttl.as_secs(),
+            pending,
+            ccm(&["stop", &self.name, "--not-gently"]),
+            ccm(&["remove", &self.name]),
+            leftovers.join(" ")
+        );
+        // A session of its own, out of the test's process group, so that killing the group
+        // spares the reaper; `setsid` runs in place in the background job and keeps its pid.
+        let detach = format!(
+            "touch {}; setsid sh -c {} </dev/null >/dev/null 2>&1 & echo $!",
+            pending,
+            shell_quote(&script)
+        );
+        let spawned = self
+            .ccm
+            .executor()
+            .run_command("sh", &["-c", &detach], None)
             .await?;
+        let pid = spawned.stdout.trim().parse().map_err(|_| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!("no pid of the TTL reaper in {:?}", spawned.stdout),
+            )
+        })?;
+        self.ttl_reaper = Some(pid);
+        self.logged_cmd
+            .log_event(
+                "ttl",
+                &format!("{} will be torn down in {:?}", self.name, ttl),
+            )
+            .await;
+        Ok(())
+    }
 
-        let mut cluster = Cluster {
-            name,
-            scylla,
-            version,
-            ip_prefix,
-            install_directory,
-            destroyed: false,
-            nodes: vec![],
-            default_node_memory: Self::DEFAULT_MEMORY,
-            default_node_smp: Self::DEFAULT_SMP,
-            default_node_config: None,
-            logged_cmd: Arc::new(lcmd),
+    /// Marks a TTL reaper still sleeping; it removes the file once the TTL ran out.
+    fn ttl_pending_file(&self) -> PathBuf {
+        Path::new(&self.install_directory).join(format!("{}.ttl", self.name))
+    }
+
+    /// Kills the TTL reaper, if any, together with its `sleep`; returns whether its TTL ran
+    /// out already, so that it tore the cluster down.
+    async fn cancel_ttl(&mut self) -> bool {
+        let Some(reaper) = self.ttl_reaper.take() else {
+            return false;
         };
+        let pending = shell_quote(&self.ttl_pending_file().to_string_lossy());
+        let kill = format!(
+            "[ -e {0} ] || exit 1; rm -f {0}; kill -TERM -{1} 2>/dev/null || kill -TERM {1}; exit 0",
+            pending, reaper
+        );
+        !self
+            .ccm
+            .executor()
+            .run_command(
+                "sh",
+                &["-c", &kill],
+                run_options!(allow_failure = Some(true)),
+            )
+            .await
+            .is_ok_and(|result| result.success())
+    }
+
+    /// Selects vnodes or tablets; the matching config keys are written by [`init`](Cluster::init)
+    /// and fail it if the server version does not support the mode.
+    pub fn set_replication_mode(&mut self, mode: ReplicationMode) {
+        self.replication_mode = Some(mode);
+    }
 
-        for datacenter_id in 0..number_of_nodes.len() {
-            for _ in 0..number_of_nodes[datacenter_id] {
-                cluster.add_node(Some((datacenter_id + 1) as i32)).await;
+    /// Sets defaults for nodes of datacenter `dc_id`, merged over `default_node_config` and
+    /// under per-node settings. Existing nodes of the datacenter pick them up too, keeping
+    /// their own values for keys they already set; call before [`init`](Cluster::init).
+    pub async fn set_dc_default_config(&mut self, dc_id: i32, config: ScyllaConfig) {
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            if node.datacenter_id == dc_id {
+                let mut merged = config.clone();
+                merged.merge(&node.config);
+                node.config = merged;
             }
         }
-        Ok(cluster)
+        self.dc_default_configs.insert(dc_id, config);
     }
 
-    pub(crate) async fn init(&self) -> Result<(), IoError> {
-        let ccm_path = PathBuf::from(format!("{}/{}", self.install_directory, self.name));
+    /// Makes the first `seeds` nodes of datacenter `dc_id` seeds (`ccm add --seeds`),
+    /// including nodes added to it later while it has fewer; call before
+    /// [`init`](Cluster::init). Datacenters without a count get no seeds from the crate.
+    pub async fn set_dc_seeds(&mut self, dc_id: i32, seeds: usize) {
+        self.dc_seeds.insert(dc_id, seeds);
+        let mut count = 0;
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            if node.datacenter_id == dc_id && !matches!(node.status, NodeStatus::DELETED) {
+                node.seed = count < seeds;
+                count += 1;
+            }
+        }
+    }
 
-        if ccm_path.exists() {
-            tokio::fs::remove_dir_all(&ccm_path).await?;
+    /// Whether a new node of datacenter `dc_id` is added as a seed.
+    async fn next_node_is_seed(&self, dc_id: i32) -> bool {
+        let Some(&seeds) = self.dc_seeds.get(&dc_id) else {
+            return false;
+        };
+        let mut count = 0;
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if node.datacenter_id == dc_id
+                && node.seed
+                && !matches!(node.status, NodeStatus::DELETED)
+            {
+                count += 1;
+            }
         }
-        let mut args: Vec<&str> = vec![
-            "create",
-            &self.name,
-            "-v",
-            &self.version,
-            "-i",
-            &self.ip_prefix,
-            "--config-dir",
-            &self.install_directory,
-        ];
-        if self.scylla {
-            args.push("--scylla");
+        count < seeds
+    }
+
+    /// Config a new node of datacenter `dc_id` starts with.
+    fn default_config_for_dc(&self, dc_id: i32) -> ScyllaConfig {
+        let mut config = self.default_node_config.clone().unwrap_or_default();
+        if let Some(dc_config) = self.dc_default_configs.get(&dc_id) {
+            config.merge(dc_config);
+        }
+        config
+    }
+
+    /// Cross-checks the keys of every config applied to a node from now on against `schema`
+    /// (the builtin schema of the server flavour when `None`, or one introspected with
+    /// [`ConfigSchema::from_scylla_binary`]). Unknown keys are logged as `warning` events in
+    /// the ccm log, or in [`AuditMode::Strict`] rejected before reaching the node, and fail
+    /// [`start`](Cluster::start) instead of being silently ignored by the server.
+    pub async fn set_config_audit(&mut self, mode: AuditMode, schema: Option<ConfigSchema>) {
+        let schema = schema.unwrap_or_else(|| ConfigSchema::builtin(self.scylla));
+        let audit = ConfigAudit::new(schema, mode);
+        for node in self.nodes.iter() {
+            node.write().await.config_audit = Some(audit.clone());
         }
-        self.logged_cmd.run_command("ccm", &args, None).await?;
+        self.config_audit = Some(audit);
+    }
 
+    /// Checks every node config against the schema of a strict
+    /// [`config audit`](Cluster::set_config_audit), keys set before it was enabled included.
+    pub async fn validate_config(&self) -> Result<(), IoError> {
         for node in self.nodes.iter() {
-            let node = Arc::clone(node);
             let node = node.read().await;
-            node.init().await?;
+            if let Some(audit) = &node.config_audit
+                && audit.mode == AuditMode::Strict
+            {
+                audit.schema.validate(&node.name, &node.config)?;
+            }
         }
+        Ok(())
+    }
+
+    /// Sets an environment variable inherited by nodes added after this call.
+    pub fn set_default_node_env(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.default_node_env.insert(key.into(), value.into());
+    }
 
+    /// Whether the cluster runs on IPv6 addresses.
+    pub fn is_ipv6(&self) -> bool {
+        self.ip_prefix.contains(':')
+    }
+
+    /// Moves a cluster that was not initialized yet to IPv6: `prefix`, e.g.
+    /// `fd00:0:0:5::`, or a free one from
+    /// [`IPV6_CLUSTER_RANGE`](ip_range::IPV6_CLUSTER_RANGE). Node addresses are the
+    /// prefix followed by the node index, so `prefix` must leave the last group open.
+    pub async fn use_ipv6(&mut self, prefix: Option<&str>) -> Result<(), IoError> {
+        if self.ip_layout == IpLayout::SubnetPerRack {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "per-rack subnets are only available on IPv4 clusters",
+            ));
+        }
+        if self.ipv6_prefix.is_some() {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "dual-stack clusters keep IPv4 node addresses",
+            ));
+        }
+        let new_prefix = match prefix {
+            Some(prefix) if !prefix.contains(':') => {
+                return Err(IoError::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} is not an IPv6 prefix", prefix),
+                ));
+            }
+            Some(prefix) => normalize_ip_prefix(prefix),
+            None => self.ip_range_allocator().reserve_ipv6().await?,
+        };
+        for node in self.nodes.iter() {
+            node.write().await.readdress(&self.ip_prefix, &new_prefix);
+        }
+        self.release_ip_prefix();
+        self.ip_prefix = new_prefix;
+        self.sniffed_ip_prefix = prefix.is_none();
         Ok(())
     }
 
-    pub(crate) async fn start(&self, opts: Option<&[NodeStartOption]>) -> Result<(), IoError> {
+    /// Allocator skipping the prefixes of every cluster in the install directory.
+    fn ip_range_allocator(&self) -> IpRangeAllocator {
+        IpRangeAllocator::default().with_ccm_config_dir(&self.install_directory)
+    }
+
+    fn release_ip_prefix(&mut self) {
+        self.release_ipv6_prefix();
+        if self.sniffed_ip_prefix {
+            IpRangeAllocator::default().release(&self.ip_prefix);
+            self.sniffed_ip_prefix = false;
+        }
+        if let Some(allocation) = self.ip_allocation.take() {
+            self.ip_strategy.release(&allocation);
+        }
+    }
+
+    fn release_reserved_ports(&self) {
+        for port in std::mem::take(&mut *self.reserved_ports.lock().unwrap()) {
+            RESERVED_PORTS.release(port);
+        }
+    }
+
+    /// Logger shared by the cluster and its nodes, e.g. to switch on
+    /// [dry-run mode](LoggedCmd::set_dry_run).
+    pub fn logged_cmd(&self) -> &LoggedCmd {
+        &self.logged_cmd
+    }
+
+    pub fn ccm_runner(&self) -> &CcmRunner {
+        &self.ccm
+    }
+
+    /// Invokes ccm through `runner` for the cluster and all its nodes, e.g. to use a
+    /// scylla-ccm checkout that is not on `PATH`.
+    pub async fn set_ccm_runner(&mut self, runner: CcmRunner) {
         for node in self.nodes.iter() {
-            let node = node.read().await;
-            node.start(opts).await?;
+            node.write().await.ccm = runner.clone();
+        }
+        self.ccm = runner;
+    }
+
+    /// Runs the cluster in a network namespace of its own, `ccm-<cluster name>`, created
+    /// here and deleted by [`destroy`](Cluster::destroy); see [`NetworkNamespace`] for what
+    /// that means for clients. Call before [`init`](Cluster::init). Loopback aliases are
+    /// not needed inside the namespace, IPv6 clusters get their range routed to it. Fails
+    /// with `Unsupported` on hosts without `ip` and `nsenter`.
+    pub async fn isolate_network(&mut self, escalation: Escalation) -> Result<(), IoError> {
+        for capability in [Capability::Ip, Capability::Nsenter] {
+            host_capabilities::require_unless_dry_run(&self.logged_cmd, capability)?;
+        }
+        let netns =
+            NetworkNamespace::new(self.ccm.executor().clone(), format!("ccm-{}", self.name))
+                .with_escalation(escalation);
+        netns.create().await?;
+        if self.is_ipv6() {
+            netns
+                .add_local_route(&format!("{}/112", self.ip_prefix))
+                .await?;
         }
+        let netns = Arc::new(netns);
+        let runner = self.ccm.clone().with_executor(netns.clone());
+        self.set_ccm_runner(runner).await;
+        self.loopback_escalation = None;
+        self.network_namespace = Some(netns);
         Ok(())
     }
 
-    pub(crate) async fn stop(&mut self) -> Result<(), IoError> {
-        if self.destroyed {
-            return Ok(());
+    pub fn network_namespace(&self) -> Option<&Arc<NetworkNamespace>> {
+        self.network_namespace.as_ref()
+    }
+
+    /// Selects the tool and escalation used by [`partition`](Cluster::partition) and
+    /// [`Node::block_port`]. Call while no rules are installed.
+    pub async fn set_firewall(&mut self, backend: FirewallBackend, escalation: Escalation) {
+        self.firewall = Arc::new(Firewall::new(backend, &self.name).with_escalation(escalation));
+        for node in self.nodes.iter() {
+            node.write().await.firewall = self.firewall.clone();
         }
-        match self
-            .logged_cmd
-            .run_command(
-                "ccm",
-                &["stop", &self.name, "--config-dir", &self.install_directory],
-                None,
+    }
+
+    /// Address of the node called `name`.
+    async fn named_node_address(&self, name: &str) -> Result<String, IoError> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.read().await.name == name {
+                return Ok(self.node_address(index));
+            }
+        }
+        Err(IoError::new(
+            std::io::ErrorKind::NotFound,
+            format!("no node named {}", name),
+        ))
+    }
+
+    /// Splits the cluster: drops all traffic between the nodes in `group_a` and those in
+    /// `group_b`, in both directions, until [`heal`](Cluster::heal). Nodes are given by
+    /// name; nodes in neither group keep talking to both sides. Fails with `Unsupported` if
+    /// the host lacks the tool of the [`firewall`](Cluster::set_firewall) backend.
+    pub async fn partition(&self, group_a: &[&str], group_b: &[&str]) -> Result<(), IoError> {
+        let mut addresses_a = vec![];
+        for name in group_a {
+            addresses_a.push(self.named_node_address(name).await?);
+        }
+        let mut addresses_b = vec![];
+        for name in group_b {
+            addresses_b.push(self.named_node_address(name).await?);
+        }
+        host_capabilities::require_unless_dry_run(
+            &self.logged_cmd,
+            self.firewall.backend.capability(),
+        )?;
+        self.logged_cmd
+            .log_event(
+                "partition",
+                &format!("{} | {}", group_a.join(","), group_b.join(",")),
             )
+            .await;
+        let executor = self.ccm.executor().as_ref();
+        for a in addresses_a.iter() {
+            for b in addresses_b.iter() {
+                self.firewall.drop_traffic(executor, a, b).await?;
+                self.firewall.drop_traffic(executor, b, a).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every rule installed by [`partition`](Cluster::partition), and port blocks of
+    /// [`Node::block_port`] too.
+    pub async fn heal(&self) -> Result<(), IoError> {
+        self.firewall.clear(self.ccm.executor().as_ref()).await
+    }
+
+    /// Installs ccm with `bootstrap` if the current ccm command is missing, and invokes
+    /// the installed one from then on.
+    pub async fn bootstrap_ccm(&mut self, bootstrap: &CcmBootstrap) -> Result<(), IoError> {
+        let runner = bootstrap.runner(self.ccm.clone()).await?;
+        self.set_ccm_runner(runner).await;
+        Ok(())
+    }
+
+    pub fn nodes(&self) -> &[Arc<RwLock<Node>>] {
+        &self.nodes
+    }
+
+    pub async fn get_free_node_id(&self, datacenter_id: i32) -> i32 {
+        'outer: for node_id in 1..=255 {
+            for node in self.nodes.iter() {
+                let node = node.read().await;
+                if node.datacenter_id == datacenter_id && node.node_id == node_id {
+                    continue 'outer;
+                }
+            }
+            return node_id;
+        }
+        256
+    }
+
+    /// Adds a node to the datacenter, 1 by default, with the cluster defaults. Its JMX and
+    /// debug ports are pinned right away, see `resolve_port_collisions`, so that other
+    /// clusters of the process don't pick them before the node is created and started.
+    pub async fn add_node(&mut self, datacenter_id: Option<i32>) -> &Arc<RwLock<Node>> {
+        self.add_new_node(datacenter_id, None).await
+    }
+
+    /// Starts describing a node with its own settings, see [`NodeBuilder`].
+    pub fn node(&mut self) -> NodeBuilder<'_> {
+        NodeBuilder::new(self)
+    }
+
+    /// Same as [`add_node`](Cluster::add_node), placing the node in `rack` of its datacenter.
+    pub async fn add_node_in_rack(
+        &mut self,
+        datacenter_id: Option<i32>,
+        rack: &str,
+    ) -> &Arc<RwLock<Node>> {
+        self.add_new_node(datacenter_id, Some(rack.to_string()))
+            .await
+    }
+
+    /// Adds a node the user asked for and pins its ports. Failing to is logged rather than
+    /// returned: [`init`](Cluster::init) and [`scale`](Cluster::scale) retry for ports still
+    /// unset and fail then.
+    pub(crate) async fn add_new_node(
+        &mut self,
+        datacenter_id: Option<i32>,
+        rack: Option<String>,
+    ) -> &Arc<RwLock<Node>> {
+        let node = self.add_node_with_rack(datacenter_id, rack).await.clone();
+        if let Err(e) = self
+            .resolve_port_collisions(std::slice::from_ref(&node))
             .await
         {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+            let name = node.read().await.name.clone();
+            self.logged_cmd
+                .log_event(
+                    "warning",
+                    &format!("failed to pin the ports of {}: {}", name, e),
+                )
+                .await;
         }
+        self.nodes.last().unwrap()
     }
 
-    pub(crate) async fn destroy(&mut self) -> Result<(), IoError> {
-        if self.destroyed {
-            return Ok(());
+    pub(crate) async fn add_node_with_rack(
+        &mut self,
+        datacenter_id: Option<i32>,
+        rack: Option<String>,
+    ) -> &Arc<RwLock<Node>> {
+        let dc = datacenter_id.unwrap_or(1);
+        let mut node = Node::new(
+            dc,
+            self.get_free_node_id(dc).await,
+            self.scylla,
+            self.default_node_smp,
+            self.default_node_memory,
+            self.default_config_for_dc(dc),
+            self.logged_cmd.clone(),
+            self.ccm.config_dir.clone(),
+            self.name.clone(),
+        );
+        node.seed = self.next_node_is_seed(dc).await;
+        node.env = self.default_node_env.clone();
+        node.config_audit = self.config_audit.clone();
+        node.default_start_options = self.default_start_options.clone();
+        node.hooks = self.hooks.clone();
+        let suffix = self.next_address_suffix(dc, &rack);
+        self.address_suffixes.push(suffix);
+        node.rack = rack;
+        node.address = Some(self.node_address(self.nodes.len()));
+        node.ipv6_address = self.node_ipv6_address(self.nodes.len());
+        node.host_address =
+            !self.host_addresses.is_empty() || self.ip_layout != IpLayout::Sequential;
+        node.hostname = self
+            .hostname_domain
+            .as_ref()
+            .map(|domain| node_hostname(&node.name, &self.name, domain));
+        node.firewall = self.firewall.clone();
+        node.ccm = self.ccm.clone();
+        self.nodes.push(Arc::new(RwLock::new(node)));
+        self.nodes.last().unwrap()
+    }
+
+    /// Renders `template` for every current node and merges the result into its config.
+    /// Available variables: `cluster_name`, `node_name`, `node_ip`, `datacenter`, `node_id`
+    /// and `install_directory`. Call before [`init`](Cluster::init), or push the
+    /// config afterwards with [`Node::update_config`].
+    #[cfg(feature = "config-yaml")]
+    pub async fn apply_template(&self, template: &ConfigTemplate) -> Result<(), IoError> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut node = node.write().await;
+            let vars = HashMap::from([
+                ("cluster_name".to_string(), self.name.clone()),
+                ("node_name".to_string(), node.name.clone()),
+                ("node_ip".to_string(), self.node_address(index)),
+                (
+                    "datacenter".to_string(),
+                    format!("dc{}", node.datacenter_id),
+                ),
+                ("node_id".to_string(), node.node_id.to_string()),
+                (
+                    "install_directory".to_string(),
+                    self.install_directory.clone(),
+                ),
+            ]);
+            let config = template
+                .render(&vars)
+                .map_err(|e| IoError::new(std::io::ErrorKind::InvalidData, e))?;
+            node.config.merge(&config);
         }
-        self.stop().await.ok();
-        match self
-            .logged_cmd
-            .run_command(
-                "ccm",
-                &[
-                    "remove",
-                    &self.name,
-                    "--config-dir",
-                    &self.install_directory,
-                ],
-                None,
-            )
-            .await
+        Ok(())
+    }
+
+    /// Gives every current node free ports from `allocator` in place of those it doesn't
+    /// override yet. Call before [`init`](Cluster::init); nodes keep their ports reserved in
+    /// `allocator` until released there.
+    pub async fn allocate_ports(&self, allocator: &PortAllocator) -> Result<(), IoError> {
+        for node in self.nodes.iter() {
+            node.write().await.ports.allocate_missing(allocator)?;
+        }
+        Ok(())
+    }
+
+    /// Pins the JMX and remote debug ports of `nodes` that don't override them. The
+    /// `7000 + dc * 100 + id` and `2000 + ...` defaults are kept unless they hit a server
+    /// port, a port of another node or, outside a network namespace, a port already bound
+    /// on the host or pinned by another cluster of the process; those get a free port
+    /// instead. Either way the port ends up in [`Node::ports`] and is reserved for the
+    /// process until [`destroy`](Cluster::destroy), since nodes bind it only once started.
+    async fn resolve_port_collisions(&self, nodes: &[Arc<RwLock<Node>>]) -> Result<(), IoError> {
+        let mut taken: BTreeSet<u16> = SERVER_PORTS.into();
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            taken.extend([node.native_port(), node.storage_port()]);
+            taken.extend(node.ports.jmx.into_iter().chain(node.ports.debug));
+        }
+        let check_host = self.network_namespace.is_none();
+        for node in nodes {
+            let mut node = node.write().await;
+            let defaults = [("jmx", node.jmx_port()), ("debug", node.debug_port())];
+            let name = node.name.clone();
+            let ports = &mut node.ports;
+            let slots = [&mut ports.jmx, &mut ports.debug];
+            for (slot, (kind, default)) in slots.into_iter().zip(defaults) {
+                if slot.is_some() {
+                    continue;
+                }
+                let port = match u16::try_from(default).ok().filter(|port| {
+                    !taken.contains(port) && (!check_host || self.reserve_port(*port))
+                }) {
+                    Some(port) => port,
+                    None => {
+                        let port = self.allocate_fallback_port(&taken)?;
+                        self.logged_cmd
+                            .log_event(
+                                "ports",
+                                &format!(
+                                    "{} {} port {} is taken, using {}",
+                                    name, kind, default, port
+                                ),
+                            )
+                            .await;
+                        port
+                    }
+                };
+                taken.insert(port);
+                *slot = Some(port);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reserves `port` for the cluster unless the host or another cluster of the process
+    /// uses it; ports the cluster reserved already stay its own.
+    fn reserve_port(&self, port: u16) -> bool {
+        let mut reserved = self.reserved_ports.lock().unwrap();
+        if reserved.contains(&port) {
+            return true;
+        }
+        if !ports::is_port_free(port) || !RESERVED_PORTS.reserve(port) {
+            return false;
+        }
+        reserved.push(port);
+        true
+    }
+
+    /// Free port from the process-wide allocator that is not in `taken`.
+    fn allocate_fallback_port(&self, taken: &BTreeSet<u16>) -> Result<u16, IoError> {
+        let mut skipped = vec![];
+        let port = loop {
+            match RESERVED_PORTS.allocate() {
+                Ok(port) if taken.contains(&port) => skipped.push(port),
+                Ok(port) => break Ok(port),
+                Err(e) => break Err(e),
+            }
+        };
+        for port in skipped {
+            RESERVED_PORTS.release(port);
+        }
+        let port = port?;
+        self.reserved_ports.lock().unwrap().push(port);
+        Ok(port)
+    }
+
+    /// Address of the node at `index` in creation order: the IP prefix followed by the
+    /// number [`IpLayout`] gave the node, unless the cluster uses host addresses.
+    fn node_address(&self, index: usize) -> String {
+        match self.host_addresses.get(index) {
+            Some(address) => address.to_string(),
+            None => {
+                let suffix = self.address_suffixes.get(index).copied();
+                format!("{}{}", self.ip_prefix, suffix.unwrap_or(index as u32 + 1))
+            }
+        }
+    }
+
+    /// IPv6 address of the node at `index` in a dual-stack cluster: the IPv6 prefix followed
+    /// by the same number as its IPv4 address.
+    fn node_ipv6_address(&self, index: usize) -> Option<String> {
+        let prefix = self.ipv6_prefix.as_ref()?;
+        let suffix = self.address_suffixes.get(index).copied();
+        Some(format!("{}{}", prefix, suffix.unwrap_or(index as u32 + 1)))
+    }
+
+    /// Gives every node an IPv6 address next to its IPv4 one: `prefix`, e.g.
+    /// `fd00:0:0:5::`, or a free one from [`IPV6_CLUSTER_RANGE`](ip_range::IPV6_CLUSTER_RANGE)
+    /// followed by the node number. Call before [`init`](Cluster::init), which makes the
+    /// nodes listen for CQL on both addresses, see `configure_dual_stack`; the range must be
+    /// routed to loopback, see [`IPV6_CLUSTER_RANGE`](ip_range::IPV6_CLUSTER_RANGE). Both
+    /// addresses show up in [`contact_points`](Cluster::contact_points) and in the hosts
+    /// file, so drivers' address family preferences can be exercised, while peers only ever
+    /// advertise IPv4. Fails with `Unsupported` for Cassandra, which prefers the IPv4 stack
+    /// and can't listen on both.
+    pub async fn enable_dual_stack(&mut self, prefix: Option<&str>) -> Result<(), IoError> {
+        if self.is_ipv6() || self.network_namespace.is_some() {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "dual stack needs an IPv4 cluster outside a network namespace",
+            ));
+        }
+        if !self.scylla {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "dual stack needs Scylla, Cassandra only listens on IPv4",
+            ));
+        }
+        let ipv6_prefix = match prefix {
+            Some(prefix) if !prefix.contains(':') => {
+                return Err(IoError::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} is not an IPv6 prefix", prefix),
+                ));
+            }
+            Some(prefix) => normalize_ip_prefix(prefix),
+            None => self.ip_range_allocator().reserve_ipv6().await?,
+        };
+        self.release_ipv6_prefix();
+        self.ipv6_prefix = Some(ipv6_prefix);
+        self.sniffed_ipv6_prefix = prefix.is_none();
+        for (index, node) in self.nodes.iter().enumerate() {
+            node.write().await.ipv6_address = self.node_ipv6_address(index);
+        }
+        Ok(())
+    }
+
+    fn release_ipv6_prefix(&mut self) {
+        if let Some(prefix) = &self.ipv6_prefix
+            && self.sniffed_ipv6_prefix
         {
-            Ok(_) => {
-                self.destroyed = true;
-                // for mut node in self.nodes {
-                //     node.mark_deleted();
-                // }
-                Ok(())
+            IpRangeAllocator::default().release(prefix);
+            self.sniffed_ipv6_prefix = false;
+        }
+    }
+
+    /// CQL addresses of the active nodes in creation order, followed by their IPv6 ones in
+    /// a dual-stack cluster.
+    pub async fn contact_points(&self) -> Vec<SocketAddr> {
+        let mut ipv4 = vec![];
+        let mut ipv6 = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if !matches!(node.status, NodeStatus::ACTIVE) {
+                continue;
             }
-            Err(e) => Err(e),
+            let port = node.native_port();
+            ipv4.extend(node.socket_address(port));
+            ipv6.extend(node.ipv6().map(|ip| SocketAddr::new(ip.into(), port)));
         }
+        ipv4.extend(ipv6);
+        ipv4
     }
-}
 
-#[tokio::test]
-async fn test_cluster_lifecycle() {
-    let mut cluster = Cluster::new(
-        "test_cluster".to_string(),
-        "release:6.2".to_string(),
-        None,
-        vec![3],
-        "/tmp/ccm".to_string(),
-        true,
-    )
-    .await
-    .expect("Failed to create cluster");
+    /// Makes dual-stack `nodes` listen for CQL on both their addresses: on the `::`
+    /// wildcard, which takes IPv4 connections too, advertising their IPv4 address. Sharing
+    /// the wildcard, every node needs CQL ports of its own; those it doesn't set are taken
+    /// from the process-wide pool and released by [`destroy`](Cluster::destroy).
+    async fn configure_dual_stack(&self, nodes: &[Arc<RwLock<Node>>]) -> Result<(), IoError> {
+        if self.ipv6_prefix.is_none() {
+            return Ok(());
+        }
+        let no_ports = BTreeSet::new();
+        for node in nodes {
+            let mut node = node.write().await;
+            let Some(address) = node.address.clone() else {
+                continue;
+            };
+            if node.ports.native.is_none() {
+                node.ports.native = Some(self.allocate_fallback_port(&no_ports)?);
+            }
+            let mut entries = HashMap::from([
+                (
+                    "rpc_address".to_string(),
+                    ScyllaConfig::String("::".to_string()),
+                ),
+                (
+                    "broadcast_rpc_address".to_string(),
+                    ScyllaConfig::String(address),
+                ),
+            ]);
+            let ScyllaConfig::Map(config) = &node.config else {
+                continue;
+            };
+            for key in DUAL_STACK_PORT_KEYS {
+                if !config.contains_key(key) {
+                    let port = self.allocate_fallback_port(&no_ports)?;
+                    entries.insert(key.to_string(), ScyllaConfig::Int(port.into()));
+                }
+            }
+            node.config.merge(&ScyllaConfig::Map(entries));
+        }
+        Ok(())
+    }
 
-    cluster.init().await.expect("Failed to initialize cluster");
-    cluster.start(None).await.expect("Failed to start cluster");
-    {
-        let node = cluster.add_node(Some(2)).await.write().await;
-        node.init().await.expect("Failed to initialize node");
-        node.start(None).await.expect("Failed to start node");
+    /// Number the next node of datacenter `dc` and `rack` gets under the current layout.
+    fn next_address_suffix(&mut self, dc: i32, rack: &Option<String>) -> u32 {
+        match self.ip_layout {
+            IpLayout::Sequential => self.address_suffixes.len() as u32 + 1,
+            IpLayout::SubnetPerRack => {
+                let key = (dc, rack.clone());
+                let block = match self.rack_blocks.iter().position(|block| *block == key) {
+                    Some(block) => block,
+                    None => {
+                        self.rack_blocks.push(key);
+                        self.rack_blocks.len() - 1
+                    }
+                } as u32;
+                let start = block * RACK_BLOCK_SIZE;
+                let taken = self
+                    .address_suffixes
+                    .iter()
+                    .filter(|suffix| (start..start + RACK_BLOCK_SIZE).contains(suffix))
+                    .count() as u32;
+                start + taken + 1
+            }
+        }
     }
-    cluster.stop().await.expect("Failed to stop cluster");
-    cluster.destroy().await.expect("Failed to destroy cluster");
+
+    /// Switches the address layout, re-addressing the nodes added so far. Call before
+    /// [`init`](Cluster::init).
+    pub async fn set_ip_layout(&mut self, layout: IpLayout) -> Result<(), IoError> {
+        if layout == IpLayout::SubnetPerRack && self.is_ipv6() {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "per-rack subnets are only available on IPv4 clusters",
+            ));
+        }
+        self.ip_layout = layout;
+        self.address_suffixes.clear();
+        self.rack_blocks.clear();
+        for index in 0..self.nodes.len() {
+            let node = self.nodes[index].clone();
+            let mut node = node.write().await;
+            let suffix = self.next_address_suffix(node.datacenter_id, &node.rack);
+            self.address_suffixes.push(suffix);
+            node.address = Some(self.node_address(index));
+            node.ipv6_address = self.node_ipv6_address(index);
+            node.host_address =
+                !self.host_addresses.is_empty() || self.ip_layout != IpLayout::Sequential;
+        }
+        Ok(())
+    }
+
+    /// Address block of every datacenter/rack pair under [`IpLayout::SubnetPerRack`], e.g.
+    /// to assert that a rack-aware policy routed to the right rack; empty otherwise.
+    pub fn rack_subnets(&self) -> Vec<RackSubnet> {
+        if self.ip_layout != IpLayout::SubnetPerRack {
+            return vec![];
+        }
+        let prefix_len = 32 - RACK_BLOCK_SIZE.trailing_zeros();
+        self.rack_blocks
+            .iter()
+            .enumerate()
+            .map(|(block, (datacenter_id, rack))| RackSubnet {
+                datacenter_id: *datacenter_id,
+                rack: rack.clone(),
+                network: format!(
+                    "{}{}/{}",
+                    self.ip_prefix,
+                    block as u32 * RACK_BLOCK_SIZE,
+                    prefix_len
+                ),
+            })
+            .collect()
+    }
+
+    /// Binds the nodes to existing host addresses instead of addresses under the IP prefix,
+    /// e.g. secondary addresses of a lab NIC, for tests needing nodes reachable from other
+    /// machines. The node at index `n` in creation order gets `addresses[n]`, and
+    /// [`init`](Cluster::init) fails if nodes outnumber the addresses. Every address must be
+    /// assigned to a local interface; ccm only accepts IPv4 ones.
+    pub async fn use_host_addresses(&mut self, addresses: Vec<IpAddr>) -> Result<(), IoError> {
+        if addresses.len() < self.nodes.len() {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} host addresses for {} nodes",
+                    addresses.len(),
+                    self.nodes.len()
+                ),
+            ));
+        }
+        for address in addresses.iter() {
+            if address.is_ipv6() {
+                return Err(IoError::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("ccm can't bind nodes to IPv6 address {}", address),
+                ));
+            }
+            // Binding fails unless the address belongs to a local interface.
+            if std::net::UdpSocket::bind((*address, 0)).is_err() {
+                return Err(IoError::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!("{} is not assigned to a local interface", address),
+                ));
+            }
+        }
+        self.host_addresses = addresses;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut node = node.write().await;
+            node.address = Some(self.node_address(index));
+            node.host_address = true;
+        }
+        self.loopback_escalation = None;
+        self.release_ip_prefix();
+        Ok(())
+    }
+
+    /// Puts a [`NodeProxy`] listening on a free `127.0.0.1` port in front of the CQL port of
+    /// every active node lacking one, and returns the proxy addresses in node order. Drivers
+    /// learn the real node addresses from `system.peers`, so tests connecting through the
+    /// proxies need an address translator mapping those to [`Node::proxy`] addresses.
+    pub async fn start_proxies(&self) -> Result<Vec<SocketAddr>, IoError> {
+        let mut addresses = vec![];
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            if !matches!(node.status, NodeStatus::ACTIVE) {
+                continue;
+            }
+            if node.proxy.is_none() {
+                let target = node.socket_address(node.native_port()).ok_or_else(|| {
+                    IoError::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("{} has no address", node.name),
+                    )
+                })?;
+                let listen = SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0));
+                node.proxy = Some(Arc::new(NodeProxy::start(listen, target).await?));
+            }
+            addresses.push(node.proxy.as_ref().unwrap().address());
+        }
+        Ok(addresses)
+    }
+
+    /// Names every node `<node name>.<cluster name>.<domain>`, e.g.
+    /// `node-1-2.my-cluster.ccm.test` (underscores are not valid in hostnames), so that
+    /// clusters sharing the domain and hosts file get distinct names, and registers the
+    /// names in `hosts_file`, kept up to date by [`init`](Cluster::init) and
+    /// [`readdress`](Cluster::readdress) and cleaned up by [`destroy`](Cluster::destroy).
+    /// Resolving them is up to the caller, e.g. by passing the file to the driver's resolver
+    /// or bind-mounting it as `/etc/hosts` in a container. Certificates issued afterwards
+    /// are valid for the names too.
+    pub async fn map_hostnames(
+        &mut self,
+        domain: &str,
+        hosts_file: impl Into<PathBuf>,
+    ) -> Result<(), IoError> {
+        let domain = domain.trim_matches('.').to_string();
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            node.hostname = Some(node_hostname(&node.name, &self.name, &domain));
+        }
+        self.hostname_domain = Some(domain);
+        self.hosts_file = Some(HostsFile::new(hosts_file, &self.name));
+        self.write_hosts_file().await
+    }
+
+    /// Registers the current addresses of active nodes in the hosts file, if any.
+    async fn write_hosts_file(&self) -> Result<(), IoError> {
+        let Some(hosts_file) = &self.hosts_file else {
+            return Ok(());
+        };
+        let mut entries = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if let (NodeStatus::ACTIVE, Some(address), Some(hostname)) =
+                (&node.status, &node.address, &node.hostname)
+            {
+                entries.push((address.clone(), hostname.clone()));
+                if let Some(ipv6) = &node.ipv6_address {
+                    entries.push((ipv6.clone(), hostname.clone()));
+                }
+            }
+        }
+        hosts_file.write(&entries).await
+    }
+
+    /// Path of the CA certificate drivers should trust, once TLS has been enabled.
+    pub fn ca_cert_path(&self) -> Option<&Path> {
+        self.certificate_authority
+            .as_ref()
+            .map(|ca| ca.cert.as_path())
+    }
+
+    /// Creates the cluster CA if needed and issues a certificate to every node lacking one.
+    /// Must be called after [`init`](Cluster::init), since ccm creates the node directories.
+    async fn ensure_node_certificates(&mut self) -> Result<CertificateAuthority, IoError> {
+        let ca = match &self.certificate_authority {
+            Some(ca) => ca.clone(),
+            None => {
+                let directory = self.directory().join("tls");
+                let ca = CertificateAuthority::generate(&self.logged_cmd, &directory, !self.scylla)
+                    .await?;
+                self.certificate_authority = Some(ca.clone());
+                ca
+            }
+        };
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut node = node.write().await;
+            if node.tls_certificate.is_none() {
+                let certificate = ca
+                    .issue(
+                        &self.logged_cmd,
+                        &node.name,
+                        &self.node_address(index),
+                        node.hostname(),
+                        &node.directory().join("conf"),
+                    )
+                    .await?;
+                node.tls_certificate = Some(certificate);
+            }
+        }
+        Ok(ca)
+    }
+
+    /// Generates a CA and per-node certificates and enables `client_encryption_options`
+    /// on every node. Call after [`init`](Cluster::init) and before starting the nodes.
+    /// Returns the CA certificate path for driver TLS configuration.
+    pub async fn enable_client_tls(&mut self) -> Result<PathBuf, IoError> {
+        let ca = self.ensure_node_certificates().await?;
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            let certificate = node.tls_certificate.clone().unwrap();
+            let config = tls::client_encryption_options(&certificate, &ca, self.scylla);
+            node.update_config(&config).await?;
+        }
+        Ok(ca.cert)
+    }
+
+    /// Distributes node keystores and enables `server_encryption_options` with the given
+    /// scope on every node, reusing the client TLS CA if one exists. Call after
+    /// [`init`](Cluster::init) and before starting the nodes. Returns the CA certificate path.
+    pub async fn enable_internode_encryption(
+        &mut self,
+        mode: InternodeEncryption,
+    ) -> Result<PathBuf, IoError> {
+        let ca = self.ensure_node_certificates().await?;
+        for node in self.nodes.iter() {
+            let mut node = node.write().await;
+            let certificate = node.tls_certificate.clone().unwrap();
+            let config = tls::server_encryption_options(&certificate, &ca, self.scylla, mode);
+            node.update_config(&config).await?;
+        }
+        Ok(ca.cert)
+    }
+
+    const DEFAULT_MEMORY: i32 = 512;
+    const FAST_MEMORY: i32 = 256;
+    const FAST_START_TIMEOUT: Duration = Duration::from_secs(60);
+    const CQL_PORT: u16 = 9042;
+    const STORAGE_PORT: u16 = 7000;
+    const PORT_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+    const AUTH_BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(120);
+    const DEFAULT_SMP: i32 = 1;
+
+    /// Install directory [`ClusterBuilder`] and [`ClusterSpec`](crate::cluster_pool::ClusterSpec)
+    /// use unless given one: `<temp dir>/ccm-binding`, apart from directories people keep
+    /// their own ccm clusters in.
+    pub fn default_install_directory() -> String {
+        std::env::temp_dir()
+            .join("ccm-binding")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Clusters ccm keeps in `config_dir`, sorted by name, read from their `cluster.conf`
+    /// and node directories rather than `ccm list`, so that versions and states come along,
+    /// e.g. for harnesses deciding whether to reuse or clean up leftovers. Clusters the crate
+    /// created in install directory `config_dir`, each in a config directory of its own,
+    /// are listed too. Server processes are looked up on this machine; those of clusters
+    /// run elsewhere only through a cluster using the remote executor, see
+    /// [`with_executor`](Cluster::with_executor).
+    pub async fn list(config_dir: impl AsRef<Path>) -> Result<Vec<ClusterInfo>, IoError> {
+        let config_dir = config_dir.as_ref();
+        let mut entries = match tokio::fs::read_dir(config_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut clusters = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let cluster_config_dir = ip_range::cluster_config_dir(config_dir, &name);
+            let cluster_dir = cluster_config_dir.join(&name);
+            let Ok(conf) = tokio::fs::read_to_string(cluster_dir.join("cluster.conf")).await else {
+                continue;
+            };
+            let current = tokio::fs::read_to_string(cluster_config_dir.join("CURRENT"))
+                .await
+                .unwrap_or_default();
+            let mut nodes = vec![];
+            let mut running = 0;
+            let mut node_dirs = tokio::fs::read_dir(&cluster_dir).await?;
+            while let Some(node_dir) = node_dirs.next_entry().await? {
+                if !node_dir.path().join("node.conf").exists() {
+                    continue;
+                }
+                nodes.push(node_dir.file_name().to_string_lossy().into_owned());
+                if ip_range::node_process_running(&node_dir.path(), None).await? {
+                    running += 1;
+                }
+            }
+            nodes.sort();
+            let state = match running {
+                0 => ClusterState::Stopped,
+                running if running == nodes.len() => ClusterState::Running,
+                _ => ClusterState::PartiallyRunning,
+            };
+            let state_file = config_dir.join(format!("{}.state.json", name));
+            let tags = tokio::fs::read_to_string(state_file)
+                .await
+                .ok()
+                .and_then(|state| serde_json::from_str::<Value>(&state).ok())
+                .and_then(|state| state["tags"].as_object().cloned())
+                .into_iter()
+                .flatten()
+                .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+                .collect();
+            clusters.push(ClusterInfo {
+                current: current.trim() == name,
+                version: cluster_conf_version(&conf),
+                ip_prefix: ip_range::parse_cluster_conf_prefix(&conf),
+                name,
+                nodes,
+                state,
+                tags,
+            });
+        }
+        clusters.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(clusters)
+    }
+
+    /// Attaches to cluster `name` ccm keeps in `config_dir`, e.g. one a colleague left
+    /// running, rebuilding it and its nodes from `cluster.conf` and the node directories.
+    /// Nothing is created or started: the cluster is controlled as it is, and
+    /// [`destroy`](Cluster::destroy) removes it like a cluster created here. A stopped
+    /// cluster whose IP prefix something else took meanwhile is moved to a free one, see
+    /// [`readdress_if_occupied`](Cluster::readdress_if_occupied). `config_dir` may also be
+    /// the install directory of a cluster created by the crate.
+    pub async fn load(name: &str, config_dir: impl AsRef<Path>) -> Result<Self, IoError> {
+        let config_dir = config_dir.as_ref();
+        let cluster_config_dir = ip_range::cluster_config_dir(config_dir, name);
+        let cluster_dir = cluster_config_dir.join(name);
+        let conf = match tokio::fs::read_to_string(cluster_dir.join("cluster.conf")).await {
+            Ok(conf) => conf,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(IoError::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no cluster {} in {}", name, config_dir.display()),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        let scylla = ip_range::conf_value(&conf, "scylla_version").is_some()
+            || ip_range::conf_value(&conf, "install_dir").is_some_and(|dir| dir.contains("scylla"));
+        let ip_prefix =
+            ip_range::parse_cluster_conf_prefix(&conf).unwrap_or_else(|| "127.0.0.".to_string());
+
+        let mut node_confs = vec![];
+        let mut node_dirs = tokio::fs::read_dir(&cluster_dir).await?;
+        while let Some(node_dir) = node_dirs.next_entry().await? {
+            if let Ok(node_conf) =
+                tokio::fs::read_to_string(node_dir.path().join("node.conf")).await
+            {
+                node_confs.push((
+                    node_dir.file_name().to_string_lossy().into_owned(),
+                    node_conf,
+                ));
+            }
+        }
+        node_confs.sort();
+
+        let mut cluster = Self::build(
+            name.to_string(),
+            cluster_conf_version(&conf).unwrap_or_default(),
+            Arc::new(FixedPrefix(ip_prefix)),
+            vec![],
+            config_dir.to_string_lossy().into_owned(),
+            scylla,
+            None,
+        )
+        .await?;
+        cluster.ccm.config_dir = cluster_config_dir.to_string_lossy().into_owned();
+        for (node_name, node_conf) in node_confs {
+            let datacenter_id = ip_range::conf_value(&node_conf, "data_center")
+                .and_then(|dc| dc.trim_start_matches("dc").parse().ok());
+            let rack = ip_range::conf_value(&node_conf, "rack").map(str::to_string);
+            let node = cluster
+                .add_node_with_rack(datacenter_id, rack)
+                .await
+                .clone();
+            let mut node = node.write().await;
+            if let Some(node_id) = node_name
+                .strip_prefix(&format!("node_{}_", node.datacenter_id))
+                .and_then(|id| id.parse().ok())
+            {
+                node.node_id = node_id;
+            }
+            let port = |key| ip_range::conf_value(&node_conf, key).and_then(|p| p.parse().ok());
+            node.ports.jmx = port("jmx_port");
+            node.ports.debug = port("remote_debug_port");
+            if let Some(address) = ip_range::conf_interface_address(&node_conf, "binary") {
+                if let Some(suffix) = address
+                    .strip_prefix(cluster.ip_prefix.as_str())
+                    .and_then(|suffix| suffix.parse().ok())
+                {
+                    *cluster.address_suffixes.last_mut().unwrap() = suffix;
+                }
+                node.address = Some(address.to_string());
+            }
+            node.name = node_name;
+        }
+        cluster
+            .logged_cmd
+            .log_event("load", &format!("attached to {}", cluster_dir.display()))
+            .await;
+        if !cluster.running().await? {
+            cluster.readdress_if_occupied().await?;
+        }
+        Ok(cluster)
+    }
+
+    /// Where [`save_state`](Cluster::save_state) writes the cluster:
+    /// `<install_directory>/<name>.state.json`, next to the cluster log.
+    pub fn state_file(&self) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/{}.state.json",
+            self.install_directory, self.name
+        ))
+    }
+
+    /// Writes the crate's view of the cluster, its addresses, authentication, defaults and
+    /// per-node ports, resources and configs, to the [`state_file`](Cluster::state_file), so
+    /// that other processes can control it through
+    /// [`from_state_file`](Cluster::from_state_file). [`init`](Cluster::init) saves it too;
+    /// save again after changing nodes for other processes to see the changes. Role
+    /// passwords are saved in clear, the file is as private as the install directory.
+    pub async fn save_state(&self) -> Result<PathBuf, IoError> {
+        let mut nodes = vec![];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let node = node.read().await;
+            nodes.push(SavedNode {
+                name: node.name.clone(),
+                datacenter_id: Some(node.datacenter_id),
+                node_id: Some(node.node_id),
+                rack: node.rack.clone(),
+                version: node.version.clone(),
+                deleted: matches!(node.status, NodeStatus::DELETED),
+                seed: node.seed,
+                smp: Some(node.smp),
+                memory: Some(node.memory),
+                config: node.config.clone(),
+                env: node.env.clone(),
+                tags: node.tags.clone(),
+                address: node.address.clone(),
+                address_suffix: self.address_suffixes.get(index).copied(),
+                ipv6_address: node.ipv6_address.clone(),
+                hostname: node.hostname.clone(),
+                ports: node.ports,
+            });
+        }
+        let state = SavedCluster {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            scylla: self.scylla,
+            install_directory: self.install_directory.clone(),
+            config_dir: Some(self.ccm.config_dir.clone()),
+            ip_prefix: self.ip_prefix.clone(),
+            ip_layout: self.ip_layout,
+            ipv6_prefix: self.ipv6_prefix.clone(),
+            host_addresses: self.host_addresses.clone(),
+            hostname_domain: self.hostname_domain.clone(),
+            hosts_file: self
+                .hosts_file
+                .as_ref()
+                .map(|hosts_file| hosts_file.path().to_path_buf()),
+            default_node_smp: Some(self.default_node_smp),
+            default_node_memory: Some(self.default_node_memory),
+            default_start_options: self.default_start_options.clone(),
+            partitioner: self.partitioner,
+            replication_mode: self.replication_mode,
+            password_auth: self.password_auth,
+            auth_test_role: self.auth_test_role.clone(),
+            auth_roles: self.auth_roles.clone(),
+            tags: self.tags.clone(),
+            dc_seeds: self.dc_seeds.clone(),
+            nodes,
+        };
+        let state = serde_json::to_string_pretty(&state).map_err(IoError::other)?;
+        // Written aside and renamed, so that readers never see a partial file.
+        let path = self.state_file();
+        let partial = path.with_extension("json.partial");
+        tokio::fs::write(&partial, format!("{}\n", state)).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(path)
+    }
+
+    /// Restores a cluster saved by [`save_state`](Cluster::save_state), e.g. in another
+    /// process, without running ccm. Network namespaces, proxies and firewall rules stay with
+    /// the process that set them up.
+    pub async fn from_state_file(path: impl AsRef<Path>) -> Result<Self, IoError> {
+        Self::load_state_file(path.as_ref(), None).await
+    }
+
+    async fn load_state_file(
+        path: &Path,
+        executor: Option<Arc<dyn CommandExecutor>>,
+    ) -> Result<Self, IoError> {
+        let state: SavedCluster = serde_json::from_str(&tokio::fs::read_to_string(path).await?)
+            .map_err(|e| {
+                IoError::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("state file {}: {}", path.display(), e),
+                )
+            })?;
+
+        let mut cluster = Self::build(
+            state.name,
+            state.version,
+            Arc::new(FixedPrefix(state.ip_prefix)),
+            vec![],
+            state.install_directory,
+            state.scylla,
+            executor,
+        )
+        .await?;
+        if let Some(config_dir) = state.config_dir {
+            cluster.ccm.config_dir = config_dir;
+        }
+        cluster.ip_layout = state.ip_layout;
+        cluster.ipv6_prefix = state.ipv6_prefix;
+        cluster.host_addresses = state.host_addresses;
+        cluster.hostname_domain = state.hostname_domain;
+        cluster.hosts_file = state
+            .hosts_file
+            .map(|hosts_file| HostsFile::new(hosts_file, &cluster.name));
+        if let Some(smp) = state.default_node_smp {
+            cluster.default_node_smp = smp;
+        }
+        if let Some(memory) = state.default_node_memory {
+            cluster.default_node_memory = memory;
+        }
+        cluster.default_start_options = state.default_start_options;
+        cluster.dc_seeds = state.dc_seeds;
+        cluster.partitioner = state.partitioner;
+        cluster.replication_mode = state.replication_mode;
+        cluster.password_auth = state.password_auth;
+        for role in state
+            .auth_test_role
+            .iter()
+            .chain(state.auth_roles.iter().map(|role| &role.credentials))
+        {
+            cluster.logged_cmd.redact(role.password.clone());
+        }
+        cluster.auth_test_role = state.auth_test_role;
+        cluster.auth_roles = state.auth_roles;
+        cluster.tags = state.tags;
+        for node_state in state.nodes {
+            let node = cluster
+                .add_node_with_rack(node_state.datacenter_id, node_state.rack)
+                .await
+                .clone();
+            if let Some(suffix) = node_state.address_suffix {
+                *cluster.address_suffixes.last_mut().unwrap() = suffix;
+            }
+            let mut node = node.write().await;
+            node.name = node_state.name;
+            if let Some(node_id) = node_state.node_id {
+                node.node_id = node_id;
+            }
+            if node_state.deleted {
+                node.status = NodeStatus::DELETED;
+            }
+            node.seed = node_state.seed;
+            node.version = node_state.version;
+            node.smp = node_state.smp.unwrap_or(node.smp);
+            node.memory = node_state.memory.unwrap_or(node.memory);
+            node.config = node_state.config;
+            node.env = node_state.env;
+            node.tags = node_state.tags;
+            node.address = node_state.address.or(node.address.take());
+            node.ipv6_address = node_state.ipv6_address;
+            node.hostname = node_state.hostname;
+            node.ports = node_state.ports;
+            node.default_start_options = cluster.default_start_options.clone();
+        }
+        Ok(cluster)
+    }
+
+    /// Scylla cluster `name` in `install_directory` with `topology` nodes per datacenter,
+    /// reusing one left by an earlier process when its version and topology match: it is
+    /// started if needed instead of being rebuilt. A mismatching cluster of that name is
+    /// removed and created anew. The cluster returned is kept alive, see
+    /// [`ClusterPolicy::KeepAlive`].
+    pub async fn ensure(
+        name: &str,
+        topology: &[i32],
+        version: &str,
+        install_directory: &str,
+    ) -> Result<Self, IoError> {
+        let state_file = PathBuf::from(format!("{}/{}.state.json", install_directory, name));
+        let existing = if state_file.exists() {
+            Some(Self::from_state_file(&state_file).await?)
+        } else if ip_range::cluster_config_dir(Path::new(install_directory), name)
+            .join(name)
+            .join("cluster.conf")
+            .exists()
+        {
+            Some(Self::load(name, install_directory).await?)
+        } else {
+            None
+        };
+        if let Some(mut cluster) = existing {
+            cluster.set_policy(ClusterPolicy::KeepAlive);
+            if cluster.version == version && cluster.topology().await == topology {
+                if !cluster.is_active().await {
+                    cluster.make_active().await?;
+                }
+                if !cluster.running().await? {
+                    cluster.readdress_if_occupied().await?;
+                    cluster.start(None).await?;
+                }
+                cluster
+                    .logged_cmd
+                    .log_event("reuse", &format!("reusing {}", cluster.name))
+                    .await;
+                return Ok(cluster);
+            }
+            cluster.set_policy(ClusterPolicy::Destroy);
+            cluster.destroy().await?;
+        }
+        let mut cluster = Self::new(
+            name.to_string(),
+            version.to_string(),
+            None,
+            topology.to_vec(),
+            install_directory.to_string(),
+            true,
+        )
+        .await?;
+        cluster.set_policy(ClusterPolicy::KeepAlive);
+        cluster.init(true).await?;
+        cluster.start(None).await?;
+        Ok(cluster)
+    }
+
+    /// Active node count of every datacenter, in datacenter order.
+    async fn topology(&self) -> Vec<i32> {
+        let mut topology = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::DELETED) || node.datacenter_id < 1 {
+                continue;
+            }
+            let datacenter = node.datacenter_id as usize;
+            if topology.len() < datacenter {
+                topology.resize(datacenter, 0);
+            }
+            topology[datacenter - 1] += 1;
+        }
+        topology
+    }
+
+    /// Whether the server of every active node runs, going by its pid file.
+    async fn running(&self) -> Result<bool, IoError> {
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) && !node.process_running().await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Starts describing a cluster fluently, see [`ClusterBuilder`].
+    pub fn builder() -> ClusterBuilder {
+        ClusterBuilder::new()
+    }
+
+    pub async fn new(
+        name: String,
+        version: String,
+        ip_prefix: Option<&str>,
+        number_of_nodes: Vec<i32>,
+        install_directory: String,
+        scylla: bool,
+    ) -> Result<Self, IoError> {
+        Self::build(
+            name,
+            version,
+            ip_strategy_for(ip_prefix),
+            number_of_nodes,
+            install_directory,
+            scylla,
+            None,
+        )
+        .await
+    }
+
+    /// Same as [`new`](Cluster::new), with node addresses picked by `ip_strategy` instead
+    /// of a fixed or sniffed loopback prefix.
+    pub async fn with_ip_strategy(
+        name: String,
+        version: String,
+        ip_strategy: Arc<dyn IpStrategy>,
+        number_of_nodes: Vec<i32>,
+        install_directory: String,
+        scylla: bool,
+    ) -> Result<Self, IoError> {
+        Self::build(
+            name,
+            version,
+            ip_strategy,
+            number_of_nodes,
+            install_directory,
+            scylla,
+            None,
+        )
+        .await
+    }
+
+    /// Same as [`new`](Cluster::new), running ccm and other node commands through
+    /// `executor`, e.g. an [`SshExecutor`](crate::executor::SshExecutor) to manage the
+    /// cluster on a remote machine. The cluster log still only records cluster events;
+    /// command output goes to the executor's own log.
+    pub async fn with_executor(
+        name: String,
+        version: String,
+        ip_prefix: Option<&str>,
+        number_of_nodes: Vec<i32>,
+        install_directory: String,
+        scylla: bool,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Result<Self, IoError> {
+        Self::build(
+            name,
+            version,
+            ip_strategy_for(ip_prefix),
+            number_of_nodes,
+            install_directory,
+            scylla,
+            Some(executor),
+        )
+        .await
+    }
+
+    async fn build(
+        name: String,
+        version: String,
+        ip_strategy: Arc<dyn IpStrategy>,
+        number_of_nodes: Vec<i32>,
+        install_directory: String,
+        scylla: bool,
+        executor: Option<Arc<dyn CommandExecutor>>,
+    ) -> Result<Self, IoError> {
+        match metadata(install_directory.as_str()).await {
+            Ok(mt) => {
+                if !mt.is_dir() {
+                    return Err(IoError::new(
+                        DirectoryNotEmpty,
+                        format!("{install_directory} already exists and it is not a dictionary"),
+                    ));
+                }
+            }
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    tokio::fs::create_dir_all(install_directory.as_str()).await?;
+                }
+                _ => {
+                    return Err(e);
+                }
+            },
+        }
+
+        let mut lcmd = LoggedCmd::new();
+        lcmd.set_log_file(format!("{install_directory}/{name}.ccm.log"))
+            .await?;
+        let test_name = test_context::current();
+        if let Some(test_name) = &test_name {
+            lcmd.log_event("test", test_name).await;
+        }
+
+        // Before picking addresses, so that a cluster waiting for room holds none.
+        let host_lease = match HostLimits::current() {
+            Some(limits) => Some(limits.acquire(&name).await?),
+            None => None,
+        };
+        let request = IpRequest {
+            cluster_name: &name,
+            install_directory: &install_directory,
+            node_count: number_of_nodes.iter().sum::<i32>().max(0) as usize,
+        };
+        let ip_allocation = ip_strategy.allocate(&request).await?;
+
+        // A config directory of its own, so that ccm's active cluster is always this one.
+        let config_dir = Path::new(&install_directory).join(&name);
+        tokio::fs::create_dir_all(&config_dir).await?;
+        let logged_cmd = Arc::new(lcmd);
+        let mut ccm = CcmRunner::new(logged_cmd.clone(), config_dir.to_string_lossy());
+        if let Some(executor) = executor {
+            ccm = ccm.with_executor(executor);
+        }
+        let firewall = Arc::new(Firewall::new(FirewallBackend::default(), &name));
+        let mut cluster = Cluster {
+            name,
+            scylla,
+            version,
+            ip_prefix: ip_allocation.ip_prefix.clone(),
+            install_directory,
+            test_name,
+            tags: BTreeMap::new(),
+            destroyed: false,
+            sniffed_ip_prefix: false,
+            nodes: vec![],
+            default_node_memory: Self::DEFAULT_MEMORY,
+            default_node_smp: Self::DEFAULT_SMP,
+            default_node_config: None,
+            dc_default_configs: HashMap::new(),
+            dc_seeds: HashMap::new(),
+            default_node_env: HashMap::new(),
+            certificate_authority: None,
+            auth_test_role: None,
+            password_auth: false,
+            auth_roles: vec![],
+            partitioner: None,
+            config_audit: None,
+            replication_mode: None,
+            loopback_escalation: loopback::aliases_required().then_some(Escalation::Sudo),
+            port_check_timeout: Some(Self::PORT_CHECK_TIMEOUT),
+            policy: ClusterPolicy::default(),
+            default_start_options: vec![],
+            destroy_mode: DestroyMode::default(),
+            health_check_cql: false,
+            capacity_policy: CapacityPolicy::default(),
+            reserved_ports: Mutex::new(vec![]),
+            ttl_reaper: None,
+            host_lease,
+            hooks: vec![],
+            loopback_aliases: Mutex::new(vec![]),
+            host_addresses: vec![],
+            ip_layout: IpLayout::default(),
+            ipv6_prefix: None,
+            sniffed_ipv6_prefix: false,
+            address_suffixes: vec![],
+            rack_blocks: vec![],
+            network_namespace: None,
+            firewall,
+            ip_strategy,
+            ip_allocation: None,
+            hostname_domain: None,
+            hosts_file: None,
+            ccm,
+            logged_cmd,
+        };
+
+        for (datacenter_id, count) in number_of_nodes.iter().enumerate() {
+            for _ in 0..*count {
+                cluster
+                    .add_node_with_rack(Some((datacenter_id + 1) as i32), None)
+                    .await;
+            }
+        }
+        // Stored only once applied, switching to host addresses releases the current one.
+        if let Err(e) = cluster.apply_ip_allocation(&ip_allocation).await {
+            cluster.ip_strategy.release(&ip_allocation);
+            return Err(e);
+        }
+        cluster.ip_allocation = Some(ip_allocation);
+        Ok(cluster)
+    }
+
+    /// Applies the [`capacity policy`](Cluster::set_capacity_policy) to the active ones of
+    /// `nodes`, those about to be created.
+    async fn check_capacity(&self, candidates: &[Arc<RwLock<Node>>]) -> Result<(), IoError> {
+        if self.logged_cmd.is_dry_run() {
+            return Ok(());
+        }
+        let (mut nodes, mut sizes) = (vec![], vec![]);
+        for node in candidates {
+            let locked = node.read().await;
+            if matches!(locked.status, NodeStatus::ACTIVE) {
+                sizes.push((locked.smp, locked.memory));
+                nodes.push(node);
+            }
+        }
+        let host = HostResources::detect(
+            self.ccm.executor().as_ref(),
+            Path::new(&self.install_directory),
+        )
+        .await;
+        let shortfalls = host.shortfalls(&sizes);
+        if shortfalls.is_empty() {
+            return Ok(());
+        }
+        let insufficient = InsufficientResources { shortfalls };
+        let downscaled = match self.capacity_policy {
+            CapacityPolicy::Warn => {
+                self.logged_cmd
+                    .log_event("warning", &insufficient.to_string())
+                    .await;
+                return Ok(());
+            }
+            CapacityPolicy::Fail => None,
+            CapacityPolicy::Downscale => host.downscale(&sizes),
+        };
+        let Some(downscaled) = downscaled else {
+            return Err(IoError::other(insufficient));
+        };
+        for (node, (smp, memory)) in nodes.iter().zip(downscaled) {
+            let mut node = node.write().await;
+            self.logged_cmd
+                .log_event(
+                    "warning",
+                    &format!(
+                        "{}: downscaled from {} smp and {} MB to {} smp and {} MB, {}",
+                        node.name, node.smp, node.memory, smp, memory, insufficient
+                    ),
+                )
+                .await;
+            node.smp = smp;
+            node.memory = memory;
+        }
+        Ok(())
+    }
+
+    async fn apply_ip_allocation(&mut self, allocation: &IpAllocation) -> Result<(), IoError> {
+        if !allocation.host_addresses.is_empty() {
+            self.use_host_addresses(allocation.host_addresses.clone())
+                .await?;
+        }
+        if let Some(escalation) = &allocation.network_namespace {
+            self.isolate_network(escalation.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Creates the cluster and its nodes with ccm. Fails with `AlreadyExists` if a cluster
+    /// of that name is in the install directory, unless `force` is set: it is then removed,
+    /// data included. See [`init_or_attach`](Cluster::init_or_attach) to reuse it instead.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(cluster = %self.name, tags = ?self.tags)))]
+    pub async fn init(&self, force: bool) -> Result<(), IoError> {
+        let ccm_path = self.directory();
+        if ccm_path.exists() && !force {
+            return Err(IoError::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "cluster {} already exists in {}, init with force to recreate it",
+                    self.name, self.ccm.config_dir
+                ),
+            ));
+        }
+
+        self.ccm.preflight(self.scylla).await?;
+        self.prepare_nodes(&self.nodes).await?;
+        if ccm_path.exists() {
+            self.logged_cmd
+                .log_event(
+                    "warning",
+                    &format!("removing existing {}", ccm_path.display()),
+                )
+                .await;
+            tokio::fs::remove_dir_all(&ccm_path).await?;
+        }
+        // ccm builds node addresses from `--ipprefix` by appending the node number, which
+        // only suits IPv4; `--ip-format` takes an explicit template.
+        let ip_format = format!("{}%d", self.ip_prefix);
+        let mut args: Vec<&str> = vec!["create", &self.name, "-v", &self.version];
+        if self.is_ipv6() {
+            args.extend(["-I", &ip_format]);
+        } else {
+            args.extend(["-i", &self.ip_prefix]);
+        }
+        if self.scylla {
+            args.push("--scylla");
+        }
+        if let Some(partitioner) = self.partitioner {
+            args.extend(["-p", partitioner.class_name()]);
+        }
+        let replication_config = self.replication_config()?;
+        self.ccm.run(&args, None).await?;
+
+        for node in self.nodes.iter() {
+            self.init_node(node, replication_config.as_ref()).await?;
+        }
+        self.save_state().await?;
+
+        Ok(())
+    }
+
+    /// Readies the host for `nodes`, the nodes ccm is about to create, the same way for
+    /// [`init`](Cluster::init) and [`scale`](Cluster::scale): applies the capacity policy
+    /// to them, resizes the host lease to all active nodes, pins ports, sets up dual-stack
+    /// listening, checks addresses, adds their loopback aliases and registers the active
+    /// nodes in the hosts file.
+    async fn prepare_nodes(&self, nodes: &[Arc<RwLock<Node>>]) -> Result<(), IoError> {
+        self.check_capacity(nodes).await?;
+        if let Some(lease) = &self.host_lease {
+            let (mut smp, mut memory) = (0, 0);
+            for node in self.nodes.iter() {
+                let node = node.read().await;
+                if matches!(node.status, NodeStatus::ACTIVE) {
+                    smp += node.smp;
+                    memory += node.memory;
+                }
+            }
+            lease.resize(smp, memory).await?;
+        }
+        self.resolve_port_collisions(nodes).await?;
+        self.configure_dual_stack(nodes).await?;
+        if self.host_addresses.is_empty()
+            && let Some(suffix) = self.address_suffixes.iter().find(|suffix| **suffix > 254)
+        {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "node address {}{} is out of range, too many nodes for {:?}",
+                    self.ip_prefix, suffix, self.ip_layout
+                ),
+            ));
+        }
+        if !self.host_addresses.is_empty() && self.nodes.len() > self.host_addresses.len() {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} host addresses for {} nodes",
+                    self.host_addresses.len(),
+                    self.nodes.len()
+                ),
+            ));
+        }
+        if let Some(escalation) = &self.loopback_escalation {
+            let addresses: Vec<String> = (0..self.nodes.len())
+                .filter(|index| {
+                    nodes
+                        .iter()
+                        .any(|node| Arc::ptr_eq(node, &self.nodes[*index]))
+                })
+                .map(|index| self.node_address(index))
+                .collect();
+            let added =
+                loopback::ensure_aliases(self.ccm.executor().as_ref(), &addresses, escalation)
+                    .await?;
+            self.loopback_aliases.lock().unwrap().extend(added);
+        }
+        self.write_hosts_file().await
+    }
+
+    /// Config the [`replication mode`](Cluster::set_replication_mode) adds to every node.
+    fn replication_config(&self) -> Result<Option<ScyllaConfig>, IoError> {
+        match &self.replication_mode {
+            Some(mode) => Ok(Some(mode.config(self.scylla, &self.version)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Merges `replication_config` into the config of `node` and adds it to the cluster
+    /// with ccm.
+    async fn init_node(
+        &self,
+        node: &Arc<RwLock<Node>>,
+        replication_config: Option<&ScyllaConfig>,
+    ) -> Result<(), IoError> {
+        if let Some(config) = replication_config {
+            node.write().await.config.merge(config);
+        }
+        node.read().await.init().await
+    }
+
+    /// Reuses the cluster of that name in the install directory, e.g. one a colleague keeps
+    /// running, or [`init`](Cluster::init)s it if there is none. An existing cluster is made
+    /// ccm's active one and left as it is: configured node sizes and config don't apply to
+    /// it. Fails if one of the nodes is missing from it.
+    pub async fn init_or_attach(&self) -> Result<InitOutcome, IoError> {
+        let ccm_path = self.directory();
+        if !ccm_path.join("cluster.conf").exists() {
+            self.init(false).await?;
+            return Ok(InitOutcome::Created);
+        }
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) && !ccm_path.join(&node.name).is_dir() {
+                return Err(IoError::new(
+                    std::io::ErrorKind::NotFound,
+                    format!(
+                        "existing cluster {} has no {}, init with force to recreate it",
+                        self.name, node.name
+                    ),
+                ));
+            }
+        }
+        if !self.is_active().await {
+            self.make_active().await?;
+        }
+        self.logged_cmd
+            .log_event("reuse", &format!("attached to {}", self.name))
+            .await;
+        self.save_state().await?;
+        Ok(InitOutcome::Attached)
+    }
+
+    /// Switches every node to `PasswordAuthenticator`. Call after [`init`](Cluster::init) and
+    /// before [`start`](Cluster::start); `start` then waits for the default superuser to be
+    /// created and creates `test_role`, if given. Returns the credentials tests should use.
+    /// Role passwords are masked in the cluster log, see [`LoggedCmd::redact`].
+    pub async fn enable_password_auth(
+        &mut self,
+        test_role: Option<Credentials>,
+    ) -> Result<Credentials, IoError> {
+        let config = ScyllaConfig::Map(HashMap::from([(
+            "authenticator".to_string(),
+            ScyllaConfig::String("PasswordAuthenticator".to_string()),
+        )]));
+        for node in self.nodes.iter() {
+            node.write().await.update_config(&config).await?;
+        }
+        if let Some(role) = &test_role {
+            self.logged_cmd.redact(role.password.clone());
+        }
+        self.password_auth = true;
+        self.auth_test_role = test_role;
+        Ok(self.credentials().unwrap())
+    }
+
+    /// Switches every node to `CassandraAuthorizer`, enabling password authentication first
+    /// if needed. Same timing rules as [`enable_password_auth`](Cluster::enable_password_auth).
+    pub async fn enable_authorization(&mut self) -> Result<(), IoError> {
+        if !self.password_auth {
+            self.enable_password_auth(self.auth_test_role.clone())
+                .await?;
+        }
+        let config = ScyllaConfig::Map(HashMap::from([(
+            "authorizer".to_string(),
+            ScyllaConfig::String("CassandraAuthorizer".to_string()),
+        )]));
+        for node in self.nodes.iter() {
+            node.write().await.update_config(&config).await?;
+        }
+        Ok(())
+    }
+
+    /// Enables Scylla experimental features on every node, written as `experimental_features`
+    /// or, for releases predating it, `experimental: true`. Call after [`init`](Cluster::init)
+    /// and before [`start`](Cluster::start).
+    pub async fn enable_experimental(&mut self, features: &[&str]) -> Result<(), IoError> {
+        if !self.scylla {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "experimental features are only available on Scylla",
+            ));
+        }
+        let config = version::experimental_config(&self.version, features);
+        for node in self.nodes.iter() {
+            node.write().await.update_config(&config).await?;
+        }
+        Ok(())
+    }
+
+    /// Queues a role (and its grants) to be created by [`start`](Cluster::start) once
+    /// authentication is ready. Grants must target resources that exist at that point,
+    /// e.g. `ALL KEYSPACES`; use [`create_role`](Cluster::create_role) later for others.
+    pub fn add_role(&mut self, role: RoleSpec) {
+        self.logged_cmd.redact(role.credentials.password.clone());
+        self.auth_roles.push(role);
+    }
+
+    /// Creates a role and applies its grants on a running cluster as the default superuser.
+    pub async fn create_role(&self, role: &RoleSpec) -> Result<(), IoError> {
+        self.logged_cmd.redact(role.credentials.password.clone());
+        let Some(node) = self.nodes.first() else {
+            return Ok(());
+        };
+        let node = node.read().await;
+        let superuser = Credentials::default_superuser();
+        for statement in role.statements() {
+            node.cqlsh(&statement, Some(&superuser)).await?;
+        }
+        Ok(())
+    }
+
+    /// Credentials for an authenticated cluster: the test role if one was requested,
+    /// otherwise the default superuser. `None` when authentication is disabled.
+    pub fn credentials(&self) -> Option<Credentials> {
+        if !self.password_auth {
+            return None;
+        }
+        Some(
+            self.auth_test_role
+                .clone()
+                .unwrap_or_else(Credentials::default_superuser),
+        )
+    }
+
+    /// Waits until the default superuser can log in, then creates the test role.
+    async fn bootstrap_password_auth(&self) -> Result<(), IoError> {
+        let Some(node) = self.nodes.first() else {
+            return Ok(());
+        };
+        let node = node.read().await;
+        let superuser = Credentials::default_superuser();
+        let deadline = tokio::time::Instant::now() + Self::AUTH_BOOTSTRAP_TIMEOUT;
+        while let Err(e) = node.cqlsh("LIST ROLES", Some(&superuser)).await {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(IoError::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("default superuser was not created in time: {}", e),
+                ));
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        if let Some(role) = &self.auth_test_role {
+            node.cqlsh(&auth::create_login_role(role, false), Some(&superuser))
+                .await?;
+        }
+        drop(node);
+        for role in self.auth_roles.iter() {
+            self.create_role(role).await?;
+        }
+        Ok(())
+    }
+
+    /// Brings up a single-node Scylla cluster tuned for start-up speed rather than realism:
+    /// data on tmpfs (`/dev/shm` when available), developer mode, no gossip settling or ring
+    /// delay, one shard with minimal memory, and a crate-side CQL port probe every 50ms
+    /// instead of ccm's slower waits. A cluster that fails to come up is destroyed.
+    pub async fn single_node_fast(version: &str) -> Result<Self, IoError> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+        let tmpfs = Path::new("/dev/shm");
+        let base = if tmpfs.is_dir() {
+            tmpfs.to_path_buf()
+        } else {
+            std::env::temp_dir()
+        };
+        let name = format!(
+            "fast_{}_{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut cluster = Cluster::new(
+            name,
+            version.to_string(),
+            None,
+            vec![],
+            base.join("ccm-binding").to_string_lossy().into_owned(),
+            true,
+        )
+        .await?;
+        cluster.set_default_node_smp(1);
+        cluster.set_default_node_memory(Self::FAST_MEMORY);
+        cluster.set_default_node_config(
+            ScyllaConfig::from_flat_string(
+                "developer_mode:true skip_wait_for_gossip_to_settle:0 ring_delay_ms:0",
+            )
+            .map_err(|e| IoError::new(std::io::ErrorKind::InvalidData, e))?,
+        );
+        cluster.add_node(Some(1)).await;
+
+        match cluster.start_fast().await {
+            Ok(()) => Ok(cluster),
+            Err(e) => Err(cluster.discard_after(e).await),
+        }
+    }
+
+    /// Creates and starts the [`single_node_fast`](Cluster::single_node_fast) cluster,
+    /// waiting for its CQL port only.
+    async fn start_fast(&self) -> Result<(), IoError> {
+        self.init(true).await?;
+        self.start(Some(&[NodeStartOption::NOWAIT])).await?;
+        let address = {
+            let node = self.nodes[0].read().await;
+            node.socket_address(node.native_port())
+        }
+        .ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid node address {}", self.node_address(0)),
+            )
+        })?;
+        wait_for_port(&address.to_string(), Self::FAST_START_TIMEOUT).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(cluster = %self.name, tags = ?self.tags)))]
+    pub async fn start(&self, opts: Option<&[NodeStartOption]>) -> Result<(), IoError> {
+        let opts = opts.unwrap_or(&self.default_start_options);
+        self.validate_config().await?;
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            node.start(Some(opts)).await?;
+        }
+        let nowait = opts
+            .iter()
+            .any(|opt| matches!(opt, NodeStartOption::NOWAIT));
+        if !nowait {
+            self.check_ports().await?;
+        }
+        if self.password_auth {
+            self.bootstrap_password_auth().await?;
+        }
+        Ok(())
+    }
+
+    /// Fails with every node whose ports are still closed once ccm reports the cluster up,
+    /// rather than leaving it to the first driver query. Skipped in dry-run mode and in a
+    /// network namespace, which the probes can't reach.
+    async fn check_ports(&self) -> Result<(), IoError> {
+        let Some(timeout) = self.port_check_timeout else {
+            return Ok(());
+        };
+        if self.logged_cmd.is_dry_run() || self.network_namespace.is_some() {
+            return Ok(());
+        }
+        let mut nodes = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                nodes.push(node);
+            }
+        }
+        let results =
+            futures::future::join_all(nodes.iter().map(|node| node.wait_for_ports(timeout))).await;
+        let mut errors: Vec<IoError> = results.into_iter().filter_map(Result::err).collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(IoError::new(
+                std::io::ErrorKind::TimedOut,
+                AggregatedError(errors.iter().map(ToString::to_string).collect()),
+            )),
+        }
+    }
+
+    /// Whether something other than this cluster holds sockets on its IP prefix. Only
+    /// meaningful while the cluster is stopped, e.g. when reusing a persisted cluster.
+    pub async fn ip_prefix_occupied(&self) -> Result<bool, IoError> {
+        let range = match format!("{}1", self.ip_prefix).parse::<Ipv6Addr>() {
+            Ok(address) => ipv6_range(address),
+            Err(_) => self.ip_prefix.clone(),
+        };
+        Ok(ip_range::used_ip_prefixes().await?.contains(&range))
+    }
+
+    /// Moves a stopped persisted cluster whose IP prefix was taken by something else,
+    /// typically after a host reboot, to a free sniffed prefix and leaves it stopped; does
+    /// nothing if the prefix is still free. [`ensure`](Cluster::ensure) and
+    /// [`load`](Cluster::load) call it before the cluster is started.
+    pub async fn readdress_if_occupied(&mut self) -> Result<(), IoError> {
+        if self.ip_prefix_occupied().await? {
+            let new_prefix = self.reserve_ip_prefix().await?;
+            self.move_to_prefix(new_prefix, true).await?;
+        }
+        Ok(())
+    }
+
+    async fn reserve_ip_prefix(&self) -> Result<String, IoError> {
+        if self.is_ipv6() {
+            self.ip_range_allocator().reserve_ipv6().await
+        } else {
+            self.ip_range_allocator().reserve().await
+        }
+    }
+
+    /// Moves the cluster to `new_prefix`, or to a free sniffed one: stops it, rewrites
+    /// listen/rpc/broadcast addresses and seeds in ccm's `cluster.conf`, every `node.conf`
+    /// and every node server config, then starts it again.
+    pub async fn readdress(&mut self, new_prefix: Option<&str>) -> Result<(), IoError> {
+        self.stop().await.ok();
+        let sniffed = new_prefix.is_none();
+        let new_prefix = match new_prefix {
+            Some(prefix) => normalize_ip_prefix(prefix),
+            None => self.reserve_ip_prefix().await?,
+        };
+        self.move_to_prefix(new_prefix, sniffed).await?;
+        self.start(None).await
+    }
+
+    /// Rewrites node addresses and configs of the stopped cluster from the current IP prefix
+    /// to `new_prefix`, `sniffed` if reserved through [`IpRangeAllocator`].
+    async fn move_to_prefix(&mut self, new_prefix: String, sniffed: bool) -> Result<(), IoError> {
+        let old_prefix = self.ip_prefix.clone();
+        self.logged_cmd
+            .log_event("readdress", &format!("{} -> {}", old_prefix, new_prefix))
+            .await;
+
+        for node in self.nodes.iter() {
+            node.write().await.readdress(&old_prefix, &new_prefix);
+        }
+        self.rewrite_config_files(|content| replace_ip_prefix(content, &old_prefix, &new_prefix))
+            .await?;
+
+        self.release_ip_prefix();
+        self.ip_prefix = new_prefix;
+        self.sniffed_ip_prefix = sniffed;
+        self.write_hosts_file().await
+    }
+
+    /// Packs the stopped cluster, configs and node data, into the gzipped tarball `path`
+    /// together with its [`state_file`](Cluster::state_file), e.g. to hand a failure state
+    /// over to someone else who restores it with [`import_archive`](Cluster::import_archive).
+    pub async fn export_archive(&self, path: impl AsRef<Path>) -> Result<(), IoError> {
+        self.ensure_stopped("exporting").await?;
+        self.save_state().await?;
+        let path = path.as_ref().to_string_lossy();
+        let state_file = format!("{}.state.json", self.name);
+        // Absolute, since tar resolves a relative `-C` against the one before it.
+        let config_dir = std::path::absolute(&self.ccm.config_dir)?;
+        let install_directory = std::path::absolute(&self.install_directory)?;
+        let args = [
+            "-czf",
+            &path,
+            "-C",
+            &config_dir.to_string_lossy(),
+            &self.name,
+            "-C",
+            &install_directory.to_string_lossy(),
+            &state_file,
+        ];
+        self.ccm.executor().run_command("tar", &args, None).await?;
+        Ok(())
+    }
+
+    /// Unpacks an archive made by [`export_archive`](Cluster::export_archive) into
+    /// `install_directory` and moves the cluster to a free IP prefix there, rewriting the
+    /// paths and addresses in its configs. An `install_dir` in ccm's version repository of
+    /// the exporting machine, `~/.ccm/`, is moved to the one of this machine, which must have
+    /// the version installed. The cluster keeps its name and is started without
+    /// [`init`](Cluster::init).
+    pub async fn import_archive(
+        path: impl AsRef<Path>,
+        install_directory: &str,
+    ) -> Result<Self, IoError> {
+        Self::import(path.as_ref(), install_directory, None).await
+    }
+
+    /// Same as [`import_archive`](Cluster::import_archive), unpacking the archive and running
+    /// the imported cluster through `executor`, the way
+    /// [`with_executor`](Cluster::with_executor) does. A remote executor needs
+    /// `install_directory` mounted at the same path on both machines; the import fails with
+    /// `Unsupported` otherwise.
+    pub async fn import_archive_with_executor(
+        path: impl AsRef<Path>,
+        install_directory: &str,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Result<Self, IoError> {
+        Self::import(path.as_ref(), install_directory, Some(executor)).await
+    }
+
+    async fn import(
+        path: &Path,
+        install_directory: &str,
+        executor: Option<Arc<dyn CommandExecutor>>,
+    ) -> Result<Self, IoError> {
+        static NEXT_IMPORT: AtomicUsize = AtomicUsize::new(1);
+
+        tokio::fs::create_dir_all(install_directory).await?;
+        let staging = Path::new(install_directory).join(format!(
+            ".import-{}-{}",
+            std::process::id(),
+            NEXT_IMPORT.fetch_add(1, Ordering::SeqCst)
+        ));
+        tokio::fs::create_dir(&staging).await?;
+        let unpacker = executor
+            .clone()
+            .unwrap_or_else(|| Arc::new(LoggedCmd::new()));
+        let imported =
+            Self::unpack_archive(unpacker.as_ref(), path, install_directory, &staging).await;
+        tokio::fs::remove_dir_all(&staging).await.ok();
+        let (state_file, old_directory) = imported?;
+
+        let mut cluster = Self::load_state_file(&state_file, executor).await?;
+        let cluster_directory = cluster.directory();
+        let new_directory = cluster_directory.to_string_lossy();
+        let home = cluster
+            .ccm
+            .executor()
+            .run_command("sh", &["-c", "printf %s \"$HOME\""], None)
+            .await?
+            .stdout;
+        let ccm_home = Path::new(home.trim_end()).join(".ccm");
+        cluster
+            .rewrite_config_files(|content| {
+                let content = replace_path(content, &old_directory, &new_directory);
+                rebase_install_dir(&content, &ccm_home.to_string_lossy())
+            })
+            .await?;
+        // The hosts file of the exporting machine means nothing here.
+        cluster.hostname_domain = None;
+        cluster.hosts_file = None;
+        let new_prefix = match cluster.is_ipv6() {
+            true => cluster.ip_range_allocator().reserve_ipv6().await?,
+            false => cluster.ip_range_allocator().reserve().await?,
+        };
+        cluster.move_to_prefix(new_prefix, true).await?;
+        cluster.save_state().await?;
+        Ok(cluster)
+    }
+
+    /// Extracts `archive` into `staging`, moves the cluster directory into a config directory
+    /// of its own in `install_directory` and writes its state file there, pointing at the
+    /// new directory. Returns the state file and the directory the cluster was exported from.
+    async fn unpack_archive(
+        executor: &dyn CommandExecutor,
+        archive: &Path,
+        install_directory: &str,
+        staging: &Path,
+    ) -> Result<(PathBuf, String), IoError> {
+        let archive = archive.to_string_lossy();
+        let staging_path = staging.to_string_lossy();
+        // The staged files are read here, so a remote machine must unpack them into a
+        // directory shared at the same path, see `SshExecutor`.
+        if executor.is_remote()
+            && !executor
+                .run_command(
+                    "test",
+                    &["-d", &staging_path],
+                    run_options!(allow_failure = Some(true)),
+                )
+                .await?
+                .success()
+        {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                format!(
+                    "{} is not shared with the remote machine: importing through it needs the \
+                     install directory mounted at the same path on both machines",
+                    install_directory
+                ),
+            ));
+        }
+        executor
+            .run_command("tar", &["-xzf", &archive, "-C", &staging_path], None)
+            .await?;
+        let mut state_file = None;
+        let mut entries = tokio::fs::read_dir(staging).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().ends_with(".state.json") {
+                state_file = Some(entry.path());
+            }
+        }
+        let invalid = |what: &str| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!("archive {}: {}", archive, what),
+            )
+        };
+        let state_file = state_file.ok_or_else(|| invalid("no cluster state file"))?;
+        let mut state: Value = serde_json::from_str(&tokio::fs::read_to_string(&state_file).await?)
+            .map_err(|e| invalid(&e.to_string()))?;
+        let (Some(name), Some(old_install_directory)) = (
+            state["name"].as_str().map(str::to_string),
+            state["install_directory"].as_str().map(str::to_string),
+        ) else {
+            return Err(invalid("state file lacks the cluster name or directory"));
+        };
+
+        let config_dir = Path::new(install_directory).join(&name);
+        if config_dir.exists() {
+            return Err(IoError::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already exists", config_dir.display()),
+            ));
+        }
+        tokio::fs::create_dir(&config_dir).await?;
+        tokio::fs::rename(staging.join(&name), config_dir.join(&name)).await?;
+        tokio::fs::write(config_dir.join("CURRENT"), format!("{}\n", name)).await?;
+        let old_config_dir = state["config_dir"]
+            .as_str()
+            .map_or(old_install_directory, str::to_string);
+        state["install_directory"] = json!(install_directory);
+        state["config_dir"] = json!(config_dir);
+        let new_state_file = Path::new(install_directory).join(format!("{}.state.json", name));
+        tokio::fs::write(&new_state_file, format!("{:#}\n", state)).await?;
+        let old_directory = Path::new(&old_config_dir).join(&name);
+        Ok((new_state_file, old_directory.to_string_lossy().into_owned()))
+    }
+
+    /// Fails with `ResourceBusy` while any active node runs.
+    async fn ensure_stopped(&self, action: &str) -> Result<(), IoError> {
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) && node.process_running().await? {
+                return Err(IoError::new(
+                    std::io::ErrorKind::ResourceBusy,
+                    format!(
+                        "{} is running, stop {} before {} it",
+                        node.name, self.name, action
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `rewrite` to ccm's `cluster.conf`, every `node.conf` and every node server
+    /// config; missing files are skipped.
+    async fn rewrite_config_files(&self, rewrite: impl Fn(&str) -> String) -> Result<(), IoError> {
+        let mut files = vec![self.directory().join("cluster.conf")];
+        for node in self.nodes.iter() {
+            let directory = node.read().await.directory();
+            files.push(directory.join("node.conf"));
+            if let Ok(mut entries) = tokio::fs::read_dir(directory.join("conf")).await {
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().is_some_and(|ext| ext == "yaml") {
+                        files.push(path);
+                    }
+                }
+            }
+        }
+        for file in files {
+            match tokio::fs::read_to_string(&file).await {
+                Ok(content) => tokio::fs::write(&file, rewrite(&content)).await?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies this stopped cluster, node data included, into a new cluster `new_name` in the
+    /// same install directory on a free IP prefix, e.g. to duplicate a pre-loaded dataset for
+    /// every test variant instead of loading it again. The copy is already initialized: start
+    /// it without [`init`](Cluster::init). Its nodes keep the server-side cluster name they
+    /// persisted, and learn their new addresses from the rewritten configs on start.
+    pub async fn clone_to(&self, new_name: &str) -> Result<Cluster, IoError> {
+        if !self.host_addresses.is_empty() || self.network_namespace.is_some() {
+            return Err(IoError::new(
+                std::io::ErrorKind::Unsupported,
+                "only clusters on an IP prefix of the host can be cloned",
+            ));
+        }
+        self.ensure_stopped("cloning").await?;
+        let source_directory = self.directory();
+        let target_config_dir = Path::new(&self.install_directory).join(new_name);
+        if target_config_dir.exists() {
+            return Err(IoError::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target_config_dir.display()),
+            ));
+        }
+
+        let mut clone = Self::with_ip_strategy(
+            new_name.to_string(),
+            self.version.clone(),
+            Arc::new(SniffedLoopback {
+                ipv6: self.is_ipv6(),
+            }),
+            vec![],
+            self.install_directory.clone(),
+            self.scylla,
+        )
+        .await?;
+        clone.ip_layout = self.ip_layout;
+        clone.default_node_smp = self.default_node_smp;
+        clone.default_node_memory = self.default_node_memory;
+        clone.default_node_config = self.default_node_config.clone();
+        clone.dc_default_configs = self.dc_default_configs.clone();
+        clone.dc_seeds = self.dc_seeds.clone();
+        clone.default_node_env = self.default_node_env.clone();
+        clone.partitioner = self.partitioner;
+        clone.replication_mode = self.replication_mode;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::DELETED) {
+                continue;
+            }
+            let copy = clone
+                .add_node_with_rack(Some(node.datacenter_id), node.rack.clone())
+                .await
+                .clone();
+            if let Some(suffix) = self.address_suffixes.get(index) {
+                *clone.address_suffixes.last_mut().unwrap() = *suffix;
+            }
+            let address = clone.node_address(clone.nodes.len() - 1);
+            let mut copy = copy.write().await;
+            copy.name = node.name.clone();
+            copy.node_id = node.node_id;
+            copy.version = node.version.clone();
+            copy.seed = node.seed;
+            copy.smp = node.smp;
+            copy.memory = node.memory;
+            copy.config = node.config.clone();
+            readdress_config(&mut copy.config, &self.ip_prefix, &clone.ip_prefix);
+            copy.env = node.env.clone();
+            copy.ports = node.ports;
+            copy.address = Some(address);
+        }
+
+        if let Err(e) = self.copy_into(&clone, &source_directory).await {
+            clone.destroyed = true;
+            clone.release_ip_prefix();
+            tokio::fs::remove_dir_all(&target_config_dir).await.ok();
+            return Err(e);
+        }
+        Ok(clone)
+    }
+
+    /// Copies the cluster directory `source_directory` into `clone` and points the copy at
+    /// the name, directory and IP prefix of `clone`.
+    async fn copy_into(&self, clone: &Cluster, source_directory: &Path) -> Result<(), IoError> {
+        let new_name = &clone.name;
+        clone
+            .logged_cmd
+            .log_event("clone", &format!("copying {}", source_directory.display()))
+            .await;
+        let target_directory = clone.directory();
+        copy_directory(source_directory, &target_directory).await?;
+        tokio::fs::write(
+            Path::new(&clone.ccm.config_dir).join("CURRENT"),
+            format!("{}\n", new_name),
+        )
+        .await?;
+        let source_path = source_directory.to_string_lossy();
+        let target_path = target_directory.to_string_lossy();
+        clone
+            .rewrite_config_files(|content| {
+                let content = replace_path(content, &source_path, &target_path);
+                replace_ip_prefix(&content, &self.ip_prefix, &clone.ip_prefix)
+            })
+            .await?;
+        let cluster_conf = target_directory.join("cluster.conf");
+        let content: String = tokio::fs::read_to_string(&cluster_conf)
+            .await?
+            .lines()
+            .map(|line| match line.starts_with("name:") {
+                true => format!("name: {}\n", new_name),
+                false => format!("{}\n", line),
+            })
+            .collect();
+        tokio::fs::write(&cluster_conf, content).await
+    }
+
+    /// Snapshots every active node under `tag`, see [`Node::snapshot`].
+    pub async fn snapshot(&self, tag: &str) -> Result<(), IoError> {
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                node.snapshot(tag).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores snapshot `tag` on every active node of the stopped cluster, see
+    /// [`Node::restore_snapshot`]; start the cluster afterwards.
+    pub async fn restore_snapshot(&self, tag: &str) -> Result<(), IoError> {
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                node.restore_snapshot(tag).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drains every active node at once; failures, e.g. of nodes that are down already,
+    /// are only logged.
+    async fn drain(&self) {
+        let mut nodes = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                nodes.push(node);
+            }
+        }
+        let results = futures::future::join_all(nodes.iter().map(|node| async {
+            let args = [&node.name, "nodetool", "drain"];
+            node.ccm
+                .run(&args, run_options!(env = node.get_ccm_env()))
+                .await
+                .map_err(|e| format!("failed to drain {}: {}", node.name, e))
+        }))
+        .await;
+        for error in results.into_iter().filter_map(Result::err) {
+            self.logged_cmd.log_event("warning", &error).await;
+        }
+    }
+
+    /// Takes the stopped cluster out of ccm without deleting it: its directory moves to
+    /// `<install_directory>/kept/<test label>/<name>`, suffixed if that is taken, where
+    /// ccm doesn't look for clusters.
+    async fn keep_data(&self) -> Result<(), IoError> {
+        let mut kept = Path::new(&self.install_directory).join("kept");
+        if let Some(label) = test_context::label() {
+            kept.push(label);
+        }
+        tokio::fs::create_dir_all(&kept).await?;
+        let mut target = kept.join(&self.name);
+        for attempt in 2.. {
+            if !target.exists() {
+                break;
+            }
+            target = kept.join(format!("{}-{}", self.name, attempt));
+        }
+        tokio::fs::rename(self.directory(), &target).await?;
+        // The rest of what `ccm remove` does.
+        if self.is_active().await {
+            tokio::fs::remove_file(Path::new(&self.ccm.config_dir).join("CURRENT")).await?;
+        }
+        self.logged_cmd
+            .log_event(
+                "kept",
+                &format!("{} moved to {}", self.name, target.display()),
+            )
+            .await;
+        Ok(())
+    }
+
+    /// Stops the active nodes `filter` selects, all at once, e.g. a quorum-breaking subset.
+    /// Every node is attempted; failures are reported together.
+    pub async fn stop_nodes(&self, filter: impl Fn(&Node) -> bool) -> Result<(), IoError> {
+        let mut nodes = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) && filter(&node) {
+                nodes.push(node);
+            }
+        }
+        let results = futures::future::join_all(nodes.iter().map(|node| async {
+            node.stop()
+                .await
+                .map_err(|e| IoError::new(e.kind(), format!("{}: {}", node.name, e)))
+        }))
+        .await;
+        let mut errors: Vec<IoError> = results.into_iter().filter_map(Result::err).collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(IoError::other(AggregatedError(
+                errors.iter().map(ToString::to_string).collect(),
+            ))),
+        }
+    }
+
+    /// Stops every node of datacenter `datacenter_id`, e.g. for multi-DC failover tests.
+    pub async fn stop_datacenter(&self, datacenter_id: i32) -> Result<(), IoError> {
+        self.stop_nodes(|node| node.datacenter_id == datacenter_id)
+            .await
+    }
+
+    /// Health of every active node right now: its `ccm status`, whether its CQL and storage
+    /// ports accept connections and, with [`set_health_check_cql`](Cluster::set_health_check_cql),
+    /// whether it answers `SELECT now()`. `ccm status` reports on ccm's active cluster, see
+    /// [`make_active`](Cluster::make_active).
+    pub async fn health(&self) -> Result<HealthReport, IoError> {
+        self.ccm.invalidate_query_cache();
+        let status = self.ccm.run(&["status"], None).await?;
+        // ccm reports on its active cluster, which in a config dir shared with other
+        // clusters may not be this one.
+        if let Some(active) = health::parse_ccm_status_cluster(&status.stdout)
+            && active != self.name
+        {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "ccm's active cluster in {} is {}, not {}, see Cluster::make_active",
+                    self.ccm.config_dir, active, self.name
+                ),
+            ));
+        }
+        let statuses = health::parse_ccm_status(&status.stdout);
+        let credentials = self.credentials();
+        let mut nodes = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                nodes.push(node);
+            }
+        }
+        let reports = futures::future::join_all(nodes.iter().map(|node| async {
+            let ccm_status = statuses.get(&node.name).cloned();
+            let ports_open = match &self.network_namespace {
+                Some(netns) => node.ports_open_through(netns.as_ref()).await,
+                None => node.wait_for_ports(Duration::ZERO).await.is_ok(),
+            };
+            let cql = match self.health_check_cql {
+                true if ports_open => Some(
+                    node.cqlsh("SELECT now() FROM system.local", credentials.as_ref())
+                        .await
+                        .is_ok(),
+                ),
+                true => Some(false),
+                false => None,
+            };
+            NodeHealthReport::new(node.name.clone(), ccm_status, ports_open, cql)
+        }))
+        .await;
+        Ok(HealthReport { nodes: reports })
+    }
+
+    /// Polls [`health`](Cluster::health) until every active node is up. Past `timeout`, fails
+    /// with a `TimedOut` error carrying the last [`HealthReport`], telling the nodes that are
+    /// down from the ones stuck starting.
+    pub async fn wait_healthy(&self, timeout: Duration) -> Result<HealthReport, IoError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let report = self.health().await?;
+            if report.healthy() {
+                return Ok(report);
+            }
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(IoError::new(std::io::ErrorKind::TimedOut, report));
+            }
+            tokio::time::sleep(remaining.min(Duration::from_secs(1))).await;
+        }
+    }
+
+    /// Starts checking every `interval` whether the servers of the current nodes still run,
+    /// reporting those that died without being stopped through the crate, e.g.
+    /// `node_1_2 died at 12:03:05 UTC, see .../node_1_2/logs/system.log`. Nodes added later
+    /// are not watched.
+    pub fn watch_crashes(&self, interval: Duration) -> CrashWatchdog {
+        CrashWatchdog::watch(self.nodes.clone(), self.logged_cmd.clone(), interval)
+    }
+
+    /// Adds and decommissions nodes until every datacenter in `targets`, `(datacenter_id,
+    /// node count)` pairs, has the given number of active nodes; other datacenters are left
+    /// alone. New nodes are created and started one at a time, before any node is
+    /// decommissioned, newest first, so that the cluster never dips below the smaller of
+    /// both sizes. `progress` is called after every node.
+    pub async fn scale(
+        &mut self,
+        targets: &[(i32, usize)],
+        mut progress: impl FnMut(&ScaleProgress),
+    ) -> Result<(), IoError> {
+        let mut additions = vec![];
+        let mut removals = vec![];
+        for &(datacenter_id, target) in targets {
+            if datacenter_id < 1 {
+                return Err(IoError::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("invalid datacenter {}", datacenter_id),
+                ));
+            }
+            let mut current = vec![];
+            for node in self.nodes.iter() {
+                let locked = node.read().await;
+                if matches!(locked.status, NodeStatus::ACTIVE)
+                    && locked.datacenter_id == datacenter_id
+                {
+                    current.push((locked.node_id, node.clone()));
+                }
+            }
+            current.sort_by_key(|(node_id, _)| std::cmp::Reverse(*node_id));
+            additions.extend(std::iter::repeat_n(
+                datacenter_id,
+                target.saturating_sub(current.len()),
+            ));
+            removals.extend(current.into_iter().skip(target).map(|(_, node)| node));
+        }
+        let total = additions.len() + removals.len();
+        let mut done = 0;
+        let mut report = |action, node: &str| {
+            done += 1;
+            let step = ScaleProgress {
+                action,
+                node: node.to_string(),
+                done,
+                total,
+            };
+            progress(&step);
+            step
+        };
+        let replication_config = self.replication_config()?;
+        for datacenter_id in additions {
+            let node = self.add_node(Some(datacenter_id)).await.clone();
+            self.prepare_nodes(std::slice::from_ref(&node)).await?;
+            self.init_node(&node, replication_config.as_ref()).await?;
+            let node = node.read().await;
+            node.start(None).await?;
+            let step = report(ScaleAction::Add, &node.name);
+            self.logged_cmd
+                .log_event(
+                    "scale",
+                    &format!("added {} ({}/{})", node.name, step.done, total),
+                )
+                .await;
+        }
+        for node in removals {
+            let mut node = node.write().await;
+            node.decommission().await?;
+            let step = report(ScaleAction::Decommission, &node.name);
+            self.logged_cmd
+                .log_event(
+                    "scale",
+                    &format!("decommissioned {} ({}/{})", node.name, step.done, total),
+                )
+                .await;
+        }
+        if total > 0 {
+            self.save_state().await?;
+        }
+        Ok(())
+    }
+
+    /// Native transport addresses of the active nodes, each on its own port, for load
+    /// tools connecting over CQL.
+    async fn cql_endpoints(&self) -> Result<Vec<SocketAddr>, IoError> {
+        let mut endpoints = vec![];
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if !matches!(node.status, NodeStatus::ACTIVE) {
+                continue;
+            }
+            if let Some(ip) = node.ip() {
+                endpoints.push(SocketAddr::new(ip, node.native_port()));
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} has no node to send load to", self.name),
+            ));
+        }
+        Ok(endpoints)
+    }
+
+    /// Runs cassandra-stress with `ccm stress` against the active nodes, on their native
+    /// port and with the cluster's [`credentials`](Cluster::credentials) unless the profile
+    /// sets `-node`, `-port` or `-mode` itself; the password is masked in the log. Output is
+    /// logged line by line while it runs. ccm runs it for its active cluster, so this fails
+    /// with `InvalidInput` unless the cluster [`is_active`](Cluster::is_active). Fails with
+    /// `InvalidData` if no summary was printed; dry runs return an empty one.
+    pub async fn stress(&self, profile: StressProfile) -> Result<StressSummary, IoError> {
+        let mut args = profile.stress_args();
+        let given = |option: &str| profile.args.iter().any(|arg| arg == option);
+        let endpoints = self.cql_endpoints().await?;
+        if !given("-node") {
+            let addresses: Vec<String> = endpoints.iter().map(|e| e.ip().to_string()).collect();
+            args.extend(["-node".to_string(), addresses.join(",")]);
+        }
+        if !given("-port") {
+            args.extend([
+                "-port".to_string(),
+                format!("native={}", endpoints[0].port()),
+            ]);
+        }
+        if let Some(credentials) = self.credentials().filter(|_| !given("-mode")) {
+            let password = format!("password={}", credentials.password);
+            self.logged_cmd.redact(password.clone());
+            args.extend([
+                "-mode".to_string(),
+                "native".to_string(),
+                "cql3".to_string(),
+                format!("user={}", credentials.username),
+                password,
+            ]);
+        }
+        if !self.is_active().await {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not ccm's active cluster in {}, see Cluster::make_active",
+                    self.name, self.ccm.config_dir
+                ),
+            ));
+        }
+        let mut ccm_args = vec!["stress"];
+        ccm_args.extend(args.iter().map(String::as_str));
+        let result = self
+            .ccm
+            .run(
+                &ccm_args,
+                run_options!(timeout = profile.timeout, stdout_lines = profile.output),
+            )
+            .await?;
+        if self.logged_cmd.is_dry_run() {
+            return Ok(StressSummary::default());
+        }
+        StressSummary::parse(&result.stdout).ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "cassandra-stress printed no summary: {}",
+                    result.stdout.lines().last().unwrap_or_default()
+                ),
+            )
+        })
+    }
+
+    /// Runs scylla-bench against the active nodes, with the cluster's
+    /// [`credentials`](Cluster::credentials) unless the workload sets `-nodes` or
+    /// `-username` itself; the password is masked in the log. Output is logged line by line
+    /// while it runs. Fails with
+    /// `InvalidData` if no results were printed; dry runs return empty ones.
+    pub async fn scylla_bench(&self, workload: BenchWorkload) -> Result<BenchSummary, IoError> {
+        let mut args = workload.bench_args();
+        let given = |option: &str| workload.args.iter().any(|arg| arg == option);
+        if !given("-nodes") {
+            let nodes: Vec<String> = self
+                .cql_endpoints()
+                .await?
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            args.extend(["-nodes".to_string(), nodes.join(",")]);
+        }
+        if let Some(credentials) = self.credentials().filter(|_| !given("-username")) {
+            self.logged_cmd
+                .redact(format!("-password {}", credentials.password));
+            args.extend([
+                "-username".to_string(),
+                credentials.username,
+                "-password".to_string(),
+                credentials.password,
+            ]);
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let result = self
+            .ccm
+            .executor()
+            .run_command(
+                &workload.program,
+                &args,
+                run_options!(timeout = workload.timeout, stdout_lines = workload.output),
+            )
+            .await?;
+        if self.logged_cmd.is_dry_run() {
+            return Ok(BenchSummary::default());
+        }
+        BenchSummary::parse(&result.stdout).ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "scylla-bench printed no results: {}",
+                    result.stdout.lines().last().unwrap_or_default()
+                ),
+            )
+        })
+    }
+
+    /// Peers as `nodetool status` on the first active node that answers sees them, in the
+    /// order printed, e.g. to check that a node left or that racks are balanced.
+    pub async fn ring_status(&self) -> Result<Vec<PeerInfo>, IoError> {
+        let mut last_error = None;
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if !matches!(node.status, NodeStatus::ACTIVE) {
+                continue;
+            }
+            match node.nodetool(&["status"]).await {
+                Ok(output) => return Ok(nodetool::parse_status(&output)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} has no active node", self.name),
+            )
+        }))
+    }
+
+    /// Streams the SSTables in `data_dir` into the cluster with sstableloader, e.g. data
+    /// written by another version in migration tests. The directory must be laid out as
+    /// `<keyspace>/<table>`, which sstableloader loads into, and the table must exist. The
+    /// sstableloader of the installed version is used, see [`tools::find_tool`], and pointed
+    /// at the active nodes, on their native ports, with the cluster's
+    /// [`credentials`](Cluster::credentials); the password is masked in the log.
+    pub async fn sstableloader(
+        &self,
+        data_dir: impl AsRef<Path>,
+        options: SstableLoaderOptions,
+    ) -> Result<(), IoError> {
+        let data_dir = data_dir.as_ref();
+        if !self.logged_cmd.is_dry_run() && !data_dir.is_dir() {
+            return Err(IoError::new(
+                std::io::ErrorKind::NotFound,
+                format!("no SSTable directory {}", data_dir.display()),
+            ));
+        }
+        let program = match &options.program {
+            Some(program) => program.clone(),
+            None => {
+                let conf_path = self.directory().join("cluster.conf");
+                let conf = tokio::fs::read_to_string(&conf_path).await.map_err(|e| {
+                    IoError::new(e.kind(), format!("reading {}: {}", conf_path.display(), e))
+                })?;
+                let install_dir = ip_range::conf_value(&conf, "install_dir").ok_or_else(|| {
+                    IoError::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("{} has no install_dir", conf_path.display()),
+                    )
+                })?;
+                let executor = self.ccm.executor();
+                tools::find_tool(executor.as_ref(), Path::new(install_dir), "sstableloader").await?
+            }
+        };
+        let endpoints = self.cql_endpoints().await?;
+        let port = endpoints[0].port();
+        // sstableloader takes one `-p` for all nodes; nodes on other ports get theirs
+        // appended to the address.
+        let nodes: Vec<String> = endpoints
+            .iter()
+            .map(|endpoint| match endpoint.port() == port {
+                true => endpoint.ip().to_string(),
+                false => endpoint.to_string(),
+            })
+            .collect();
+        let mut args = vec![
+            "-d".to_string(),
+            nodes.join(","),
+            "-p".to_string(),
+            port.to_string(),
+        ];
+        if let Some(credentials) = self.credentials() {
+            self.logged_cmd
+                .redact(format!("-pw {}", credentials.password));
+            args.extend([
+                "-u".to_string(),
+                credentials.username,
+                "-pw".to_string(),
+                credentials.password,
+            ]);
+        }
+        args.extend(options.loader_args());
+        args.push(data_dir.to_string_lossy().into_owned());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.ccm
+            .executor()
+            .run_command(
+                &program.to_string_lossy(),
+                &args,
+                run_options!(timeout = options.timeout, stdout_lines = options.output),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Directory ccm keeps the cluster in: `<install_directory>/<name>/<name>`, inside the
+    /// ccm config dir of its own the cluster gets, so that clusters sharing an install
+    /// directory never switch ccm's active cluster under each other. Clusters
+    /// [`load`](Cluster::load)ed from a shared config dir stay in `<config dir>/<name>`.
+    pub fn directory(&self) -> PathBuf {
+        Path::new(&self.ccm.config_dir).join(&self.name)
+    }
+
+    /// What stays behind once ccm removed the cluster, removed by
+    /// [`destroy`](Cluster::destroy) and the TTL reaper: the state file and the ccm config
+    /// dir if it is the cluster's own.
+    fn leftover_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.state_file()];
+        if self.owns_config_dir() {
+            paths.push(PathBuf::from(&self.ccm.config_dir));
+        }
+        paths
+    }
+
+    /// Whether the ccm config dir is the cluster's own, removed along with it.
+    fn owns_config_dir(&self) -> bool {
+        Path::new(&self.ccm.config_dir) == Path::new(&self.install_directory).join(&self.name)
+    }
+
+    /// Whether this is ccm's active cluster in its config dir, the one node subcommands
+    /// (`ccm <node> ...`) act on. A cluster created by the crate has a config dir of its own
+    /// and stays active; one [`load`](Cluster::load)ed from a config dir shared with other
+    /// clusters loses that to any cluster created there, e.g. from a shell.
+    pub async fn is_active(&self) -> bool {
+        let current = Path::new(&self.ccm.config_dir).join("CURRENT");
+        tokio::fs::read_to_string(current)
+            .await
+            .is_ok_and(|current| current.trim() == self.name)
+    }
+
+    /// Makes this ccm's active cluster in its config dir (`ccm switch`), e.g. before node
+    /// commands once another cluster was created next to it in a shared config dir. Every
+    /// ccm command of the crate pins `--config-dir`, so clusters in other directories are
+    /// never affected.
+    pub async fn make_active(&self) -> Result<(), IoError> {
+        self.ccm.run(&["switch", &self.name], None).await?;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(cluster = %self.name, tags = ?self.tags)))]
+    pub async fn stop(&mut self) -> Result<(), IoError> {
+        if self.destroyed {
+            return Ok(());
+        }
+        self.request_stop().await;
+        match self.ccm.run(&["stop", &self.name], None).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Runs the stop hooks of the active nodes and tells a [`CrashWatchdog`] that their
+    /// servers are about to go away.
+    async fn request_stop(&self) {
+        for node in self.nodes.iter() {
+            let node = node.read().await;
+            if matches!(node.status, NodeStatus::ACTIVE) {
+                node.run_stop_hooks().await;
+            }
+            node.stop_requested.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Removes the loopback aliases added by `init`; failures are only logged since the
+    /// cluster itself is gone already.
+    async fn remove_loopback_aliases(&self) {
+        let aliases = std::mem::take(&mut *self.loopback_aliases.lock().unwrap());
+        let Some(escalation) = &self.loopback_escalation else {
+            return;
+        };
+        for address in aliases {
+            if let Err(e) =
+                loopback::remove_alias(self.ccm.executor().as_ref(), &address, escalation).await
+            {
+                self.logged_cmd
+                    .log_event(
+                        "warning",
+                        &format!("failed to remove alias {}: {}", address, e),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Destroys the cluster after `error` broke its setup, so that no half-built cluster is
+    /// left behind, and returns `error`; a failing destroy is only logged.
+    pub(crate) async fn discard_after(&mut self, error: IoError) -> IoError {
+        if let Err(e) = self.destroy().await {
+            self.logged_cmd
+                .log_event(
+                    "warning",
+                    &format!("failed to destroy {} after {}: {}", self.name, error, e),
+                )
+                .await;
+        }
+        error
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(cluster = %self.name, tags = ?self.tags)))]
+    pub async fn destroy(&mut self) -> Result<(), IoError> {
+        if self.destroyed {
+            return Ok(());
+        }
+        if self.policy == ClusterPolicy::KeepAlive {
+            self.save_state().await?;
+            self.destroyed = true;
+            self.logged_cmd
+                .log_event("keep-alive", &format!("left {} running", self.name))
+                .await;
+            return Ok(());
+        }
+        for hook in self.hooks.iter() {
+            if let Err(e) = hook.on_destroy(self).await {
+                self.logged_cmd
+                    .log_event(
+                        "warning",
+                        &format!("destroy hook of {} failed: {}", self.name, e),
+                    )
+                    .await;
+            }
+        }
+        let expired = self.cancel_ttl().await;
+        if expired {
+            self.logged_cmd
+                .log_event("ttl", &format!("{} was torn down by its TTL", self.name))
+                .await;
+        }
+        match self.destroy_mode {
+            _ if expired => {}
+            DestroyMode::Graceful => {
+                self.drain().await;
+                self.stop().await.ok();
+            }
+            DestroyMode::Fast => {
+                self.request_stop().await;
+                let args = ["stop", &self.name, "--not-gently"];
+                self.ccm.run(&args, None).await.ok();
+            }
+            DestroyMode::Stop | DestroyMode::KeepData => {
+                self.stop().await.ok();
+            }
+        }
+        let removed = match self.destroy_mode {
+            _ if expired => Ok(()),
+            DestroyMode::KeepData => self.keep_data().await,
+            _ => self.ccm.run(&["remove", &self.name], None).await.map(drop),
+        };
+        match removed {
+            Ok(()) => {
+                self.destroyed = true;
+                self.release_ip_prefix();
+                self.host_lease = None;
+                self.release_reserved_ports();
+                self.remove_loopback_aliases().await;
+                for path in self.leftover_paths() {
+                    match tokio::fs::metadata(&path).await {
+                        Ok(metadata) if metadata.is_dir() => {
+                            tokio::fs::remove_dir_all(&path).await.ok();
+                        }
+                        Ok(_) => {
+                            tokio::fs::remove_file(&path).await.ok();
+                        }
+                        Err(_) => {}
+                    }
+                }
+                if let Err(e) = self.heal().await {
+                    self.logged_cmd
+                        .log_event(
+                            "warning",
+                            &format!("failed to remove firewall rules: {}", e),
+                        )
+                        .await;
+                }
+                if let Some(hosts_file) = &self.hosts_file
+                    && let Err(e) = hosts_file.remove().await
+                {
+                    self.logged_cmd
+                        .log_event(
+                            "warning",
+                            &format!(
+                                "failed to remove hostnames from {}: {}",
+                                hosts_file.path().display(),
+                                e
+                            ),
+                        )
+                        .await;
+                }
+                if let Some(netns) = &self.network_namespace
+                    && let Err(e) = netns.delete().await
+                {
+                    self.logged_cmd
+                        .log_event(
+                            "warning",
+                            &format!("failed to delete network namespace {}: {}", netns.name, e),
+                        )
+                        .await;
+                }
+                for node in self.nodes.iter() {
+                    let mut node = node.write().await;
+                    node.proxy = None;
+                    node.mark_deleted();
+                }
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Addresses per rack under [`IpLayout::SubnetPerRack`], a `/27`.
+const RACK_BLOCK_SIZE: u32 = 32;
+
+/// CQL ports Scylla binds on its `rpc_address` besides the native transport one, moved off
+/// their defaults for nodes sharing the `::` wildcard, see `configure_dual_stack`.
+const DUAL_STACK_PORT_KEYS: [&str; 3] = [
+    "native_shard_aware_transport_port",
+    "native_transport_port_ssl",
+    "native_shard_aware_transport_port_ssl",
+];
+
+/// Ports the servers listen on by default: storage, SSL storage, JMX, CQL, CQL over SSL,
+/// Thrift, Scylla's REST API and its shard-aware CQL port.
+const SERVER_PORTS: [u16; 8] = [7000, 7001, 7199, 9042, 9142, 9160, 10000, 19042];
+
+/// Ports the nodes of all clusters of the process were given, so that they don't clash
+/// before the nodes bind them; hands out the fallback ports too.
+static RESERVED_PORTS: LazyLock<PortAllocator> = LazyLock::new(PortAllocator::default);
+
+/// Hostname of node `node_name` of cluster `cluster_name` in `domain`, see
+/// [`Cluster::map_hostnames`].
+fn node_hostname(node_name: &str, cluster_name: &str, domain: &str) -> String {
+    format!(
+        "{}.{}.{}",
+        node_name.replace('_', "-"),
+        cluster_name.replace('_', "-"),
+        domain
+    )
+}
+
+/// Strategy behind the `ip_prefix` argument of [`Cluster::new`]: the given prefix, or a
+/// sniffed one.
+fn ip_strategy_for(ip_prefix: Option<&str>) -> Arc<dyn IpStrategy> {
+    match ip_prefix {
+        Some(prefix) => Arc::new(FixedPrefix(prefix.to_string())),
+        None => Arc::new(SniffedLoopback::default()),
+    }
+}
+
+/// Copies directory `from` to `to`, which must not exist yet, recreating symlinks as
+/// symlinks.
+async fn copy_directory(from: &Path, to: &Path) -> Result<(), IoError> {
+    let mut pending = vec![(from.to_path_buf(), to.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        tokio::fs::create_dir(&to).await?;
+        let mut entries = tokio::fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let target = to.join(entry.file_name());
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                pending.push((entry.path(), target));
+            } else if file_type.is_symlink() {
+                copy_symlink(&entry.path(), &target).await?;
+            } else {
+                tokio::fs::copy(entry.path(), target).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recreates symlink `from` at `to`.
+#[cfg(unix)]
+async fn copy_symlink(from: &Path, to: &Path) -> Result<(), IoError> {
+    tokio::fs::symlink(tokio::fs::read_link(from).await?, to).await
+}
+
+/// Copies what symlink `from` points to, since creating symlinks may need privileges.
+#[cfg(not(unix))]
+async fn copy_symlink(from: &Path, to: &Path) -> Result<(), IoError> {
+    tokio::fs::copy(from, to).await.map(|_| ())
+}
+
+/// Regular files directly in `directory`.
+async fn list_files(directory: &Path) -> Result<Vec<PathBuf>, IoError> {
+    let mut files = vec![];
+    let mut entries = tokio::fs::read_dir(directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// Replaces path `from` in `text` with `to`, leaving longer names that start with it
+/// (`/tmp/ccm/a2` vs `/tmp/ccm/a`) alone.
+fn replace_path(text: &str, from: &str, to: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for (index, _) in text.match_indices(from) {
+        let longer = text[index + from.len()..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !longer {
+            result.push_str(&text[last..index]);
+            result.push_str(to);
+            last = index + from.len();
+        }
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+/// `install_dir` lines of ccm confs in `text` moved from the version repository in another
+/// machine's ccm home, `<home>/.ccm/`, to `ccm_home`; other install dirs are kept.
+fn rebase_install_dir(text: &str, ccm_home: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let rebased = line.strip_prefix("install_dir:").and_then(|dir| {
+            let (_, repository) = dir.split_once("/.ccm/")?;
+            Some(format!("install_dir: {}/{}", ccm_home, repository))
+        });
+        result.push_str(rebased.as_deref().unwrap_or(line));
+    }
+    result
+}
+
+/// Replaces IP addresses starting with `old_prefix` in `text`, leaving longer addresses that
+/// merely end with it (`10.127.0.1.` vs `127.0.1.`) alone.
+fn replace_ip_prefix(text: &str, old_prefix: &str, new_prefix: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for (index, _) in text.match_indices(old_prefix) {
+        let embedded = text[..index]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_hexdigit() || c == '.' || c == ':');
+        if !embedded {
+            result.push_str(&text[last..index]);
+            result.push_str(new_prefix);
+            last = index + old_prefix.len();
+        }
+    }
+    result.push_str(&text[last..]);
+    result
+}
+
+fn readdress_config(config: &mut ScyllaConfig, old_prefix: &str, new_prefix: &str) {
+    match config {
+        ScyllaConfig::String(value) => *value = replace_ip_prefix(value, old_prefix, new_prefix),
+        ScyllaConfig::List(items) => items
+            .iter_mut()
+            .for_each(|item| readdress_config(item, old_prefix, new_prefix)),
+        ScyllaConfig::Map(map) => map
+            .values_mut()
+            .for_each(|value| readdress_config(value, old_prefix, new_prefix)),
+        _ => {}
+    }
+}
+
+/// Polls `address` every 50ms until it accepts TCP connections or `timeout` expires.
+async fn wait_for_port(address: &str, timeout: Duration) -> Result<(), IoError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if TcpStream::connect(address).await.is_ok() {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(IoError::new(
+                std::io::ErrorKind::TimedOut,
+                format!(
+                    "{} did not accept connections within {:?}",
+                    address, timeout
+                ),
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_cluster_lifecycle() {
+    crate::require_capability!(crate::host_capabilities::Capability::Ccm);
+    let mut cluster = Cluster::new(
+        "test_cluster".to_string(),
+        "release:6.2".to_string(),
+        None,
+        vec![3],
+        "/tmp/ccm".to_string(),
+        true,
+    )
+    .await
+    .expect("Failed to create cluster");
+
+    cluster
+        .init(false)
+        .await
+        .expect("Failed to initialize cluster");
+    cluster.start(None).await.expect("Failed to start cluster");
+    {
+        let node = cluster.add_node(Some(2)).await.write().await;
+        node.init().await.expect("Failed to initialize node");
+        node.start(None).await.expect("Failed to start node");
+    }
+    cluster.stop().await.expect("Failed to stop cluster");
+    cluster.destroy().await.expect("Failed to destroy cluster");
+}
+
+#[test]
+fn test_node_env_is_merged_into_ccm_env() {
+    let mut node = Node::new(
+        1,
+        1,
+        true,
+        2,
+        0,
+        ScyllaConfig::default(),
+        Arc::new(LoggedCmd::new()),
+        "/tmp/ccm".to_string(),
+        "test_cluster".to_string(),
+    );
+    node.set_env("JAVA_HOME", "/opt/java");
+    node.set_env("SCYLLA_EXT_OPTS", "--ignored");
+
+    let env = node.get_ccm_env();
+    assert_eq!(env.get("JAVA_HOME").map(String::as_str), Some("/opt/java"));
+    assert_eq!(
+        env.get("SCYLLA_EXT_OPTS").map(String::as_str),
+        Some("--smp=2 --memory=1024M")
+    );
+}
+
+#[test]
+fn test_replication_mode_config_depends_on_version() {
+    let vnodes = ReplicationMode::Vnodes { num_tokens: 16 };
+    let flat = |mode: ReplicationMode, scylla, version| {
+        mode.config(scylla, version).unwrap().to_flat_string()
+    };
+    assert_eq!(
+        flat(vnodes, true, "release:2025.1"),
+        "num_tokens:16 tablets_mode_for_new_keyspaces:disabled"
+    );
+    assert_eq!(
+        flat(vnodes, true, "release:6.2"),
+        "enable_tablets:false num_tokens:16"
+    );
+    assert_eq!(flat(vnodes, false, "4.1.5"), "num_tokens:16");
+    assert_eq!(
+        flat(ReplicationMode::Tablets, true, "release:6.2"),
+        "enable_tablets:true"
+    );
+    assert_eq!(
+        flat(ReplicationMode::Tablets, true, "release:5.4"),
+        "experimental_features:[tablets,consistent-topology-changes]"
+    );
+    let err = ReplicationMode::Tablets
+        .config(true, "release:5.2")
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[tokio::test]
+async fn test_init_passes_partitioner_to_ccm_create() {
+    let mut cluster = test_cluster("partitioner", vec![1]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_partitioner(Partitioner::ByteOrdered);
+    cluster.set_loopback_aliases(None);
+    cluster.init(false).await.unwrap();
+
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(
+        recorded[0].args,
+        [
+            "create",
+            "partitioner",
+            "-v",
+            "release:6.2",
+            "-i",
+            &cluster.ip_prefix,
+            "--scylla",
+            "-p",
+            "org.apache.cassandra.dht.ByteOrderedPartitioner",
+            "--config-dir",
+            install_directory.join("partitioner").to_str().unwrap(),
+        ]
+    );
+    assert_eq!(recorded[1].args[..2], ["add", "node_1_1"]);
+}
+
+#[tokio::test]
+async fn test_commands_pin_config_dir() {
+    let mut cluster = test_cluster("pinned", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster.set_port_check_timeout(None);
+    cluster.init(false).await.unwrap();
+    cluster.start(None).await.unwrap();
+    assert!(!cluster.is_active().await);
+    let config_dir = format!("{}/pinned", cluster.install_directory);
+    cluster.make_active().await.unwrap();
+    cluster.nodes[0]
+        .read()
+        .await
+        .cqlsh("SELECT now() FROM system.local", None)
+        .await
+        .unwrap();
+    cluster.destroy().await.unwrap();
+
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert!(
+        recorded
+            .iter()
+            .any(|command| command.args[..2] == ["switch", "pinned"])
+    );
+    for command in recorded {
+        assert!(
+            command
+                .args
+                .windows(2)
+                .any(|pair| pair == ["--config-dir", config_dir.as_str()]),
+            "{:?}",
+            command.args
+        );
+    }
+    tokio::fs::create_dir_all(&config_dir).await.unwrap();
+    tokio::fs::write(Path::new(&config_dir).join("CURRENT"), "pinned\n")
+        .await
+        .unwrap();
+    assert!(cluster.is_active().await);
+}
+
+#[tokio::test]
+async fn test_node_port_overrides() {
+    let mut cluster = test_cluster("ports", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster.nodes[1].write().await.ports = NodePorts {
+        native: Some(19042),
+        storage: Some(17000),
+        jmx: Some(17199),
+        debug: None,
+    };
+    let allocator = PortAllocator::default();
+    cluster.allocate_ports(&allocator).await.unwrap();
+    assert!(cluster.nodes[0].read().await.ports.debug.is_some());
+    assert_eq!(allocator.allocated().len(), 5);
+    cluster.nodes[0].write().await.ports = NodePorts::default();
+    cluster.init(false).await.unwrap();
+
+    // The 7101 and 2101 defaults, unless another cluster of the test process pinned them.
+    let ports = cluster.nodes[0].read().await.ports;
+    let (jmx, debug) = (
+        ports.jmx.unwrap().to_string(),
+        ports.debug.unwrap().to_string(),
+    );
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(
+        recorded[1].args[..8],
+        [
+            "add",
+            "node_1_1",
+            "--data-center",
+            "dc1",
+            "--jmx-port",
+            &jmx,
+            "--remote-debug-port",
+            &debug
+        ]
+    );
+    assert_eq!(recorded[2].args[4..6], ["--jmx-port", "17199"]);
+    assert_eq!(
+        recorded[2].args[8..12],
+        [
+            "--binary-itf",
+            &format!("{}2:19042", cluster.ip_prefix),
+            "--storage-itf",
+            &format!("{}2:17000", cluster.ip_prefix)
+        ]
+    );
+    assert_eq!(cluster.nodes[1].read().await.native_port(), 19042);
+}
+
+#[tokio::test]
+async fn test_isolate_network() {
+    let mut cluster = test_cluster("netns", vec![1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster
+        .isolate_network(Escalation::Prefix(vec![]))
+        .await
+        .unwrap();
+    assert!(cluster.loopback_escalation.is_none());
+    cluster.init(false).await.unwrap();
+    cluster.destroy().await.unwrap();
+
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(recorded[0].args, ["netns", "add", "ccm-netns"]);
+    assert_eq!(
+        recorded[2].args[..4],
+        ["--net=/run/netns/ccm-netns", "--", "ccm", "create"]
+    );
+    let last = recorded.last().unwrap();
+    assert_eq!(last.args, ["netns", "delete", "ccm-netns"]);
+}
+
+#[tokio::test]
+async fn test_partition() {
+    let cluster = test_cluster("partition", vec![3]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    assert!(
+        cluster
+            .partition(&["node_1_1"], &["node_9_9"])
+            .await
+            .is_err()
+    );
+    cluster
+        .partition(&["node_1_1"], &["node_1_2", "node_1_3"])
+        .await
+        .unwrap();
+    let rules: Vec<_> = cluster
+        .firewall
+        .rules()
+        .into_iter()
+        .map(|rule| format!("{}>{}", rule.source.unwrap(), rule.destination))
+        .collect();
+    let prefix = &cluster.ip_prefix;
+    assert_eq!(
+        rules,
+        [
+            format!("{prefix}1>{prefix}2"),
+            format!("{prefix}2>{prefix}1"),
+            format!("{prefix}1>{prefix}3"),
+            format!("{prefix}3>{prefix}1"),
+        ]
+    );
+    cluster.heal().await.unwrap();
+    assert!(cluster.firewall.rules().is_empty());
+    assert_eq!(cluster.logged_cmd().recorded_commands().len(), 8);
+
+    let node = cluster.nodes[1].read().await;
+    node.block_port(9042, Verdict::Reset).await.unwrap();
+    node.block_port(7000, Verdict::Drop).await.unwrap();
+    node.unblock_port(9042).await.unwrap();
+    let rules = cluster.firewall.rules();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].destination, format!("{prefix}2"));
+    assert_eq!(rules[0].port, Some(7000));
+}
+
+#[tokio::test]
+async fn test_host_addresses() {
+    let mut cluster = test_cluster("host_addresses", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    let localhost = IpAddr::from(std::net::Ipv4Addr::LOCALHOST);
+    let err = cluster.use_host_addresses(vec![localhost]).await;
+    assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    let documentation = "192.0.2.1".parse().unwrap();
+    let err = cluster
+        .use_host_addresses(vec![localhost, documentation])
+        .await;
+    assert_eq!(
+        err.unwrap_err().kind(),
+        std::io::ErrorKind::AddrNotAvailable
+    );
+
+    if cfg!(target_os = "linux") {
+        let second = "127.0.241.7".parse().unwrap();
+        cluster
+            .use_host_addresses(vec![localhost, second])
+            .await
+            .unwrap();
+        assert_eq!(cluster.nodes[1].read().await.ip(), Some(second));
+        cluster.init(false).await.unwrap();
+        let recorded = cluster.logged_cmd().recorded_commands();
+        assert_eq!(recorded[2].args[8..10], ["--itfs", "127.0.241.7"]);
+
+        cluster.add_node(None).await;
+        assert!(cluster.init(false).await.is_err());
+    }
+}
+
+/// Cluster `name` of `topology` Scylla nodes for a unit test, in an install directory of
+/// its own under the temp dir, emptied first, on a free IP prefix. Dropping it neither
+/// destroys nor stops anything through ccm; it gives the prefix back and removes the install
+/// directory.
+#[cfg(test)]
+async fn test_cluster(name: &str, topology: Vec<i32>) -> TestCluster {
+    let install_directory = std::env::temp_dir().join(format!("ccm_binding_test_{}", name));
+    tokio::fs::remove_dir_all(&install_directory).await.ok();
+    let cluster = Cluster::new(
+        name.to_string(),
+        "release:6.2".to_string(),
+        None,
+        topology,
+        install_directory.to_string_lossy().into_owned(),
+        true,
+    )
+    .await
+    .unwrap();
+    TestCluster(cluster)
+}
+
+/// See [`test_cluster`].
+#[cfg(test)]
+struct TestCluster(Cluster);
+
+#[cfg(test)]
+impl std::ops::Deref for TestCluster {
+    type Target = Cluster;
+
+    fn deref(&self) -> &Cluster {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl std::ops::DerefMut for TestCluster {
+    fn deref_mut(&mut self) -> &mut Cluster {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        self.0.destroyed = true;
+        self.0.release_ip_prefix();
+        std::fs::remove_dir_all(&self.0.install_directory).ok();
+    }
+}
+
+/// Hands out a fixed prefix and records what is given back.
+#[cfg(test)]
+#[derive(Default)]
+struct RecordingStrategy {
+    released: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl IpStrategy for RecordingStrategy {
+    fn allocate<'a>(
+        &'a self,
+        request: &'a IpRequest<'a>,
+    ) -> futures::future::BoxFuture<'a, Result<IpAllocation, IoError>> {
+        Box::pin(async move {
+            assert_eq!(request.node_count, 3);
+            Ok(IpAllocation {
+                ip_prefix: "127.0.240.".to_string(),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn release(&self, allocation: &IpAllocation) {
+        self.released
+            .lock()
+            .unwrap()
+            .push(allocation.ip_prefix.clone());
+    }
+}
+
+#[tokio::test]
+async fn test_ip_strategy() {
+    let install_directory = std::env::temp_dir().join("ccm_binding_test_ip_strategy");
+    let strategy = Arc::new(RecordingStrategy::default());
+    let mut cluster = Cluster::with_ip_strategy(
+        "ip_strategy".to_string(),
+        "release:6.2".to_string(),
+        strategy.clone(),
+        vec![2, 1],
+        install_directory.to_string_lossy().into_owned(),
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(cluster.ip_prefix, "127.0.240.");
+    assert_eq!(cluster.node_address(2), "127.0.240.3");
+    cluster.release_ip_prefix();
+    cluster.release_ip_prefix();
+    assert_eq!(*strategy.released.lock().unwrap(), ["127.0.240."]);
+    cluster.destroyed = true;
+
+    let result = Cluster::with_ip_strategy(
+        "ip_strategy".to_string(),
+        "release:6.2".to_string(),
+        Arc::new(crate::ip_strategy::ExplicitAddresses(vec![IpAddr::from(
+            std::net::Ipv4Addr::LOCALHOST,
+        )])),
+        vec![2],
+        install_directory.to_string_lossy().into_owned(),
+        true,
+    )
+    .await;
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::InvalidInput)
+    );
+}
+
+#[tokio::test]
+async fn test_map_hostnames() {
+    let mut cluster = test_cluster("map_hostnames", vec![1]).await;
+    let hosts = Path::new(&cluster.install_directory).join("hosts");
+    tokio::fs::write(&hosts, "127.0.0.1 localhost\n")
+        .await
+        .unwrap();
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster.map_hostnames(".ccm.test", &hosts).await.unwrap();
+    cluster.add_node(Some(2)).await;
+    assert_eq!(
+        cluster.nodes[1].read().await.hostname(),
+        Some("node-2-1.map-hostnames.ccm.test")
+    );
+    cluster.init(false).await.unwrap();
+    assert_eq!(
+        tokio::fs::read_to_string(&hosts).await.unwrap(),
+        format!(
+            "127.0.0.1 localhost\n\
+             # BEGIN ccm-binding map_hostnames\n\
+             {0}1 node-1-1.map-hostnames.ccm.test\n\
+             {0}2 node-2-1.map-hostnames.ccm.test\n\
+             # END ccm-binding map_hostnames\n",
+            cluster.ip_prefix
+        )
+    );
+
+    cluster.destroy().await.unwrap();
+    assert_eq!(
+        tokio::fs::read_to_string(&hosts).await.unwrap(),
+        "127.0.0.1 localhost\n"
+    );
+}
+
+#[tokio::test]
+async fn test_port_collisions() {
+    let mut cluster = test_cluster("port_collisions", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    // node_1_2's default JMX port.
+    cluster.nodes[0].write().await.ports.debug = Some(7102);
+    cluster.init(false).await.unwrap();
+
+    let ports = cluster.nodes[1].read().await.ports;
+    let jmx = ports.jmx.unwrap();
+    assert_ne!(jmx, 7102);
+    assert!(crate::ports::DEFAULT_PORT_RANGE.contains(&jmx));
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(recorded[1].args[7], "7102");
+    assert_eq!(recorded[2].args[5], jmx.to_string());
+    assert!(cluster.reserved_ports.lock().unwrap().contains(&jmx));
+
+    // Another cluster of the process, created but not started, doesn't get the same ports,
+    // neither at init nor for nodes added later.
+    let mut other = test_cluster("port_collisions_other", vec![2]).await;
+    other.logged_cmd().set_dry_run(true);
+    other.set_loopback_aliases(None);
+    other.init(false).await.unwrap();
+    other.add_node(None).await;
+    let mut taken: BTreeSet<u16> = cluster
+        .reserved_ports
+        .lock()
+        .unwrap()
+        .iter()
+        .copied()
+        .collect();
+    for node in other.nodes.iter() {
+        let ports = node.read().await.ports;
+        assert!(taken.insert(ports.jmx.unwrap()), "{:?}", ports);
+        assert!(taken.insert(ports.debug.unwrap()), "{:?}", ports);
+    }
+    assert_eq!(other.reserved_ports.lock().unwrap().len(), 6);
+
+    // Dropping a cluster without destroying it gives its ports back.
+    let reserved = other.reserved_ports.lock().unwrap().clone();
+    drop(other);
+    for port in reserved {
+        assert!(RESERVED_PORTS.reserve(port), "{} still reserved", port);
+        RESERVED_PORTS.release(port);
+    }
+}
+
+#[tokio::test]
+async fn test_start_proxies() {
+    let cluster = test_cluster("start_proxies", vec![1]).await;
+    let listener = tokio::net::TcpListener::bind(format!("{}1:0", cluster.ip_prefix))
+        .await
+        .unwrap();
+    cluster.nodes[0].write().await.ports.native = Some(listener.local_addr().unwrap().port());
+
+    let addresses = cluster.start_proxies().await.unwrap();
+    assert_eq!(cluster.start_proxies().await.unwrap(), addresses);
+    let node = cluster.nodes[0].read().await;
+    let proxy = node.proxy().unwrap();
+    assert_eq!(proxy.address(), addresses[0]);
+    assert_eq!(proxy.target(), listener.local_addr().unwrap());
+    TcpStream::connect(addresses[0]).await.unwrap();
+    listener.accept().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_subnet_per_rack() {
+    let mut cluster = test_cluster("subnet_per_rack", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster
+        .set_ip_layout(IpLayout::SubnetPerRack)
+        .await
+        .unwrap();
+    cluster.add_node_in_rack(Some(2), "r1").await;
+    cluster.add_node_in_rack(Some(1), "r1").await;
+    cluster.add_node_in_rack(Some(2), "r1").await;
+    let prefix = cluster.ip_prefix.clone();
+    assert_eq!(cluster.node_address(1), format!("{prefix}2"));
+    assert_eq!(cluster.node_address(2), format!("{prefix}33"));
+    assert_eq!(cluster.node_address(3), format!("{prefix}65"));
+    assert_eq!(cluster.node_address(4), format!("{prefix}34"));
+    assert_eq!(
+        cluster.rack_subnets()[1],
+        RackSubnet {
+            datacenter_id: 2,
+            rack: Some("r1".to_string()),
+            network: format!("{prefix}32/27"),
+        }
+    );
+    assert!(cluster.use_ipv6(None).await.is_err());
+    cluster.init(false).await.unwrap();
+
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(
+        recorded[3].args[8..12],
+        ["--itfs", &format!("{prefix}33"), "--rack", "r1"]
+    );
+}
+
+#[tokio::test]
+async fn test_dual_stack() {
+    let mut cluster = test_cluster("dual_stack", vec![1]).await;
+    let prefix = cluster.ip_prefix.clone();
+    assert!(cluster.enable_dual_stack(Some("127.0.1.")).await.is_err());
+    cluster.enable_dual_stack(Some("fd00:0:0:7")).await.unwrap();
+    cluster.add_node(None).await;
+    assert_eq!(
+        cluster.nodes[1].read().await.ipv6(),
+        Some("fd00:0:0:7::2".parse().unwrap())
+    );
+    let contact_points: Vec<String> = cluster
+        .contact_points()
+        .await
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        contact_points,
+        [
+            format!("{prefix}1:9042"),
+            format!("{prefix}2:9042"),
+            "[fd00:0:0:7::1]:9042".to_string(),
+            "[fd00:0:0:7::2]:9042".to_string(),
+        ]
+    );
+    assert!(cluster.use_ipv6(None).await.is_err());
+
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster.init(false).await.unwrap();
+    let node = cluster.nodes[1].read().await;
+    let native = node.ports.native.unwrap();
+    let ScyllaConfig::Map(config) = &node.config else {
+        panic!("{:?}", node.config);
+    };
+    assert!(matches!(&config["rpc_address"], ScyllaConfig::String(address) if address == "::"));
+    assert!(matches!(
+        &config["broadcast_rpc_address"],
+        ScyllaConfig::String(address) if *address == format!("{prefix}2")
+    ));
+    assert!(matches!(
+        config["native_shard_aware_transport_port"],
+        ScyllaConfig::Int(port) if port != 19042
+    ));
+    let recorded = cluster.logged_cmd().recorded_commands();
+    let add = recorded
+        .iter()
+        .find(|command| command.args[..2] == ["add", "node_1_2"])
+        .unwrap();
+    assert!(
+        add.args
+            .windows(2)
+            .any(|pair| pair == ["--binary-itf", &format!("{prefix}2:{native}")])
+    );
+    drop(node);
+
+    let mut cassandra = test_cluster("dual_stack_cassandra", vec![1]).await;
+    cassandra.scylla = false;
+    assert_eq!(
+        cassandra
+            .enable_dual_stack(Some("fd00:0:0:8"))
+            .await
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::Unsupported
+    );
+}
+
+#[tokio::test]
+async fn test_ipv6_cluster() {
+    let mut cluster = test_cluster("ipv6", vec![1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    assert!(cluster.use_ipv6(Some("127.0.248.")).await.is_err());
+    cluster.use_ipv6(Some("fd00:0:0:5")).await.unwrap();
+    assert!(cluster.is_ipv6());
+    assert_eq!(cluster.node_address(0), "fd00:0:0:5::1");
+    let node = cluster.nodes[0].read().await;
+    assert_eq!(node.ip(), Some("fd00:0:0:5::1".parse().unwrap()));
+    assert_eq!(
+        node.socket_address(9042).unwrap().to_string(),
+        "[fd00:0:0:5::1]:9042"
+    );
+    drop(node);
+    cluster.set_loopback_aliases(None);
+    cluster.init(false).await.unwrap();
+
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(recorded[0].args[4..6], ["-I", "fd00:0:0:5::%d"]);
+
+    cluster.use_ipv6(None).await.unwrap();
+    assert!(cluster.ip_prefix.starts_with("fd6c:636d:0:"));
+}
+
+#[test]
+fn test_replace_ip_prefix() {
+    assert_eq!(
+        replace_ip_prefix(
+            "seeds: 127.0.1.1,127.0.1.2\nrpc: 10.127.0.1.5",
+            "127.0.1.",
+            "127.0.9."
+        ),
+        "seeds: 127.0.9.1,127.0.9.2\nrpc: 10.127.0.1.5"
+    );
+}
+
+#[tokio::test]
+async fn test_strict_config_audit_rejects_unknown_keys() {
+    let mut node = Node::new(
+        1,
+        1,
+        true,
+        1,
+        0,
+        ScyllaConfig::default(),
+        Arc::new(LoggedCmd::new()),
+        "/tmp/ccm".to_string(),
+        "test_cluster".to_string(),
+    );
+    node.config_audit = Some(ConfigAudit::new(
+        ConfigSchema::builtin(true),
+        AuditMode::Strict,
+    ));
+    let config = ScyllaConfig::from_flat_string("num_tokns:16").unwrap();
+
+    let err = node.update_config(&config).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.to_string(),
+        "Unknown config keys for node_1_1: num_tokns (did you mean num_tokens?)"
+    );
+}
+
+#[tokio::test]
+async fn test_dc_default_config_is_layered() {
+    let mut cluster = test_cluster("dc_defaults", vec![1, 1]).await;
+    cluster.set_default_node_config(
+        ScyllaConfig::from_flat_string("num_tokens:8 compaction_throughput_mb_per_sec:16").unwrap(),
+    );
+    cluster.nodes[1]
+        .write()
+        .await
+        .config
+        .merge(&ScyllaConfig::from_flat_string("hinted_handoff_enabled:false").unwrap());
+    let dc2 = ScyllaConfig::from_flat_string(
+        "compaction_throughput_mb_per_sec:64 hinted_handoff_enabled:true",
+    )
+    .unwrap();
+    cluster.set_dc_default_config(2, dc2).await;
+    cluster.add_node(Some(2)).await;
+
+    assert_eq!(cluster.nodes[0].read().await.config.to_flat_string(), "");
+    assert_eq!(
+        cluster.nodes[1].read().await.config.to_flat_string(),
+        "compaction_throughput_mb_per_sec:64 hinted_handoff_enabled:false"
+    );
+    assert_eq!(
+        cluster.nodes[2].read().await.config.to_flat_string(),
+        "compaction_throughput_mb_per_sec:64 hinted_handoff_enabled:true num_tokens:8"
+    );
+}
+
+#[tokio::test]
+async fn test_reload_config_reports_restart_required_keys() {
+    let node = Node::new(
+        1,
+        1,
+        true,
+        1,
+        0,
+        ScyllaConfig::default(),
+        Arc::new(LoggedCmd::new()),
+        "/tmp/ccm".to_string(),
+        "test_cluster".to_string(),
+    );
+    node.unapplied_config_keys
+        .lock()
+        .unwrap()
+        .extend(["num_tokens".to_string(), "enable_tablets".to_string()]);
+
+    let reload = node.reload_config().await.unwrap();
+    assert!(reload.restart_required());
+    assert!(reload.reloaded.is_empty());
+    assert_eq!(
+        reload.restart_required,
+        vec!["enable_tablets", "num_tokens"]
+    );
+    assert_eq!(node.unapplied_config_keys.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_node_wait_for_ports() {
+    let cluster = test_cluster("wait_for_ports", vec![1]).await;
+    let address = format!("{}1:0", cluster.ip_prefix);
+    let native = tokio::net::TcpListener::bind(&address).await.unwrap();
+    let storage = tokio::net::TcpListener::bind(&address).await.unwrap();
+    let mut node = cluster.nodes[0].write().await;
+    node.ports.native = Some(native.local_addr().unwrap().port());
+    node.ports.storage = Some(storage.local_addr().unwrap().port());
+    node.wait_for_ports(Duration::from_secs(1)).await.unwrap();
+
+    drop(storage);
+    let err = node
+        .wait_for_ports(Duration::from_millis(120))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    assert!(err.to_string().starts_with("node_1_1 never came up"));
+}
+
+#[tokio::test]
+async fn test_node_start_timeout() {
+    let mut cluster = test_cluster("start_timeout", vec![1]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let calls = install_directory.join("calls");
+    tokio::fs::create_dir_all(&install_directory).await.unwrap();
+    tokio::fs::remove_file(&calls).await.ok();
+    let script = format!(
+        "case \"$1\" in start) sleep 5;; *) echo \"$@\" >> {};; esac",
+        calls.display()
+    );
+    let hanging = CcmRunner::new(cluster.logged_cmd.clone(), "/tmp/ccm")
+        .with_command("sh", ["-c", script.as_str(), "ccm"]);
+    cluster.set_ccm_runner(hanging).await;
+
+    let node = cluster.nodes[0].read().await;
+    let options = [
+        NodeStartOption::WaitForBinaryProto,
+        NodeStartOption::Timeout(Duration::from_millis(100)),
+    ];
+    let err = node.start(Some(&options)).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    let timeout = err.get_ref().unwrap().downcast_ref::<NodeStartTimeout>();
+    assert_eq!(timeout.unwrap().node, "node_1_1");
+    assert_eq!(
+        tokio::fs::read_to_string(&calls).await.unwrap(),
+        "node_1_1 stop --not-gently --config-dir /tmp/ccm\n"
+    );
+}
+
+#[tokio::test]
+async fn test_list_clusters() {
+    let config_dir = std::env::temp_dir().join("ccm_binding_test_list_clusters");
+    tokio::fs::remove_dir_all(&config_dir).await.ok();
+    for (cluster, conf, nodes) in [
+        (
+            "first",
+            "name: first\nipprefix: 127.0.1.\nscylla_version: '6.2.0'\n",
+            &["node_1_1", "node_1_2"][..],
+        ),
+        ("second", "name: second\nipprefix: 127.0.2.\n", &[][..]),
+        ("own/own", "name: own\nipprefix: 127.0.3.\n", &[][..]),
+    ] {
+        let cluster_dir = config_dir.join(cluster);
+        tokio::fs::create_dir_all(&cluster_dir).await.unwrap();
+        tokio::fs::write(cluster_dir.join("cluster.conf"), conf)
+            .await
+            .unwrap();
+        for node in nodes {
+            tokio::fs::create_dir_all(cluster_dir.join(node))
+                .await
+                .unwrap();
+            tokio::fs::write(cluster_dir.join(node).join("node.conf"), "")
+                .await
+                .unwrap();
+        }
+    }
+    let pid = std::process::id().to_string();
+    tokio::fs::write(config_dir.join("first/node_1_2/cassandra.pid"), pid)
+        .await
+        .unwrap();
+    tokio::fs::write(config_dir.join("CURRENT"), "second\n")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        config_dir.join("first.state.json"),
+        r#"{"tags": {"owner": "ci"}}"#,
+    )
+    .await
+    .unwrap();
+
+    let clusters = Cluster::list(&config_dir).await.unwrap();
+    assert_eq!(
+        clusters[0],
+        ClusterInfo {
+            name: "first".to_string(),
+            version: Some("6.2.0".to_string()),
+            ip_prefix: Some("127.0.1.".to_string()),
+            nodes: vec!["node_1_1".to_string(), "node_1_2".to_string()],
+            state: ClusterState::PartiallyRunning,
+            current: false,
+            tags: HashMap::from([("owner".to_string(), "ci".to_string())]),
+        }
+    );
+    assert_eq!(clusters[1].name, "own");
+    assert_eq!(clusters[1].ip_prefix.as_deref(), Some("127.0.3."));
+    assert_eq!(clusters[2].name, "second");
+    assert_eq!(clusters[2].state, ClusterState::Stopped);
+    assert!(clusters[2].current);
+    assert!(
+        Cluster::list(config_dir.join("missing"))
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    tokio::fs::remove_dir_all(&config_dir).await.ok();
+}
+
+#[tokio::test]
+async fn test_load_cluster() {
+    let config_dir = std::env::temp_dir().join("ccm_binding_test_load_cluster");
+    tokio::fs::remove_dir_all(&config_dir).await.ok();
+    let cluster_dir = config_dir.join("left_running");
+    for (node, dc, address, jmx) in [
+        ("node_1_1", "dc1", "127.0.230.1", 7101),
+        ("node_1_3", "dc1", "127.0.230.3", 7103),
+        ("node_2_1", "dc2", "127.0.230.4", 7104),
+    ] {
+        tokio::fs::create_dir_all(cluster_dir.join(node))
+            .await
+            .unwrap();
+        let conf = format!(
+            "name: {node}\ndata_center: {dc}\ninterfaces:\n  binary:\n  - {address}\n  \
+             - 9042\njmx_port: '{jmx}'\nremote_debug_port: '0'\n"
+        );
+        tokio::fs::write(cluster_dir.join(node).join("node.conf"), conf)
+            .await
+            .unwrap();
+    }
+    tokio::fs::write(
+        cluster_dir.join("cluster.conf"),
+        "name: left_running\nipprefix: 127.0.230.\nscylla_version: release:6.2\n",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        Cluster::load("missing", &config_dir)
+            .await
+            .err()
+            .map(|e| e.kind()),
+        Some(std::io::ErrorKind::NotFound)
+    );
+    let mut cluster = Cluster::load("left_running", &config_dir).await.unwrap();
+    cluster.destroyed = true;
+    assert!(cluster.scylla);
+    assert_eq!(cluster.version, "release:6.2");
+    assert_eq!(cluster.ip_prefix, "127.0.230.");
+    assert_eq!(cluster.address_suffixes, vec![1, 3, 4]);
+    let node = cluster.nodes[1].read().await;
+    assert_eq!(node.name, "node_1_3");
+    assert_eq!((node.datacenter_id, node.node_id), (1, 3));
+    assert_eq!(node.address.as_deref(), Some("127.0.230.3"));
+    assert_eq!((node.ports.jmx, node.ports.debug), (Some(7103), Some(0)));
+    assert_eq!(cluster.nodes[2].read().await.datacenter_id, 2);
+    drop(node);
+    tokio::fs::remove_dir_all(&config_dir).await.ok();
+}
+
+#[tokio::test]
+async fn test_state_file_round_trip() {
+    let mut cluster = test_cluster("state_file", vec![2, 1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    {
+        let mut node = cluster.nodes[1].write().await;
+        node.config =
+            ScyllaConfig::from_flat_string("num_tokens:8 hinted_handoff_enabled:false").unwrap();
+        node.set_env("SCYLLA_HOME", "/opt/scylla");
+        node.ports.jmx = Some(7299);
+        node.rack = Some("r1".to_string());
+        node.tags.insert("role".to_string(), "seed".to_string());
+        node.seed = true;
+    }
+    cluster.set_tag("ticket", "SCYLLA-123").await.unwrap();
+    let saved = tokio::fs::read_to_string(cluster.state_file())
+        .await
+        .unwrap();
+    assert!(saved.contains("SCYLLA-123"));
+    cluster.set_partitioner(Partitioner::ByteOrdered);
+    cluster.set_replication_mode(ReplicationMode::Vnodes { num_tokens: 16 });
+    cluster
+        .set_default_start_options(&[
+            NodeStartOption::WaitForBinaryProto,
+            NodeStartOption::Timeout(Duration::from_secs(90)),
+        ])
+        .await;
+    cluster.password_auth = true;
+    cluster.auth_test_role = Some(Credentials::new("tester", "s3cret"));
+    cluster.init(false).await.unwrap();
+
+    let mut restored = Cluster::from_state_file(cluster.state_file())
+        .await
+        .unwrap();
+    restored.destroyed = true;
+    assert_eq!(restored.name, "state_file");
+    assert_eq!(restored.ip_prefix, cluster.ip_prefix);
+    assert_eq!(restored.address_suffixes, cluster.address_suffixes);
+    let node = restored.nodes[1].read().await;
+    assert_eq!(node.name, "node_1_2");
+    assert_eq!(node.address, Some(format!("{}2", cluster.ip_prefix)));
+    assert_eq!(node.ports.jmx, Some(7299));
+    assert_eq!(node.rack.as_deref(), Some("r1"));
+    assert_eq!(node.env["SCYLLA_HOME"], "/opt/scylla");
+    assert_eq!(node.tags["role"], "seed");
+    assert_eq!(restored.tags["ticket"], "SCYLLA-123");
+    assert!(node.seed);
+    assert!(!restored.nodes[0].read().await.seed);
+    assert_eq!(
+        node.config.to_flat_string(),
+        cluster.nodes[1].read().await.config.to_flat_string()
+    );
+    assert_eq!(restored.nodes[2].read().await.datacenter_id, 2);
+    assert_eq!(restored.partitioner, Some(Partitioner::ByteOrdered));
+    assert_eq!(
+        restored.replication_mode,
+        Some(ReplicationMode::Vnodes { num_tokens: 16 })
+    );
+    assert_eq!(
+        restored.default_start_options,
+        cluster.default_start_options
+    );
+    assert_eq!(node.default_start_options, cluster.default_start_options);
+    assert!(restored.password_auth);
+    assert_eq!(restored.credentials(), cluster.credentials());
+    assert_eq!(
+        restored.logged_cmd().redacted("password s3cret"),
+        "password ***"
+    );
+    drop(node);
+
+    cluster.destroy().await.unwrap();
+    assert!(!cluster.state_file().exists());
+}
+
+#[tokio::test]
+async fn test_keep_alive_and_ensure() {
+    let mut cluster = test_cluster("kept", vec![2]).await;
+    let install_directory = cluster.install_directory.clone();
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_loopback_aliases(None);
+    cluster.init(false).await.unwrap();
+    for node in cluster.nodes() {
+        let directory = node.read().await.directory();
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        tokio::fs::write(
+            directory.join("cassandra.pid"),
+            std::process::id().to_string(),
+        )
+        .await
+        .unwrap();
+    }
+    tokio::fs::write(Path::new(&install_directory).join("kept/CURRENT"), "kept")
+        .await
+        .unwrap();
+    cluster.set_policy(ClusterPolicy::KeepAlive);
+    cluster.destroy().await.unwrap();
+    assert!(cluster.destroyed);
+    assert!(cluster.state_file().exists());
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert!(!recorded.iter().any(|command| command.args[0] == "remove"));
+
+    let mut reused = Cluster::ensure("kept", &[2], "release:6.2", &install_directory)
+        .await
+        .unwrap();
+    reused.destroyed = true;
+    assert_eq!(reused.policy, ClusterPolicy::KeepAlive);
+    assert_eq!(reused.ip_prefix, cluster.ip_prefix);
+    assert_eq!(reused.nodes.len(), 2);
+}
+
+#[tokio::test]
+async fn test_clone_to() {
+    let cluster = test_cluster("source", vec![2]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let source = install_directory.join("source/source");
+    for node in ["node_1_1", "node_1_2"] {
+        tokio::fs::create_dir_all(source.join(node).join("conf"))
+            .await
+            .unwrap();
+        tokio::fs::create_dir_all(source.join(node).join("data/ks"))
+            .await
+            .unwrap();
+        tokio::fs::write(source.join(node).join("data/ks/table.db"), "rows")
+            .await
+            .unwrap();
+        tokio::fs::write(
+            source.join(node).join("node.conf"),
+            format!("name: {}\n", node),
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            source.join(node).join("conf/scylla.yaml"),
+            format!(
+                "cluster_name: source\nlisten_address: {}1\n\
+                 data_file_directories:\n- {}/{}/data\n",
+                cluster.ip_prefix,
+                source.display(),
+                node
+            ),
+        )
+        .await
+        .unwrap();
+    }
+    tokio::fs::write(
+        source.join("cluster.conf"),
+        format!("name: source\nipprefix: {}\n", cluster.ip_prefix),
+    )
+    .await
+    .unwrap();
+
+    let mut clone = cluster.clone_to("copy").await.unwrap();
+    clone.destroyed = true;
+    clone.release_ip_prefix();
+    assert_ne!(clone.ip_prefix, cluster.ip_prefix);
+    assert_eq!(clone.nodes.len(), 2);
+    let copy = install_directory.join("copy/copy");
+    let node = clone.nodes[1].read().await;
+    assert_eq!(node.name, "node_1_2");
+    assert_eq!(node.directory(), copy.join("node_1_2"));
+    assert_eq!(node.address, Some(format!("{}2", clone.ip_prefix)));
+    drop(node);
+    let read = |path: PathBuf| async move { tokio::fs::read_to_string(path).await.unwrap() };
+    assert_eq!(
+        read(copy.join("cluster.conf")).await,
+        format!("name: copy\nipprefix: {}\n", clone.ip_prefix)
+    );
+    assert_eq!(
+        read(copy.join("node_1_2/node.conf")).await,
+        "name: node_1_2\n"
+    );
+    assert_eq!(
+        read(copy.join("node_1_2/conf/scylla.yaml")).await,
+        format!(
+            "cluster_name: source\nlisten_address: {}1\ndata_file_directories:\n- {}/node_1_2/data\n",
+            clone.ip_prefix,
+            copy.display()
+        )
+    );
+    assert_eq!(read(copy.join("node_1_2/data/ks/table.db")).await, "rows");
+    assert_eq!(
+        cluster.clone_to("copy").await.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::AlreadyExists)
+    );
+    tokio::fs::remove_file(source.join("cluster.conf"))
+        .await
+        .unwrap();
+    assert_eq!(
+        cluster.clone_to("broken").await.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::NotFound)
+    );
+    assert!(!install_directory.join("broken").exists());
+}
+
+#[tokio::test]
+async fn test_snapshot_and_restore() {
+    let cluster = test_cluster("snapshots", vec![1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.snapshot("before").await.unwrap();
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(
+        recorded[0].args[..5],
+        ["node_1_1", "nodetool", "snapshot", "-t", "before"]
+    );
+
+    let node = cluster.nodes[0].read().await;
+    let table = node.directory().join("data/ks/table-1234");
+    let snapshot = table.join("snapshots/before");
+    tokio::fs::create_dir_all(&snapshot).await.unwrap();
+    tokio::fs::create_dir_all(node.directory().join("commitlogs"))
+        .await
+        .unwrap();
+    for (path, content) in [
+        (snapshot.join("me-1-big-Data.db"), "old"),
+        (snapshot.join("manifest.json"), "{}"),
+        (table.join("me-1-big-Data.db"), "old"),
+        (table.join("me-2-big-Data.db"), "new"),
+        (node.directory().join("commitlogs/CommitLog-1.log"), "new"),
+    ] {
+        tokio::fs::write(path, content).await.unwrap();
+    }
+    assert_eq!(
+        node.restore_snapshot("missing")
+            .await
+            .err()
+            .map(|e| e.kind()),
+        Some(std::io::ErrorKind::NotFound)
+    );
+    drop(node);
+    cluster.restore_snapshot("before").await.unwrap();
+
+    let mut restored: Vec<_> = list_files(&table)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    restored.sort();
+    assert_eq!(restored, ["me-1-big-Data.db"]);
+    assert!(snapshot.join("me-1-big-Data.db").exists());
+    let commitlogs = cluster.nodes[0].read().await.directory().join("commitlogs");
+    assert!(list_files(&commitlogs).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_export_and_import_archive() {
+    let cluster = test_cluster("shared", vec![1]).await;
+    let temp_dir = std::env::temp_dir();
+    let importing = temp_dir.join("ccm_binding_test_import");
+    tokio::fs::remove_dir_all(&importing).await.ok();
+    let node_directory = cluster.nodes[0].read().await.directory();
+    tokio::fs::create_dir_all(node_directory.join("conf"))
+        .await
+        .unwrap();
+    tokio::fs::create_dir_all(node_directory.join("data"))
+        .await
+        .unwrap();
+    tokio::fs::write(node_directory.join("data/table.db"), "rows")
+        .await
+        .unwrap();
+    tokio::fs::write(
+        node_directory.join("conf/scylla.yaml"),
+        format!(
+            "listen_address: {}1\ncommitlog_directory: {}/commitlogs\n",
+            cluster.ip_prefix,
+            node_directory.display()
+        ),
+    )
+    .await
+    .unwrap();
+    tokio::fs::write(
+        cluster.directory().join("cluster.conf"),
+        format!(
+            "name: shared\nipprefix: {}\n\
+             install_dir: /home/exporter/.ccm/scylla-repository/release/6.2\n",
+            cluster.ip_prefix
+        ),
+    )
+    .await
+    .unwrap();
+    let archive = temp_dir.join("ccm_binding_test_export.tar.gz");
+    cluster.export_archive(&archive).await.unwrap();
+
+    let importing_directory = importing.to_string_lossy().into_owned();
+    let mut imported = Cluster::import_archive(&archive, &importing_directory)
+        .await
+        .unwrap();
+    imported.destroyed = true;
+    imported.release_ip_prefix();
+    assert_eq!(imported.name, "shared");
+    assert_eq!(imported.install_directory, importing_directory);
+    assert_ne!(imported.ip_prefix, cluster.ip_prefix);
+    let node = imported.nodes[0].read().await;
+    assert_eq!(node.address, Some(format!("{}1", imported.ip_prefix)));
+    let yaml = tokio::fs::read_to_string(node.directory().join("conf/scylla.yaml"))
+        .await
+        .unwrap();
+    assert_eq!(
+        yaml,
+        format!(
+            "listen_address: {}1\ncommitlog_directory: {}/commitlogs\n",
+            imported.ip_prefix,
+            node.directory().display()
+        )
+    );
+    assert!(node.directory().join("data/table.db").exists());
+    let cluster_conf = tokio::fs::read_to_string(imported.directory().join("cluster.conf"))
+        .await
+        .unwrap();
+    let home = std::env::var("HOME").unwrap_or_default();
+    assert!(cluster_conf.contains(&format!(
+        "install_dir: {}/.ccm/scylla-repository/release/6.2\n",
+        home
+    )));
+    drop(node);
+    assert_eq!(
+        Cluster::import_archive(&archive, &importing_directory)
+            .await
+            .err()
+            .map(|e| e.kind()),
+        Some(std::io::ErrorKind::AlreadyExists)
+    );
+
+    // A remote machine that doesn't see the install directory.
+    struct Unshared(LoggedCmd);
+    impl CommandExecutor for Unshared {
+        fn run_command<'a>(
+            &'a self,
+            command: &'a str,
+            args: &'a [&'a str],
+            opts: Option<RunOptions>,
+        ) -> futures::future::BoxFuture<'a, Result<crate::ccm_cli::CommandResult, IoError>>
+        {
+            let command = if command == "test" { "false" } else { command };
+            CommandExecutor::run_command(&self.0, command, args, opts)
+        }
+
+        fn is_remote(&self) -> bool {
+            true
+        }
+    }
+    let elsewhere = importing.join("elsewhere");
+    let err = Cluster::import_archive_with_executor(
+        &archive,
+        &elsewhere.to_string_lossy(),
+        Arc::new(Unshared(LoggedCmd::new())),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    assert!(!elsewhere.join("shared").exists());
+    tokio::fs::remove_dir_all(&importing).await.ok();
+    tokio::fs::remove_file(&archive).await.ok();
+}
+
+#[tokio::test]
+async fn test_stop_datacenter() {
+    let cluster = test_cluster("stop_dc", vec![2, 2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.stop_datacenter(2).await.unwrap();
+    cluster.stop_nodes(|node| node.node_id == 1).await.unwrap();
+
+    let mut stopped: Vec<_> = cluster
+        .logged_cmd()
+        .recorded_commands()
+        .into_iter()
+        .map(|command| command.args[..2].join(" "))
+        .collect();
+    stopped[..2].sort();
+    stopped[2..].sort();
+    assert_eq!(
+        stopped,
+        [
+            "node_2_1 stop",
+            "node_2_2 stop",
+            "node_1_1 stop",
+            "node_2_1 stop"
+        ]
+    );
+}
+
+/// Subscriber recording the name and fields of every span created while it is the default.
+#[cfg(all(test, feature = "tracing"))]
+#[derive(Default)]
+struct SpanRecorder {
+    spans: Mutex<Vec<(String, BTreeMap<String, String>)>>,
+}
+
+#[cfg(all(test, feature = "tracing"))]
+impl tracing::Subscriber for SpanRecorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        struct Fields<'a>(&'a mut BTreeMap<String, String>);
+        impl tracing::field::Visit for Fields<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+        let mut fields = BTreeMap::new();
+        span.record(&mut Fields(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name().to_string(), fields));
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, _: &tracing::Event<'_>) {}
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn test_tracing_spans() {
+    let mut cluster = test_cluster("traced", vec![1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster.set_tag("suite", "smoke").await.unwrap();
+    cluster.logged_cmd().redact("s3cret");
+    let recorder = Arc::new(SpanRecorder::default());
+    let guard = tracing::subscriber::set_default(recorder.clone());
+
+    let node = cluster.nodes[0].clone();
+    node.read()
+        .await
+        .nodetool(&["status", "s3cret"])
+        .await
+        .unwrap();
+    cluster.destroy().await.unwrap();
+    drop(guard);
+
+    let spans = recorder.spans.lock().unwrap().clone();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|(span, _)| span == name)
+            .map(|(_, fields)| fields.clone())
+            .unwrap_or_else(|| panic!("no {} span in {:?}", name, spans))
+    };
+    assert_eq!(
+        span("destroy"),
+        BTreeMap::from([
+            ("cluster".to_string(), "traced".to_string()),
+            ("tags".to_string(), r#"{"suite": "smoke"}"#.to_string()),
+        ])
+    );
+    assert_eq!(
+        span("nodetool"),
+        BTreeMap::from([("node".to_string(), "node_1_1".to_string())])
+    );
+    let (_, nodetool) = spans
+        .iter()
+        .find(|(span, fields)| {
+            span == "run_command" && fields["args"].starts_with("node_1_1 nodetool")
+        })
+        .unwrap_or_else(|| panic!("no nodetool command span in {:?}", spans));
+    assert!(nodetool["args"].starts_with("node_1_1 nodetool status ***"));
+    assert!(!nodetool["command"].is_empty());
+}
+
+#[tokio::test]
+async fn test_destroy_modes() {
+    let mut commands = vec![];
+    for mode in [
+        DestroyMode::Graceful,
+        DestroyMode::Fast,
+        DestroyMode::KeepData,
+    ] {
+        let mut cluster = test_cluster("doomed", vec![1]).await;
+        cluster.logged_cmd().set_dry_run(true);
+        cluster.set_destroy_mode(mode);
+        tokio::fs::create_dir_all(cluster.directory().join("node_1_1"))
+            .await
+            .unwrap();
+        cluster.destroy().await.unwrap();
+        commands.push(
+            cluster
+                .logged_cmd()
+                .recorded_commands()
+                .into_iter()
+                .map(|command| command.args[..command.args.len() - 2].join(" "))
+                .collect::<Vec<_>>(),
+        );
+        if mode == DestroyMode::KeepData {
+            let install_directory = Path::new(&cluster.install_directory);
+            let kept = install_directory
+                .join("kept")
+                .join(test_context::label().unwrap());
+            assert!(kept.ends_with("kept/cluster.test_destroy_modes"));
+            assert!(kept.join("doomed/node_1_1").is_dir());
+            assert!(!install_directory.join("doomed").exists());
+        }
+    }
+    assert_eq!(
+        commands,
+        [
+            vec!["node_1_1 nodetool drain", "stop doomed", "remove doomed"],
+            vec!["stop doomed --not-gently", "remove doomed"],
+            vec!["stop doomed"],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_default_start_options() {
+    let mut cluster = test_cluster("start_options", vec![1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    cluster
+        .set_default_start_options(&[NodeStartOption::WaitForBinaryProto])
+        .await;
+    cluster.start(None).await.unwrap();
+    cluster
+        .add_node(None)
+        .await
+        .read()
+        .await
+        .start(None)
+        .await
+        .unwrap();
+    cluster
+        .start(Some(&[NodeStartOption::NOWAIT]))
+        .await
+        .unwrap();
+
+    let recorded: Vec<_> = cluster
+        .logged_cmd()
+        .recorded_commands()
+        .into_iter()
+        .map(|command| command.args[..3].join(" "))
+        .collect();
+    assert_eq!(
+        recorded,
+        [
+            "start node_1_1 --wait-for-binary-proto",
+            "start node_1_2 --wait-for-binary-proto",
+            "start node_1_1 --no-wait",
+            "start node_1_2 --no-wait",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_wait_healthy() {
+    let mut cluster = test_cluster("healthy", vec![3]).await;
+    cluster.ccm = cluster.ccm.clone().with_command(
+        "sh",
+        [
+            "-c",
+            "printf 'node_1_1: UP\\nnode_1_2: UP\\nnode_1_3: DOWN\\n'",
+        ],
+    );
+    let first = format!("{}1:0", cluster.ip_prefix);
+    let native = tokio::net::TcpListener::bind(&first).await.unwrap();
+    let storage = tokio::net::TcpListener::bind(&first).await.unwrap();
+    let closed = tokio::net::TcpListener::bind(format!("{}2:0", cluster.ip_prefix))
+        .await
+        .unwrap();
+    let closed_port = closed.local_addr().unwrap().port();
+    drop(closed);
+    let nodes = cluster.nodes();
+    {
+        let mut node = nodes[0].write().await;
+        node.ports.native = Some(native.local_addr().unwrap().port());
+        node.ports.storage = Some(storage.local_addr().unwrap().port());
+    }
+    {
+        let mut node = nodes[1].write().await;
+        node.ports.native = Some(closed_port);
+        node.ports.storage = Some(closed_port);
+    }
+
+    let err = cluster
+        .wait_healthy(Duration::from_millis(100))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    let report = err
+        .get_ref()
+        .unwrap()
+        .downcast_ref::<HealthReport>()
+        .unwrap();
+    assert_eq!(
+        report.to_string(),
+        "up: node_1_1; down: node_1_3; stuck: node_1_2"
+    );
+
+    for node in &nodes[1..] {
+        node.write().await.status = NodeStatus::DELETED;
+    }
+    let report = cluster.wait_healthy(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(report.nodes.len(), 1);
+
+    let executor = LoggedCmd::new();
+    assert!(nodes[0].read().await.ports_open_through(&executor).await);
+    assert!(!nodes[1].read().await.ports_open_through(&executor).await);
+
+    cluster.ccm = cluster.ccm.clone().with_command(
+        "sh",
+        ["-c", "printf \"Cluster: 'other'\\nnode_1_1: UP\\n\""],
+    );
+    let err = cluster.health().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn test_watch_crashes() {
+    let cluster = test_cluster("watched", vec![2]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    let mut servers = vec![];
+    for node in cluster.nodes() {
+        let directory = node.read().await.directory();
+        tokio::fs::create_dir_all(&directory).await.unwrap();
+        let server = tokio::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = server.id().unwrap().to_string();
+        tokio::fs::write(directory.join("cassandra.pid"), pid)
+            .await
+            .unwrap();
+        servers.push(server);
+    }
+    let mut watchdog = cluster.watch_crashes(Duration::from_millis(20));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    cluster.nodes()[1].read().await.stop().await.unwrap();
+    for server in servers.iter_mut() {
+        server.kill().await.unwrap();
+    }
+    let crash = tokio::time::timeout(Duration::from_secs(5), watchdog.next_crash())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(crash.node, "node_1_1");
+    assert!(crash.log.ends_with("node_1_1/logs/system.log"));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    watchdog.check().unwrap();
+}
+
+#[tokio::test]
+async fn test_ttl() {
+    let mut cluster = test_cluster("expiring", vec![1]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let calls = install_directory.join("calls");
+    let script = format!("echo \"$*\" >> {}", calls.display());
+    cluster.ccm = cluster
+        .ccm
+        .clone()
+        .with_command("sh", ["-c", &script, "ccm"]);
+    cluster.set_ttl(Some(Duration::ZERO)).await.unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    let read_calls = || async {
+        tokio::fs::read_to_string(&calls)
+            .await
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.split(" --config-dir").next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    while read_calls().await.len() < 2 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let expected = ["stop expiring --not-gently", "remove expiring"];
+    assert_eq!(read_calls().await, expected);
+    let config_dir = install_directory.join("expiring");
+    while config_dir.exists() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(!config_dir.exists());
+
+    cluster.destroy().await.unwrap();
+    assert_eq!(read_calls().await, expected);
+}
+
+#[tokio::test]
+async fn test_scale() {
+    let mut cluster = test_cluster("scaled", vec![2, 1]).await;
+    cluster.logged_cmd().set_dry_run(true);
+    let mut steps = vec![];
+    cluster
+        .scale(&[(1, 3), (2, 0)], |step| {
+            steps.push((step.action, step.node.clone(), step.done, step.total))
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        steps,
+        [
+            (ScaleAction::Add, "node_1_3".to_string(), 1, 2),
+            (ScaleAction::Decommission, "node_2_1".to_string(), 2, 2),
+        ]
+    );
+    let recorded: Vec<_> = cluster
+        .logged_cmd()
+        .recorded_commands()
+        .into_iter()
+        .map(|command| command.args[..2].join(" "))
+        .filter(|command| !command.starts_with("create"))
+        .collect();
+    assert_eq!(
+        recorded,
+        [
+            "add node_1_3",
+            "start node_1_3",
+            "node_2_1 decommission",
+            "node_2_1 remove"
+        ]
+    );
+    assert_eq!(cluster.topology().await, [3]);
+    let added = cluster.nodes[3].read().await;
+    assert_eq!(added.name, "node_1_3");
+    assert!(added.ports.jmx.is_some() && added.ports.debug.is_some());
+    drop(added);
+    cluster.destroy().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_init_existing_cluster() {
+    let mut cluster = test_cluster("precious", vec![2]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    cluster.logged_cmd().set_dry_run(true);
+    let existing = install_directory.join("precious/precious");
+    tokio::fs::create_dir_all(existing.join("node_1_1"))
+        .await
+        .unwrap();
+    tokio::fs::write(existing.join("cluster.conf"), "name: precious\n")
+        .await
+        .unwrap();
+
+    let err = cluster.init(false).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert!(existing.join("node_1_1").is_dir());
+    let err = cluster.init_or_attach().await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+    tokio::fs::create_dir_all(existing.join("node_1_2"))
+        .await
+        .unwrap();
+    assert_eq!(
+        cluster.init_or_attach().await.unwrap(),
+        InitOutcome::Attached
+    );
+    let recorded = cluster.logged_cmd().recorded_commands();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].args[..2], ["switch", "precious"]);
+
+    cluster.init(true).await.unwrap();
+    assert!(!existing.exists());
+    assert_eq!(
+        cluster.init_or_attach().await.unwrap(),
+        InitOutcome::Created
+    );
+    cluster.destroy().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stress() {
+    let mut cluster = test_cluster("stressed", vec![2]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let calls = install_directory.join("calls");
+    let script = format!(
+        "echo \"$*\" >> {}; [ \"$1\" = stress ] || exit 0; \
+         printf 'Results:\\nOp rate : 1,500 op/s\\nLatency 99th percentile : 2.5 ms\\n'",
+        calls.display()
+    );
+    cluster.ccm = cluster
+        .ccm
+        .clone()
+        .with_command("sh", ["-c", &script, "ccm"]);
+    cluster.password_auth = true;
+    assert_eq!(
+        cluster
+            .stress(StressProfile::write())
+            .await
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    tokio::fs::write(install_directory.join("stressed/CURRENT"), "stressed\n")
+        .await
+        .unwrap();
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let summary = cluster
+        .stress(StressProfile::write().ops(1000).output(sender))
+        .await
+        .unwrap();
+    assert_eq!(summary.op_rate, 1500.0);
+    assert_eq!(summary.latency_p99, 2.5);
+    assert_eq!(receiver.recv().await.unwrap(), "Results:");
+    let calls = tokio::fs::read_to_string(&calls).await.unwrap();
+    let calls: Vec<_> = calls
+        .lines()
+        .map(|line| line.split(" --config-dir").next().unwrap())
+        .collect();
+    let prefix = &cluster.ip_prefix;
+    assert_eq!(
+        calls,
+        [format!(
+            "stress write n=1000 -node {prefix}1,{prefix}2 -port native=9042 \
+             -mode native cql3 user=cassandra password=cassandra"
+        )]
+    );
+    let log = tokio::fs::read_to_string(install_directory.join("stressed.ccm.log"))
+        .await
+        .unwrap();
+    assert!(log.contains("user=cassandra ***"));
+    assert!(!log.contains("password="));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_scylla_bench() {
+    let mut cluster = test_cluster("benched", vec![1]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let bench = install_directory.join("scylla-bench");
+    tokio::fs::write(
+        &bench,
+        "#!/bin/sh\necho \"$*\" > \"$0.args\"\nprintf 'Results\\nTotal ops: 500\\nOperations/s: 50.5\\n'\n",
+    )
+    .await
+    .unwrap();
+    std::fs::set_permissions(&bench, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    cluster.password_auth = true;
+    let workload = BenchWorkload::new(crate::scylla_bench::BenchMode::Write)
+        .program(bench.to_string_lossy())
+        .concurrency(4);
+    let summary = cluster.scylla_bench(workload).await.unwrap();
+    assert_eq!(summary.total_ops, 500);
+    assert_eq!(summary.ops_per_second, 50.5);
+    let args = tokio::fs::read_to_string(install_directory.join("scylla-bench.args"))
+        .await
+        .unwrap();
+    assert_eq!(
+        args.trim(),
+        format!(
+            "-mode write -workload sequential -concurrency 4 -nodes {}1:9042 \
+             -username cassandra -password cassandra",
+            cluster.ip_prefix
+        )
+    );
+    let log = tokio::fs::read_to_string(install_directory.join("benched.ccm.log"))
+        .await
+        .unwrap();
+    assert!(log.contains("-username cassandra ***"));
+    assert!(!log.contains("-password"));
+}
+
+#[tokio::test]
+async fn test_ring_status() {
+    let mut cluster = test_cluster("ringed", vec![2]).await;
+    let script = "[ \"$1\" = node_1_1 ] && exit 1; \
+                  echo 'Datacenter: datacenter1'; \
+                  echo 'UN  127.0.209.1  1 KB  256  ?  id-1  rack1'; \
+                  echo 'DN  127.0.209.2  ?  256  ?  id-2  rack1'";
+    let runner = cluster
+        .ccm
+        .clone()
+        .with_command("sh", ["-c", script, "ccm"]);
+    cluster.set_ccm_runner(runner).await;
+    let peers = cluster.ring_status().await.unwrap();
+    assert_eq!(peers.len(), 2);
+    assert_eq!(peers[0].host_id, "id-1");
+    assert_eq!(peers[0].load, Some(1024));
+    assert!(!peers[1].up);
+    assert_eq!(peers[1].dc, "datacenter1");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sstableloader() {
+    use std::os::unix::fs::PermissionsExt;
+    let mut cluster = test_cluster("loaded", vec![2]).await;
+    let install_directory = PathBuf::from(&cluster.install_directory);
+    let install_dir = install_directory.join("repository/6.2");
+    let bin = install_dir.join("share/cassandra/bin");
+    tokio::fs::create_dir_all(&bin).await.unwrap();
+    let calls = install_directory.join("calls");
+    tokio::fs::write(
+        bin.join("sstableloader"),
+        format!("#!/bin/sh\necho \"$@\" > {}\n", calls.display()),
+    )
+    .await
+    .unwrap();
+    tokio::fs::set_permissions(
+        bin.join("sstableloader"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .await
+    .unwrap();
+    tokio::fs::create_dir_all(install_directory.join("loaded/loaded"))
+        .await
+        .unwrap();
+    tokio::fs::write(
+        install_directory.join("loaded/loaded/cluster.conf"),
+        format!("name: loaded\ninstall_dir: {}\n", install_dir.display()),
+    )
+    .await
+    .unwrap();
+    let data_dir = install_directory.join("data/ks/t");
+    tokio::fs::create_dir_all(&data_dir).await.unwrap();
+    cluster.nodes[1].write().await.ports.native = Some(9043);
+    cluster.password_auth = true;
+    cluster
+        .sstableloader(&data_dir, SstableLoaderOptions::new().throttle(100))
+        .await
+        .unwrap();
+    assert_eq!(
+        tokio::fs::read_to_string(&calls).await.unwrap(),
+        format!(
+            "-d {0}1,{0}2:9043 -p 9042 -u cassandra -pw cassandra --no-progress \
+             --throttle 100 {1}\n",
+            cluster.ip_prefix,
+            data_dir.display()
+        )
+    );
+    let log = tokio::fs::read_to_string(install_directory.join("loaded.ccm.log"))
+        .await
+        .unwrap();
+    assert!(log.contains("-u cassandra *** --no-progress"));
+    assert!(!log.contains("-pw"));
+
+    tokio::fs::remove_dir_all(install_directory.join("repository"))
+        .await
+        .unwrap();
+    assert_eq!(
+        cluster
+            .sstableloader(&data_dir, SstableLoaderOptions::new())
+            .await
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_port() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    wait_for_port(&address, Duration::from_secs(1))
+        .await
+        .expect("Listening port was not detected");
+
+    drop(listener);
+    let err = wait_for_port(&address, Duration::from_millis(120))
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
 }
@@ -0,0 +1,360 @@
+use crate::cluster::{Cluster, NodeStartOption, Partitioner};
+use crate::cluster_config::ScyllaConfig;
+use crate::ip_strategy::{FixedPrefix, IpStrategy, SniffedLoopback};
+use std::io::Error as IoError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fluent alternative to [`Cluster::new`] followed by setters, e.g.
+/// `Cluster::builder().version("release:6.2").topology(&[3, 3]).node_memory(1024).start().await`.
+/// Only the version is required; the name defaults to a unique `cluster_<pid>_<n>`.
+pub struct ClusterBuilder {
+    name: Option<String>,
+    version: Option<String>,
+    scylla: bool,
+    topology: Vec<i32>,
+    install_directory: String,
+    ip_strategy: Option<Arc<dyn IpStrategy>>,
+    node_smp: Option<i32>,
+    node_memory: Option<i32>,
+    config: Option<ScyllaConfig>,
+    partitioner: Option<Partitioner>,
+    start_options: Vec<NodeStartOption>,
+    datacenters: Vec<DatacenterSpec>,
+}
+
+/// Datacenter of a [`ClusterBuilder`] with settings of its own, e.g.
+/// `DatacenterSpec::new(3).racks(&["r1", "r2", "r3"]).version("release:6.1")` for the old
+/// half of an upgrade test.
+#[derive(Debug, Clone)]
+pub struct DatacenterSpec {
+    nodes: i32,
+    config: Option<ScyllaConfig>,
+    racks: Vec<String>,
+    version: Option<String>,
+    seeds: usize,
+}
+
+impl DatacenterSpec {
+    pub fn new(nodes: i32) -> Self {
+        DatacenterSpec {
+            nodes,
+            config: None,
+            racks: vec![],
+            version: None,
+            seeds: 1,
+        }
+    }
+
+    /// Default config of the datacenter's nodes, see [`Cluster::set_dc_default_config`].
+    pub fn config(mut self, config: ScyllaConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Racks the nodes are spread over in turn; ccm's default rack without any.
+    pub fn racks(mut self, racks: &[&str]) -> Self {
+        self.racks = racks.iter().map(|rack| rack.to_string()).collect();
+        self
+    }
+
+    /// Version the datacenter's nodes run instead of the cluster's.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Number of the datacenter's first nodes added as seeds, 1 by default, see
+    /// [`Cluster::set_dc_seeds`].
+    pub fn seeds(mut self, seeds: usize) -> Self {
+        self.seeds = seeds;
+        self
+    }
+}
+
+impl Default for ClusterBuilder {
+    fn default() -> Self {
+        ClusterBuilder {
+            name: None,
+            version: None,
+            scylla: true,
+            topology: vec![1],
+            install_directory: Cluster::default_install_directory(),
+            ip_strategy: None,
+            node_smp: None,
+            node_memory: None,
+            config: None,
+            partitioner: None,
+            start_options: vec![],
+            datacenters: vec![],
+        }
+    }
+}
+
+impl ClusterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// ccm version, e.g. `release:6.2` or a local install path.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Scylla (the default) or Cassandra.
+    pub fn scylla(mut self, scylla: bool) -> Self {
+        self.scylla = scylla;
+        self
+    }
+
+    /// Node count of every datacenter, e.g. `&[3, 3]` for two datacenters of three nodes.
+    pub fn topology(mut self, nodes_per_dc: &[i32]) -> Self {
+        self.topology = nodes_per_dc.to_vec();
+        self
+    }
+
+    /// Adds a datacenter with settings of its own; once any is added, they replace the
+    /// [`topology`](ClusterBuilder::topology), numbered in the order they were added.
+    pub fn datacenter(mut self, datacenter: DatacenterSpec) -> Self {
+        self.datacenters.push(datacenter);
+        self
+    }
+
+    /// Directory the cluster and its log are kept in, see
+    /// [`Cluster::default_install_directory`] for the default.
+    pub fn install_directory(mut self, install_directory: impl Into<String>) -> Self {
+        self.install_directory = install_directory.into();
+        self
+    }
+
+    /// Fixed IP prefix such as `127.0.5.` instead of a sniffed one.
+    pub fn ip_prefix(self, ip_prefix: impl Into<String>) -> Self {
+        self.ip_strategy(Arc::new(FixedPrefix(ip_prefix.into())))
+    }
+
+    /// See [`Cluster::with_ip_strategy`].
+    pub fn ip_strategy(mut self, ip_strategy: Arc<dyn IpStrategy>) -> Self {
+        self.ip_strategy = Some(ip_strategy);
+        self
+    }
+
+    pub fn node_smp(mut self, smp: i32) -> Self {
+        self.node_smp = Some(smp);
+        self
+    }
+
+    /// Memory per node in megabytes.
+    pub fn node_memory(mut self, memory: i32) -> Self {
+        self.node_memory = Some(memory);
+        self
+    }
+
+    /// Default config of every node, see [`Cluster::set_default_node_config`].
+    pub fn config(mut self, config: ScyllaConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// See [`Cluster::set_partitioner`].
+    pub fn partitioner(mut self, partitioner: Partitioner) -> Self {
+        self.partitioner = Some(partitioner);
+        self
+    }
+
+    /// Options [`start`](ClusterBuilder::start) starts the nodes with.
+    pub fn start_options(mut self, options: Vec<NodeStartOption>) -> Self {
+        self.start_options = options;
+        self
+    }
+
+    /// Creates the cluster and its nodes without running ccm; call
+    /// [`init`](Cluster::init) and [`start`](Cluster::start) when ready, e.g. after further
+    /// setup.
+    pub async fn build(&self) -> Result<Cluster, IoError> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+        let version = self.version.clone().ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                "ClusterBuilder needs a version",
+            )
+        })?;
+        let name = self.name.clone().unwrap_or_else(|| {
+            format!(
+                "cluster_{}_{}",
+                std::process::id(),
+                NEXT_ID.fetch_add(1, Ordering::SeqCst)
+            )
+        });
+        let ip_strategy = self
+            .ip_strategy
+            .clone()
+            .unwrap_or_else(|| Arc::new(SniffedLoopback::default()));
+        // Nodes are added once the defaults are in place, so that they pick them up.
+        let mut cluster = Cluster::with_ip_strategy(
+            name,
+            version,
+            ip_strategy,
+            vec![],
+            self.install_directory.clone(),
+            self.scylla,
+        )
+        .await?;
+        if let Some(smp) = self.node_smp {
+            cluster.set_default_node_smp(smp);
+        }
+        if let Some(memory) = self.node_memory {
+            cluster.set_default_node_memory(memory);
+        }
+        if let Some(config) = &self.config {
+            cluster.set_default_node_config(config.clone());
+        }
+        if let Some(partitioner) = self.partitioner {
+            cluster.set_partitioner(partitioner);
+        }
+        if self.datacenters.is_empty() {
+            for (datacenter_id, count) in self.topology.iter().enumerate() {
+                for _ in 0..*count {
+                    cluster
+                        .add_node_with_rack(Some(datacenter_id as i32 + 1), None)
+                        .await;
+                }
+            }
+        }
+        for (index, datacenter) in self.datacenters.iter().enumerate() {
+            let datacenter_id = index as i32 + 1;
+            if let Some(config) = &datacenter.config {
+                cluster
+                    .set_dc_default_config(datacenter_id, config.clone())
+                    .await;
+            }
+            cluster.set_dc_seeds(datacenter_id, datacenter.seeds).await;
+            for position in 0..datacenter.nodes.max(0) as usize {
+                let rack = (!datacenter.racks.is_empty())
+                    .then(|| datacenter.racks[position % datacenter.racks.len()].clone());
+                let node = cluster.add_node_with_rack(Some(datacenter_id), rack).await;
+                node.write().await.version = datacenter.version.clone();
+            }
+        }
+        Ok(cluster)
+    }
+
+    /// Builds, initializes and starts the cluster with the
+    /// [`start_options`](ClusterBuilder::start_options), destroying it again if that fails.
+    pub async fn start(self) -> Result<Cluster, IoError> {
+        let mut cluster = self.build().await?;
+        let started = match cluster.init(false).await {
+            Ok(()) => cluster.start(Some(&self.start_options)).await,
+            Err(e) => Err(e),
+        };
+        match started {
+            Ok(()) => Ok(cluster),
+            Err(e) => Err(cluster.discard_after(e).await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::NodeStatus;
+
+    #[tokio::test]
+    async fn test_build() {
+        assert!(ClusterBuilder::new().build().await.is_err());
+
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_cluster_builder");
+        let config = ScyllaConfig::from_flat_string("num_tokens:16").unwrap();
+        let mut cluster = Cluster::builder()
+            .name("builder")
+            .version("release:6.2")
+            .topology(&[2, 1])
+            .install_directory(install_directory.to_string_lossy())
+            .ip_prefix("127.0.232")
+            .node_smp(2)
+            .node_memory(1024)
+            .config(config.clone())
+            .partitioner(Partitioner::Murmur3)
+            .build()
+            .await
+            .unwrap();
+        cluster.logged_cmd().set_dry_run(true);
+        assert_eq!(cluster.name, "builder");
+        assert_eq!(cluster.ip_prefix, "127.0.232.");
+        assert_eq!(cluster.partitioner, Some(Partitioner::Murmur3));
+        let nodes = cluster.nodes();
+        assert_eq!(nodes.len(), 3);
+        let node = nodes[2].read().await;
+        assert_eq!(node.name, "node_2_1");
+        assert_eq!((node.smp, node.memory), (2, 1024));
+        assert_eq!(format!("{:?}", node.config), format!("{:?}", config));
+        drop(node);
+        cluster.destroy().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_datacenters() {
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_datacenters");
+        let mut cluster = Cluster::builder()
+            .name("multi_dc")
+            .version("release:6.2")
+            .install_directory(install_directory.to_string_lossy())
+            .ip_prefix("127.0.214.")
+            .datacenter(DatacenterSpec::new(3).racks(&["r1", "r2"]).seeds(2))
+            .datacenter(
+                DatacenterSpec::new(1)
+                    .version("release:6.1")
+                    .config(ScyllaConfig::from_flat_string("num_tokens:8").unwrap()),
+            )
+            .build()
+            .await
+            .unwrap();
+        cluster.logged_cmd().set_dry_run(true);
+        cluster.set_loopback_aliases(None);
+        cluster.init(false).await.unwrap();
+        let recorded: Vec<_> = cluster
+            .logged_cmd()
+            .recorded_commands()
+            .into_iter()
+            .filter(|command| command.args[0] == "add" || command.args[1] == "setdir")
+            .map(|command| {
+                let end = command.args.iter().position(|arg| arg == "--scylla");
+                let args = &command.args[..end.unwrap_or(command.args.len() - 2)];
+                args.iter()
+                    .filter(|arg| !arg.starts_with("--") || *arg == "--seeds")
+                    .filter(|arg| arg.parse::<u16>().is_err())
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                "add node_1_1 dc1 r1 --seeds",
+                "add node_1_2 dc1 r2 --seeds",
+                "add node_1_3 dc1 r1",
+                "add node_2_1 dc2 --seeds",
+                "node_2_1 setdir -v release:6.1",
+            ]
+        );
+        let node = cluster.nodes()[3].read().await;
+        assert_eq!(
+            format!("{:?}", node.config),
+            "Map({\"num_tokens\": Int(8)})"
+        );
+        drop(node);
+        cluster.nodes()[0].write().await.status = NodeStatus::DELETED;
+        let replacement = cluster.add_node_with_rack(Some(1), None).await;
+        assert!(replacement.read().await.seed);
+        let extra = cluster.add_node_with_rack(Some(2), None).await;
+        assert!(!extra.read().await.seed);
+        cluster.destroy().await.unwrap();
+    }
+}
@@ -1,8 +1,11 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+#[cfg(feature = "config-yaml")]
 use serde_yaml::{Value};
 
-/// Represents arbitrary data
-#[derive(Debug, Clone)]
+/// Represents arbitrary data; serialized as the plain value it holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum ScyllaConfig {
     Null,
     Bool(bool),
@@ -20,6 +23,7 @@ impl Default for ScyllaConfig {
     }
 }
 
+#[cfg(feature = "config-yaml")]
 impl ScyllaConfig {
     pub fn to_yaml(&self) -> Value {
         match self {
@@ -88,8 +92,96 @@ impl ScyllaConfig {
         }
     }
 
-    // Represents config in format 'l1key1.l2key1:val1 l1key1.l2key2:val2 l1key3:val3'
+    /// Reads a YAML file into a ClusterConfig structure
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<ScyllaConfig, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_yaml_str(&content)
+    }
+
+    /// Parses YAML text into a ClusterConfig structure
+    pub fn from_yaml_str(content: &str) -> Result<ScyllaConfig, String> {
+        let value: Value =
+            serde_yaml::from_str(content).map_err(|e| format!("Invalid YAML: {}", e))?;
+        Self::from_yaml(value)
+    }
+}
+
+#[cfg(feature = "config-toml")]
+impl ScyllaConfig {
+    /// Parses a TOML document; tables become maps, datetimes are kept as strings.
+    pub fn from_toml(content: &str) -> Result<ScyllaConfig, String> {
+        let table: toml::Table =
+            toml::from_str(content).map_err(|e| format!("Invalid TOML: {}", e))?;
+        Ok(Self::from_toml_value(toml::Value::Table(table)))
+    }
+
+    fn from_toml_value(value: toml::Value) -> ScyllaConfig {
+        match value {
+            toml::Value::String(s) => ScyllaConfig::String(s),
+            toml::Value::Integer(i) => ScyllaConfig::Int(i),
+            toml::Value::Float(f) => ScyllaConfig::Float(f),
+            toml::Value::Boolean(b) => ScyllaConfig::Bool(b),
+            toml::Value::Datetime(datetime) => ScyllaConfig::String(datetime.to_string()),
+            toml::Value::Array(array) => {
+                ScyllaConfig::List(array.into_iter().map(Self::from_toml_value).collect())
+            }
+            toml::Value::Table(table) => ScyllaConfig::Map(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key, Self::from_toml_value(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl ScyllaConfig {
+    /// Parses a Java `.properties` file such as `cassandra-rackdc.properties`.
+    /// Supports `=`, `:` and whitespace separators, `#`/`!` comments and `\` line
+    /// continuations; values are typed like flat strings and dotted keys become nested maps.
+    pub fn from_properties(content: &str) -> Result<ScyllaConfig, String> {
+        let mut map = HashMap::new();
+        let mut logical_line = String::new();
+        for line in content.lines() {
+            let line = line.trim_start();
+            if logical_line.is_empty() && (line.is_empty() || line.starts_with(['#', '!'])) {
+                continue;
+            }
+            match line.strip_suffix('\\') {
+                Some(continued) => {
+                    logical_line.push_str(continued);
+                    continue;
+                }
+                None => logical_line.push_str(line),
+            }
+            let entry = std::mem::take(&mut logical_line);
+            let (key, value) = match entry.find(['=', ':', ' ', '\t']) {
+                Some(index) => {
+                    let value = entry[index + 1..].trim_start();
+                    let value = value
+                        .strip_prefix(['=', ':'])
+                        .filter(|_| entry[index..].starts_with([' ', '\t']))
+                        .unwrap_or(value);
+                    (entry[..index].trim(), value.trim())
+                }
+                None => (entry.trim(), ""),
+            };
+            insert_dotted_key(&mut map, key, parse_flat_scalar(value))?;
+        }
+        Ok(ScyllaConfig::Map(map))
+    }
+}
+
+impl ScyllaConfig {
+    // Represents config in format 'l1key1.l2key1:val1 l1key1.l2key2:val2 l1key3:[a,b]'
     pub fn to_flat_string(&self) -> String {
+        self.to_flat_entries().join(" ")
+    }
+
+    /// Same as [`to_flat_string`](ScyllaConfig::to_flat_string), one `key:value` entry per item.
+    pub fn to_flat_entries(&self) -> Vec<String> {
         fn flatten_map(
             map: &HashMap<String, ScyllaConfig>,
             prefix: String,
@@ -111,28 +203,8 @@ impl ScyllaConfig {
                     ScyllaConfig::Map(inner_map) => {
                         flatten_map(inner_map, full_key, output);
                     }
-                    ScyllaConfig::String(s) => {
-                        output.push(format!("{}:{}", full_key, s));
-                    }
-                    ScyllaConfig::Int(i) => {
-                        output.push(format!("{}:{}", full_key, i));
-                    }
-                    ScyllaConfig::Float(f) => {
-                        output.push(format!("{}:{}", full_key, f));
-                    }
-                    ScyllaConfig::Bool(b) => {
-                        output.push(format!("{}:{}", full_key, b));
-                    }
-                    ScyllaConfig::Null => {
-                        output.push(format!("{}:null", full_key));
-                    }
-                    ScyllaConfig::List(list) => {
-                        let list_str = list
-                            .iter()
-                            .map(|item| format!("{:?}", item))
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        output.push(format!("{}:[{}]", full_key, list_str));
+                    _ => {
+                        output.push(format!("{}:{}", full_key, format_flat_value(value)));
                     }
                 }
             }
@@ -142,7 +214,58 @@ impl ScyllaConfig {
         if let ScyllaConfig::Map(map) = self {
             flatten_map(map, String::new(), &mut result);
         }
-        result.join(" ")
+        result
+    }
+
+    /// Deep-merges `other` into `self`: maps are merged key by key, any other value replaces.
+    pub fn merge(&mut self, other: &ScyllaConfig) {
+        match (self, other) {
+            (ScyllaConfig::Map(target), ScyllaConfig::Map(source)) => {
+                for (key, value) in source {
+                    match target.get_mut(key) {
+                        Some(existing) => existing.merge(value),
+                        None => {
+                            target.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+            (target, source) => *target = source.clone(),
+        }
+    }
+
+    /// Returns true for `Null` and for maps without any keys.
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ScyllaConfig::Null => true,
+            ScyllaConfig::Map(map) => map.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Parses the format produced by [`to_flat_string`](ScyllaConfig::to_flat_string)
+    /// back into a map, expanding dotted keys into nested maps.
+    pub fn from_flat_string(input: &str) -> Result<ScyllaConfig, String> {
+        let mut parser = FlatParser {
+            chars: input.chars().collect(),
+            pos: 0,
+        };
+        let mut root = HashMap::new();
+        loop {
+            parser.skip_whitespace();
+            if parser.peek().is_none() {
+                break;
+            }
+            let key = parser.parse_key()?;
+            let value = parser.parse_value(false)?;
+            if let Some(c) = parser.peek()
+                && !c.is_whitespace()
+            {
+                return Err(format!("Unexpected '{}' after value of '{}'", c, key));
+            }
+            insert_dotted_key(&mut root, &key, value)?;
+        }
+        Ok(ScyllaConfig::Map(root))
     }
 
     /// Returns a mutable reference to the output of the future.
@@ -168,11 +291,222 @@ impl ScyllaConfig {
     }
 }
 
+/// Formats a non-map value in flat notation: scalars as-is, lists as `[a,b]`, maps nested
+/// in lists as `{k: v, k2: v2}`, the YAML flow form ccm reads them in (`{k:v}` would be the
+/// single key `k:v` to YAML); strings are quoted only when needed to round-trip.
+fn format_flat_value(value: &ScyllaConfig) -> String {
+    match value {
+        ScyllaConfig::Null => "null".to_string(),
+        ScyllaConfig::Bool(b) => b.to_string(),
+        ScyllaConfig::Int(i) => i.to_string(),
+        ScyllaConfig::Float(f) => format!("{:?}", f),
+        ScyllaConfig::String(s) => {
+            if needs_quoting(s) {
+                format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                s.clone()
+            }
+        }
+        ScyllaConfig::List(list) => {
+            let items: Vec<String> = list.iter().map(format_flat_value).collect();
+            format!("[{}]", items.join(","))
+        }
+        ScyllaConfig::Map(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let items: Vec<String> = keys
+                .into_iter()
+                .map(|key| format!("{}: {}", key, format_flat_value(&map[key])))
+                .collect();
+            format!("{{{}}}", items.join(", "))
+        }
+    }
+}
+
+fn needs_quoting(s: &str) -> bool {
+    s.is_empty()
+        || s.chars().any(|c| {
+            c.is_whitespace() || matches!(c, ':' | ',' | '[' | ']' | '{' | '}' | '"' | '\\')
+        })
+        || !matches!(parse_flat_scalar(s), ScyllaConfig::String(_))
+}
+
+fn parse_flat_scalar(s: &str) -> ScyllaConfig {
+    match s {
+        "null" => ScyllaConfig::Null,
+        "true" => ScyllaConfig::Bool(true),
+        "false" => ScyllaConfig::Bool(false),
+        _ => {
+            if let Ok(i) = s.parse::<i64>() {
+                ScyllaConfig::Int(i)
+            } else if let Ok(f) = s.parse::<f64>() {
+                ScyllaConfig::Float(f)
+            } else {
+                ScyllaConfig::String(s.to_string())
+            }
+        }
+    }
+}
+
+fn insert_dotted_key(
+    map: &mut HashMap<String, ScyllaConfig>,
+    key: &str,
+    value: ScyllaConfig,
+) -> Result<(), String> {
+    match key.split_once('.') {
+        None => {
+            map.insert(key.to_string(), value);
+            Ok(())
+        }
+        Some((head, rest)) => {
+            let entry = map
+                .entry(head.to_string())
+                .or_insert_with(|| ScyllaConfig::Map(HashMap::new()));
+            match entry {
+                ScyllaConfig::Map(inner) => insert_dotted_key(inner, rest, value),
+                _ => Err(format!("Key '{}' is both a value and a map", head)),
+            }
+        }
+    }
+}
+
+struct FlatParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl FlatParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(format!("Expected '{}' but found '{}'", expected, c)),
+            None => Err(format!("Expected '{}' but input ended", expected)),
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c == ':' {
+                break;
+            }
+            if c.is_whitespace() || matches!(c, ',' | '[' | ']' | '{' | '}' | '"') {
+                return Err(format!("Unexpected '{}' in key", c));
+            }
+            self.pos += 1;
+        }
+        let key: String = self.chars[start..self.pos].iter().collect();
+        self.expect(':')?;
+        if key.is_empty() {
+            return Err("Empty key".to_string());
+        }
+        Ok(key)
+    }
+
+    fn parse_value(&mut self, nested: bool) -> Result<ScyllaConfig, String> {
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let mut list = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(ScyllaConfig::List(list));
+                }
+                loop {
+                    self.skip_whitespace();
+                    list.push(self.parse_value(true)?);
+                    self.skip_whitespace();
+                    if self.peek() == Some(',') {
+                        self.pos += 1;
+                    } else {
+                        self.expect(']')?;
+                        return Ok(ScyllaConfig::List(list));
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut map = HashMap::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(ScyllaConfig::Map(map));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.parse_key()?;
+                    self.skip_whitespace();
+                    map.insert(key, self.parse_value(true)?);
+                    self.skip_whitespace();
+                    if self.peek() == Some(',') {
+                        self.pos += 1;
+                    } else {
+                        self.expect('}')?;
+                        return Ok(ScyllaConfig::Map(map));
+                    }
+                }
+            }
+            Some('"') => {
+                self.pos += 1;
+                let mut value = String::new();
+                loop {
+                    match self.peek() {
+                        Some('"') => {
+                            self.pos += 1;
+                            return Ok(ScyllaConfig::String(value));
+                        }
+                        Some('\\') => {
+                            self.pos += 1;
+                            match self.peek() {
+                                Some(c) => value.push(c),
+                                None => return Err("Unterminated escape".to_string()),
+                            }
+                            self.pos += 1;
+                        }
+                        Some(c) => {
+                            value.push(c);
+                            self.pos += 1;
+                        }
+                        None => return Err("Unterminated quoted string".to_string()),
+                    }
+                }
+            }
+            _ => {
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || (nested && matches!(c, ',' | ']' | '}')) {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let raw: String = self.chars[start..self.pos].iter().collect();
+                Ok(parse_flat_scalar(&raw))
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "config-yaml")]
     use serde_yaml::Value;
 
+    #[cfg(feature = "config-yaml")]
     #[test]
     fn test_from_yaml_and_to_yaml() {
         // Define a sample YAML string
@@ -205,6 +539,7 @@ mod tests {
         assert_eq!(yaml_value, converted_yaml_value);
     }
 
+    #[cfg(feature = "config-yaml")]
     #[test]
     fn test_to_yaml_empty_structures() {
         // Test empty list
@@ -216,6 +551,7 @@ mod tests {
         assert_eq!(empty_map.to_yaml(), Value::Mapping(serde_yaml::Mapping::new()));
     }
 
+    #[cfg(feature = "config-yaml")]
     #[test]
     fn test_from_yaml_invalid_cases() {
         // Test unsupported YAML type (e.g., unhashable keys)
@@ -229,6 +565,14 @@ mod tests {
         assert!(result.is_err(), "Expected error for invalid YAML type");
     }
 
+    #[test]
+    fn test_merge_nested_maps() {
+        let mut base = ScyllaConfig::from_flat_string("a.b:1 a.c:2 d:x").unwrap();
+        let overlay = ScyllaConfig::from_flat_string("a.c:3 a.e:[1] d.f:true").unwrap();
+        base.merge(&overlay);
+        assert_eq!(base.to_flat_string(), "a.b:1 a.c:3 a.e:[1] d.f:true");
+    }
+
     #[test]
     fn test_to_flat_string_simple_map() {
         let mut map = HashMap::new();
@@ -242,6 +586,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_to_flat_string_nested_map() {
         let mut inner_map = HashMap::new();
         inner_map.insert("inner_key".to_string(), ScyllaConfig::Bool(true));
@@ -283,9 +628,109 @@ mod tests {
         let flat_representation = cluster_config.to_flat_string();
 
         // Lists are serialized as comma-separated values in brackets.
+        assert_eq!(flat_representation, "key_with_list:[1,2,three]");
+    }
+
+    #[test]
+    fn test_flat_string_round_trip() {
+        let mut inner_map = HashMap::new();
+        inner_map.insert("enabled".to_string(), ScyllaConfig::Bool(false));
+        inner_map.insert("ratio".to_string(), ScyllaConfig::Float(2.0));
+
+        let mut map = HashMap::new();
+        map.insert("nested".to_string(), ScyllaConfig::Map(inner_map.clone()));
+        map.insert("null_key".to_string(), ScyllaConfig::Null);
+        map.insert(
+            "numeric_string".to_string(),
+            ScyllaConfig::String("42".to_string()),
+        );
+        map.insert(
+            "spaced".to_string(),
+            ScyllaConfig::String("a \"b\": c".to_string()),
+        );
+        map.insert(
+            "list".to_string(),
+            ScyllaConfig::List(vec![
+                ScyllaConfig::Int(1),
+                ScyllaConfig::String("two, three".to_string()),
+                ScyllaConfig::List(vec![]),
+                ScyllaConfig::Map(inner_map),
+            ]),
+        );
+
+        let flat = ScyllaConfig::Map(map).to_flat_string();
         assert_eq!(
-            flat_representation,
-            "key_with_list:[Int(1), Int(2), String(\"three\")]"
+            flat,
+            "list:[1,\"two, three\",[],{enabled: false, ratio: 2.0}] nested.enabled:false \
+             nested.ratio:2.0 null_key:null numeric_string:\"42\" spaced:\"a \\\"b\\\": c\""
+        );
+        let parsed = ScyllaConfig::from_flat_string(&flat).expect("Failed to parse flat string");
+        assert_eq!(parsed.to_flat_string(), flat);
+    }
+
+    #[test]
+    fn test_flat_string_round_trip_of_maps_in_lists() {
+        let flat = "seed_provider:[{class_name: org.apache.cassandra.locator.SimpleSeedProvider, \
+                    parameters: [{seeds: \"127.0.0.1,127.0.0.2\"}]}]";
+        let parsed = ScyllaConfig::from_flat_string(flat).expect("Failed to parse flat string");
+        let ScyllaConfig::Map(map) = &parsed else {
+            panic!("not a map: {:?}", parsed);
+        };
+        let ScyllaConfig::List(providers) = &map["seed_provider"] else {
+            panic!("not a list: {:?}", map["seed_provider"]);
+        };
+        let ScyllaConfig::Map(provider) = &providers[0] else {
+            panic!("not a map: {:?}", providers[0]);
+        };
+        assert!(matches!(
+            &provider["class_name"],
+            ScyllaConfig::String(name) if name.ends_with("SimpleSeedProvider")
+        ));
+        assert_eq!(parsed.to_flat_string(), flat);
+        // Compact input parses the same.
+        let compact = flat.replace(": ", ":").replace(", ", ",");
+        let reparsed = ScyllaConfig::from_flat_string(&compact).unwrap();
+        assert_eq!(reparsed.to_flat_string(), flat);
+    }
+
+    #[test]
+    fn test_from_flat_string_invalid_cases() {
+        assert!(ScyllaConfig::from_flat_string("no_colon").is_err());
+        assert!(ScyllaConfig::from_flat_string("key:[1,2").is_err());
+        assert!(ScyllaConfig::from_flat_string("key:\"open").is_err());
+        assert!(ScyllaConfig::from_flat_string("a:1 a.b:2").is_err());
+    }
+
+    #[cfg(feature = "config-toml")]
+    #[test]
+    fn test_from_toml() {
+        let config = ScyllaConfig::from_toml(
+            r#"
+            num_tokens = 16
+            seeds = ["127.0.0.1", "127.0.0.2"]
+
+            [client_encryption_options]
+            enabled = true
+            "#,
+        )
+        .expect("Failed to parse TOML");
+        assert_eq!(
+            config.to_flat_string(),
+            "client_encryption_options.enabled:true num_tokens:16 seeds:[127.0.0.1,127.0.0.2]"
+        );
+        assert!(ScyllaConfig::from_toml("key = ").is_err());
+    }
+
+    #[test]
+    fn test_from_properties() {
+        let config = ScyllaConfig::from_properties(
+            "# rackdc\ndc=dc1\nrack : rack1\nprefer_local true\n\
+             ! comment\nextra.timeout_ms = 10\\\n  00\n",
+        )
+        .expect("Failed to parse properties");
+        assert_eq!(
+            config.to_flat_string(),
+            "dc:dc1 extra.timeout_ms:1000 prefer_local:true rack:rack1"
         );
     }
 
@@ -0,0 +1,505 @@
+use crate::cluster::Cluster;
+use crate::cluster_builder::ClusterBuilder;
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Error as IoError;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Describes clusters a [`ClusterPool`] builds.
+#[derive(Debug, Clone)]
+pub struct ClusterSpec {
+    /// Clusters are named `<name_prefix>_<n>`.
+    pub name_prefix: String,
+    pub version: String,
+    pub topology: Vec<i32>,
+    pub install_directory: String,
+    pub scylla: bool,
+}
+
+impl ClusterSpec {
+    pub fn new(name_prefix: impl Into<String>, version: impl Into<String>) -> Self {
+        ClusterSpec {
+            name_prefix: name_prefix.into(),
+            version: version.into(),
+            topology: vec![1],
+            install_directory: Cluster::default_install_directory(),
+            scylla: true,
+        }
+    }
+
+    /// Builder of the cluster named `name`, e.g. to set more than the spec describes.
+    pub fn builder(&self, name: String) -> ClusterBuilder {
+        Cluster::builder()
+            .name(name)
+            .version(self.version.clone())
+            .topology(&self.topology)
+            .install_directory(self.install_directory.clone())
+            .scylla(self.scylla)
+    }
+
+    /// Creates, initializes and starts a cluster named `name`, destroying it again if
+    /// that fails, see [`ClusterBuilder::start`].
+    pub async fn build(&self, name: String) -> Result<Cluster, IoError> {
+        self.builder(name).start().await
+    }
+
+    /// Picks the next cluster name no pool of this or another process uses, skipping names
+    /// whose `<install_directory>/<name>.lease` lock file a live process holds and names of
+    /// clusters already in the install directory. The lock is held for as long as the file
+    /// returned is; without a usable install directory only this process is protected.
+    fn claim_name(&self, next_id: &AtomicUsize) -> (String, Option<File>) {
+        let directory = Path::new(&self.install_directory);
+        loop {
+            let id = next_id.fetch_add(1, Ordering::SeqCst) + 1;
+            let name = format!("{}_{}", self.name_prefix, id);
+            let lock = std::fs::create_dir_all(directory).and_then(|_| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(directory.join(format!("{}.lease", name)))
+            });
+            let lock = match lock {
+                Ok(file) => match file.try_lock() {
+                    Ok(()) => Some(file),
+                    Err(std::fs::TryLockError::WouldBlock) => continue,
+                    Err(std::fs::TryLockError::Error(_)) => None,
+                },
+                Err(_) => None,
+            };
+            // Checked once locked, so that no other pool creates it meanwhile.
+            if directory.join(&name).exists() {
+                continue;
+            }
+            return (name, lock);
+        }
+    }
+}
+
+/// Cluster in a [`ClusterPool`], with the lease lock keeping other processes off its name.
+struct Pooled {
+    spec: Arc<ClusterSpec>,
+    cluster: Cluster,
+    lock: Option<File>,
+}
+
+/// Builds clusters ahead of time so suites can overlap cluster creation with test execution.
+/// Clusters [`provision`](ClusterPool::provision)ed into the pool are handed out to
+/// concurrent tests as [`ClusterLease`]s and come back once the lease is dropped; the pool
+/// lock is never held across a build, so tests leasing clusters don't wait on each other's
+/// creation.
+#[derive(Default)]
+pub struct ClusterPool {
+    next_id: Arc<AtomicUsize>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<PoolState>,
+    /// Signalled whenever a cluster becomes idle or a build fails.
+    changed: Notify,
+}
+
+#[derive(Default)]
+struct PoolState {
+    idle: Vec<Pooled>,
+    /// Clusters being built or leased out, by name prefix.
+    busy: HashMap<String, usize>,
+    /// Failed builds, reported when nothing is left to lease.
+    failures: Vec<String>,
+}
+
+impl ClusterPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts building `count` clusters from `spec` in background tasks and returns
+    /// immediately; use the returned handle to wait for them. Names are claimed as for
+    /// [`provision`](ClusterPool::provision), until the cluster directory exists.
+    pub fn prewarm(&self, spec: ClusterSpec, count: usize) -> Prewarmed {
+        let spec = Arc::new(spec);
+        let tasks = (0..count)
+            .map(|_| {
+                let spec = spec.clone();
+                let next_id = self.next_id.clone();
+                tokio::spawn(async move {
+                    let (name, _lock) = spec.claim_name(&next_id);
+                    spec.build(name).await
+                })
+            })
+            .collect();
+        Prewarmed { tasks }
+    }
+
+    /// Starts building `count` clusters from `spec` in background tasks, adding each to the
+    /// pool once it is up. Build failures are reported by [`lease`](ClusterPool::lease) when
+    /// no cluster is left to wait for.
+    ///
+    /// Pools of several processes may share an install directory: each cluster holds a
+    /// `<name>.lease` lock file there while in the pool, so that other pools pick other names.
+    pub fn provision(&self, spec: ClusterSpec, count: usize) {
+        let spec = Arc::new(spec);
+        *self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .busy
+            .entry(spec.name_prefix.clone())
+            .or_default() += count;
+        for _ in 0..count {
+            let spec = spec.clone();
+            let next_id = self.next_id.clone();
+            let shared = self.shared.clone();
+            let mut slot = BusySlot {
+                shared: shared.clone(),
+                name_prefix: spec.name_prefix.clone(),
+                name: None,
+            };
+            tokio::spawn(async move {
+                let (name, lock) = spec.claim_name(&next_id);
+                slot.name = Some(name.clone());
+                let result = spec.build(name.clone()).await;
+                let mut state = shared.state.lock().unwrap();
+                match result {
+                    Ok(cluster) => state.idle.push(Pooled {
+                        spec,
+                        cluster,
+                        lock,
+                    }),
+                    Err(e) => state.failures.push(format!("{}: {}", name, e)),
+                }
+                drop(state);
+                slot.name = None;
+                drop(slot);
+            });
+        }
+    }
+
+    /// Adds a cluster built elsewhere, e.g. by [`Cluster::ensure`], under `spec`.
+    pub fn add(&self, spec: ClusterSpec, cluster: Cluster) {
+        self.shared.state.lock().unwrap().idle.push(Pooled {
+            spec: Arc::new(spec),
+            cluster,
+            lock: None,
+        });
+        self.shared.changed.notify_waiters();
+    }
+
+    /// Leases any idle cluster, waiting for one while others are being built or leased out.
+    /// Fails once the pool has nothing left to hand out.
+    pub async fn lease(&self) -> Result<ClusterLease, IoError> {
+        self.lease_where(None).await
+    }
+
+    /// Same as [`lease`](ClusterPool::lease), limited to clusters of the spec with
+    /// `name_prefix`.
+    pub async fn lease_of(&self, name_prefix: &str) -> Result<ClusterLease, IoError> {
+        self.lease_where(Some(name_prefix)).await
+    }
+
+    async fn lease_where(&self, name_prefix: Option<&str>) -> Result<ClusterLease, IoError> {
+        let matches = |prefix: &str| name_prefix.is_none_or(|name_prefix| name_prefix == prefix);
+        loop {
+            // Registered before looking, so that a cluster returned meanwhile still wakes us.
+            let mut changed = std::pin::pin!(self.shared.changed.notified());
+            changed.as_mut().enable();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some(index) = state
+                    .idle
+                    .iter()
+                    .position(|pooled| matches(&pooled.spec.name_prefix))
+                {
+                    let pooled = state.idle.swap_remove(index);
+                    *state
+                        .busy
+                        .entry(pooled.spec.name_prefix.clone())
+                        .or_default() += 1;
+                    return Ok(ClusterLease {
+                        cluster: Some(pooled.cluster),
+                        spec: pooled.spec,
+                        lock: pooled.lock,
+                        shared: self.shared.clone(),
+                    });
+                }
+                if !state
+                    .busy
+                    .iter()
+                    .any(|(prefix, busy)| *busy > 0 && matches(prefix))
+                {
+                    let mut message = "no cluster left to lease".to_string();
+                    if !state.failures.is_empty() {
+                        message =
+                            format!("{}, failed builds: {}", message, state.failures.join("; "));
+                    }
+                    return Err(IoError::new(std::io::ErrorKind::NotFound, message));
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Destroys the idle clusters; call once every lease is returned.
+    pub async fn destroy(&self) -> Result<(), IoError> {
+        let idle = std::mem::take(&mut self.shared.state.lock().unwrap().idle);
+        let mut result = Ok(());
+        for mut pooled in idle {
+            if let Err(e) = pooled.cluster.destroy().await {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+/// Cluster leased from a [`ClusterPool`], returned to the pool when dropped. Tests should
+/// leave it as they found it, or [`discard`](ClusterLease::discard) it.
+pub struct ClusterLease {
+    cluster: Option<Cluster>,
+    spec: Arc<ClusterSpec>,
+    lock: Option<File>,
+    shared: Arc<Shared>,
+}
+
+impl ClusterLease {
+    pub fn spec(&self) -> &ClusterSpec {
+        &self.spec
+    }
+
+    /// Destroys the cluster instead of returning it, e.g. after a test broke it.
+    pub async fn discard(mut self) -> Result<(), IoError> {
+        let mut cluster = self.cluster.take().unwrap();
+        cluster.destroy().await
+    }
+}
+
+impl Deref for ClusterLease {
+    type Target = Cluster;
+
+    fn deref(&self) -> &Cluster {
+        self.cluster.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for ClusterLease {
+    fn deref_mut(&mut self) -> &mut Cluster {
+        self.cluster.as_mut().unwrap()
+    }
+}
+
+impl Drop for ClusterLease {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(busy) = state.busy.get_mut(&self.spec.name_prefix) {
+            *busy -= 1;
+        }
+        if let Some(cluster) = self.cluster.take() {
+            state.idle.push(Pooled {
+                spec: self.spec.clone(),
+                cluster,
+                lock: self.lock.take(),
+            });
+        }
+        drop(state);
+        self.shared.changed.notify_waiters();
+    }
+}
+
+/// Cluster of a [`ClusterPool::provision`] call being built, counted as busy until dropped,
+/// so that a build that panics doesn't leave [`ClusterPool::lease`] waiting for it forever.
+struct BusySlot {
+    shared: Arc<Shared>,
+    name_prefix: String,
+    /// Cluster being built, reported as failed if the slot is dropped while it is set.
+    name: Option<String>,
+}
+
+impl Drop for BusySlot {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(busy) = state.busy.get_mut(&self.name_prefix) {
+            *busy -= 1;
+        }
+        if let Some(name) = self.name.take() {
+            state.failures.push(format!("{}: build aborted", name));
+        }
+        drop(state);
+        self.shared.changed.notify_waiters();
+    }
+}
+
+/// Clusters being built by [`ClusterPool::prewarm`]. Clusters not taken before the handle is
+/// dropped are destroyed once built, in a task of their own, rather than aborted halfway
+/// through their build and left behind.
+pub struct Prewarmed {
+    tasks: FuturesUnordered<JoinHandle<Result<Cluster, IoError>>>,
+}
+
+impl Prewarmed {
+    /// Number of clusters not yet returned by [`next`](Prewarmed::next).
+    pub fn pending(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Waits for whichever cluster finishes first; `None` once all were returned.
+    pub async fn next(&mut self) -> Option<Result<Cluster, IoError>> {
+        let joined = self.tasks.next().await?;
+        Some(joined.unwrap_or_else(|e| Err(IoError::other(e))))
+    }
+
+    /// Waits until every cluster is ready, in completion order.
+    pub async fn ready(mut self) -> Vec<Result<Cluster, IoError>> {
+        let mut clusters = Vec::with_capacity(self.pending());
+        while let Some(cluster) = self.next().await {
+            clusters.push(cluster);
+        }
+        clusters
+    }
+}
+
+impl Drop for Prewarmed {
+    fn drop(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        let mut tasks = std::mem::take(&mut self.tasks);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    while let Some(joined) = tasks.next().await {
+                        if let Ok(Ok(mut cluster)) = joined {
+                            cluster.destroy().await.ok();
+                        }
+                    }
+                });
+            }
+            Err(_) => tasks.iter().for_each(JoinHandle::abort),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prewarm_reports_each_build_failure() {
+        let blocker = std::env::temp_dir().join("ccm_binding_test_prewarm_blocker");
+        tokio::fs::write(&blocker, "not a directory").await.unwrap();
+        let mut spec = ClusterSpec::new("prewarm", "release:6.2");
+        spec.install_directory = blocker.to_string_lossy().into_owned();
+
+        let pool = ClusterPool::new();
+        let prewarmed = pool.prewarm(spec, 2);
+        assert_eq!(prewarmed.pending(), 2);
+
+        let results = prewarmed.ready().await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.is_err()));
+        tokio::fs::remove_file(&blocker).await.unwrap();
+    }
+
+    #[test]
+    fn test_claim_name() {
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_claim_name");
+        std::fs::remove_dir_all(&install_directory).ok();
+        let mut spec = ClusterSpec::new("claimed", "release:6.2");
+        spec.install_directory = install_directory.to_string_lossy().into_owned();
+        let (name, lock) = spec.claim_name(&AtomicUsize::new(0));
+        assert_eq!(name, "claimed_1");
+        assert!(lock.is_some());
+        std::fs::create_dir_all(install_directory.join("claimed_2")).unwrap();
+
+        // Another pool, e.g. of another process, skips the held and the existing name.
+        let other_pool = AtomicUsize::new(0);
+        assert_eq!(spec.claim_name(&other_pool).0, "claimed_3");
+        drop(lock);
+        assert_eq!(spec.claim_name(&AtomicUsize::new(0)).0, "claimed_1");
+        std::fs::remove_dir_all(&install_directory).ok();
+    }
+
+    #[tokio::test]
+    async fn test_panicking_build_frees_its_slot() {
+        let pool = ClusterPool::new();
+        pool.shared
+            .state
+            .lock()
+            .unwrap()
+            .busy
+            .insert("panicking".to_string(), 1);
+        let slot = BusySlot {
+            shared: pool.shared.clone(),
+            name_prefix: "panicking".to_string(),
+            name: Some("panicking_1".to_string()),
+        };
+        let build = tokio::spawn(async move {
+            let _slot = slot;
+            panic!("build panicked");
+        });
+        assert!(build.await.is_err());
+        let err = pool.lease_of("panicking").await.err().unwrap();
+        assert!(
+            err.to_string().contains("panicking_1: build aborted"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leases() {
+        let spec = ClusterSpec::new("leased", "release:6.2");
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_leases");
+        let cluster = Cluster::new(
+            "leased_1".to_string(),
+            spec.version.clone(),
+            Some("127.0.227."),
+            vec![1],
+            install_directory.to_string_lossy().into_owned(),
+            true,
+        )
+        .await
+        .unwrap();
+        cluster.logged_cmd().set_dry_run(true);
+        let pool = Arc::new(ClusterPool::new());
+        pool.add(spec, cluster);
+
+        let lease = pool.lease().await.unwrap();
+        assert_eq!(lease.name, "leased_1");
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pool.lease_of("leased")
+                    .await
+                    .map(|lease| lease.name.clone())
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        drop(lease);
+        assert_eq!(waiting.await.unwrap().unwrap(), "leased_1");
+        assert_eq!(
+            pool.lease_of("other").await.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::NotFound)
+        );
+
+        let blocker = std::env::temp_dir().join("ccm_binding_test_leases_blocker");
+        tokio::fs::write(&blocker, "not a directory").await.unwrap();
+        let mut failing = ClusterSpec::new("failing", "release:6.2");
+        failing.install_directory = blocker.to_string_lossy().into_owned();
+        pool.provision(failing, 1);
+        let err = pool.lease_of("failing").await.err().unwrap();
+        assert!(err.to_string().contains("failing_1"), "{}", err);
+        tokio::fs::remove_file(&blocker).await.unwrap();
+        pool.destroy().await.unwrap();
+    }
+}
@@ -0,0 +1,377 @@
+use crate::cluster_config::ScyllaConfig;
+use std::collections::HashSet;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::Path;
+use thiserror::Error;
+
+/// Top-level keys understood by both Scylla and Cassandra.
+const COMMON_KEYS: &[&str] = &[
+    "authenticator",
+    "authorizer",
+    "auto_bootstrap",
+    "auto_snapshot",
+    "batch_size_fail_threshold_in_kb",
+    "batch_size_warn_threshold_in_kb",
+    "cas_contention_timeout_in_ms",
+    "client_encryption_options",
+    "cluster_name",
+    "column_index_size_in_kb",
+    "commitlog_directory",
+    "commitlog_segment_size_in_mb",
+    "commitlog_sync",
+    "commitlog_sync_period_in_ms",
+    "commitlog_total_space_in_mb",
+    "compaction_throughput_mb_per_sec",
+    "counter_write_request_timeout_in_ms",
+    "data_file_directories",
+    "endpoint_snitch",
+    "hinted_handoff_enabled",
+    "hints_directory",
+    "incremental_backups",
+    "initial_token",
+    "internode_compression",
+    "listen_address",
+    "listen_interface",
+    "max_hint_window_in_ms",
+    "native_transport_port",
+    "native_transport_port_ssl",
+    "num_tokens",
+    "partitioner",
+    "permissions_validity_in_ms",
+    "phi_convict_threshold",
+    "range_request_timeout_in_ms",
+    "read_request_timeout_in_ms",
+    "request_timeout_in_ms",
+    "role_manager",
+    "rpc_address",
+    "rpc_interface",
+    "rpc_port",
+    "saved_caches_directory",
+    "seed_provider",
+    "server_encryption_options",
+    "snapshot_before_compaction",
+    "ssl_storage_port",
+    "start_native_transport",
+    "start_rpc",
+    "storage_port",
+    "tombstone_failure_threshold",
+    "tombstone_warn_threshold",
+    "truncate_request_timeout_in_ms",
+    "write_request_timeout_in_ms",
+    "broadcast_address",
+    "broadcast_rpc_address",
+];
+
+/// Top-level keys specific to Scylla.
+const SCYLLA_KEYS: &[&str] = &[
+    "alternator_port",
+    "alternator_https_port",
+    "alternator_address",
+    "alternator_write_isolation",
+    "api_address",
+    "api_port",
+    "api_ui_dir",
+    "api_doc_dir",
+    "consistent_cluster_management",
+    "developer_mode",
+    "enable_tablets",
+    "enable_user_defined_functions",
+    "experimental",
+    "experimental_features",
+    "force_schema_commit_log",
+    "murmur3_partitioner_ignore_msb_bits",
+    "overprovisioned",
+    "prometheus_address",
+    "prometheus_port",
+    "ring_delay_ms",
+    "skip_wait_for_gossip_to_settle",
+    "tablets_mode_for_new_keyspaces",
+    "workdir",
+];
+
+/// Top-level keys specific to Cassandra.
+const CASSANDRA_KEYS: &[&str] = &[
+    "concurrent_reads",
+    "concurrent_writes",
+    "concurrent_counter_writes",
+    "disk_access_mode",
+    "enable_materialized_views",
+    "enable_sasi_indexes",
+    "enable_user_defined_functions",
+    "enable_scripted_user_defined_functions",
+    "key_cache_size_in_mb",
+    "memtable_allocation_type",
+    "row_cache_size_in_mb",
+];
+
+/// Top-level Scylla keys picked up on SIGHUP without a restart.
+const SCYLLA_LIVE_UPDATABLE_KEYS: &[&str] = &[
+    "batch_size_fail_threshold_in_kb",
+    "batch_size_warn_threshold_in_kb",
+    "cas_contention_timeout_in_ms",
+    "compaction_enforce_min_threshold",
+    "compaction_static_shares",
+    "compaction_throughput_mb_per_sec",
+    "counter_write_request_timeout_in_ms",
+    "permissions_cache_max_entries",
+    "permissions_update_interval_in_ms",
+    "permissions_validity_in_ms",
+    "query_tombstone_page_limit",
+    "range_request_timeout_in_ms",
+    "read_request_timeout_in_ms",
+    "request_timeout_in_ms",
+    "stream_io_throughput_mb_per_sec",
+    "tombstone_warn_threshold",
+    "truncate_request_timeout_in_ms",
+    "write_request_timeout_in_ms",
+];
+
+/// Top-level Cassandra keys reloadable at runtime (`nodetool reloadseeds`).
+const CASSANDRA_LIVE_UPDATABLE_KEYS: &[&str] = &["seed_provider"];
+
+/// Whether a change of top-level `key` can be applied without restarting the server.
+pub fn is_live_updatable(scylla: bool, key: &str) -> bool {
+    if scylla {
+        SCYLLA_LIVE_UPDATABLE_KEYS.contains(&key)
+    } else {
+        CASSANDRA_LIVE_UPDATABLE_KEYS.contains(&key)
+    }
+}
+
+/// Key rejected by a [`ConfigSchema`], with the closest known key if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    pub key: String,
+    pub suggestion: Option<String>,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown config keys for {node}: {}", describe(.keys))]
+pub struct UnknownConfigKeys {
+    pub node: String,
+    pub keys: Vec<UnknownKey>,
+}
+
+fn describe(keys: &[UnknownKey]) -> String {
+    keys.iter()
+        .map(|k| match &k.suggestion {
+            Some(suggestion) => format!("{} (did you mean {}?)", k.key, suggestion),
+            None => k.key.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl From<UnknownConfigKeys> for IoError {
+    fn from(e: UnknownConfigKeys) -> Self {
+        IoError::new(ErrorKind::InvalidInput, e)
+    }
+}
+
+/// What to do when a config applied to a node has keys outside the schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditMode {
+    /// Log the unknown keys to the ccm log and apply the config anyway.
+    Warn,
+    /// Refuse to apply the config.
+    Strict,
+}
+
+/// Schema check run every time a config is pushed to a node.
+#[derive(Debug, Clone)]
+pub struct ConfigAudit {
+    pub schema: ConfigSchema,
+    pub mode: AuditMode,
+}
+
+impl ConfigAudit {
+    pub fn new(schema: ConfigSchema, mode: AuditMode) -> Self {
+        ConfigAudit { schema, mode }
+    }
+}
+
+/// Set of top-level config keys accepted by a server.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSchema {
+    keys: HashSet<String>,
+}
+
+impl ConfigSchema {
+    /// Schema shipped with the crate for Scylla or Cassandra.
+    pub fn builtin(scylla: bool) -> Self {
+        let specific = if scylla { SCYLLA_KEYS } else { CASSANDRA_KEYS };
+        ConfigSchema {
+            keys: COMMON_KEYS
+                .iter()
+                .chain(specific)
+                .map(|key| key.to_string())
+                .collect(),
+        }
+    }
+
+    /// Extracts option names from `scylla --help` output; `--listen-address` becomes
+    /// `listen_address`.
+    pub fn from_help_text(help: &str) -> Self {
+        let keys = help
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix("--"))
+            .map(|option| option.split(['=', ',', ' ']).next().unwrap_or(option))
+            .filter(|option| {
+                !option.is_empty()
+                    && option
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+            .map(|option| option.replace('-', "_"))
+            .collect();
+        ConfigSchema { keys }
+    }
+
+    /// Introspects the options of a Scylla binary by running it with `--help`. Fails if the
+    /// binary exits with an error or prints no options, rather than returning a schema that
+    /// would reject every key.
+    pub async fn from_scylla_binary(path: &Path) -> Result<Self, IoError> {
+        let output = tokio::process::Command::new(path)
+            .arg("--help")
+            .output()
+            .await?;
+        if !output.status.success() {
+            return Err(IoError::other(format!(
+                "{} --help failed with {}: {}",
+                path.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let schema = Self::from_help_text(&String::from_utf8_lossy(&output.stdout));
+        if schema.keys.is_empty() {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("{} --help printed no options", path.display()),
+            ));
+        }
+        Ok(schema)
+    }
+
+    /// Adds the keys of `other`, e.g. introspected options on top of the builtin schema.
+    pub fn extend(&mut self, other: &ConfigSchema) {
+        self.keys.extend(other.keys.iter().cloned());
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// Returns top-level keys of `config` not present in the schema.
+    pub fn unknown_keys(&self, config: &ScyllaConfig) -> Vec<UnknownKey> {
+        let ScyllaConfig::Map(map) = config else {
+            return vec![];
+        };
+        let mut keys: Vec<&String> = map.keys().filter(|key| !self.contains(key)).collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| UnknownKey {
+                key: key.clone(),
+                suggestion: self.closest(key),
+            })
+            .collect()
+    }
+
+    /// Fails with [`UnknownConfigKeys`] if `config` has keys outside the schema.
+    pub fn validate(&self, node: &str, config: &ScyllaConfig) -> Result<(), UnknownConfigKeys> {
+        let keys = self.unknown_keys(config);
+        if keys.is_empty() {
+            Ok(())
+        } else {
+            Err(UnknownConfigKeys {
+                node: node.to_string(),
+                keys,
+            })
+        }
+    }
+
+    fn closest(&self, key: &str) -> Option<String> {
+        self.keys
+            .iter()
+            .map(|known| (edit_distance(key, known), known))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, known)| known.clone())
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_misspelled_keys() {
+        let schema = ConfigSchema::builtin(true);
+        let config =
+            ScyllaConfig::from_flat_string("num_tokens:16 athenticator:x totally_new:1").unwrap();
+
+        let err = schema.validate("node_1_1", &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown config keys for node_1_1: athenticator (did you mean authenticator?), totally_new"
+        );
+        assert!(
+            schema
+                .validate("node_1_1", &ScyllaConfig::default())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_is_live_updatable() {
+        assert!(is_live_updatable(true, "compaction_throughput_mb_per_sec"));
+        assert!(!is_live_updatable(true, "num_tokens"));
+        assert!(is_live_updatable(false, "seed_provider"));
+        assert!(!is_live_updatable(
+            false,
+            "compaction_throughput_mb_per_sec"
+        ));
+    }
+
+    #[test]
+    fn test_from_help_text() {
+        let help = "Scylla options:\n  --listen-address arg (=localhost)  address\n  \
+                    --smp arg\n  --developer-mode arg\n  -h [ --help ]";
+        let schema = ConfigSchema::from_help_text(help);
+        assert!(schema.contains("listen_address"));
+        assert!(schema.contains("developer_mode"));
+        assert!(schema.contains("smp"));
+        assert!(!schema.contains("arg"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_from_scylla_binary_checks_exit_status() {
+        let err = ConfigSchema::from_scylla_binary(Path::new("false"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("false --help failed"), "{}", err);
+        // `true --help` of coreutils prints its own usage, so use a script that prints nothing.
+        let silent = std::env::temp_dir().join("ccm_binding_test_silent_scylla");
+        std::fs::write(&silent, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&silent, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let err = ConfigSchema::from_scylla_binary(&silent).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        std::fs::remove_file(&silent).ok();
+    }
+}
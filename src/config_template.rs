@@ -0,0 +1,191 @@
+use crate::cluster_config::ScyllaConfig;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A YAML config with `${variable}` placeholders, rendered per node.
+/// Use `$$` to produce a literal `$`.
+#[derive(Debug, Clone)]
+pub struct ConfigTemplate {
+    pub name: String,
+    source: String,
+}
+
+impl ConfigTemplate {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        ConfigTemplate {
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Loads a template from a YAML file; the template is named after the file stem.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid template file name: {}", path.display()))?;
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Self::new(name, source))
+    }
+
+    /// Returns the names of all placeholders used by the template.
+    pub fn variables(&self) -> Result<Vec<String>, String> {
+        let mut names = Vec::new();
+        substitute(&self.source, |name| {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+            Some(String::new())
+        })?;
+        Ok(names)
+    }
+
+    /// Substitutes placeholders with `vars` and parses the result.
+    /// Fails if the template references a variable missing from `vars`.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<ScyllaConfig, String> {
+        let rendered = substitute(&self.source, |name| vars.get(name).cloned())
+            .map_err(|e| format!("Template '{}': {}", self.name, e))?;
+        ScyllaConfig::from_yaml_str(&rendered)
+            .map_err(|e| format!("Template '{}': {}", self.name, e))
+    }
+}
+
+fn substitute(
+    source: &str,
+    mut lookup: impl FnMut(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(pos) = rest.find('$') {
+        output.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            output.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| "Unterminated '${' placeholder".to_string())?;
+            let name = &after[..end];
+            let value = lookup(name).ok_or_else(|| format!("Unknown variable '{}'", name))?;
+            output.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// A set of named templates, e.g. a directory of canned TLS-on/auth-on/CDC-on configs.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateLibrary {
+    templates: HashMap<String, ConfigTemplate>,
+}
+
+impl TemplateLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every `*.yaml` / `*.yml` file in `dir` as a template named after its file stem.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, String> {
+        let dir = dir.as_ref();
+        let mut library = Self::new();
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+                .path();
+            let is_yaml = path
+                .extension()
+                .is_some_and(|ext| ext == "yaml" || ext == "yml");
+            if path.is_file() && is_yaml {
+                library.add(ConfigTemplate::from_file(&path)?);
+            }
+        }
+        Ok(library)
+    }
+
+    pub fn add(&mut self, template: ConfigTemplate) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConfigTemplate> {
+        self.templates.get(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.templates.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_substitutes_variables() {
+        let template = ConfigTemplate::new(
+            "tls",
+            "cluster_name: ${cluster_name}\nlisten_address: ${node_ip}\nprice: $$5\n",
+        );
+        let vars = HashMap::from([
+            ("cluster_name".to_string(), "c1".to_string()),
+            ("node_ip".to_string(), "127.0.1.1".to_string()),
+        ]);
+
+        let config = template.render(&vars).expect("Failed to render template");
+        assert_eq!(
+            config.to_flat_string(),
+            "cluster_name:c1 listen_address:127.0.1.1 price:$5"
+        );
+        assert_eq!(
+            template.variables().unwrap(),
+            vec!["cluster_name".to_string(), "node_ip".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_render_rejects_unknown_variables() {
+        let template = ConfigTemplate::new("broken", "a: ${missing}\n");
+        assert!(template.render(&HashMap::new()).is_err());
+
+        let template = ConfigTemplate::new("broken", "a: ${unterminated\n");
+        assert!(template.render(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_library_from_dir() {
+        let dir = std::env::temp_dir().join("ccm_binding_test_library_from_dir");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("auth-on.yaml"),
+            "authenticator: PasswordAuthenticator\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("cdc-on.yml"), "experimental_features: [cdc]\n").unwrap();
+        std::fs::write(dir.join("README.md"), "not a template").unwrap();
+
+        let library = TemplateLibrary::from_dir(&dir).expect("Failed to load library");
+        assert_eq!(library.names(), vec!["auth-on", "cdc-on"]);
+        let config = library
+            .get("auth-on")
+            .unwrap()
+            .render(&HashMap::new())
+            .unwrap();
+        assert_eq!(
+            config.to_flat_string(),
+            "authenticator:PasswordAuthenticator"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
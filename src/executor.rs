@@ -0,0 +1,176 @@
+use crate::ccm_cli::{CommandResult, LoggedCmd, RunOptions, shell_quote};
+use futures::future::BoxFuture;
+use std::io::Error as IoError;
+use std::sync::Arc;
+
+/// Runs commands on behalf of a cluster: [`LoggedCmd`] runs them locally, [`SshExecutor`]
+/// on a remote machine. See [`Cluster::with_executor`](crate::cluster::Cluster::with_executor).
+pub trait CommandExecutor: Send + Sync {
+    fn run_command<'a>(
+        &'a self,
+        command: &'a str,
+        args: &'a [&'a str],
+        opts: Option<RunOptions>,
+    ) -> BoxFuture<'a, Result<CommandResult, IoError>>;
+
+    /// Whether commands run on another machine, whose processes this one can't see.
+    fn is_remote(&self) -> bool {
+        false
+    }
+}
+
+impl CommandExecutor for LoggedCmd {
+    fn run_command<'a>(
+        &'a self,
+        command: &'a str,
+        args: &'a [&'a str],
+        opts: Option<RunOptions>,
+    ) -> BoxFuture<'a, Result<CommandResult, IoError>> {
+        Box::pin(LoggedCmd::run_command(self, command, args, opts))
+    }
+}
+
+/// Runs commands on `destination` (`host` or `user@host`) through `ssh`, e.g. to drive ccm
+/// on a lab machine. The remote output is streamed back into `logged_cmd`'s log, and
+/// `env`, `cwd` and `escalation` apply on the remote side.
+///
+/// Only commands go over SSH: files the crate reads or writes itself (JVM option edits,
+/// TLS material, node logs) are still accessed locally, so those features need the install
+/// directory mounted at the same path on both machines. Node processes are looked up on
+/// the remote machine, see [`CommandExecutor::is_remote`].
+pub struct SshExecutor {
+    pub destination: String,
+    /// Extra `ssh` arguments placed before the destination, e.g. `["-p", "2222"]`.
+    pub ssh_args: Vec<String>,
+    logged_cmd: Arc<LoggedCmd>,
+}
+
+impl SshExecutor {
+    pub fn new(logged_cmd: Arc<LoggedCmd>, destination: impl Into<String>) -> Self {
+        SshExecutor {
+            destination: destination.into(),
+            ssh_args: vec![],
+            logged_cmd,
+        }
+    }
+
+    pub fn with_ssh_args<I, S>(mut self, ssh_args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.ssh_args = ssh_args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn logged_cmd(&self) -> &Arc<LoggedCmd> {
+        &self.logged_cmd
+    }
+
+    /// Shell command line run on the remote machine.
+    pub fn remote_command(&self, command: &str, args: &[&str], opts: &RunOptions) -> String {
+        let mut words = vec![];
+        if !opts.env.is_empty() {
+            let mut env: Vec<_> = opts.env.iter().collect();
+            env.sort();
+            words.push("env".to_string());
+            words.extend(
+                env.iter()
+                    .map(|(key, value)| shell_quote(&format!("{key}={value}"))),
+            );
+        }
+        if let Some(escalation) = &opts.escalation {
+            words.extend(
+                escalation
+                    .prefix(&opts.env)
+                    .iter()
+                    .map(|word| shell_quote(word)),
+            );
+        }
+        words.push(shell_quote(command));
+        words.extend(args.iter().map(|arg| shell_quote(arg)));
+        let line = words.join(" ");
+        match &opts.cwd {
+            Some(cwd) => format!("cd {} && {}", shell_quote(&cwd.to_string_lossy()), line),
+            None => line,
+        }
+    }
+}
+
+impl CommandExecutor for SshExecutor {
+    fn run_command<'a>(
+        &'a self,
+        command: &'a str,
+        args: &'a [&'a str],
+        opts: Option<RunOptions>,
+    ) -> BoxFuture<'a, Result<CommandResult, IoError>> {
+        Box::pin(async move {
+            let mut opts = opts.unwrap_or_default();
+            let remote = self.remote_command(command, args, &opts);
+            opts.env.clear();
+            opts.cwd = None;
+            opts.escalation = None;
+
+            // BatchMode makes a missing key fail the command instead of prompting for it.
+            let mut ssh_args = vec!["-o", "BatchMode=yes"];
+            ssh_args.extend(self.ssh_args.iter().map(String::as_str));
+            ssh_args.extend([self.destination.as_str(), "--", remote.as_str()]);
+            self.logged_cmd
+                .run_command("ssh", &ssh_args, Some(opts))
+                .await
+        })
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ccm_cli::Escalation;
+    use crate::run_options;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_ssh_executor_invocation() {
+        let logged_cmd = Arc::new(LoggedCmd::new());
+        logged_cmd.set_dry_run(true);
+        let executor =
+            SshExecutor::new(logged_cmd.clone(), "ci@lab-1").with_ssh_args(["-p", "2222"]);
+
+        let env = HashMap::from([("SCYLLA_EXT_OPTS".to_string(), "--smp 1".to_string())]);
+        CommandExecutor::run_command(
+            &executor,
+            "ccm",
+            &["node_1_1", "start", "--jvm_arg=it's"],
+            run_options!(
+                env = env,
+                cwd = Some(PathBuf::from("/srv/ccm")),
+                escalation = Some(Escalation::Sudo)
+            ),
+        )
+        .await
+        .unwrap();
+
+        let recorded = logged_cmd.recorded_commands();
+        assert_eq!(recorded[0].command, "ssh");
+        assert!(recorded[0].env.is_empty());
+        assert_eq!(recorded[0].cwd, None);
+        assert_eq!(
+            recorded[0].args,
+            [
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "ci@lab-1",
+                "--",
+                "cd /srv/ccm && env 'SCYLLA_EXT_OPTS=--smp 1' sudo -n \
+                 --preserve-env=SCYLLA_EXT_OPTS ccm node_1_1 start '--jvm_arg=it'\\''s'"
+            ]
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use crate::ccm_cli::{Escalation, RunOptions};
+use crate::executor::CommandExecutor;
+use crate::host_capabilities::Capability;
+use crate::run_options;
+use std::io::Error as IoError;
+use std::sync::Mutex;
+
+/// Tool installing the rules of a [`Firewall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirewallBackend {
+    /// `iptables`/`ip6tables` rules in the `OUTPUT` chain, removed one by one.
+    #[default]
+    Iptables,
+    /// Rules in a dedicated `inet` table.
+    Nftables,
+}
+
+impl FirewallBackend {
+    /// Host tool the backend installs rules with.
+    pub fn capability(&self) -> Capability {
+        match self {
+            FirewallBackend::Iptables => Capability::Iptables,
+            FirewallBackend::Nftables => Capability::Nft,
+        }
+    }
+}
+
+/// What a client sees when its packets hit a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verdict {
+    /// Packets vanish: connection attempts time out.
+    #[default]
+    Drop,
+    /// A TCP reset is sent back: connections are refused.
+    Reset,
+    /// An ICMP host unreachable error is sent back.
+    Unreachable,
+}
+
+/// Traffic matched by a [`Firewall`] rule: packets to `destination`, optionally only from
+/// `source` and only to TCP port `port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirewallRule {
+    pub source: Option<String>,
+    pub destination: String,
+    pub port: Option<u16>,
+    pub verdict: Verdict,
+}
+
+impl FirewallRule {
+    fn is_ipv6(&self) -> bool {
+        self.destination.contains(':')
+    }
+
+    fn is_tcp(&self) -> bool {
+        self.port.is_some() || self.verdict == Verdict::Reset
+    }
+
+    fn iptables(&self) -> &'static str {
+        if self.is_ipv6() {
+            "ip6tables"
+        } else {
+            "iptables"
+        }
+    }
+
+    fn iptables_args(&self, action: &str, comment: &str) -> Vec<String> {
+        let mut args = vec![action.to_string(), "OUTPUT".to_string()];
+        if let Some(source) = &self.source {
+            args.extend(["-s".to_string(), source.clone()]);
+        }
+        args.extend(["-d".to_string(), self.destination.clone()]);
+        if self.is_tcp() {
+            args.extend(["-p", "tcp"].map(str::to_string));
+        }
+        if let Some(port) = self.port {
+            args.extend(["--dport".to_string(), port.to_string()]);
+        }
+        args.extend(["-m", "comment", "--comment", comment].map(str::to_string));
+        let target: &[&str] = match (self.verdict, self.is_ipv6()) {
+            (Verdict::Drop, _) => &["-j", "DROP"],
+            (Verdict::Reset, _) => &["-j", "REJECT", "--reject-with", "tcp-reset"],
+            (Verdict::Unreachable, false) => {
+                &["-j", "REJECT", "--reject-with", "icmp-host-unreachable"]
+            }
+            (Verdict::Unreachable, true) => {
+                &["-j", "REJECT", "--reject-with", "icmp6-addr-unreachable"]
+            }
+        };
+        args.extend(target.iter().map(|word| word.to_string()));
+        args
+    }
+
+    /// Statement appended to the nftables chain, e.g.
+    /// `ip daddr 127.0.5.1 tcp dport 9042 drop`.
+    fn nft_statement(&self) -> Vec<String> {
+        let family = if self.is_ipv6() { "ip6" } else { "ip" };
+        let mut words = vec![];
+        if let Some(source) = &self.source {
+            words.extend([family.to_string(), "saddr".to_string(), source.clone()]);
+        }
+        words.extend([
+            family.to_string(),
+            "daddr".to_string(),
+            self.destination.clone(),
+        ]);
+        if let Some(port) = self.port {
+            words.extend(["tcp".to_string(), "dport".to_string(), port.to_string()]);
+        }
+        let verdict: &[&str] = match self.verdict {
+            Verdict::Drop => &["drop"],
+            Verdict::Reset => &["reject", "with", "tcp", "reset"],
+            Verdict::Unreachable => &["reject", "with", "icmpx", "type", "host-unreachable"],
+        };
+        words.extend(verdict.iter().map(|word| word.to_string()));
+        words
+    }
+}
+
+/// Packet filter rules injecting network faults on node addresses. Loopback traffic
+/// passes the `OUTPUT` hook, so matching it there covers local clients and nodes alike.
+/// Changing firewall rules needs root, hence `escalation`.
+#[derive(Debug)]
+pub struct Firewall {
+    pub backend: FirewallBackend,
+    pub escalation: Escalation,
+    /// nftables table holding the rules; also names the rules in `iptables -S` output.
+    table: String,
+    rules: Mutex<Vec<FirewallRule>>,
+}
+
+impl Firewall {
+    /// Firewall for the rules of `owner`, e.g. a cluster name.
+    pub fn new(backend: FirewallBackend, owner: &str) -> Self {
+        let owner: String = owner
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        Firewall {
+            backend,
+            escalation: Escalation::Sudo,
+            table: format!("ccm_{}", owner),
+            rules: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn with_escalation(mut self, escalation: Escalation) -> Self {
+        self.escalation = escalation;
+        self
+    }
+
+    /// Rules currently installed, in installation order.
+    pub fn rules(&self) -> Vec<FirewallRule> {
+        self.rules.lock().unwrap().clone()
+    }
+
+    async fn run(
+        &self,
+        executor: &dyn CommandExecutor,
+        command: &str,
+        args: &[String],
+    ) -> Result<(), IoError> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        executor
+            .run_command(
+                command,
+                &args,
+                run_options!(escalation = Some(self.escalation.clone())),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn nft(&self, executor: &dyn CommandExecutor, args: &[&str]) -> Result<(), IoError> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        self.run(executor, "nft", &args).await
+    }
+
+    async fn nft_add_rule(
+        &self,
+        executor: &dyn CommandExecutor,
+        rule: &FirewallRule,
+    ) -> Result<(), IoError> {
+        let mut args: Vec<String> = ["add", "rule", "inet", &self.table, "output"]
+            .map(str::to_string)
+            .to_vec();
+        args.extend(rule.nft_statement());
+        self.run(executor, "nft", &args).await
+    }
+
+    /// Installs `rule`; rules already installed are skipped.
+    pub async fn add(
+        &self,
+        executor: &dyn CommandExecutor,
+        rule: FirewallRule,
+    ) -> Result<(), IoError> {
+        if self.rules.lock().unwrap().contains(&rule) {
+            return Ok(());
+        }
+        match self.backend {
+            FirewallBackend::Iptables => {
+                let args = rule.iptables_args("-I", &self.table);
+                self.run(executor, rule.iptables(), &args).await?;
+            }
+            FirewallBackend::Nftables => {
+                if self.rules.lock().unwrap().is_empty() {
+                    self.nft(executor, &["add", "table", "inet", &self.table])
+                        .await?;
+                    let chain = "{ type filter hook output priority 0 ; }";
+                    self.nft(
+                        executor,
+                        &["add", "chain", "inet", &self.table, "output", chain],
+                    )
+                    .await?;
+                }
+                self.nft_add_rule(executor, &rule).await?;
+            }
+        }
+        self.rules.lock().unwrap().push(rule);
+        Ok(())
+    }
+
+    /// Drops packets from `source` to `destination`.
+    pub async fn drop_traffic(
+        &self,
+        executor: &dyn CommandExecutor,
+        source: &str,
+        destination: &str,
+    ) -> Result<(), IoError> {
+        let rule = FirewallRule {
+            source: Some(source.to_string()),
+            destination: destination.to_string(),
+            port: None,
+            verdict: Verdict::Drop,
+        };
+        self.add(executor, rule).await
+    }
+
+    /// Removes an installed `rule`. nftables deletes single rules by handle only, so the
+    /// chain is flushed and the remaining rules are added again.
+    pub async fn remove(
+        &self,
+        executor: &dyn CommandExecutor,
+        rule: &FirewallRule,
+    ) -> Result<(), IoError> {
+        if !self.rules.lock().unwrap().contains(rule) {
+            return Ok(());
+        }
+        match self.backend {
+            FirewallBackend::Iptables => {
+                let args = rule.iptables_args("-D", &self.table);
+                self.run(executor, rule.iptables(), &args).await?;
+            }
+            FirewallBackend::Nftables => {
+                self.nft(executor, &["flush", "chain", "inet", &self.table, "output"])
+                    .await?;
+                for remaining in self.rules().iter().filter(|installed| *installed != rule) {
+                    self.nft_add_rule(executor, remaining).await?;
+                }
+            }
+        }
+        self.rules
+            .lock()
+            .unwrap()
+            .retain(|installed| installed != rule);
+        Ok(())
+    }
+
+    /// Removes every rule installed through this firewall.
+    pub async fn clear(&self, executor: &dyn CommandExecutor) -> Result<(), IoError> {
+        let rules = self.rules();
+        if rules.is_empty() {
+            return Ok(());
+        }
+        match self.backend {
+            FirewallBackend::Iptables => {
+                for rule in rules.iter() {
+                    self.remove(executor, rule).await?;
+                }
+            }
+            FirewallBackend::Nftables => {
+                self.nft(executor, &["delete", "table", "inet", &self.table])
+                    .await?;
+                self.rules.lock().unwrap().clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ccm_cli::LoggedCmd;
+
+    #[tokio::test]
+    async fn test_iptables_rules() {
+        let logged_cmd = LoggedCmd::new();
+        logged_cmd.set_dry_run(true);
+        let firewall = Firewall::new(FirewallBackend::Iptables, "my-cluster");
+        firewall
+            .drop_traffic(&logged_cmd, "127.0.5.1", "127.0.5.2")
+            .await
+            .unwrap();
+        firewall
+            .drop_traffic(&logged_cmd, "127.0.5.1", "127.0.5.2")
+            .await
+            .unwrap();
+        firewall
+            .drop_traffic(&logged_cmd, "fd00::1", "fd00::2")
+            .await
+            .unwrap();
+        let reset = FirewallRule {
+            source: None,
+            destination: "127.0.5.1".to_string(),
+            port: Some(9042),
+            verdict: Verdict::Reset,
+        };
+        firewall.add(&logged_cmd, reset).await.unwrap();
+        firewall.clear(&logged_cmd).await.unwrap();
+        assert!(firewall.rules().is_empty());
+
+        let comment = "-m comment --comment ccm_my_cluster";
+        let drop_v4 = format!("OUTPUT -s 127.0.5.1 -d 127.0.5.2 {comment} -j DROP");
+        let drop_v6 = format!("OUTPUT -s fd00::1 -d fd00::2 {comment} -j DROP");
+        let reset = format!(
+            "OUTPUT -d 127.0.5.1 -p tcp --dport 9042 {comment} -j REJECT --reject-with tcp-reset"
+        );
+        assert_eq!(
+            logged_cmd.recorded_lines(),
+            [
+                format!("sudo -n iptables -I {drop_v4}"),
+                format!("sudo -n ip6tables -I {drop_v6}"),
+                format!("sudo -n iptables -I {reset}"),
+                format!("sudo -n iptables -D {drop_v4}"),
+                format!("sudo -n ip6tables -D {drop_v6}"),
+                format!("sudo -n iptables -D {reset}"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nftables_rules() {
+        let logged_cmd = LoggedCmd::new();
+        logged_cmd.set_dry_run(true);
+        let firewall = Firewall::new(FirewallBackend::Nftables, "c1")
+            .with_escalation(Escalation::Prefix(vec![]));
+        firewall
+            .drop_traffic(&logged_cmd, "127.0.5.1", "127.0.5.2")
+            .await
+            .unwrap();
+        let unreachable = FirewallRule {
+            source: None,
+            destination: "127.0.5.2".to_string(),
+            port: Some(7000),
+            verdict: Verdict::Unreachable,
+        };
+        firewall
+            .add(&logged_cmd, unreachable.clone())
+            .await
+            .unwrap();
+        firewall.remove(&logged_cmd, &unreachable).await.unwrap();
+        firewall.clear(&logged_cmd).await.unwrap();
+
+        let drop = "nft add rule inet ccm_c1 output ip saddr 127.0.5.1 ip daddr 127.0.5.2 drop";
+        assert_eq!(
+            logged_cmd.recorded_lines(),
+            [
+                "nft add table inet ccm_c1",
+                "nft add chain inet ccm_c1 output { type filter hook output priority 0 ; }",
+                drop,
+                "nft add rule inet ccm_c1 output ip daddr 127.0.5.2 tcp dport 7000 \
+                 reject with icmpx type host-unreachable",
+                "nft flush chain inet ccm_c1 output",
+                drop,
+                "nft delete table inet ccm_c1",
+            ]
+        );
+    }
+}
@@ -1,69 +1,202 @@
+use crate::ip_range::{PROC_NET_TABLES, parse_proc_net_table};
 use std::collections::HashSet;
+use std::fmt;
 use std::fs;
-use std::net::Ipv4Addr;
-
-
-/// Parse /proc/net/tcp to retrieve a set of active IPv4 addresses.
-fn get_active_networks() -> HashSet<Ipv4Addr> {
-    let mut active_nets: HashSet<Ipv4Addr> = HashSet::new();
-
-    if let Ok(content) = fs::read_to_string("/proc/net/tcp") {
-        for line in content.lines().skip(1) { // Skip the header line
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() > 1 {
-                // Parse the local address (e.g., 0100007F:0016)
-                if let Some(local_address) = parts.get(1) {
-                    if let Some(ip) = ip_str_to_net(local_address) {
-                        active_nets.insert(ip);
-                    }
-                }
-            }
+use std::io::Error as IoError;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Aligned block of IPv4 addresses, `2^(32 - prefix_len)` of them starting at `network`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpRange {
+    network: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Range of `2^(32 - prefix_len)` addresses containing `address`.
+    pub fn new(address: Ipv4Addr, prefix_len: u8) -> Self {
+        let prefix_len = prefix_len.min(32);
+        let mask = u32::MAX
+            .checked_shl(32 - u32::from(prefix_len))
+            .unwrap_or(0);
+        IpRange {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix_len,
         }
     }
-    active_nets
+
+    /// Smallest range holding `size` addresses, aligned to its own size.
+    fn sized(network: Ipv4Addr, size: u32) -> Self {
+        let bits = size
+            .max(1)
+            .checked_next_power_of_two()
+            .map_or(32, u32::trailing_zeros);
+        IpRange::new(network, (32 - bits) as u8)
+    }
+
+    pub fn network(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn len(&self) -> u64 {
+        1 << (32 - u32::from(self.prefix_len))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Address at `index` in the range, `None` past its end.
+    pub fn get(&self, index: u64) -> Option<Ipv4Addr> {
+        (index < self.len()).then(|| Ipv4Addr::from(u32::from(self.network) + index as u32))
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        IpRange::new(address, self.prefix_len).network == self.network
+    }
+
+    pub fn first(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    pub fn last(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.network) + (self.len() - 1) as u32)
+    }
+
+    /// Every address of the range in order.
+    pub fn iter(&self) -> impl Iterator<Item = Ipv4Addr> + use<> {
+        let start = u32::from(self.network);
+        (0..self.len()).map(move |index| Ipv4Addr::from(start + index as u32))
+    }
+
+    fn overlaps(&self, other: &IpRange) -> bool {
+        self.contains(other.network) || other.contains(self.network)
+    }
 }
 
-/// Parse a hexadecimal IP address from /proc/net/tcp (e.g., "0100007F:0016").
-fn ip_str_to_net(hex_ip: &str) -> Option<Ipv4Addr> {
-    let ip_port: Vec<&str> = hex_ip.split(':').collect();
-    if ip_port.len() == 2 {
-        if let Ok(ip) = u32::from_str_radix(ip_port[0], 16) {
-            return Some(Ipv4Addr::new(
-                (ip & 0xFF) as u8,
-                ((ip >> 8) & 0xFF) as u8,
-                ((ip >> 16) & 0xFF) as u8,
-                0,
-            ));
-        }
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
     }
-    None
 }
 
-/// Find free IP ranges of 255 addresses each, starting from 127.0.1.0 to 127.255.255.255.
-fn find_available_iprange() -> Result<Ipv4Addr, String> {
-    let active_nets = get_active_networks();
+/// Which sockets count as keeping their address busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SocketState {
+    /// Every bound socket, outgoing connections included.
+    #[default]
+    Any,
+    /// Listening TCP sockets and unconnected UDP ones, i.e. servers only.
+    Listening,
+}
 
-    for i in 0..=255 {
-        for j in 0..=255 {
-            if j == 0 && i == 0 {
-                continue;
-            }
-            let net = Ipv4Addr::new(127, i, j, 0);
-            if !active_nets.contains(&net) {
-                return Ok(net)
+/// Local IPv4 addresses bound by TCP and UDP sockets in `state`, IPv4-mapped IPv6 ones
+/// included. Only Linux exposes them in `/proc`; elsewhere the set is empty.
+pub fn get_active_addresses(state: SocketState) -> HashSet<Ipv4Addr> {
+    let mut active: HashSet<Ipv4Addr> = HashSet::new();
+
+    for (table, tcp) in PROC_NET_TABLES {
+        if let Ok(content) = fs::read_to_string(table) {
+            let listening_only = state == SocketState::Listening;
+            for address in parse_proc_net_table(&content, tcp, listening_only) {
+                if let IpAddr::V4(ip) = address {
+                    active.insert(ip);
+                }
             }
         }
     }
-    Err("No free IP ranges found".to_string())
+    active
+}
+
+/// `/24` networks holding an address of [`get_active_addresses`].
+pub fn get_active_networks(state: SocketState) -> HashSet<IpRange> {
+    get_active_addresses(state)
+        .into_iter()
+        .map(|address| IpRange::new(address, 24))
+        .collect()
 }
 
+/// Finds a free range of at least `size` addresses in `127/8`: the size is rounded up to
+/// a power of two and the range aligned to it, e.g. 16 addresses give a `/28` and 200 a
+/// `/24`. `127.0.0.0/24`, where `127.0.0.1` lives, is never handed out.
+pub fn find_available_iprange(size: u32) -> Result<IpRange, IoError> {
+    find_free_iprange(size, &get_active_addresses(SocketState::Any))
+}
+
+/// Same as [`find_available_iprange`], with busy addresses given by the caller instead of
+/// read from the host, e.g. [`get_active_addresses`] filtered for one's own sockets.
+pub fn find_free_iprange(size: u32, active: &HashSet<Ipv4Addr>) -> Result<IpRange, IoError> {
+    let loopback = IpRange::new(Ipv4Addr::new(127, 0, 0, 0), 8);
+    let reserved = IpRange::new(Ipv4Addr::LOCALHOST, 24);
+    let mut range = IpRange::sized(loopback.network(), size);
+    if range.prefix_len() < 9 {
+        return Err(IoError::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} addresses don't fit into {}", size, loopback),
+        ));
+    }
+
+    while loopback.contains(range.network()) {
+        if !range.overlaps(&reserved) && !active.iter().any(|ip| range.contains(*ip)) {
+            return Ok(range);
+        }
+        let next = u32::from(range.network()) + range.len() as u32;
+        range = IpRange::new(Ipv4Addr::from(next), range.prefix_len());
+    }
+    Err(IoError::new(
+        std::io::ErrorKind::AddrNotAvailable,
+        format!("no free range of {} addresses in {}", size, loopback),
+    ))
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::find_available_iprange::find_available_iprange;
+    use super::*;
+
+    #[test]
+    fn test_ip_range() {
+        let range = IpRange::sized(Ipv4Addr::new(127, 0, 5, 21), 10);
+        assert_eq!(range.to_string(), "127.0.5.16/28");
+        assert_eq!(range.len(), 16);
+        assert_eq!(range.last(), Ipv4Addr::new(127, 0, 5, 31));
+        assert_eq!(range.get(2), Some(Ipv4Addr::new(127, 0, 5, 18)));
+        assert_eq!(range.get(16), None);
+        assert!(range.contains(Ipv4Addr::new(127, 0, 5, 20)));
+        assert!(!range.contains(Ipv4Addr::new(127, 0, 5, 32)));
+        assert_eq!(range.iter().count(), 16);
+        assert_eq!(IpRange::sized(Ipv4Addr::LOCALHOST, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_find_free_range() {
+        let active = HashSet::from([
+            Ipv4Addr::new(127, 0, 1, 3),
+            Ipv4Addr::new(127, 0, 1, 20),
+            Ipv4Addr::new(10, 0, 0, 1),
+        ]);
+        let range = find_free_iprange(16, &active).unwrap();
+        assert_eq!(range.to_string(), "127.0.1.32/28");
+        assert_eq!(
+            find_free_iprange(256, &active).unwrap().to_string(),
+            "127.0.2.0/24"
+        );
+        assert_eq!(
+            find_free_iprange(1, &HashSet::new()).unwrap().to_string(),
+            "127.0.1.0/32"
+        );
+    }
 
     #[test]
     fn test_find_available_range() {
-        println!("{:?}", find_available_iprange())
+        let range = find_available_iprange(16).unwrap();
+        assert_eq!(range.prefix_len(), 28);
+        assert!(range.network() >= Ipv4Addr::new(127, 0, 1, 0));
+        assert_eq!(find_available_iprange(200).unwrap().prefix_len(), 24);
+        assert!(find_available_iprange(1 << 24).is_err());
+        assert!(find_available_iprange(u32::MAX).is_err());
     }
 }
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// State of one node in a [`HealthReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeHealth {
+    /// ccm reports the node up, its ports accept connections and, if checked, it answers
+    /// CQL queries.
+    Up,
+    /// ccm reports the node down, or doesn't list it.
+    Down,
+    /// ccm reports the node up, but its ports are closed or CQL queries fail, e.g. while it
+    /// is still joining or hangs on startup.
+    Stuck,
+}
+
+/// Checks of one node behind its [`NodeHealth`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeHealthReport {
+    pub node: String,
+    pub health: NodeHealth,
+    /// State printed by `ccm status`, e.g. `UP` or `DOWN`.
+    pub ccm_status: Option<String>,
+    /// Whether both the CQL and the storage port accept connections.
+    pub ports_open: bool,
+    /// Whether `SELECT now()` succeeded; `None` when not checked.
+    pub cql: Option<bool>,
+}
+
+impl NodeHealthReport {
+    pub fn new(
+        node: impl Into<String>,
+        ccm_status: Option<String>,
+        ports_open: bool,
+        cql: Option<bool>,
+    ) -> Self {
+        let health = if ccm_status.as_deref() != Some("UP") {
+            NodeHealth::Down
+        } else if ports_open && cql != Some(false) {
+            NodeHealth::Up
+        } else {
+            NodeHealth::Stuck
+        };
+        NodeHealthReport {
+            node: node.into(),
+            health,
+            ccm_status,
+            ports_open,
+            cql,
+        }
+    }
+}
+
+/// Health of every active node of a cluster, see
+/// [`Cluster::wait_healthy`](crate::cluster::Cluster::wait_healthy). Carried by an `IoError` of
+/// kind `TimedOut` when some node never became healthy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    pub nodes: Vec<NodeHealthReport>,
+}
+
+impl HealthReport {
+    pub fn healthy(&self) -> bool {
+        self.nodes.iter().all(|node| node.health == NodeHealth::Up)
+    }
+
+    /// Names of the nodes in `health`.
+    pub fn nodes_in(&self, health: NodeHealth) -> Vec<&str> {
+        self.nodes
+            .iter()
+            .filter(|node| node.health == health)
+            .map(|node| node.node.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = [
+            ("up", NodeHealth::Up),
+            ("down", NodeHealth::Down),
+            ("stuck", NodeHealth::Stuck),
+        ];
+        let mut first = true;
+        for (label, health) in groups {
+            let nodes = self.nodes_in(health);
+            if nodes.is_empty() {
+                continue;
+            }
+            if !first {
+                f.write_str("; ")?;
+            }
+            first = false;
+            write!(f, "{}: {}", label, nodes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HealthReport {}
+
+/// Cluster `ccm status` reports on, from its `Cluster: 'name'` header; ccm's active one in
+/// the config dir it ran with.
+pub(crate) fn parse_ccm_status_cluster(output: &str) -> Option<&str> {
+    output.lines().find_map(|line| {
+        line.strip_prefix("Cluster:")?
+            .trim()
+            .strip_prefix('\'')?
+            .strip_suffix('\'')
+    })
+}
+
+/// Node states listed by `ccm status`, from lines such as `node_1_1: UP`. Other lines, e.g.
+/// the cluster header, may show up as well; look nodes up by name.
+pub(crate) fn parse_ccm_status(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (node, state) = line.split_once(':')?;
+            let state = state.split_whitespace().next()?;
+            let node = node.trim();
+            (!node.is_empty() && !node.contains(' ')).then(|| (node.to_string(), state.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ccm_status() {
+        let output =
+            "Cluster: 'test'\n--------------\nnode_1_1: UP (Not initialized)\nnode_1_2: DOWN\n";
+        let statuses = parse_ccm_status(output);
+        assert_eq!(statuses["node_1_1"], "UP");
+        assert_eq!(statuses["node_1_2"], "DOWN");
+        assert_eq!(parse_ccm_status_cluster(output), Some("test"));
+        assert_eq!(parse_ccm_status_cluster("node_1_1: UP\n"), None);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = HealthReport {
+            nodes: vec![
+                NodeHealthReport::new("node_1_1", Some("UP".to_string()), true, Some(true)),
+                NodeHealthReport::new("node_1_2", Some("UP".to_string()), true, Some(false)),
+                NodeHealthReport::new("node_1_3", Some("UP".to_string()), false, None),
+                NodeHealthReport::new("node_2_1", None, false, None),
+            ],
+        };
+        assert!(!report.healthy());
+        assert_eq!(report.nodes_in(NodeHealth::Stuck), ["node_1_2", "node_1_3"]);
+        assert_eq!(
+            report.to_string(),
+            "up: node_1_1; down: node_2_1; stuck: node_1_2, node_1_3"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::cluster::{Cluster, Node};
+use futures::future::BoxFuture;
+use std::io::Error as IoError;
+
+/// Async callbacks run at fixed points of a cluster's life, see
+/// [`Cluster::add_hooks`](crate::cluster::Cluster::add_hooks), e.g. to write certificates
+/// before a node starts or load seed data once it is up. Every method does nothing by
+/// default. Failing start hooks fail the start; failing stop and destroy hooks are logged
+/// as warnings, so that they never keep a cluster from going away.
+pub trait LifecycleHooks: Send + Sync {
+    /// Before `ccm start` runs for the node.
+    fn on_before_node_start<'a>(&'a self, _node: &'a Node) -> BoxFuture<'a, Result<(), IoError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Once ccm started the node, with the waits of its start options done.
+    fn on_after_node_start<'a>(&'a self, _node: &'a Node) -> BoxFuture<'a, Result<(), IoError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Before the node is stopped, on its own or with the whole cluster, while it still
+    /// serves requests.
+    fn on_node_stop<'a>(&'a self, _node: &'a Node) -> BoxFuture<'a, Result<(), IoError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// When [`destroy`](Cluster::destroy) starts, before the nodes are stopped.
+    fn on_destroy<'a>(&'a self, _cluster: &'a Cluster) -> BoxFuture<'a, Result<(), IoError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn record(&self, event: &str, name: &str) -> BoxFuture<'_, Result<(), IoError>> {
+            self.0.lock().unwrap().push(format!("{} {}", event, name));
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    impl LifecycleHooks for Recorder {
+        fn on_before_node_start<'a>(
+            &'a self,
+            node: &'a Node,
+        ) -> BoxFuture<'a, Result<(), IoError>> {
+            self.record("before_start", &node.name)
+        }
+
+        fn on_after_node_start<'a>(&'a self, node: &'a Node) -> BoxFuture<'a, Result<(), IoError>> {
+            self.record("after_start", &node.name)
+        }
+
+        fn on_node_stop<'a>(&'a self, node: &'a Node) -> BoxFuture<'a, Result<(), IoError>> {
+            self.record("stop", &node.name)
+        }
+
+        fn on_destroy<'a>(&'a self, cluster: &'a Cluster) -> BoxFuture<'a, Result<(), IoError>> {
+            self.record("destroy", &cluster.name)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_hooks");
+        let mut cluster = Cluster::builder()
+            .name("hooked")
+            .version("release:6.2")
+            .topology(&[2])
+            .install_directory(install_directory.to_string_lossy())
+            .ip_prefix("127.0.216.")
+            .build()
+            .await
+            .unwrap();
+        cluster.logged_cmd().set_dry_run(true);
+        let recorder = Arc::new(Recorder::default());
+        cluster.add_hooks(recorder.clone()).await;
+        cluster.start(None).await.unwrap();
+        cluster.nodes()[1].read().await.stop().await.unwrap();
+        cluster.destroy().await.unwrap();
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "before_start node_1_1",
+                "after_start node_1_1",
+                "before_start node_1_2",
+                "after_start node_1_2",
+                "stop node_1_2",
+                "destroy hooked",
+                "stop node_1_1",
+                "stop node_1_2",
+            ]
+        );
+    }
+}
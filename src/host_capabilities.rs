@@ -0,0 +1,188 @@
+use crate::ccm_cli::LoggedCmd;
+use std::collections::HashSet;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use thiserror::Error;
+
+/// Optional host tool that some subsystems depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Ccm,
+    Iptables,
+    Tc,
+    Perf,
+    Openssl,
+    Nft,
+    /// iproute2, managing network namespaces.
+    Ip,
+    /// Runs commands in network namespaces.
+    Nsenter,
+    /// Java keytool, writing truststores Java accepts whatever the OpenSSL version.
+    Keytool,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 9] = [
+        Capability::Ccm,
+        Capability::Iptables,
+        Capability::Tc,
+        Capability::Perf,
+        Capability::Openssl,
+        Capability::Nft,
+        Capability::Ip,
+        Capability::Nsenter,
+        Capability::Keytool,
+    ];
+
+    /// Executable looked up on `PATH` to detect the capability.
+    pub fn tool(&self) -> &'static str {
+        match self {
+            Capability::Ccm => "ccm",
+            Capability::Iptables => "iptables",
+            Capability::Tc => "tc",
+            Capability::Perf => "perf",
+            Capability::Openssl => "openssl",
+            Capability::Nft => "nft",
+            Capability::Ip => "ip",
+            Capability::Nsenter => "nsenter",
+            Capability::Keytool => "keytool",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{capability:?} is not supported on this host: `{}` was not found in PATH", capability.tool())]
+pub struct Unsupported {
+    pub capability: Capability,
+}
+
+impl From<Unsupported> for IoError {
+    fn from(e: Unsupported) -> Self {
+        IoError::new(ErrorKind::Unsupported, e)
+    }
+}
+
+/// Set of optional host tools found at detection time.
+#[derive(Debug, Clone, Default)]
+pub struct HostCapabilities {
+    available: HashSet<Capability>,
+}
+
+impl HostCapabilities {
+    /// Probes `PATH` for every known capability.
+    pub fn detect() -> Self {
+        let available = Capability::ALL
+            .into_iter()
+            .filter(|capability| find_executable(capability.tool()).is_some())
+            .collect();
+        HostCapabilities { available }
+    }
+
+    /// Returns capabilities detected once per process.
+    pub fn get() -> &'static HostCapabilities {
+        static DETECTED: OnceLock<HostCapabilities> = OnceLock::new();
+        DETECTED.get_or_init(Self::detect)
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.available.contains(&capability)
+    }
+
+    pub fn require(&self, capability: Capability) -> Result<(), Unsupported> {
+        if self.has(capability) {
+            Ok(())
+        } else {
+            Err(Unsupported { capability })
+        }
+    }
+}
+
+/// Fails with `Unsupported` before a subsystem runs the tools of `capability` on a host
+/// lacking them; dry runs of `logged_cmd` run no tool and always pass.
+pub(crate) fn require_unless_dry_run(
+    logged_cmd: &LoggedCmd,
+    capability: Capability,
+) -> Result<(), IoError> {
+    if logged_cmd.is_dry_run() {
+        return Ok(());
+    }
+    Ok(HostCapabilities::get().require(capability)?)
+}
+
+/// Returns from the enclosing test, printing a note, when the capability is absent.
+#[macro_export]
+macro_rules! require_capability {
+    ($capability:expr) => {
+        if let Err(e) = $crate::host_capabilities::HostCapabilities::get().require($capability) {
+            eprintln!("skipping test: {}", e);
+            return;
+        }
+    };
+}
+
+/// Finds `name` in the directories listed in `PATH`, trying the `PATHEXT` extensions on
+/// Windows.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .flat_map(|dir| executable_candidates(dir.join(name)))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(windows)]
+fn executable_candidates(path: PathBuf) -> Vec<PathBuf> {
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    let mut candidates = vec![path.clone()];
+    candidates.extend(
+        extensions
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(|extension| {
+                let mut candidate = path.clone().into_os_string();
+                candidate.push(extension);
+                PathBuf::from(candidate)
+            }),
+    );
+    candidates
+}
+
+#[cfg(not(windows))]
+fn executable_candidates(path: PathBuf) -> Vec<PathBuf> {
+    vec![path]
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_require_reports_missing_capability() {
+        let capabilities = HostCapabilities::default();
+        let err = capabilities.require(Capability::Iptables).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Iptables is not supported on this host: `iptables` was not found in PATH"
+        );
+        assert_eq!(IoError::from(err).kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_find_executable() {
+        assert!(find_executable("sh").is_some());
+        assert!(find_executable("definitely-not-a-real-tool").is_none());
+    }
+}
@@ -0,0 +1,373 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error as IoError, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Environment variables configuring [`HostLimits::from_env`]: the maximum number of
+/// clusters, their total smp and total memory in megabytes, and how many seconds to wait
+/// for room before failing.
+pub const MAX_CLUSTERS_ENV: &str = "CCM_BINDING_MAX_CLUSTERS";
+pub const MAX_SMP_ENV: &str = "CCM_BINDING_MAX_SMP";
+pub const MAX_MEMORY_ENV: &str = "CCM_BINDING_MAX_MEMORY";
+pub const LIMIT_WAIT_ENV: &str = "CCM_BINDING_LIMIT_WAIT";
+
+/// Limits installed with [`HostLimits::install`].
+static INSTALLED: LazyLock<Mutex<Option<HostLimits>>> = LazyLock::new(|| Mutex::new(None));
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Caps on the clusters of every process on the host, e.g. to keep parallel test binaries
+/// from each starting a 3-node cluster on a small CI machine. Every cluster holds a lease
+/// file in the lock directory, locked for as long as it lives; the OS drops the lock if
+/// the process dies, and such leases no longer count.
+///
+/// Once [installed](HostLimits::install), or configured through the environment, every
+/// cluster created takes a slot and [`init`](crate::cluster::Cluster::init) accounts the smp
+/// and memory of its nodes. Both wait for room up to [`wait`](HostLimits::with_wait) and
+/// then fail with `QuotaExceeded`.
+#[derive(Debug, Clone)]
+pub struct HostLimits {
+    pub max_clusters: Option<usize>,
+    pub max_smp: Option<i32>,
+    /// Megabytes.
+    pub max_memory: Option<i32>,
+    /// How long to wait for other clusters to go away, not at all with `None`.
+    pub wait: Option<Duration>,
+    lock_dir: PathBuf,
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        HostLimits {
+            max_clusters: None,
+            max_smp: None,
+            max_memory: None,
+            wait: None,
+            lock_dir: std::env::temp_dir().join("ccm-binding-host-limits"),
+        }
+    }
+}
+
+impl HostLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_clusters(mut self, max_clusters: usize) -> Self {
+        self.max_clusters = Some(max_clusters);
+        self
+    }
+
+    pub fn with_max_smp(mut self, max_smp: i32) -> Self {
+        self.max_smp = Some(max_smp);
+        self
+    }
+
+    /// Total memory in megabytes.
+    pub fn with_max_memory(mut self, max_memory: i32) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    pub fn with_wait(mut self, wait: Duration) -> Self {
+        self.wait = Some(wait);
+        self
+    }
+
+    /// Keeps lease files in `lock_dir`; only processes sharing the directory count each
+    /// other's clusters.
+    pub fn with_lock_dir(mut self, lock_dir: impl Into<PathBuf>) -> Self {
+        self.lock_dir = lock_dir.into();
+        self
+    }
+
+    pub fn lock_dir(&self) -> &Path {
+        &self.lock_dir
+    }
+
+    /// Limits from [`MAX_CLUSTERS_ENV`], [`MAX_SMP_ENV`], [`MAX_MEMORY_ENV`] and
+    /// [`LIMIT_WAIT_ENV`], or `None` if no limit is set.
+    pub fn from_env() -> Option<Self> {
+        let var = |name| std::env::var(name).ok()?.trim().parse().ok();
+        let limits = HostLimits {
+            max_clusters: var(MAX_CLUSTERS_ENV).map(|max: i32| max.max(0) as usize),
+            max_smp: var(MAX_SMP_ENV),
+            max_memory: var(MAX_MEMORY_ENV),
+            wait: var(LIMIT_WAIT_ENV)
+                .map(|seconds: i32| Duration::from_secs(seconds.max(0) as u64)),
+            ..Self::default()
+        };
+        let limited = limits.max_clusters.is_some()
+            || limits.max_smp.is_some()
+            || limits.max_memory.is_some();
+        limited.then_some(limits)
+    }
+
+    /// Applies the limits to every cluster created afterwards in this process, instead of
+    /// those from the environment.
+    pub fn install(self) {
+        *INSTALLED.lock().unwrap() = Some(self);
+    }
+
+    /// Removes installed limits; the environment applies again.
+    pub fn uninstall() {
+        *INSTALLED.lock().unwrap() = None;
+    }
+
+    /// Installed limits, or those from the environment.
+    pub(crate) fn current() -> Option<Self> {
+        INSTALLED.lock().unwrap().clone().or_else(Self::from_env)
+    }
+
+    /// Takes a slot for `cluster`, without smp or memory until
+    /// [`resize`](HostLease::resize)d.
+    pub async fn acquire(&self, cluster: &str) -> Result<HostLease, IoError> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+        let path = self.lock_dir.join(format!(
+            "{}-{}.lease",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = self
+            .admit(&path, cluster, 0, 0, || {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .open(&path)?;
+                file.try_lock().map_err(IoError::from)?;
+                write!(file, "0 0 {}", cluster)?;
+                Ok(file)
+            })
+            .await?;
+        Ok(HostLease {
+            limits: self.clone(),
+            cluster: cluster.to_string(),
+            path,
+            file,
+        })
+    }
+
+    /// Clusters holding leases in the lock directory. Leases left by dead processes are
+    /// removed, under the lock of the directory so that a lease another process is still
+    /// taking is not mistaken for one.
+    pub async fn usage(&self) -> Result<HostUsage, IoError> {
+        let _guard = self.lock_directory().await?;
+        self.usage_besides(None)
+    }
+
+    /// Must be called under [`lock_directory`](HostLimits::lock_directory): unlocked leases
+    /// are removed.
+    fn usage_besides(&self, own: Option<&Path>) -> Result<HostUsage, IoError> {
+        let mut usage = HostUsage::default();
+        let entries = match std::fs::read_dir(&self.lock_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(usage),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "lease") || Some(path.as_path()) == own {
+                continue;
+            }
+            let Ok(mut file) = File::open(&path) else {
+                continue;
+            };
+            if file.try_lock_shared().is_ok() {
+                std::fs::remove_file(&path).ok();
+                continue;
+            }
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+            let mut fields = content.splitn(3, ' ');
+            let mut number = || fields.next().and_then(|field| field.parse::<i32>().ok());
+            let (smp, memory) = (number().unwrap_or(0), number().unwrap_or(0));
+            usage
+                .clusters
+                .push(fields.next().unwrap_or_default().to_string());
+            usage.smp += smp;
+            usage.memory += memory;
+        }
+        usage.clusters.sort();
+        Ok(usage)
+    }
+
+    /// Runs `take` once `smp` and `memory` more for one more cluster fit next to the other
+    /// leases, waiting up to [`wait`](HostLimits::wait) for room. Leases are only counted and
+    /// changed under the lock of the directory, so that processes don't both take the last
+    /// slot.
+    async fn admit<T>(
+        &self,
+        own: &Path,
+        cluster: &str,
+        smp: i32,
+        memory: i32,
+        take: impl FnOnce() -> Result<T, IoError>,
+    ) -> Result<T, IoError> {
+        let deadline = tokio::time::Instant::now() + self.wait.unwrap_or_default();
+        loop {
+            let guard = self.lock_directory().await?;
+            let usage = self.usage_besides(Some(own))?;
+            if self.fits(&usage, smp, memory) {
+                let taken = take();
+                drop(guard);
+                return taken;
+            }
+            drop(guard);
+            if tokio::time::Instant::now() >= deadline {
+                return Err(IoError::new(
+                    std::io::ErrorKind::QuotaExceeded,
+                    format!(
+                        "{} ({} smp, {} MB) exceeds host limits, in use: {}",
+                        cluster, smp, memory, usage
+                    ),
+                ));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    fn fits(&self, usage: &HostUsage, smp: i32, memory: i32) -> bool {
+        self.max_clusters
+            .is_none_or(|max| usage.clusters.len() < max)
+            && self.max_smp.is_none_or(|max| usage.smp + smp <= max)
+            && self
+                .max_memory
+                .is_none_or(|max| usage.memory + memory <= max)
+    }
+
+    /// Exclusive lock of the lease directory, released when the file is dropped.
+    async fn lock_directory(&self) -> Result<File, IoError> {
+        std::fs::create_dir_all(&self.lock_dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.lock_dir.join("host-limits.lock"))?;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(file),
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    tokio::time::sleep(Duration::from_millis(10)).await
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+/// What the clusters holding leases use, see [`HostLimits::usage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostUsage {
+    /// Names of the clusters, sorted.
+    pub clusters: Vec<String>,
+    pub smp: i32,
+    /// Megabytes.
+    pub memory: i32,
+}
+
+impl std::fmt::Display for HostUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} clusters ({}), {} smp, {} MB",
+            self.clusters.len(),
+            self.clusters.join(", "),
+            self.smp,
+            self.memory
+        )
+    }
+}
+
+/// Slot of one cluster under [`HostLimits`], freed when dropped.
+#[derive(Debug)]
+pub struct HostLease {
+    limits: HostLimits,
+    cluster: String,
+    path: PathBuf,
+    file: File,
+}
+
+impl HostLease {
+    /// Accounts `smp` and `memory` for the cluster instead of what it had, waiting for room
+    /// like [`HostLimits::acquire`].
+    pub async fn resize(&self, smp: i32, memory: i32) -> Result<(), IoError> {
+        self.limits
+            .admit(&self.path, &self.cluster, smp, memory, || {
+                let mut file = &self.file;
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                write!(file, "{} {} {}", smp, memory, self.cluster)
+            })
+            .await
+    }
+}
+
+impl Drop for HostLease {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limits() {
+        let lock_dir = std::env::temp_dir().join("ccm_binding_test_host_limits");
+        std::fs::remove_dir_all(&lock_dir).ok();
+        let limits = HostLimits::new()
+            .with_lock_dir(&lock_dir)
+            .with_max_clusters(2)
+            .with_max_smp(4);
+        let a = limits.acquire("a").await.unwrap();
+        let b = limits.acquire("b").await.unwrap();
+        let err = limits.acquire("c").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::QuotaExceeded);
+
+        a.resize(3, 1536).await.unwrap();
+        assert_eq!(b.resize(2, 1024).await.unwrap_err().kind(), err.kind());
+        b.resize(1, 512).await.unwrap();
+        let usage = limits.usage().await.unwrap();
+        assert_eq!(usage.to_string(), "2 clusters (a, b), 4 smp, 2048 MB");
+
+        let waiting = limits.clone().with_wait(Duration::from_secs(5));
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(a);
+        });
+        let c = waiting.acquire("c").await.unwrap();
+        assert_eq!(limits.usage().await.unwrap().clusters, ["b", "c"]);
+        drop((b, c));
+        assert!(limits.usage().await.unwrap().clusters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_usage_spares_leases_being_taken() {
+        let lock_dir = std::env::temp_dir().join("ccm_binding_test_host_limits_usage");
+        std::fs::remove_dir_all(&lock_dir).ok();
+        let limits = HostLimits::new().with_lock_dir(&lock_dir);
+        // A lease created but not yet locked, as `acquire` leaves it for a moment.
+        let guard = limits.lock_directory().await.unwrap();
+        let lease = lock_dir.join("1-1.lease");
+        std::fs::write(&lease, "0 0 taking").unwrap();
+
+        let usage = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.usage().await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!usage.is_finished());
+        assert!(lease.exists());
+
+        // Once the directory is unlocked, the lease still unlocked is a dead one.
+        drop(guard);
+        assert!(usage.await.unwrap().unwrap().clusters.is_empty());
+        assert!(!lease.exists());
+        std::fs::remove_dir_all(&lock_dir).ok();
+    }
+}
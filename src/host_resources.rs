@@ -0,0 +1,199 @@
+use crate::ccm_cli::RunOptions;
+use crate::executor::CommandExecutor;
+use crate::run_options;
+use std::path::Path;
+use thiserror::Error;
+
+/// Free disk space every node needs in the install directory, for its data and logs.
+pub const DISK_PER_NODE_MB: u64 = 1024;
+
+/// Memory a node is never downscaled below.
+pub const MIN_NODE_MEMORY_MB: i32 = 256;
+
+/// What [`init`](crate::cluster::Cluster::init) does when the nodes need more CPUs, memory
+/// or disk than the host has, see
+/// [`Cluster::set_capacity_policy`](crate::cluster::Cluster::set_capacity_policy).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CapacityPolicy {
+    /// Logs a warning and creates the nodes as configured.
+    #[default]
+    Warn,
+    /// Fails with [`InsufficientResources`].
+    Fail,
+    /// Shrinks the smp and memory of every node to fit, with a warning; fails like
+    /// [`Fail`](CapacityPolicy::Fail) if they can't.
+    Downscale,
+}
+
+#[derive(Debug, Error)]
+#[error("host can't run the cluster: {}", shortfalls.join("; "))]
+pub struct InsufficientResources {
+    pub shortfalls: Vec<String>,
+}
+
+/// CPUs, memory and disk space available on the host the nodes run on. Memory and disk are
+/// only known on Linux and Unix respectively; unknown amounts are never short.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostResources {
+    pub cpus: usize,
+    /// `MemAvailable` from `/proc/meminfo`, in megabytes.
+    pub memory_mb: Option<u64>,
+    /// Free space in the directory nodes are created in, in megabytes.
+    pub disk_mb: Option<u64>,
+}
+
+impl HostResources {
+    /// Resources available right now on the machine `executor` runs commands on, with disk
+    /// space measured in `directory`.
+    pub async fn detect(executor: &dyn CommandExecutor, directory: &Path) -> Self {
+        let output = |command: &'static str, args: Vec<String>| async move {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            executor
+                .run_command(command, &args, run_options!(allow_failure = Some(true)))
+                .await
+                .ok()
+                .filter(|result| result.success())
+                .map(|result| result.stdout)
+        };
+        let cpus = output("getconf", vec!["_NPROCESSORS_ONLN".to_string()])
+            .await
+            .and_then(|cpus| cpus.trim().parse().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |cpus| cpus.get()));
+        let memory_mb = output("cat", vec!["/proc/meminfo".to_string()])
+            .await
+            .and_then(|meminfo| parse_meminfo_available(&meminfo));
+        let directory = directory.to_string_lossy().into_owned();
+        let disk_mb = output("df", vec!["-Pk".to_string(), directory])
+            .await
+            .and_then(|df| parse_df_available(&df));
+        HostResources {
+            cpus,
+            memory_mb,
+            disk_mb,
+        }
+    }
+
+    /// What the host lacks to run nodes of the given `(smp, memory)` sizes, empty if they fit.
+    pub fn shortfalls(&self, nodes: &[(i32, i32)]) -> Vec<String> {
+        let mut shortfalls = vec![];
+        let smp: i64 = nodes.iter().map(|(smp, _)| *smp as i64).sum();
+        if smp > self.cpus as i64 {
+            shortfalls.push(format!(
+                "{} smp requested, {} CPUs available",
+                smp, self.cpus
+            ));
+        }
+        let memory: i64 = nodes.iter().map(|(_, memory)| *memory as i64).sum();
+        if let Some(available) = self.memory_mb
+            && memory > available as i64
+        {
+            shortfalls.push(format!(
+                "{} MB of memory requested, {} MB available",
+                memory, available
+            ));
+        }
+        let disk = nodes.len() as u64 * DISK_PER_NODE_MB;
+        if let Some(available) = self.disk_mb
+            && disk > available
+        {
+            shortfalls.push(format!("{} MB of disk needed, {} MB free", disk, available));
+        }
+        shortfalls
+    }
+
+    /// `nodes` shrunk to fit the host: smp split evenly among the nodes, at least 1 each,
+    /// and memory scaled down proportionally, at least [`MIN_NODE_MEMORY_MB`] each. `None`
+    /// if even that doesn't fit.
+    pub fn downscale(&self, nodes: &[(i32, i32)]) -> Option<Vec<(i32, i32)>> {
+        let count = nodes.len().max(1);
+        let max_smp = (self.cpus / count).max(1) as i32;
+        let memory: i64 = nodes.iter().map(|(_, memory)| *memory as i64).sum();
+        let ratio = match self.memory_mb {
+            Some(available) if memory > available as i64 => available as f64 / memory as f64,
+            _ => 1.0,
+        };
+        let downscaled: Vec<_> = nodes
+            .iter()
+            .map(|&(smp, memory)| {
+                let scaled = (memory as f64 * ratio) as i32;
+                (smp.min(max_smp), scaled.max(MIN_NODE_MEMORY_MB).min(memory))
+            })
+            .collect();
+        self.shortfalls(&downscaled)
+            .is_empty()
+            .then_some(downscaled)
+    }
+}
+
+fn parse_meminfo_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes / 1024)
+}
+
+/// Available space from `df -Pk` output: the fourth column of the second line.
+fn parse_df_available(output: &str) -> Option<u64> {
+    let kilobytes: u64 = output
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(kilobytes / 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let meminfo = "MemTotal:       16314640 kB\nMemAvailable:    8157320 kB\n";
+        assert_eq!(parse_meminfo_available(meminfo), Some(7966));
+        let df = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+                  /dev/sda1 102400000 51200000 51200000 50% /\n";
+        assert_eq!(parse_df_available(df), Some(50000));
+    }
+
+    #[test]
+    fn test_shortfalls_and_downscale() {
+        let host = HostResources {
+            cpus: 4,
+            memory_mb: Some(3072),
+            disk_mb: Some(10240),
+        };
+        let nodes = [(2, 2048); 3];
+        assert_eq!(
+            host.shortfalls(&nodes),
+            [
+                "6 smp requested, 4 CPUs available",
+                "6144 MB of memory requested, 3072 MB available"
+            ]
+        );
+        assert_eq!(host.downscale(&nodes), Some(vec![(1, 1024); 3]));
+
+        let small = HostResources {
+            cpus: 2,
+            memory_mb: None,
+            disk_mb: Some(512),
+        };
+        assert_eq!(
+            small.shortfalls(&[(1, 512)]),
+            ["1024 MB of disk needed, 512 MB free"]
+        );
+        assert_eq!(small.downscale(&[(1, 512)]), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_detect_through_executor() {
+        let executor = crate::ccm_cli::LoggedCmd::new();
+        let host = HostResources::detect(&executor, &std::env::temp_dir()).await;
+        assert!(host.cpus >= 1);
+        assert!(host.memory_mb.is_some());
+        assert!(host.disk_mb.is_some());
+    }
+}
@@ -0,0 +1,150 @@
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+/// Block of `/etc/hosts`-style entries owned by one cluster in a shared hosts file,
+/// between `# BEGIN ccm-binding <owner>` and `# END ccm-binding <owner>` markers. Lines
+/// outside the block, other clusters' blocks included, are left alone. Updates hold an
+/// exclusive lock on `<path>.lock`, so that clusters of other processes sharing the file
+/// don't lose each other's blocks, and replace the file with a renamed temporary file, so
+/// that readers never see it half written.
+#[derive(Debug, Clone)]
+pub struct HostsFile {
+    path: PathBuf,
+    owner: String,
+}
+
+impl HostsFile {
+    pub fn new(path: impl Into<PathBuf>, owner: impl Into<String>) -> Self {
+        HostsFile {
+            path: path.into(),
+            owner: owner.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replaces the owner's block with `entries`, `(address, hostname)` pairs, creating
+    /// the file if needed.
+    pub async fn write(&self, entries: &[(String, String)]) -> Result<(), IoError> {
+        self.update(entries.to_vec(), true).await
+    }
+
+    /// Drops the owner's block; a missing file is not an error.
+    pub async fn remove(&self) -> Result<(), IoError> {
+        self.update(vec![], false).await
+    }
+
+    /// Replaces the owner's block with `entries` under the lock; a missing file is created
+    /// only if `create` is set.
+    async fn update(&self, entries: Vec<(String, String)>, create: bool) -> Result<(), IoError> {
+        let (path, owner) = (self.path.clone(), self.owner.clone());
+        tokio::task::spawn_blocking(move || {
+            let mut lock_path = path.clone().into_os_string();
+            lock_path.push(".lock");
+            let lock = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(lock_path)?;
+            lock.lock()?;
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && create => String::new(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let mut temp_path = path.clone().into_os_string();
+            temp_path.push(format!(".{}.tmp", std::process::id()));
+            std::fs::write(&temp_path, replace_block(&content, &owner, &entries))?;
+            std::fs::rename(&temp_path, &path).inspect_err(|_| {
+                std::fs::remove_file(&temp_path).ok();
+            })
+        })
+        .await
+        .map_err(IoError::other)?
+    }
+}
+
+/// `content` with the block of `owner` replaced by `entries`, appended at the end if
+/// there was none, or removed when `entries` is empty.
+fn replace_block(content: &str, owner: &str, entries: &[(String, String)]) -> String {
+    let begin = format!("# BEGIN ccm-binding {}", owner);
+    let end = format!("# END ccm-binding {}", owner);
+    let mut result = String::new();
+    let mut inside = false;
+    for line in content.lines() {
+        if line == begin {
+            inside = true;
+        } else if line == end {
+            inside = false;
+        } else if !inside {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    if !entries.is_empty() {
+        result.push_str(&begin);
+        result.push('\n');
+        for (address, hostname) in entries {
+            result.push_str(&format!("{} {}\n", address, hostname));
+        }
+        result.push_str(&end);
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_block() {
+        let entries = |address: &str| vec![(address.to_string(), "node-1-1.test".to_string())];
+        let content = replace_block("127.0.0.1 localhost\n", "a", &entries("127.0.1.1"));
+        let content = replace_block(&content, "b", &entries("127.0.2.1"));
+        let content = replace_block(&content, "a", &entries("127.0.3.1"));
+        assert_eq!(
+            content,
+            "127.0.0.1 localhost\n\
+             # BEGIN ccm-binding b\n127.0.2.1 node-1-1.test\n# END ccm-binding b\n\
+             # BEGIN ccm-binding a\n127.0.3.1 node-1-1.test\n# END ccm-binding a\n"
+        );
+        let content = replace_block(&content, "b", &[]);
+        assert_eq!(
+            content,
+            "127.0.0.1 localhost\n\
+             # BEGIN ccm-binding a\n127.0.3.1 node-1-1.test\n# END ccm-binding a\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writes_keep_every_block() {
+        let directory = std::env::temp_dir().join("ccm_binding_test_hosts_concurrent");
+        std::fs::remove_dir_all(&directory).ok();
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("hosts");
+        let writes = (0..8).map(|i| {
+            let hosts_file = HostsFile::new(&path, format!("c{}", i));
+            async move {
+                let entries = [(format!("127.0.{}.1", i), format!("node-1-1.c{}.test", i))];
+                hosts_file.write(&entries).await
+            }
+        });
+        for result in futures::future::join_all(writes).await {
+            result.unwrap();
+        }
+        let content = std::fs::read_to_string(&path).unwrap();
+        for i in 0..8 {
+            assert!(content.contains(&format!("# BEGIN ccm-binding c{}\n", i)));
+        }
+        HostsFile::new(directory.join("missing"), "c0")
+            .remove()
+            .await
+            .unwrap();
+        assert!(!directory.join("missing").exists());
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
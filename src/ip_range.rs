@@ -0,0 +1,975 @@
+use crate::ccm_cli::RunOptions;
+use crate::ccm_runner::CcmRunner;
+use crate::cluster::AggregatedError;
+use crate::executor::CommandExecutor;
+use crate::find_available_iprange::{
+    IpRange, SocketState, find_free_iprange, get_active_addresses,
+};
+use crate::run_options;
+use std::collections::{HashMap, HashSet};
+use std::io::Error as IoError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Reserved prefixes with the lock file held for each, if the lock directory is usable.
+type Reservations = HashMap<String, Option<std::fs::File>>;
+
+/// Prefixes reserved in this process and not yet released. Clusters built concurrently
+/// bind no sockets until started, so sniffing alone would give them all the same prefix.
+/// Each reservation holds an exclusive lock on a file in the allocator's lock directory so
+/// that other processes skip the prefix too; the OS drops the lock if the process dies.
+static RESERVED_IP_PREFIXES: LazyLock<Mutex<Reservations>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Environment variable overriding the directory holding IP prefix lock files.
+pub const IP_PREFIX_LOCK_DIR_ENV: &str = "CCM_BINDING_LOCK_DIR";
+
+/// Unique local range IPv6 clusters are sniffed from, one `/112` per cluster. Unlike
+/// `127/8`, only `::1` is on loopback by default, so the range has to be routed to it once,
+/// e.g. `ip -6 route add local fd6c:636d::/32 dev lo`.
+pub const IPV6_CLUSTER_RANGE: [u16; 2] = [0xfd6c, 0x636d];
+
+/// Directory holding the lock files of reserved IP prefixes, shared by every process on
+/// the host: [`IP_PREFIX_LOCK_DIR_ENV`] or `ccm-binding-ip-prefixes` in the temp dir.
+pub fn ip_prefix_lock_dir() -> PathBuf {
+    std::env::var_os(IP_PREFIX_LOCK_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("ccm-binding-ip-prefixes"))
+}
+
+/// Hands out cluster IP prefixes (`127.a.b.` or `fd6c:636d:0:n::`) no other cluster on the
+/// host uses. [`Cluster::new`](crate::cluster::Cluster::new) reserves one itself when given
+/// no prefix; harnesses managing several clusters can reserve them up front instead and
+/// pass them in, in which case releasing them is up to the harness.
+///
+/// Reservations are process-wide: every allocator sees and releases the same set.
+#[derive(Debug, Clone)]
+pub struct IpRangeAllocator {
+    lock_dir: PathBuf,
+    /// ccm config directories whose clusters' prefixes are never handed out.
+    ccm_config_dirs: Vec<PathBuf>,
+}
+
+impl Default for IpRangeAllocator {
+    fn default() -> Self {
+        IpRangeAllocator {
+            lock_dir: ip_prefix_lock_dir(),
+            ccm_config_dirs: vec![],
+        }
+    }
+}
+
+impl IpRangeAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps lock files in `lock_dir` instead of [`ip_prefix_lock_dir`]; only processes
+    /// sharing the directory see each other's reservations.
+    pub fn with_lock_dir(mut self, lock_dir: impl Into<PathBuf>) -> Self {
+        self.lock_dir = lock_dir.into();
+        self
+    }
+
+    pub fn lock_dir(&self) -> &Path {
+        &self.lock_dir
+    }
+
+    /// Also skips the prefixes of clusters ccm keeps in `config_dir`: a stopped cluster
+    /// holds no sockets, but starting it again on a reused prefix would clash.
+    pub fn with_ccm_config_dir(mut self, config_dir: impl Into<PathBuf>) -> Self {
+        self.ccm_config_dirs.push(config_dir.into());
+        self
+    }
+
+    /// Prefixes in use by sockets or by clusters in the ccm config directories.
+    async fn unavailable_prefixes(&self) -> Result<HashSet<String>, IoError> {
+        let mut unavailable = used_ip_prefixes().await?;
+        for config_dir in self.ccm_config_dirs.iter() {
+            unavailable.extend(configured_ip_prefixes(config_dir).await?);
+        }
+        Ok(unavailable)
+    }
+
+    /// Reserves a free IPv4 prefix such as `127.0.5.`.
+    pub async fn reserve(&self) -> Result<String, IoError> {
+        let used = self.unavailable_prefixes().await?;
+        let mut reserved = RESERVED_IP_PREFIXES.lock().unwrap();
+        for a in 1..=255 {
+            for b in 1..=255 {
+                let ip_prefix = format!("127.{}.{}.", a, b);
+                if !used.contains(&ip_prefix) && self.lock(&mut reserved, &ip_prefix) {
+                    return Ok(ip_prefix);
+                }
+            }
+        }
+        Err(IoError::from_raw_os_error(1))
+    }
+
+    /// Reserves a free IPv6 prefix from [`IPV6_CLUSTER_RANGE`].
+    pub async fn reserve_ipv6(&self) -> Result<String, IoError> {
+        let used = self.unavailable_prefixes().await?;
+        let mut reserved = RESERVED_IP_PREFIXES.lock().unwrap();
+        let [a, b] = IPV6_CLUSTER_RANGE;
+        for range in 1..=0xffff {
+            let ip_prefix = Ipv6Addr::new(a, b, 0, range, 0, 0, 0, 0).to_string();
+            if !used.contains(&ip_prefix) && self.lock(&mut reserved, &ip_prefix) {
+                return Ok(ip_prefix);
+            }
+        }
+        Err(IoError::from_raw_os_error(1))
+    }
+
+    /// Reserves the given prefix, e.g. to pin a cluster to a known range, and returns it in
+    /// the form clusters use (`127.0.5` becomes `127.0.5.`). Fails with
+    /// [`AddrInUse`](std::io::ErrorKind::AddrInUse) if this or another process holds it;
+    /// sockets already bound on the prefix are not checked.
+    pub fn reserve_prefix(&self, prefix: &str) -> Result<String, IoError> {
+        let ip_prefix = normalize_ip_prefix(prefix);
+        if self.lock(&mut RESERVED_IP_PREFIXES.lock().unwrap(), &ip_prefix) {
+            Ok(ip_prefix)
+        } else {
+            Err(IoError::new(
+                std::io::ErrorKind::AddrInUse,
+                format!("IP prefix {} is already reserved", ip_prefix),
+            ))
+        }
+    }
+
+    /// Reserves a free aligned range of at least `size` IPv4 addresses in `127/8`, see
+    /// [`find_free_iprange`], e.g. for harnesses addressing nodes themselves or placing
+    /// more nodes than one prefix holds. Every `/24` prefix the range touches is reserved
+    /// as a whole, so the range never overlaps a prefix handed out by
+    /// [`reserve`](IpRangeAllocator::reserve) here or in another process. Release it with
+    /// [`release_range`](IpRangeAllocator::release_range).
+    pub async fn reserve_range(&self, size: u32) -> Result<IpRange, IoError> {
+        let unavailable = self.unavailable_prefixes().await?;
+        let mut active = get_active_addresses(SocketState::Any);
+        let mut reserved = RESERVED_IP_PREFIXES.lock().unwrap();
+        for prefix in unavailable.iter().chain(reserved.keys()) {
+            active.extend(prefix_range(prefix).iter().flat_map(IpRange::iter));
+        }
+        loop {
+            let range = find_free_iprange(size, &active)?;
+            let prefixes = range_prefixes(&range);
+            let taken = prefixes
+                .iter()
+                .position(|prefix| !self.lock(&mut reserved, prefix));
+            let Some(taken) = taken else {
+                return Ok(range);
+            };
+            for prefix in &prefixes[..taken] {
+                reserved.remove(prefix);
+            }
+            active.extend(
+                prefix_range(&prefixes[taken])
+                    .iter()
+                    .flat_map(IpRange::iter),
+            );
+        }
+    }
+
+    /// Releases a range reserved with [`reserve_range`](IpRangeAllocator::reserve_range);
+    /// returns whether it was reserved.
+    pub fn release_range(&self, range: &IpRange) -> bool {
+        let mut released = false;
+        for prefix in range_prefixes(range) {
+            released |= self.release(&prefix);
+        }
+        released
+    }
+
+    /// Releases a prefix reserved in this process; returns whether it was reserved.
+    pub fn release(&self, prefix: &str) -> bool {
+        RESERVED_IP_PREFIXES
+            .lock()
+            .unwrap()
+            .remove(&normalize_ip_prefix(prefix))
+            .is_some()
+    }
+
+    pub fn is_reserved(&self, prefix: &str) -> bool {
+        RESERVED_IP_PREFIXES
+            .lock()
+            .unwrap()
+            .contains_key(&normalize_ip_prefix(prefix))
+    }
+
+    /// Prefixes currently reserved by this process, sorted.
+    pub fn reserved(&self) -> Vec<String> {
+        let mut reserved: Vec<_> = RESERVED_IP_PREFIXES
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        reserved.sort();
+        reserved
+    }
+
+    /// Reserves `ip_prefix` unless this or another process holds it. Without a usable lock
+    /// directory the reservation only protects against clusters in this process.
+    fn lock(&self, reserved: &mut Reservations, ip_prefix: &str) -> bool {
+        if reserved.contains_key(ip_prefix) {
+            return false;
+        }
+        let lock = std::fs::create_dir_all(&self.lock_dir).and_then(|_| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(self.lock_path(ip_prefix))
+        });
+        let lock = match lock {
+            Ok(file) => match file.try_lock() {
+                Ok(()) => Some(file),
+                Err(std::fs::TryLockError::WouldBlock) => return false,
+                Err(std::fs::TryLockError::Error(_)) => None,
+            },
+            Err(_) => None,
+        };
+        reserved.insert(ip_prefix.to_string(), lock);
+        true
+    }
+
+    fn lock_path(&self, ip_prefix: &str) -> PathBuf {
+        self.lock_dir
+            .join(format!("{}lock", ip_prefix.replace(':', "_")))
+    }
+
+    /// Whether this or another live process holds `ip_prefix`.
+    fn is_held(&self, ip_prefix: &str) -> bool {
+        if self.is_reserved(ip_prefix) {
+            return true;
+        }
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.lock_path(ip_prefix))
+        {
+            Ok(file) => matches!(file.try_lock(), Err(std::fs::TryLockError::WouldBlock)),
+            Err(_) => false,
+        }
+    }
+
+    /// Finds clusters crashed runs left in the ccm config directories of the allocator:
+    /// clusters older than `min_age` whose IP prefix no live process holds or binds and
+    /// whose nodes have no running process. They keep their prefixes from being handed
+    /// out, see [`with_ccm_config_dir`](IpRangeAllocator::with_ccm_config_dir), until
+    /// removed; with `ccm` given, each is removed with `ccm remove` on a copy of the runner
+    /// pointed at its config directory, otherwise they are only reported.
+    ///
+    /// `min_age` spares clusters a live process created with a prefix of its own choosing
+    /// and has not started yet, which look the same.
+    ///
+    /// Every config dir and cluster is attempted; failures are reported together.
+    pub async fn cleanup_stale_allocations(
+        &self,
+        min_age: Duration,
+        ccm: Option<&CcmRunner>,
+    ) -> Result<Vec<StaleCluster>, IoError> {
+        let used = used_ip_prefixes().await?;
+        let mut stale = vec![];
+        let mut errors = vec![];
+        for config_dir in self.ccm_config_dirs.iter() {
+            let mut entries = match tokio::fs::read_dir(config_dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    errors.push(format!("{}: {}", config_dir.display(), e));
+                    continue;
+                }
+            };
+            loop {
+                let entry = match entries.next_entry().await {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => break,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", config_dir.display(), e));
+                        break;
+                    }
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                match self
+                    .stale_cluster(config_dir, name.clone(), min_age, &used, ccm)
+                    .await
+                {
+                    Ok(Some(cluster)) => stale.push(cluster),
+                    Ok(None) => {}
+                    Err(e) => errors.push(format!("{}: {}", name, e)),
+                }
+            }
+        }
+        stale.sort_by(|a, b| a.name.cmp(&b.name));
+        match errors.len() {
+            0 => Ok(stale),
+            1 => Err(IoError::other(errors.remove(0))),
+            _ => Err(IoError::other(AggregatedError(errors))),
+        }
+    }
+
+    /// Entry `name` of `config_dir` if it is a stale cluster, removed with `ccm` if given.
+    async fn stale_cluster(
+        &self,
+        config_dir: &Path,
+        name: String,
+        min_age: Duration,
+        used: &HashSet<String>,
+        ccm: Option<&CcmRunner>,
+    ) -> Result<Option<StaleCluster>, IoError> {
+        let cluster_config_dir = cluster_config_dir(config_dir, &name);
+        let cluster_dir = cluster_config_dir.join(&name);
+        let conf = cluster_dir.join("cluster.conf");
+        let Ok(content) = tokio::fs::read_to_string(&conf).await else {
+            return Ok(None);
+        };
+        let age = tokio::fs::metadata(&conf)
+            .await?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+        let ip_prefix = parse_cluster_conf_prefix(&content);
+        let owned = ip_prefix
+            .as_ref()
+            .is_some_and(|prefix| used.contains(prefix) || self.is_held(prefix));
+        let executor = ccm.map(|ccm| ccm.executor().as_ref());
+        if age < min_age || owned || has_running_node(&cluster_dir, executor).await? {
+            return Ok(None);
+        }
+        let mut cluster = StaleCluster {
+            config_dir: cluster_config_dir,
+            name,
+            ip_prefix,
+            removed: false,
+        };
+        if let Some(ccm) = ccm {
+            let mut ccm = ccm.clone();
+            ccm.config_dir = cluster.config_dir.to_string_lossy().into_owned();
+            ccm.run(&["remove", &cluster.name], None).await?;
+            cluster.removed = true;
+        }
+        Ok(Some(cluster))
+    }
+}
+
+/// Cluster left behind by a process that is gone, see
+/// [`IpRangeAllocator::cleanup_stale_allocations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleCluster {
+    /// ccm config directory the cluster is in, see [`cluster_config_dir`].
+    pub config_dir: PathBuf,
+    pub name: String,
+    pub ip_prefix: Option<String>,
+    /// Whether `ccm remove` ran for it, freeing its prefix.
+    pub removed: bool,
+}
+
+/// Whether a node of the cluster in `cluster_dir` has a live server process, looked up
+/// through `executor` if given, see [`node_process_running`].
+async fn has_running_node(
+    cluster_dir: &Path,
+    executor: Option<&dyn CommandExecutor>,
+) -> Result<bool, IoError> {
+    let mut nodes = tokio::fs::read_dir(cluster_dir).await?;
+    while let Some(node) = nodes.next_entry().await? {
+        if node_process_running(&node.path(), executor).await? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Whether the server of the node in `node_dir` runs, going by the pid file ccm writes
+/// into it. With a [remote](CommandExecutor::is_remote) `executor` the pid file is read
+/// and the process looked up with `kill -0` on the remote machine. Otherwise they are
+/// looked up on this one: in `/proc` on Linux and with `kill -0` on other Unixes;
+/// elsewhere processes can't be looked up, and any pid file counts as running.
+pub(crate) async fn node_process_running(
+    node_dir: &Path,
+    executor: Option<&dyn CommandExecutor>,
+) -> Result<bool, IoError> {
+    let pid_file = node_dir.join("cassandra.pid");
+    if let Some(executor) = executor.filter(|executor| executor.is_remote()) {
+        let pid_file = pid_file.to_string_lossy();
+        let result = executor
+            .run_command(
+                "sh",
+                &[
+                    "-c",
+                    r#"pid=$(cat "$1" 2>/dev/null) && kill -0 "$pid" 2>/dev/null"#,
+                    "sh",
+                    &pid_file,
+                ],
+                run_options!(allow_failure = Some(true)),
+            )
+            .await?;
+        return Ok(result.success());
+    }
+    let Ok(pid) = tokio::fs::read_to_string(pid_file).await else {
+        return Ok(false);
+    };
+    let pid = pid.trim();
+    if pid.parse::<u32>().is_err() {
+        return Ok(false);
+    }
+    Ok(if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid).exists()
+    } else if cfg!(unix) {
+        tokio::process::Command::new("kill")
+            .args(["-0", pid])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await
+            .is_ok_and(|status| status.success())
+    } else {
+        true
+    })
+}
+
+/// `/proc/net` socket tables scanned for used addresses, and whether they list TCP sockets.
+pub(crate) const PROC_NET_TABLES: [(&str, bool); 4] = [
+    ("/proc/net/tcp", true),
+    ("/proc/net/tcp6", true),
+    ("/proc/net/udp", false),
+    ("/proc/net/udp6", false),
+];
+
+/// TCP states of sockets that no longer keep their address from being bound: `TIME_WAIT`
+/// and `CLOSE`.
+const RELEASED_TCP_STATES: [&str; 2] = ["06", "07"];
+
+/// State of listening TCP sockets.
+const TCP_LISTEN: &str = "0A";
+
+/// State of UDP sockets bound without a peer, i.e. servers.
+const UDP_UNCONNECTED: &str = "07";
+
+/// IPv4 `/24` prefixes (`a.b.c.`) and IPv6 `/112` prefixes (`fd6c:636d:0:1::`) of every
+/// local address with a TCP or UDP socket bound to it. Only Linux exposes them in `/proc`;
+/// elsewhere no prefix is reported as used.
+pub async fn used_ip_prefixes() -> Result<HashSet<String>, IoError> {
+    let mut used_ips = HashSet::new();
+    if !cfg!(target_os = "linux") {
+        return Ok(used_ips);
+    }
+    for (table, tcp) in PROC_NET_TABLES {
+        let content = match tokio::fs::read_to_string(table).await {
+            Ok(content) => content,
+            // Hosts with IPv6 disabled have no tcp6 and udp6 tables.
+            Err(_) if table.ends_with('6') => continue,
+            Err(e) => return Err(e),
+        };
+        used_ips.extend(
+            parse_proc_net_table(&content, tcp, false)
+                .into_iter()
+                .map(address_prefix),
+        );
+    }
+    Ok(used_ips)
+}
+
+/// Local addresses of the sockets listed in a `/proc/net/{tcp,tcp6,udp,udp6}` table. TCP
+/// sockets in [`RELEASED_TCP_STATES`] are skipped; UDP sockets hold their address in any
+/// state. With `listening_only`, only TCP sockets in `LISTEN` and unconnected UDP sockets
+/// are kept.
+pub(crate) fn parse_proc_net_table(content: &str, tcp: bool, listening_only: bool) -> Vec<IpAddr> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let local_address = columns.nth(1)?;
+            let state = columns.nth(1)?;
+            if tcp && RELEASED_TCP_STATES.contains(&state) {
+                return None;
+            }
+            let listening = if tcp { TCP_LISTEN } else { UDP_UNCONNECTED };
+            if listening_only && state != listening {
+                return None;
+            }
+            parse_proc_address(local_address)
+        })
+        .collect()
+}
+
+/// Address from a `/proc/net` `local_address` column: `0100007F:0016` for IPv4, or four
+/// 32-bit words such as `000080FE00000000FF565002BD69B1FE:0016` for IPv6, all in host byte
+/// order. IPv4-mapped addresses of dual-stack sockets come back as IPv4.
+fn parse_proc_address(address: &str) -> Option<IpAddr> {
+    let (hex, _port) = address.split_once(':')?;
+    match hex.len() {
+        8 => {
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(value.to_ne_bytes())))
+        }
+        32 => {
+            let mut octets = [0u8; 16];
+            for (word, chunk) in octets.chunks_mut(4).enumerate() {
+                let value = u32::from_str_radix(&hex[word * 8..word * 8 + 8], 16).ok()?;
+                chunk.copy_from_slice(&value.to_ne_bytes());
+            }
+            let address = Ipv6Addr::from(octets);
+            Some(
+                address
+                    .to_ipv4_mapped()
+                    .map_or(IpAddr::V6(address), IpAddr::V4),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Cluster prefix `address` falls in: its `/24` for IPv4, its `/112` for IPv6.
+fn address_prefix(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, _] = address.octets();
+            format!("{}.{}.{}.", a, b, c)
+        }
+        IpAddr::V6(address) => ipv6_range(address),
+    }
+}
+
+/// ccm config directory of cluster `name` found in `dir`: `<dir>/<name>` if the cluster has
+/// one of its own, as clusters the crate creates do, `dir` itself if it is a config
+/// directory shared by several clusters.
+pub fn cluster_config_dir(dir: &Path, name: &str) -> PathBuf {
+    let own = dir.join(name);
+    match own.join(name).join("cluster.conf").is_file() {
+        true => own,
+        false => dir.to_path_buf(),
+    }
+}
+
+/// IP prefixes of the clusters in ccm config directory `config_dir`, from the `ipprefix`
+/// or `ipformat` entry of each `<cluster>/cluster.conf`, clusters with a config directory
+/// of their own in it included, see [`cluster_config_dir`]. A missing directory has none.
+pub async fn configured_ip_prefixes(config_dir: &Path) -> Result<HashSet<String>, IoError> {
+    let mut prefixes = HashSet::new();
+    let mut entries = match tokio::fs::read_dir(config_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(prefixes),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let conf = cluster_config_dir(config_dir, &name)
+            .join(&name)
+            .join("cluster.conf");
+        if let Ok(content) = tokio::fs::read_to_string(conf).await
+            && let Some(prefix) = parse_cluster_conf_prefix(&content)
+        {
+            prefixes.insert(prefix);
+        }
+    }
+    Ok(prefixes)
+}
+
+/// Prefix of a ccm `cluster.conf`: `ipformat` (`fd00::%d`) wins over `ipprefix`
+/// (`127.0.1.`), as it does in ccm.
+pub(crate) fn parse_cluster_conf_prefix(content: &str) -> Option<String> {
+    match conf_value(content, "ipformat") {
+        Some(format) => Some(normalize_ip_prefix(format.strip_suffix("%d")?)),
+        None => conf_value(content, "ipprefix").map(normalize_ip_prefix),
+    }
+}
+
+/// Top-level scalar `key` of a ccm `cluster.conf` or `node.conf`, unquoted; `None` if
+/// missing, empty or `null`.
+pub(crate) fn conf_value<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    content.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?.trim();
+        let value = value.trim_matches(|c| c == '\'' || c == '"');
+        (!value.is_empty() && value != "null").then_some(value)
+    })
+}
+
+/// Address of `interface` (`binary`, `storage`, ...) under the `interfaces` section of a
+/// ccm `node.conf`, where every interface is an `[address, port]` list.
+pub(crate) fn conf_interface_address<'a>(content: &'a str, interface: &str) -> Option<&'a str> {
+    let mut lines = content
+        .lines()
+        .skip_while(|line| !line.starts_with("interfaces:"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' ') || line.starts_with('-'))
+        .skip_while(|line| {
+            !line
+                .trim_start()
+                .strip_prefix(interface)
+                .is_some_and(|rest| rest.starts_with(':'))
+        });
+    let value = lines.next()?.trim_start()[interface.len() + 1..].trim();
+    let address = match value.find('[') {
+        Some(start) => value[start + 1..].split(',').next()?,
+        None if value.is_empty() || value.starts_with("!!") => {
+            lines.next()?.trim_start().strip_prefix('-')?
+        }
+        None => return None,
+    };
+    let address = address.trim().trim_matches(|c| c == '\'' || c == '"');
+    (!address.is_empty()).then_some(address)
+}
+
+/// Appends the separator node numbers follow: `.` for IPv4, and `::` for IPv6 prefixes
+/// that don't end with a separator yet (`fd00:0:0:5` becomes `fd00:0:0:5::`).
+pub(crate) fn normalize_ip_prefix(prefix: &str) -> String {
+    let separator = match (prefix.contains(':'), prefix.chars().next_back()) {
+        (true, Some(':')) | (false, Some('.')) => "",
+        (true, _) => "::",
+        (false, _) => ".",
+    };
+    format!("{}{}", prefix, separator)
+}
+
+/// `/112` prefix of `address` in the form IPv6 cluster prefixes use, e.g. `fd6c:636d:0:1::`.
+pub(crate) fn ipv6_range(address: Ipv6Addr) -> String {
+    let mut segments = address.segments();
+    segments[7] = 0;
+    Ipv6Addr::from(segments).to_string()
+}
+
+/// The `/24` an IPv4 prefix such as `127.0.5.` stands for; `None` for IPv6 prefixes.
+fn prefix_range(prefix: &str) -> Option<IpRange> {
+    let network: Ipv4Addr = format!("{}0", prefix).parse().ok()?;
+    Some(IpRange::new(network, 24))
+}
+
+/// Prefixes of every `/24` `range` touches.
+fn range_prefixes(range: &IpRange) -> Vec<String> {
+    let networks = (range.len() / 256).max(1);
+    (0..networks)
+        .filter_map(|index| range.get(index * 256))
+        .map(|network| {
+            let [a, b, c, _] = network.octets();
+            format!("{}.{}.{}.", a, b, c)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_reserve_range() {
+        let allocator = IpRangeAllocator::new();
+        let small = allocator.reserve_range(16).await.unwrap();
+        assert_eq!(small.prefix_len(), 28);
+        let [a, b, c, _] = small.network().octets();
+        let prefix = format!("{}.{}.{}.", a, b, c);
+        assert!(allocator.is_reserved(&prefix));
+
+        let large = allocator.reserve_range(512).await.unwrap();
+        assert_eq!(large.prefix_len(), 23);
+        assert!(!large.contains(small.network()));
+        let prefixes = range_prefixes(&large);
+        assert_eq!(prefixes.len(), 2);
+        assert!(prefixes.iter().all(|prefix| allocator.is_reserved(prefix)));
+
+        assert!(allocator.release_range(&small));
+        assert!(!allocator.is_reserved(&prefix));
+        assert!(allocator.release_range(&large));
+        assert!(!allocator.release_range(&large));
+    }
+
+    #[test]
+    fn test_reserve_prefix_is_exclusive_across_processes() {
+        let allocator = IpRangeAllocator::new();
+        let path = allocator.lock_dir().join("127.0.247.lock");
+        let mut reserved = HashMap::new();
+        assert!(allocator.lock(&mut reserved, "127.0.247."));
+        assert!(!allocator.lock(&mut reserved, "127.0.247."));
+
+        // Another process holding the lock file looks the same as a second open handle.
+        let mut other_process = HashMap::new();
+        assert!(!allocator.lock(&mut other_process, "127.0.247."));
+        reserved.remove("127.0.247.");
+        assert!(allocator.lock(&mut other_process, "127.0.247."));
+        assert!(path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_reserve_and_release() {
+        let allocator = IpRangeAllocator::new();
+        assert_eq!(allocator.reserve_prefix("127.0.246").unwrap(), "127.0.246.");
+        let error = allocator.reserve_prefix("127.0.246.").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(allocator.reserved().contains(&"127.0.246.".to_string()));
+
+        let sniffed = allocator.reserve().await.unwrap();
+        assert_ne!(sniffed, "127.0.246.");
+        assert!(IpRangeAllocator::new().is_reserved(&sniffed));
+
+        assert!(allocator.release("127.0.246."));
+        assert!(!allocator.release("127.0.246."));
+        assert!(allocator.release(&sniffed));
+        assert!(!allocator.reserved().contains(&sniffed));
+    }
+
+    #[tokio::test]
+    async fn test_configured_ip_prefixes() {
+        let config_dir = std::env::temp_dir().join("ccm_binding_test_configured_prefixes");
+        for (cluster, conf) in [
+            ("v4", "name: v4\nipprefix: 127.0.242.\nipformat: null\n"),
+            (
+                "v6",
+                "name: v6\nipprefix: null\nipformat: 'fd00:0:0:9::%d'\n",
+            ),
+        ] {
+            tokio::fs::create_dir_all(config_dir.join(cluster))
+                .await
+                .unwrap();
+            tokio::fs::write(config_dir.join(cluster).join("cluster.conf"), conf)
+                .await
+                .unwrap();
+        }
+        tokio::fs::write(config_dir.join("CURRENT"), "v4")
+            .await
+            .unwrap();
+        let own = config_dir.join("own");
+        tokio::fs::create_dir_all(own.join("own")).await.unwrap();
+        tokio::fs::write(own.join("own/cluster.conf"), "ipprefix: 127.0.241.\n")
+            .await
+            .unwrap();
+
+        let prefixes = configured_ip_prefixes(&config_dir).await.unwrap();
+        assert_eq!(
+            prefixes,
+            HashSet::from([
+                "127.0.242.".to_string(),
+                "fd00:0:0:9::".to_string(),
+                "127.0.241.".to_string()
+            ])
+        );
+        assert_eq!(cluster_config_dir(&config_dir, "own"), own);
+        assert_eq!(cluster_config_dir(&config_dir, "v4"), config_dir);
+        let missing = config_dir.join("missing");
+        assert!(configured_ip_prefixes(&missing).await.unwrap().is_empty());
+        let allocator = IpRangeAllocator::new().with_ccm_config_dir(&config_dir);
+        assert!(
+            allocator
+                .unavailable_prefixes()
+                .await
+                .unwrap()
+                .contains("127.0.242.")
+        );
+        tokio::fs::remove_dir_all(&config_dir).await.ok();
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_stale_allocations() {
+        // Node processes are only looked up in /proc.
+        if !cfg!(target_os = "linux") {
+            return;
+        }
+        let config_dir = std::env::temp_dir().join("ccm_binding_test_stale_allocations");
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        for (cluster, prefix, pid) in [
+            ("crashed", "127.0.236.", dead_pid),
+            ("running", "127.0.235.", std::process::id()),
+            ("reserved", "127.0.234.", dead_pid),
+        ] {
+            let node_dir = config_dir.join(cluster).join("node1");
+            tokio::fs::create_dir_all(&node_dir).await.unwrap();
+            let conf = format!("name: {}\nipprefix: {}\n", cluster, prefix);
+            tokio::fs::write(config_dir.join(cluster).join("cluster.conf"), conf)
+                .await
+                .unwrap();
+            tokio::fs::write(node_dir.join("cassandra.pid"), pid.to_string())
+                .await
+                .unwrap();
+        }
+        let allocator = IpRangeAllocator::new().with_ccm_config_dir(&config_dir);
+        allocator.reserve_prefix("127.0.234.").unwrap();
+
+        let recent = allocator
+            .cleanup_stale_allocations(Duration::from_secs(3600), None)
+            .await
+            .unwrap();
+        assert!(recent.is_empty());
+        let logged_cmd = Arc::new(crate::ccm_cli::LoggedCmd::new());
+        logged_cmd.set_dry_run(true);
+        let ccm = CcmRunner::new(logged_cmd.clone(), "/elsewhere");
+        let stale = allocator
+            .cleanup_stale_allocations(Duration::ZERO, Some(&ccm))
+            .await
+            .unwrap();
+        assert_eq!(
+            stale,
+            [StaleCluster {
+                config_dir: config_dir.clone(),
+                name: "crashed".to_string(),
+                ip_prefix: Some("127.0.236.".to_string()),
+                removed: true,
+            }]
+        );
+        let recorded = logged_cmd.recorded_commands();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].args[..2], ["remove", "crashed"]);
+        assert!(
+            recorded[0]
+                .args
+                .contains(&config_dir.to_string_lossy().into_owned())
+        );
+
+        // A failed removal does not stop the others.
+        let node_dir = config_dir.join("broken").join("node1");
+        tokio::fs::create_dir_all(&node_dir).await.unwrap();
+        tokio::fs::write(
+            config_dir.join("broken").join("cluster.conf"),
+            "name: broken\nipprefix: 127.0.233.\n",
+        )
+        .await
+        .unwrap();
+        let attempts = config_dir.join("attempts");
+        let script = format!(
+            "echo $2 >> {}; [ $2 != broken ]",
+            attempts.to_string_lossy()
+        );
+        let mut logged_cmd = crate::ccm_cli::LoggedCmd::new();
+        logged_cmd
+            .set_log_file(config_dir.join("ccm.log").to_string_lossy().into_owned())
+            .await
+            .unwrap();
+        let ccm = CcmRunner::new(Arc::new(logged_cmd), "/elsewhere")
+            .with_command("sh", ["-c", script.as_str(), "sh"]);
+        let err = allocator
+            .cleanup_stale_allocations(Duration::ZERO, Some(&ccm))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("broken: "), "{}", err);
+        let mut attempted: Vec<_> = tokio::fs::read_to_string(&attempts)
+            .await
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        attempted.sort();
+        assert_eq!(attempted, ["broken", "crashed"]);
+
+        allocator.release("127.0.234.");
+        tokio::fs::remove_dir_all(&config_dir).await.ok();
+    }
+
+    #[test]
+    fn test_conf_interface_address() {
+        let block = "name: node_1_2\ninterfaces:\n  binary:\n  - 127.0.5.2\n  - 9042\n  \
+                     storage: !!python/tuple\n  - 127.0.5.3\n  - 7000\n  thrift: null\n\
+                     jmx_port: '7199'\n";
+        assert_eq!(conf_interface_address(block, "binary"), Some("127.0.5.2"));
+        assert_eq!(conf_interface_address(block, "storage"), Some("127.0.5.3"));
+        assert_eq!(conf_interface_address(block, "thrift"), None);
+        let flow = "interfaces:\n  binary: ['127.0.5.4', 9042]\n";
+        assert_eq!(conf_interface_address(flow, "binary"), Some("127.0.5.4"));
+        assert_eq!(
+            conf_interface_address("binary: [127.0.0.1]\n", "binary"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_proc_address() {
+        let (loopback, mapped) = if cfg!(target_endian = "little") {
+            (
+                "00000000000000000000000001000000:2352",
+                "0000000000000000FFFF00000105007F:2352",
+            )
+        } else {
+            (
+                "00000000000000000000000000000001:2352",
+                "00000000000000000000FFFF7F000501:2352",
+            )
+        };
+        assert_eq!(
+            parse_proc_address(loopback),
+            Some(Ipv6Addr::LOCALHOST.into())
+        );
+        assert_eq!(
+            parse_proc_address(mapped),
+            Some(Ipv4Addr::new(127, 0, 5, 1).into())
+        );
+        let address: Ipv6Addr = "fd6c:636d:0:7::3".parse().unwrap();
+        assert_eq!(address_prefix(address.into()), "fd6c:636d:0:7::");
+        assert_eq!(normalize_ip_prefix("127.0.3"), "127.0.3.");
+        assert_eq!(normalize_ip_prefix("fd00::"), "fd00::");
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_parse_proc_net_table() {
+        let header = "  sl  local_address rem_address   st tx_queue rx_queue\n";
+        let tcp = format!(
+            "{header}\
+             0: 0105007F:2352 00000000:0000 0A 00000000:00000000\n\
+             1: 0106007F:C350 0100007F:2352 06 00000000:00000000\n\
+             2: 0107007F:C351 0100007F:2352 01 00000000:00000000\n"
+        );
+        let prefixes: Vec<_> = parse_proc_net_table(&tcp, true, false)
+            .into_iter()
+            .map(address_prefix)
+            .collect();
+        assert_eq!(prefixes, ["127.0.5.", "127.0.7."]);
+
+        let udp = format!("{header}   0: 0106007F:1F90 00000000:0000 07 00000000:00000000\n");
+        assert_eq!(
+            parse_proc_net_table(&udp, false, true),
+            [IpAddr::from(Ipv4Addr::new(127, 0, 6, 1))]
+        );
+        assert_eq!(
+            parse_proc_net_table(&tcp, true, true),
+            [IpAddr::from(Ipv4Addr::new(127, 0, 5, 1))]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_node_process_running() {
+        let node_dir = std::env::temp_dir().join("ccm_binding_test_node_process_running");
+        tokio::fs::create_dir_all(&node_dir).await.unwrap();
+        let pid_file = node_dir.join("cassandra.pid");
+        tokio::fs::remove_file(&pid_file).await.ok();
+        // Runs locally but is taken for a remote machine.
+        struct Remote(crate::ccm_cli::LoggedCmd);
+        impl CommandExecutor for Remote {
+            fn run_command<'a>(
+                &'a self,
+                command: &'a str,
+                args: &'a [&'a str],
+                opts: Option<RunOptions>,
+            ) -> futures::future::BoxFuture<'a, Result<crate::ccm_cli::CommandResult, IoError>>
+            {
+                CommandExecutor::run_command(&self.0, command, args, opts)
+            }
+
+            fn is_remote(&self) -> bool {
+                true
+            }
+        }
+        let mut logged_cmd = crate::ccm_cli::LoggedCmd::new();
+        logged_cmd
+            .set_log_file(node_dir.join("remote.log").to_string_lossy().into_owned())
+            .await
+            .unwrap();
+        let remote = Remote(logged_cmd);
+
+        for executor in [None, Some(&remote as &dyn CommandExecutor)] {
+            tokio::fs::remove_file(&pid_file).await.ok();
+            assert!(!node_process_running(&node_dir, executor).await.unwrap());
+            tokio::fs::write(&pid_file, format!("{}\n", std::process::id()))
+                .await
+                .unwrap();
+            assert!(node_process_running(&node_dir, executor).await.unwrap());
+            tokio::fs::write(&pid_file, "not a pid").await.unwrap();
+            assert!(!node_process_running(&node_dir, executor).await.unwrap());
+        }
+        let log = tokio::fs::read_to_string(node_dir.join("remote.log"))
+            .await
+            .unwrap();
+        assert_eq!(log.matches("started[").count(), 3);
+        tokio::fs::remove_dir_all(&node_dir).await.ok();
+    }
+}
@@ -0,0 +1,143 @@
+use crate::ccm_cli::Escalation;
+use crate::ip_range::{IpRangeAllocator, normalize_ip_prefix};
+use futures::future::BoxFuture;
+use std::io::Error as IoError;
+use std::net::IpAddr;
+
+/// What a new cluster asks its [`IpStrategy`] for.
+#[derive(Debug, Clone)]
+pub struct IpRequest<'a> {
+    pub cluster_name: &'a str,
+    /// ccm config directory the cluster lives in, next to other clusters.
+    pub install_directory: &'a str,
+    pub node_count: usize,
+}
+
+/// Addresses handed to a cluster by an [`IpStrategy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpAllocation {
+    /// Prefix node addresses are built from, e.g. `127.0.5.` or `fd6c:636d:0:1::`.
+    pub ip_prefix: String,
+    /// Addresses the nodes are bound to instead, see
+    /// [`Cluster::use_host_addresses`](crate::cluster::Cluster::use_host_addresses).
+    pub host_addresses: Vec<IpAddr>,
+    /// Runs the cluster in a network namespace of its own entered with this escalation,
+    /// see [`Cluster::isolate_network`](crate::cluster::Cluster::isolate_network).
+    pub network_namespace: Option<Escalation>,
+}
+
+/// Picks the addresses of a new cluster, see
+/// [`Cluster::with_ip_strategy`](crate::cluster::Cluster::with_ip_strategy). Harnesses
+/// with their own address management implement it to hand out their ranges.
+pub trait IpStrategy: Send + Sync {
+    fn allocate<'a>(
+        &'a self,
+        request: &'a IpRequest<'a>,
+    ) -> BoxFuture<'a, Result<IpAllocation, IoError>>;
+
+    /// Takes back an allocation once the cluster is destroyed or moved to other addresses.
+    fn release(&self, _allocation: &IpAllocation) {}
+}
+
+/// Free loopback prefix reserved through [`IpRangeAllocator`], skipping those of other
+/// clusters in the same install directory. What [`Cluster::new`](crate::cluster::Cluster::new)
+/// uses without an explicit prefix.
+#[derive(Debug, Clone, Default)]
+pub struct SniffedLoopback {
+    pub ipv6: bool,
+}
+
+impl IpStrategy for SniffedLoopback {
+    fn allocate<'a>(
+        &'a self,
+        request: &'a IpRequest<'a>,
+    ) -> BoxFuture<'a, Result<IpAllocation, IoError>> {
+        Box::pin(async move {
+            let allocator =
+                IpRangeAllocator::default().with_ccm_config_dir(request.install_directory);
+            let ip_prefix = if self.ipv6 {
+                allocator.reserve_ipv6().await?
+            } else {
+                allocator.reserve().await?
+            };
+            Ok(IpAllocation {
+                ip_prefix,
+                ..Default::default()
+            })
+        })
+    }
+
+    fn release(&self, allocation: &IpAllocation) {
+        IpRangeAllocator::default().release(&allocation.ip_prefix);
+    }
+}
+
+/// A prefix chosen by the caller, e.g. one reserved up front with [`IpRangeAllocator`].
+#[derive(Debug, Clone)]
+pub struct FixedPrefix(pub String);
+
+impl IpStrategy for FixedPrefix {
+    fn allocate<'a>(
+        &'a self,
+        _request: &'a IpRequest<'a>,
+    ) -> BoxFuture<'a, Result<IpAllocation, IoError>> {
+        Box::pin(async move {
+            Ok(IpAllocation {
+                ip_prefix: normalize_ip_prefix(&self.0),
+                ..Default::default()
+            })
+        })
+    }
+}
+
+/// A network namespace per cluster: nothing else shares its loopback interface, so every
+/// cluster can use the same prefix.
+#[derive(Debug, Clone)]
+pub struct IsolatedNamespace {
+    pub escalation: Escalation,
+}
+
+impl IpStrategy for IsolatedNamespace {
+    fn allocate<'a>(
+        &'a self,
+        _request: &'a IpRequest<'a>,
+    ) -> BoxFuture<'a, Result<IpAllocation, IoError>> {
+        Box::pin(async move {
+            Ok(IpAllocation {
+                ip_prefix: "127.0.1.".to_string(),
+                network_namespace: Some(self.escalation.clone()),
+                ..Default::default()
+            })
+        })
+    }
+}
+
+/// Existing host addresses, one per node in creation order.
+#[derive(Debug, Clone)]
+pub struct ExplicitAddresses(pub Vec<IpAddr>);
+
+impl IpStrategy for ExplicitAddresses {
+    fn allocate<'a>(
+        &'a self,
+        request: &'a IpRequest<'a>,
+    ) -> BoxFuture<'a, Result<IpAllocation, IoError>> {
+        Box::pin(async move {
+            if self.0.len() < request.node_count {
+                return Err(IoError::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} host addresses for {} nodes",
+                        self.0.len(),
+                        request.node_count
+                    ),
+                ));
+            }
+            // ccm still wants a prefix on create; the nodes never bind under it.
+            Ok(IpAllocation {
+                ip_prefix: "127.0.0.".to_string(),
+                host_addresses: self.0.clone(),
+                ..Default::default()
+            })
+        })
+    }
+}
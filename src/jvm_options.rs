@@ -0,0 +1,98 @@
+use std::io::Error as IoError;
+use std::path::Path;
+
+/// JVM configuration file of a Cassandra node, relative to the node `conf` directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JvmFile {
+    /// `jvm.options` (Cassandra 3.x).
+    JvmOptions,
+    /// `jvm-server.options` (Cassandra 4.0+).
+    JvmServerOptions,
+    CassandraEnv,
+}
+
+impl JvmFile {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            JvmFile::JvmOptions => "jvm.options",
+            JvmFile::JvmServerOptions => "jvm-server.options",
+            JvmFile::CassandraEnv => "cassandra-env.sh",
+        }
+    }
+}
+
+/// Line-based edit applied to a JVM configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JvmEdit {
+    /// Appends the line unless an identical line is already present.
+    Append(String),
+    /// Replaces every line starting with `prefix`; appends `line` if none matched.
+    /// E.g. `Replace { prefix: "-Xmx".into(), line: "-Xmx2G".into() }`.
+    Replace { prefix: String, line: String },
+    /// Drops every line starting with the given prefix.
+    Remove(String),
+}
+
+/// Applies `edits` in order to the file contents.
+pub fn apply_edits(content: &str, edits: &[JvmEdit]) -> String {
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    for edit in edits {
+        match edit {
+            JvmEdit::Append(line) => {
+                if !lines.iter().any(|l| l == line) {
+                    lines.push(line.clone());
+                }
+            }
+            JvmEdit::Replace { prefix, line } => {
+                let mut replaced = false;
+                for l in lines.iter_mut() {
+                    if l.starts_with(prefix.as_str()) {
+                        *l = line.clone();
+                        replaced = true;
+                    }
+                }
+                if !replaced {
+                    lines.push(line.clone());
+                }
+            }
+            JvmEdit::Remove(prefix) => lines.retain(|l| !l.starts_with(prefix.as_str())),
+        }
+    }
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Rewrites `path` in place with `edits` applied.
+pub async fn edit_file(path: &Path, edits: &[JvmEdit]) -> Result<(), IoError> {
+    let content = tokio::fs::read_to_string(path).await?;
+    tokio::fs::write(path, apply_edits(&content, edits)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_edits() {
+        let content = "# heap\n-Xms1G\n-Xmx1G\n-XX:+UseParNewGC\n";
+        let edits = vec![
+            JvmEdit::Replace {
+                prefix: "-Xmx".to_string(),
+                line: "-Xmx4G".to_string(),
+            },
+            JvmEdit::Replace {
+                prefix: "-Xmn".to_string(),
+                line: "-Xmn800M".to_string(),
+            },
+            JvmEdit::Remove("-XX:+UseParNewGC".to_string()),
+            JvmEdit::Append("-XX:+UseG1GC".to_string()),
+            JvmEdit::Append("-Xms1G".to_string()),
+        ];
+
+        assert_eq!(
+            apply_edits(content, &edits),
+            "# heap\n-Xms1G\n-Xmx4G\n-Xmn800M\n-XX:+UseG1GC\n"
+        );
+    }
+}
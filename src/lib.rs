@@ -0,0 +1,38 @@
+pub mod auth;
+pub mod ccm_bootstrap;
+pub mod ccm_cli;
+pub mod ccm_runner;
+pub mod cluster;
+pub mod cluster_builder;
+pub mod cluster_config;
+pub mod cluster_pool;
+pub mod config_schema;
+#[cfg(feature = "config-yaml")]
+pub mod config_template;
+pub mod executor;
+pub mod faults;
+pub mod find_available_iprange;
+pub mod health;
+pub mod hooks;
+pub mod host_capabilities;
+pub mod host_limits;
+pub mod host_resources;
+pub mod hosts;
+pub mod ip_range;
+pub mod ip_strategy;
+pub mod jvm_options;
+pub mod log_tail;
+pub mod loopback;
+pub mod netns;
+pub mod node_builder;
+pub mod nodetool;
+pub mod ports;
+pub mod proxy;
+mod saved_state;
+pub mod scylla_bench;
+pub mod stress;
+pub mod test_context;
+pub mod tls;
+pub mod tools;
+pub mod version;
+pub mod watchdog;
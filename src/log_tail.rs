@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Line appended to one of the followed log files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLine {
+    /// File name within the followed directory, e.g. `system.log`.
+    pub file: String,
+    pub line: String,
+}
+
+/// Live lines of every `*.log` file in a directory, including files created later.
+/// Following stops when this value is dropped.
+pub struct LogFollower {
+    receiver: mpsc::Receiver<OutputLine>,
+    task: JoinHandle<()>,
+}
+
+impl LogFollower {
+    /// Starts following `directory`; with `from_start` existing content is replayed first,
+    /// otherwise only lines written after this call are returned.
+    pub fn follow(directory: impl Into<PathBuf>, from_start: bool) -> Self {
+        let (sender, receiver) = mpsc::channel(1024);
+        let task = tokio::spawn(follow_directory(directory.into(), from_start, sender));
+        LogFollower { receiver, task }
+    }
+
+    /// Waits for the next line; `None` if the follower task ended.
+    pub async fn next_line(&mut self) -> Option<OutputLine> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for LogFollower {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct FollowedFile {
+    offset: u64,
+    partial: String,
+}
+
+async fn log_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    if let Ok(mut entries) = tokio::fs::read_dir(directory).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "log") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+async fn follow_directory(directory: PathBuf, from_start: bool, sender: mpsc::Sender<OutputLine>) {
+    let mut followed: HashMap<PathBuf, FollowedFile> = HashMap::new();
+    for path in log_files(&directory).await {
+        let offset = if from_start {
+            0
+        } else {
+            tokio::fs::metadata(&path)
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0)
+        };
+        followed.insert(
+            path,
+            FollowedFile {
+                offset,
+                partial: String::new(),
+            },
+        );
+    }
+
+    loop {
+        for path in log_files(&directory).await {
+            let state = followed.entry(path.clone()).or_insert(FollowedFile {
+                offset: 0,
+                partial: String::new(),
+            });
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            for line in read_new_lines(&path, state).await {
+                let line = OutputLine {
+                    file: file_name.clone(),
+                    line,
+                };
+                if sender.send(line).await.is_err() {
+                    return;
+                }
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn read_new_lines(path: &Path, state: &mut FollowedFile) -> Vec<String> {
+    let Ok(mut file) = File::open(path).await else {
+        return vec![];
+    };
+    let length = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+    if length < state.offset {
+        // Truncated or rotated: start over.
+        state.offset = 0;
+        state.partial.clear();
+    }
+    if length == state.offset || file.seek(SeekFrom::Start(state.offset)).await.is_err() {
+        return vec![];
+    }
+    let mut buffer = Vec::new();
+    if file.read_to_end(&mut buffer).await.is_err() {
+        return vec![];
+    }
+    state.offset += buffer.len() as u64;
+    state.partial.push_str(&String::from_utf8_lossy(&buffer));
+
+    let mut lines: Vec<String> = state.partial.split('\n').map(str::to_string).collect();
+    state.partial = lines.pop().unwrap_or_default();
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_follow_new_lines() {
+        let dir = std::env::temp_dir().join("ccm_binding_test_follow_new_lines");
+        tokio::fs::remove_dir_all(&dir).await.ok();
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("system.log"), "old line\n")
+            .await
+            .unwrap();
+
+        let mut follower = LogFollower::follow(&dir, false);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("system.log"))
+            .await
+            .unwrap();
+        file.write_all(b"new line\npartial").await.unwrap();
+        tokio::fs::write(dir.join("stderr.log"), "err\n")
+            .await
+            .unwrap();
+
+        let mut received = vec![
+            follower.next_line().await.unwrap(),
+            follower.next_line().await.unwrap(),
+        ];
+        received.sort_by(|a, b| a.file.cmp(&b.file));
+        assert_eq!(
+            received,
+            vec![
+                OutputLine {
+                    file: "stderr.log".to_string(),
+                    line: "err".to_string()
+                },
+                OutputLine {
+                    file: "system.log".to_string(),
+                    line: "new line".to_string()
+                },
+            ]
+        );
+
+        drop(follower);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+use crate::ccm_cli::{Escalation, RunOptions};
+use crate::executor::CommandExecutor;
+use crate::run_options;
+use std::collections::HashSet;
+use std::io::Error as IoError;
+
+/// Loopback interface node addresses are aliased on.
+const LOOPBACK_INTERFACE: &str = "lo0";
+
+/// Whether node addresses other than `127.0.0.1` need explicit loopback aliases. Linux
+/// routes all of `127/8` to `lo`; macOS only configures `127.0.0.1` on `lo0`.
+pub fn aliases_required() -> bool {
+    cfg!(target_os = "macos")
+}
+
+/// Addresses configured on the loopback interface, from `ifconfig lo0` output.
+pub fn parse_ifconfig_addresses(output: &str) -> HashSet<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("inet") | Some("inet6") => words.next(),
+                _ => None,
+            }
+        })
+        // Link-local IPv6 addresses carry a zone, e.g. `fe80::1%lo0`.
+        .map(|address| address.split('%').next().unwrap_or(address).to_string())
+        .collect()
+}
+
+pub async fn existing_addresses(
+    executor: &dyn CommandExecutor,
+) -> Result<HashSet<String>, IoError> {
+    let result = executor
+        .run_command("ifconfig", &[LOOPBACK_INTERFACE], None)
+        .await?;
+    Ok(parse_ifconfig_addresses(&result.stdout))
+}
+
+fn alias_args(address: &str, add: bool) -> Vec<&str> {
+    let mut args = vec![LOOPBACK_INTERFACE];
+    match (address.contains(':'), add) {
+        (false, true) => args.extend(["alias", address, "up"]),
+        (false, false) => args.extend(["-alias", address]),
+        (true, true) => args.extend(["inet6", address, "prefixlen", "128", "alias"]),
+        (true, false) => args.extend(["inet6", address, "-alias"]),
+    }
+    args
+}
+
+/// Adds `address` to the loopback interface; changing interfaces needs root, hence
+/// `escalation`.
+pub async fn add_alias(
+    executor: &dyn CommandExecutor,
+    address: &str,
+    escalation: &Escalation,
+) -> Result<(), IoError> {
+    executor
+        .run_command(
+            "ifconfig",
+            &alias_args(address, true),
+            run_options!(escalation = Some(escalation.clone())),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn remove_alias(
+    executor: &dyn CommandExecutor,
+    address: &str,
+    escalation: &Escalation,
+) -> Result<(), IoError> {
+    executor
+        .run_command(
+            "ifconfig",
+            &alias_args(address, false),
+            run_options!(escalation = Some(escalation.clone())),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Adds the `addresses` missing from the loopback interface and returns them, so that
+/// only aliases created here are removed later. If one cannot be added, those added
+/// before it are removed again and the error is returned.
+pub async fn ensure_aliases(
+    executor: &dyn CommandExecutor,
+    addresses: &[String],
+    escalation: &Escalation,
+) -> Result<Vec<String>, IoError> {
+    let existing = existing_addresses(executor).await?;
+    let mut added: Vec<String> = vec![];
+    for address in addresses {
+        if existing.contains(address) {
+            continue;
+        }
+        if let Err(error) = add_alias(executor, address, escalation).await {
+            for address in added.iter().rev() {
+                // Best effort: the original error is the one worth reporting.
+                remove_alias(executor, address, escalation).await.ok();
+            }
+            return Err(error);
+        }
+        added.push(address.clone());
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ccm_cli::{CommandResult, LoggedCmd};
+    use futures::future::BoxFuture;
+    use std::io::ErrorKind;
+
+    /// Dry-run executor failing every command that mentions `failing`.
+    struct FailingOn {
+        logged_cmd: LoggedCmd,
+        failing: &'static str,
+    }
+
+    impl CommandExecutor for FailingOn {
+        fn run_command<'a>(
+            &'a self,
+            command: &'a str,
+            args: &'a [&'a str],
+            opts: Option<RunOptions>,
+        ) -> BoxFuture<'a, Result<CommandResult, IoError>> {
+            Box::pin(async move {
+                if args.contains(&self.failing) {
+                    return Err(IoError::new(ErrorKind::PermissionDenied, "not permitted"));
+                }
+                self.logged_cmd.run_command(command, args, opts).await
+            })
+        }
+    }
+
+    #[test]
+    fn test_parse_ifconfig_addresses() {
+        let output = "lo0: flags=8049<UP,LOOPBACK,RUNNING,MULTICAST> mtu 16384\n\
+             \toptions=1203<RXCSUM,TXCSUM,TXSTATUS,SW_TIMESTAMP>\n\
+             \tinet 127.0.0.1 netmask 0xff000000\n\
+             \tinet6 ::1 prefixlen 128\n\
+             \tinet6 fe80::1%lo0 prefixlen 64 scopeid 0x1\n\
+             \tinet 127.0.5.1 netmask 0xff000000\n";
+        assert_eq!(
+            parse_ifconfig_addresses(output),
+            HashSet::from(["127.0.0.1", "::1", "fe80::1", "127.0.5.1"].map(str::to_string))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ensure_aliases() {
+        let logged_cmd = LoggedCmd::new();
+        logged_cmd.set_dry_run(true);
+        let addresses = ["127.0.5.1".to_string(), "fd00::1".to_string()];
+        let added = ensure_aliases(&logged_cmd, &addresses, &Escalation::Sudo)
+            .await
+            .unwrap();
+        assert_eq!(added, addresses);
+        remove_alias(&logged_cmd, "127.0.5.1", &Escalation::Sudo)
+            .await
+            .unwrap();
+
+        let recorded = logged_cmd.recorded_lines();
+        assert_eq!(
+            recorded,
+            [
+                "ifconfig lo0",
+                "sudo -n ifconfig lo0 alias 127.0.5.1 up",
+                "sudo -n ifconfig lo0 inet6 fd00::1 prefixlen 128 alias",
+                "sudo -n ifconfig lo0 -alias 127.0.5.1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ensure_aliases_rolls_back() {
+        let executor = FailingOn {
+            logged_cmd: LoggedCmd::new(),
+            failing: "127.0.5.3",
+        };
+        executor.logged_cmd.set_dry_run(true);
+        let addresses = ["127.0.5.1", "127.0.5.2", "127.0.5.3"].map(str::to_string);
+        let err = ensure_aliases(&executor, &addresses, &Escalation::Sudo)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        let recorded = executor.logged_cmd.recorded_lines();
+        assert_eq!(
+            recorded,
+            [
+                "ifconfig lo0",
+                "sudo -n ifconfig lo0 alias 127.0.5.1 up",
+                "sudo -n ifconfig lo0 alias 127.0.5.2 up",
+                "sudo -n ifconfig lo0 -alias 127.0.5.2",
+                "sudo -n ifconfig lo0 -alias 127.0.5.1",
+            ]
+        );
+    }
+}
@@ -570,11 +570,342 @@
 //     println!("Validation result: {}", is_valid); // Output: Validation result: true
 // }
 
-mod cluster_config;
-mod find_available_iprange;
-mod cluster;
-mod ccm_cli;
+use ccm_binding::cluster::{Cluster, ClusterPolicy};
+use ccm_binding::ip_range;
+use ccm_binding::log_tail::LogFollower;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::Error as IoError;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
 
-fn main() {
+const USAGE: &str = "usage: ccm-binding [--output text|json] <command> [options]
 
+commands:
+  create <name> --version <version> [--nodes 3,3] [--dir <dir>] [--ip-prefix <prefix>] [--cassandra] [--force]
+  stop <name> [--dir <dir>]
+  destroy <name> [--dir <dir>]
+  attach <name> <node> [--dir <dir>] [--from-start]";
+
+const DEFAULT_DIR: &str = "/tmp/ccm";
+
+#[derive(PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+/// Result of a subcommand, printed as text or as a single JSON object.
+struct Summary {
+    command: String,
+    cluster: Option<Value>,
+    durations: Vec<(&'static str, u128)>,
+    error: Option<IoError>,
+}
+
+impl Summary {
+    fn new(command: &str) -> Self {
+        Summary {
+            command: command.to_string(),
+            cluster: None,
+            durations: vec![],
+            error: None,
+        }
+    }
+
+    /// Runs `step`, recording its duration under `name`; later steps are skipped after an error.
+    async fn step<T>(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<T, IoError>>,
+    ) -> Option<T> {
+        if self.error.is_some() {
+            return None;
+        }
+        let started = Instant::now();
+        let result = step.await;
+        self.durations.push((name, started.elapsed().as_millis()));
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let durations: serde_json::Map<String, Value> = self
+            .durations
+            .iter()
+            .map(|(name, ms)| (name.to_string(), json!(ms)))
+            .collect();
+        json!({
+            "command": self.command,
+            "status": if self.error.is_none() { "ok" } else { "error" },
+            "cluster": self.cluster,
+            "durations_ms": durations,
+            "error": self.error.as_ref().map(|e| json!({
+                "kind": format!("{:?}", e.kind()),
+                "message": e.to_string(),
+            })),
+        })
+    }
+
+    fn print(&self, format: &OutputFormat) {
+        match format {
+            OutputFormat::Json => println!("{}", self.to_json()),
+            OutputFormat::Text => {
+                for (name, ms) in self.durations.iter() {
+                    println!("{:10} {} ms", name, ms);
+                }
+                match &self.error {
+                    None => println!("{}: ok", self.command),
+                    Some(e) => eprintln!("{}: failed: {}", self.command, e),
+                }
+            }
+        }
+    }
+}
+
+async fn describe(cluster: &Cluster) -> Value {
+    let mut nodes = vec![];
+    for node in cluster.nodes() {
+        let node = node.read().await;
+        nodes.push(json!({
+            "name": node.name,
+            "datacenter_id": node.datacenter_id,
+            "node_id": node.node_id,
+            "tags": node.tags,
+        }));
+    }
+    json!({
+        "name": cluster.name,
+        "version": cluster.version,
+        "scylla": cluster.scylla,
+        "ip_prefix": cluster.ip_prefix,
+        "install_directory": cluster.install_directory,
+        "tags": cluster.tags(),
+        "nodes": nodes,
+    })
+}
+
+fn parse_topology(value: &str) -> Result<Vec<i32>, String> {
+    value
+        .split(',')
+        .map(|count| {
+            count
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("invalid node count '{}'", count))
+        })
+        .collect()
+}
+
+/// Splits `args` into positional arguments and `--flag [value]` options.
+fn parse_options(
+    args: &[String],
+    switches: &[&str],
+) -> Result<(Vec<String>, HashMap<String, String>), String> {
+    let mut positional = vec![];
+    let mut options = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if let Some(flag) = arg.strip_prefix("--") {
+            if switches.contains(&flag) {
+                options.insert(flag.to_string(), String::new());
+            } else {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("missing value for --{}", flag))?;
+                options.insert(flag.to_string(), value.clone());
+            }
+        } else {
+            positional.push(arg.clone());
+        }
+    }
+    Ok((positional, options))
+}
+
+/// Streams the node server output until interrupted; never returns on success.
+async fn attach(args: &[String], format: &OutputFormat) -> Result<(), String> {
+    let (positional, options) = parse_options(args, &["from-start"])?;
+    let [cluster, node] = positional.as_slice() else {
+        return Err("attach requires a cluster name and a node name".to_string());
+    };
+    let dir = options.get("dir").map_or(DEFAULT_DIR, String::as_str);
+    let logs = ip_range::cluster_config_dir(Path::new(dir), cluster)
+        .join(cluster)
+        .join(node)
+        .join("logs");
+    if !logs.is_dir() {
+        return Err(format!("{} does not exist", logs.display()));
+    }
+    let mut follower = LogFollower::follow(logs, options.contains_key("from-start"));
+    while let Some(output) = follower.next_line().await {
+        match format {
+            OutputFormat::Json => println!("{}", json!({"file": output.file, "line": output.line})),
+            OutputFormat::Text => println!("{:20} {}", output.file, output.line),
+        }
+    }
+    Ok(())
+}
+
+async fn run(command: &str, args: &[String]) -> Result<Summary, String> {
+    let (positional, options) = parse_options(args, &["cassandra", "force"])?;
+    let name = positional
+        .first()
+        .ok_or_else(|| format!("{} requires a cluster name", command))?
+        .clone();
+    let dir = options
+        .get("dir")
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_DIR.to_string());
+    let mut summary = Summary::new(command);
+
+    match command {
+        "create" => {
+            let version = options
+                .get("version")
+                .ok_or("create requires --version")?
+                .clone();
+            let topology = parse_topology(options.get("nodes").map_or("1", String::as_str))?;
+            let cluster = summary
+                .step(
+                    "new",
+                    Cluster::new(
+                        name,
+                        version,
+                        options.get("ip-prefix").map(String::as_str),
+                        topology,
+                        dir,
+                        !options.contains_key("cassandra"),
+                    ),
+                )
+                .await;
+            if let Some(mut cluster) = cluster {
+                // Left running for `stop` and `destroy` of a later invocation.
+                cluster.set_policy(ClusterPolicy::KeepAlive);
+                summary
+                    .step("init", cluster.init(options.contains_key("force")))
+                    .await;
+                summary.step("start", cluster.start(None)).await;
+                summary.cluster = Some(describe(&cluster).await);
+            }
+        }
+        "stop" | "destroy" => {
+            let cluster = summary.step("load", Cluster::load(&name, &dir)).await;
+            if let Some(mut cluster) = cluster {
+                if command == "stop" {
+                    cluster.set_policy(ClusterPolicy::KeepAlive);
+                    summary.step("stop", cluster.stop()).await;
+                } else {
+                    summary.step("destroy", cluster.destroy()).await;
+                }
+                summary.cluster = Some(describe(&cluster).await);
+            }
+        }
+        _ => return Err(format!("unknown command '{}'", command)),
+    }
+    Ok(summary)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut format = OutputFormat::Text;
+    if let Some(pos) = args.iter().position(|arg| arg == "--output") {
+        format = match args.get(pos + 1).map(String::as_str) {
+            Some("json") => OutputFormat::Json,
+            Some("text") => OutputFormat::Text,
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        };
+        args.drain(pos..pos + 2);
+    }
+    let Some(command) = args.first().cloned() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let result = if command == "attach" {
+        attach(&args[1..], &format).await.map(|_| None)
+    } else {
+        run(&command, &args[1..]).await.map(Some)
+    };
+    match result {
+        Ok(None) => ExitCode::SUCCESS,
+        Ok(Some(summary)) => {
+            summary.print(&format);
+            if summary.error.is_none() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(usage_error) => {
+            if format == OutputFormat::Json {
+                println!(
+                    "{}",
+                    json!({
+                        "command": command,
+                        "status": "error",
+                        "error": {"kind": "InvalidInput", "message": usage_error},
+                    })
+                );
+            } else {
+                eprintln!("{}\n{}", usage_error, USAGE);
+            }
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_attach_finds_own_config_dir() {
+        let dir = std::env::temp_dir().join("ccm_binding_test_attach");
+        tokio::fs::remove_dir_all(&dir).await.ok();
+        // `<dir>/<name>/<name>/`, the layout of clusters created by the crate.
+        let cluster_dir = dir.join("attached").join("attached");
+        tokio::fs::create_dir_all(cluster_dir.join("node_1_1").join("logs"))
+            .await
+            .unwrap();
+        tokio::fs::write(cluster_dir.join("cluster.conf"), "name: attached\n")
+            .await
+            .unwrap();
+        let args = |node: &str| {
+            vec![
+                "attached".to_string(),
+                node.to_string(),
+                "--dir".to_string(),
+                dir.to_string_lossy().into_owned(),
+            ]
+        };
+
+        let err = attach(&args("node_1_2"), &OutputFormat::Text)
+            .await
+            .unwrap_err();
+        assert!(
+            err.ends_with("attached/attached/node_1_2/logs does not exist"),
+            "{}",
+            err
+        );
+        // Following the logs never returns.
+        let following = tokio::time::timeout(
+            Duration::from_millis(200),
+            attach(&args("node_1_1"), &OutputFormat::Text),
+        )
+        .await;
+        assert!(following.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.ok();
+    }
 }
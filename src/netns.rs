@@ -0,0 +1,146 @@
+use crate::ccm_cli::{CommandResult, Escalation, RunOptions};
+use crate::executor::CommandExecutor;
+use crate::run_options;
+use futures::future::BoxFuture;
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Directory `ip netns` keeps named namespaces in.
+const NETNS_DIR: &str = "/run/netns";
+
+/// Linux network namespace a cluster runs in, so that its addresses and ports can't clash
+/// with anything else on the host. Commands are run in it through `nsenter` on top of
+/// another executor; entering a namespace needs root, hence `escalation`, and the
+/// commands, ccm and the servers it starts included, run with the escalated privileges.
+///
+/// Only the loopback interface exists in the namespace: nothing outside of it, the
+/// crate's own readiness probes included, can reach the nodes. Tests talking to the
+/// cluster have to run inside the namespace too, e.g. under `ip netns exec`.
+pub struct NetworkNamespace {
+    pub name: String,
+    pub escalation: Escalation,
+    executor: Arc<dyn CommandExecutor>,
+}
+
+impl NetworkNamespace {
+    pub fn new(executor: Arc<dyn CommandExecutor>, name: impl Into<String>) -> Self {
+        NetworkNamespace {
+            name: name.into(),
+            escalation: Escalation::Sudo,
+            executor,
+        }
+    }
+
+    /// Escalation used to manage and enter the namespace; `Escalation::Prefix(vec![])`
+    /// when already running as root, e.g. in CI containers.
+    pub fn with_escalation(mut self, escalation: Escalation) -> Self {
+        self.escalation = escalation;
+        self
+    }
+
+    /// Bind mount `ip netns` pins the namespace with.
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(NETNS_DIR).join(&self.name)
+    }
+
+    async fn ip(&self, args: &[&str]) -> Result<(), IoError> {
+        self.executor
+            .run_command(
+                "ip",
+                args,
+                run_options!(escalation = Some(self.escalation.clone())),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the namespace and brings its loopback interface up, which routes all of
+    /// `127/8` to it.
+    pub async fn create(&self) -> Result<(), IoError> {
+        self.ip(&["netns", "add", &self.name]).await?;
+        self.ip(&["-n", &self.name, "link", "set", "lo", "up"])
+            .await
+    }
+
+    /// Routes `cidr`, e.g. an IPv6 cluster range, to the namespace's loopback interface.
+    pub async fn add_local_route(&self, cidr: &str) -> Result<(), IoError> {
+        let family = if cidr.contains(':') { "-6" } else { "-4" };
+        self.ip(&[
+            "-n", &self.name, family, "route", "add", "local", cidr, "dev", "lo",
+        ])
+        .await
+    }
+
+    /// Deletes the namespace; processes still running in it keep it alive until they exit.
+    pub async fn delete(&self) -> Result<(), IoError> {
+        self.ip(&["netns", "delete", &self.name]).await
+    }
+}
+
+impl CommandExecutor for NetworkNamespace {
+    fn run_command<'a>(
+        &'a self,
+        command: &'a str,
+        args: &'a [&'a str],
+        opts: Option<RunOptions>,
+    ) -> BoxFuture<'a, Result<CommandResult, IoError>> {
+        Box::pin(async move {
+            let mut opts = opts.unwrap_or_default();
+            let net = format!("--net={}", self.path().display());
+            let mut words: Vec<String> = vec![net, "--".to_string()];
+            // Escalation asked for by the caller applies inside the namespace.
+            if let Some(escalation) = opts.escalation.take() {
+                words.extend(escalation.prefix(&opts.env));
+            }
+            words.push(command.to_string());
+            words.extend(args.iter().map(|arg| arg.to_string()));
+            opts.escalation = Some(self.escalation.clone());
+
+            let words: Vec<&str> = words.iter().map(String::as_str).collect();
+            self.executor
+                .run_command("nsenter", &words, Some(opts))
+                .await
+        })
+    }
+    fn is_remote(&self) -> bool {
+        self.executor.is_remote()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ccm_cli::LoggedCmd;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_netns_invocation() {
+        let logged_cmd = Arc::new(LoggedCmd::new());
+        logged_cmd.set_dry_run(true);
+        let netns = NetworkNamespace::new(logged_cmd.clone(), "ccm-test");
+        netns.create().await.unwrap();
+        let env = HashMap::from([("SCYLLA_EXT_OPTS".to_string(), "--smp 1".to_string())]);
+        CommandExecutor::run_command(
+            &netns,
+            "ccm",
+            &["node_1_1", "start"],
+            run_options!(env = env),
+        )
+        .await
+        .unwrap();
+        netns.delete().await.unwrap();
+
+        let recorded = logged_cmd.recorded_lines();
+        assert_eq!(
+            recorded,
+            [
+                "sudo -n ip netns add ccm-test",
+                "sudo -n ip -n ccm-test link set lo up",
+                "sudo -n --preserve-env=SCYLLA_EXT_OPTS nsenter --net=/run/netns/ccm-test -- \
+                 ccm node_1_1 start",
+                "sudo -n ip netns delete ccm-test",
+            ]
+        );
+    }
+}
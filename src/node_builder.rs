@@ -0,0 +1,141 @@
+use crate::cluster::{Cluster, Node};
+use crate::cluster_config::ScyllaConfig;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Node with settings of its own, for heterogeneous clusters, e.g.
+/// `cluster.node().dc(2).smp(4).memory(2048).add().await`. Unset settings fall back to the
+/// cluster defaults, as with [`Cluster::add_node`].
+pub struct NodeBuilder<'a> {
+    cluster: &'a mut Cluster,
+    datacenter_id: Option<i32>,
+    rack: Option<String>,
+    smp: Option<i32>,
+    memory: Option<i32>,
+    config: Option<ScyllaConfig>,
+    version: Option<String>,
+}
+
+impl<'a> NodeBuilder<'a> {
+    pub fn new(cluster: &'a mut Cluster) -> Self {
+        NodeBuilder {
+            cluster,
+            datacenter_id: None,
+            rack: None,
+            smp: None,
+            memory: None,
+            config: None,
+            version: None,
+        }
+    }
+
+    /// Datacenter id, 1 by default.
+    pub fn dc(mut self, datacenter_id: i32) -> Self {
+        self.datacenter_id = Some(datacenter_id);
+        self
+    }
+
+    pub fn rack(mut self, rack: impl Into<String>) -> Self {
+        self.rack = Some(rack.into());
+        self
+    }
+
+    pub fn smp(mut self, smp: i32) -> Self {
+        self.smp = Some(smp);
+        self
+    }
+
+    /// Memory in megabytes.
+    pub fn memory(mut self, memory: i32) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Merged over the defaults of the node's datacenter.
+    pub fn config(mut self, config: ScyllaConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Version the node runs instead of the cluster's, e.g. for mixed-version upgrade tests.
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Adds the node to the cluster; it is created by the next [`init`](Cluster::init).
+    pub async fn add(self) -> &'a Arc<RwLock<Node>> {
+        let default_memory = self.cluster.default_node_memory;
+        let node = self
+            .cluster
+            .add_new_node(self.datacenter_id, self.rack)
+            .await;
+        {
+            let mut node = node.write().await;
+            if let Some(smp) = self.smp {
+                node.smp = smp;
+                if default_memory == 0 {
+                    node.memory = 512 * smp;
+                }
+            }
+            if let Some(memory) = self.memory {
+                node.memory = memory;
+            }
+            if let Some(config) = &self.config {
+                node.config.merge(config);
+            }
+            node.version = self.version;
+        }
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_node() {
+        let install_directory = std::env::temp_dir().join("ccm_binding_test_node_builder");
+        let mut cluster = Cluster::builder()
+            .version("release:6.2")
+            .topology(&[1])
+            .install_directory(install_directory.to_string_lossy())
+            .ip_prefix("127.0.231.")
+            .build()
+            .await
+            .unwrap();
+        cluster.logged_cmd().set_dry_run(true);
+        cluster.set_loopback_aliases(None);
+        let config = ScyllaConfig::from_flat_string("num_tokens:8").unwrap();
+        let node = cluster
+            .node()
+            .dc(2)
+            .rack("r2")
+            .smp(4)
+            .memory(2048)
+            .config(config)
+            .version("release:6.1")
+            .add()
+            .await
+            .clone();
+        let node = node.read().await;
+        assert_eq!(node.name, "node_2_1");
+        assert_eq!((node.smp, node.memory), (4, 2048));
+        assert_eq!(node.rack.as_deref(), Some("r2"));
+        drop(node);
+
+        cluster.init(false).await.unwrap();
+        let recorded = cluster.logged_cmd().recorded_commands();
+        let setdir: Vec<_> = recorded
+            .iter()
+            .filter(|command| command.args.contains(&"setdir".to_string()))
+            .collect();
+        assert_eq!(setdir.len(), 1);
+        assert_eq!(
+            setdir[0].args[..4],
+            ["node_2_1", "setdir", "-v", "release:6.1"]
+        );
+        cluster.destroy().await.unwrap();
+    }
+}
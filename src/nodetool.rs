@@ -0,0 +1,342 @@
+use std::net::IpAddr;
+
+/// Token ownership state of a peer, the second letter of its `nodetool status` code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerState {
+    Normal,
+    Leaving,
+    Joining,
+    Moving,
+}
+
+/// One node as seen in `nodetool status` of another, see
+/// [`Cluster::ring_status`](crate::cluster::Cluster::ring_status).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub address: IpAddr,
+    pub dc: String,
+    pub rack: String,
+    /// Whether the peer is up (`U`) rather than down (`D`).
+    pub up: bool,
+    pub state: PeerState,
+    /// Data on disk in bytes, `None` when not known, e.g. while the peer is down.
+    pub load: Option<u64>,
+    pub tokens: u32,
+    pub host_id: String,
+}
+
+/// Peers listed by `nodetool status`, in the order printed. Lines that don't describe a
+/// peer, such as headers and legends, are skipped.
+pub fn parse_status(output: &str) -> Vec<PeerInfo> {
+    let mut dc = String::new();
+    let mut peers = vec![];
+    for line in output.lines() {
+        if let Some(name) = line.strip_prefix("Datacenter:") {
+            dc = name.trim().to_string();
+            continue;
+        }
+        if let Some(peer) = parse_peer(line, &dc) {
+            peers.push(peer);
+        }
+    }
+    peers
+}
+
+/// `UN  127.0.0.1  86.07 KB  256  ?  <host id>  rack1`; the owns column may be missing.
+fn parse_peer(line: &str, dc: &str) -> Option<PeerInfo> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let mut code = fields.first()?.chars();
+    let up = match code.next()? {
+        'U' => true,
+        'D' => false,
+        _ => return None,
+    };
+    let state = match code.next()? {
+        'N' => PeerState::Normal,
+        'L' => PeerState::Leaving,
+        'J' => PeerState::Joining,
+        'M' => PeerState::Moving,
+        _ => return None,
+    };
+    let address = fields.get(1)?.parse().ok()?;
+    let (load, rest) = match fields.get(2..)? {
+        [amount, unit, rest @ ..] if parse_size(amount, unit).is_some() => {
+            (parse_size(amount, unit), rest)
+        }
+        [_, rest @ ..] => (None, rest),
+        [] => return None,
+    };
+    let (tokens, host_id, rack) = match rest {
+        [tokens, _, host_id, rack] | [tokens, host_id, rack] => (tokens, host_id, rack),
+        _ => return None,
+    };
+    Some(PeerInfo {
+        address,
+        dc: dc.to_string(),
+        rack: rack.to_string(),
+        up,
+        state,
+        load,
+        tokens: tokens.parse().ok()?,
+        host_id: host_id.to_string(),
+    })
+}
+
+/// Table metrics from `nodetool tablestats`, see
+/// [`Node::table_stats`](crate::cluster::Node::table_stats). Latencies are in milliseconds,
+/// `None` while there were no requests.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableStats {
+    pub sstable_count: u64,
+    /// Bytes used by the SSTables in use.
+    pub space_used_live: u64,
+    /// Bytes used by all SSTables, obsolete ones not deleted yet included.
+    pub space_used_total: u64,
+    pub partitions: u64,
+    pub memtable_cells: u64,
+    pub read_count: u64,
+    pub read_latency: Option<f64>,
+    pub write_count: u64,
+    pub write_latency: Option<f64>,
+    pub pending_flushes: u64,
+}
+
+/// Metrics of the table section of `nodetool tablestats <keyspace>.<table>`, `None` if the
+/// output has no table section.
+pub fn parse_table_stats(output: &str) -> Option<TableStats> {
+    let mut lines = output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("Table:"));
+    lines.next()?;
+    let mut stats = TableStats::default();
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let count = || {
+            let mut words = value.split_whitespace();
+            let amount = words.next()?;
+            match words.next() {
+                Some(unit) => parse_size(amount, unit),
+                None => amount.replace(',', "").parse().ok(),
+            }
+        };
+        let latency = || {
+            value
+                .strip_suffix("ms")?
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|latency| latency.is_finite())
+        };
+        match key.trim() {
+            "SSTable count" => stats.sstable_count = count().unwrap_or_default(),
+            "Space used (live)" => stats.space_used_live = count().unwrap_or_default(),
+            "Space used (total)" => stats.space_used_total = count().unwrap_or_default(),
+            "Number of partitions (estimate)" => stats.partitions = count().unwrap_or_default(),
+            "Memtable cell count" => stats.memtable_cells = count().unwrap_or_default(),
+            "Local read count" => stats.read_count = count().unwrap_or_default(),
+            "Local read latency" => stats.read_latency = latency(),
+            "Local write count" => stats.write_count = count().unwrap_or_default(),
+            "Local write latency" => stats.write_latency = latency(),
+            "Pending flushes" => stats.pending_flushes = count().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    Some(stats)
+}
+
+/// Compaction running on a node, a row of `nodetool compactionstats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveCompaction {
+    pub id: String,
+    /// E.g. `Compaction` or `Validation`.
+    pub kind: String,
+    pub keyspace: String,
+    pub table: String,
+    pub completed: u64,
+    pub total: u64,
+    /// What `completed` and `total` count, usually `bytes`.
+    pub unit: String,
+}
+
+/// Compaction backlog of a node from `nodetool compactionstats`, see
+/// [`Node::compaction_stats`](crate::cluster::Node::compaction_stats).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub pending_tasks: u64,
+    /// Pending tasks per `<keyspace>.<table>`, when listed.
+    pub pending_by_table: Vec<(String, u64)>,
+    pub active: Vec<ActiveCompaction>,
+}
+
+/// Parses `nodetool compactionstats`; `None` without a `pending tasks` line.
+pub fn parse_compaction_stats(output: &str) -> Option<CompactionStats> {
+    let mut stats = CompactionStats::default();
+    let mut pending = None;
+    let mut in_table = false;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(count) = line.strip_prefix("pending tasks:") {
+            pending = count.trim().parse().ok();
+        } else if let Some((table, count)) = line.strip_prefix("- ").and_then(|t| t.split_once(':'))
+        {
+            if let Ok(count) = count.trim().parse() {
+                stats
+                    .pending_by_table
+                    .push((table.trim().to_string(), count));
+            }
+        } else if line.starts_with("id ") && line.contains("compaction type") {
+            in_table = true;
+        } else if in_table {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [
+                id,
+                kind @ ..,
+                keyspace,
+                table,
+                completed,
+                total,
+                unit,
+                _progress,
+            ] = fields.as_slice()
+            else {
+                in_table = false;
+                continue;
+            };
+            let (Ok(completed), Ok(total)) = (completed.parse(), total.parse()) else {
+                in_table = false;
+                continue;
+            };
+            stats.active.push(ActiveCompaction {
+                id: id.to_string(),
+                kind: kind.join(" "),
+                keyspace: keyspace.to_string(),
+                table: table.to_string(),
+                completed,
+                total,
+                unit: unit.to_string(),
+            });
+        }
+    }
+    stats.pending_tasks = pending?;
+    Some(stats)
+}
+
+/// Bytes in a size printed by nodetool, e.g. `86.07 KB` or `1.2 GiB`.
+pub(crate) fn parse_size(amount: &str, unit: &str) -> Option<u64> {
+    let exponent = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        "" | "bytes" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+    let amount: f64 = amount.replace(',', "").parse().ok()?;
+    Some((amount * 1024f64.powi(exponent)).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let output = "\
+Datacenter: dc1
+===============
+Status=Up/Down
+|/ State=Normal/Leaving/Joining/Moving
+--  Address     Load       Tokens       Owns    Host ID                               Rack
+UN  127.0.0.1   86.07 KB   256          ?       a6f7c1d2-3e4f-4a5b-8c9d-0e1f2a3b4c5d  rack1
+DN  127.0.0.2   ?          256          ?       b6f7c1d2-3e4f-4a5b-8c9d-0e1f2a3b4c5d  rack2
+
+Datacenter: dc2
+===============
+--  Address     Load       Tokens  Host ID                               Rack
+UJ  127.0.0.3   1.5 MiB    256     c6f7c1d2-3e4f-4a5b-8c9d-0e1f2a3b4c5d  rack1
+";
+        let peers = parse_status(output);
+        assert_eq!(peers.len(), 3);
+        assert_eq!(
+            peers[0],
+            PeerInfo {
+                address: "127.0.0.1".parse().unwrap(),
+                dc: "dc1".to_string(),
+                rack: "rack1".to_string(),
+                up: true,
+                state: PeerState::Normal,
+                load: Some(88136),
+                tokens: 256,
+                host_id: "a6f7c1d2-3e4f-4a5b-8c9d-0e1f2a3b4c5d".to_string(),
+            }
+        );
+        assert!(!peers[1].up);
+        assert_eq!(peers[1].load, None);
+        assert_eq!(peers[1].rack, "rack2");
+        assert_eq!(peers[2].dc, "dc2");
+        assert_eq!(peers[2].state, PeerState::Joining);
+        assert_eq!(peers[2].load, Some(1572864));
+    }
+
+    #[test]
+    fn test_parse_table_stats() {
+        let output = "\
+Total number of tables: 1
+----------------
+Keyspace : ks
+\tRead Count: 99
+\tRead Latency: 0.5 ms
+\t\tTable: t
+\t\tSSTable count: 3
+\t\tSpace used (live): 12345
+\t\tSpace used (total): 1.5 KiB
+\t\tNumber of partitions (estimate): 100
+\t\tMemtable cell count: 7
+\t\tLocal read count: 10
+\t\tLocal read latency: 0.123 ms
+\t\tLocal write count: 100
+\t\tLocal write latency: NaN ms
+\t\tPending flushes: 0
+";
+        let stats = parse_table_stats(output).unwrap();
+        assert_eq!(stats.sstable_count, 3);
+        assert_eq!(stats.space_used_live, 12345);
+        assert_eq!(stats.space_used_total, 1536);
+        assert_eq!(stats.read_count, 10);
+        assert_eq!(stats.read_latency, Some(0.123));
+        assert_eq!(stats.write_count, 100);
+        assert_eq!(stats.write_latency, None);
+        assert_eq!(parse_table_stats("Keyspace : ks"), None);
+    }
+
+    #[test]
+    fn test_parse_compaction_stats() {
+        let output = "\
+pending tasks: 3
+- ks.t: 2
+- ks.u: 1
+
+id                                   compaction type keyspace table completed total unit  progress
+4d1e3a70-0000-11ee-8000-000000000000 Compaction      ks       t     1234      5678  bytes 21.73%
+4d1e3a70-0000-11ee-8000-000000000001 Anticompaction after repair ks u 10      20    bytes 50.00%
+Active compaction remaining time :   0h00m00s
+";
+        let stats = parse_compaction_stats(output).unwrap();
+        assert_eq!(stats.pending_tasks, 3);
+        assert_eq!(
+            stats.pending_by_table,
+            [("ks.t".to_string(), 2), ("ks.u".to_string(), 1)]
+        );
+        assert_eq!(stats.active.len(), 2);
+        assert_eq!(stats.active[0].table, "t");
+        assert_eq!(stats.active[0].completed, 1234);
+        assert_eq!(stats.active[1].kind, "Anticompaction after repair");
+        assert_eq!(
+            parse_compaction_stats("pending tasks: 0\n").unwrap().active,
+            []
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::Error as IoError;
+use std::net::{Ipv4Addr, TcpListener};
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+/// Ports [`PortAllocator::default`] hands out from, below the Linux ephemeral range so that
+/// outgoing connections don't grab them between allocation and node start.
+pub const DEFAULT_PORT_RANGE: RangeInclusive<u16> = 20000..=32767;
+
+/// Per-node port overrides passed to `ccm add`. Unset ports keep the defaults: ccm's 9042
+/// and 7000 for the native and storage ports, and a fixed per-node formula for JMX and
+/// remote debugging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodePorts {
+    /// CQL native transport port (`ccm add --binary-itf`).
+    pub native: Option<u16>,
+    /// Internode storage port (`ccm add --storage-itf`).
+    pub storage: Option<u16>,
+    pub jmx: Option<u16>,
+    pub debug: Option<u16>,
+}
+
+impl NodePorts {
+    /// Fills every unset port with a free one from `allocator`.
+    pub fn allocate_missing(&mut self, allocator: &PortAllocator) -> Result<(), IoError> {
+        for port in [
+            &mut self.native,
+            &mut self.storage,
+            &mut self.jmx,
+            &mut self.debug,
+        ] {
+            if port.is_none() {
+                *port = Some(allocator.allocate()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hands out ports nothing on the host listens on, so clusters can coexist with other
+/// services. A port is free if it can be bound on all interfaces; ports handed out stay
+/// reserved in the allocator until released, since nodes bind them only once started.
+#[derive(Debug)]
+pub struct PortAllocator {
+    range: RangeInclusive<u16>,
+    allocated: Mutex<BTreeSet<u16>>,
+}
+
+impl Default for PortAllocator {
+    fn default() -> Self {
+        Self::new(DEFAULT_PORT_RANGE)
+    }
+}
+
+impl PortAllocator {
+    pub fn new(range: RangeInclusive<u16>) -> Self {
+        PortAllocator {
+            range,
+            allocated: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// A free port from the range, reserved until [`release`](PortAllocator::release)d.
+    pub fn allocate(&self) -> Result<u16, IoError> {
+        let mut allocated = self.allocated.lock().unwrap();
+        for port in self.range.clone() {
+            if !allocated.contains(&port) && is_port_free(port) {
+                allocated.insert(port);
+                return Ok(port);
+            }
+        }
+        Err(IoError::new(
+            std::io::ErrorKind::AddrInUse,
+            format!(
+                "no free port in {}..={}",
+                self.range.start(),
+                self.range.end()
+            ),
+        ))
+    }
+
+    /// A full set of free ports for one node.
+    pub fn allocate_node_ports(&self) -> Result<NodePorts, IoError> {
+        let mut ports = NodePorts::default();
+        ports.allocate_missing(self)?;
+        Ok(ports)
+    }
+
+    /// Reserves `port`, e.g. a fixed default outside the range, until
+    /// [`release`](PortAllocator::release)d. Returns `false` if it is already handed out;
+    /// whether the host uses it is up to the caller to check.
+    pub fn reserve(&self, port: u16) -> bool {
+        self.allocated.lock().unwrap().insert(port)
+    }
+
+    /// Returns whether `port` was allocated.
+    pub fn release(&self, port: u16) -> bool {
+        self.allocated.lock().unwrap().remove(&port)
+    }
+
+    /// Ports currently handed out, sorted.
+    pub fn allocated(&self) -> Vec<u16> {
+        self.allocated.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/// Whether `port` can be bound on all IPv4 interfaces, which fails if anything listens on
+/// it at any address.
+pub fn is_port_free(port: u16) -> bool {
+    TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_skips_busy_ports() {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let busy = listener.local_addr().unwrap().port();
+        let allocator = PortAllocator::new(busy..=busy.saturating_add(2));
+
+        let first = allocator.allocate().unwrap();
+        assert_ne!(first, busy);
+        let mut ports = NodePorts {
+            native: Some(9042),
+            ..Default::default()
+        };
+        let error = ports.allocate_missing(&allocator).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+        assert_eq!(ports.native, Some(9042));
+        assert!(!allocator.allocated().contains(&busy));
+
+        assert!(allocator.release(first));
+        assert!(!allocator.release(first));
+
+        assert!(allocator.reserve(7101));
+        assert!(!allocator.reserve(7101));
+        assert!(allocator.allocated().contains(&7101));
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::Error as IoError;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// What a [`NodeProxy`] does to the traffic it forwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProxyFault {
+    /// Forwards traffic untouched.
+    #[default]
+    None,
+    /// Holds every chunk of data, in both directions, for the given time.
+    Delay(Duration),
+    /// Accepts connections and swallows their data, like a peer that stopped answering.
+    Drop,
+    /// Resets open connections and every new one right after accepting it.
+    Reset,
+}
+
+/// TCP forwarder in front of one node, for driver resilience tests: clients connect to
+/// [`address`](NodeProxy::address) and traffic reaches the node until a fault is injected.
+/// Faults apply to open connections as well as new ones. The forwarder stops when the
+/// proxy is dropped, closing its connections.
+pub struct NodeProxy {
+    address: SocketAddr,
+    target: SocketAddr,
+    fault: watch::Sender<ProxyFault>,
+    task: JoinHandle<()>,
+}
+
+impl NodeProxy {
+    /// Starts forwarding connections accepted on `listen`, e.g. `127.0.0.1:0` for any free
+    /// port, to `target`.
+    pub async fn start(listen: SocketAddr, target: SocketAddr) -> Result<Self, IoError> {
+        let listener = TcpListener::bind(listen).await?;
+        let address = listener.local_addr()?;
+        let (fault, faults) = watch::channel(ProxyFault::None);
+        let task = tokio::spawn(accept_loop(listener, target, faults));
+        Ok(NodeProxy {
+            address,
+            target,
+            fault,
+            task,
+        })
+    }
+
+    /// Address clients connect to instead of the node.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn target(&self) -> SocketAddr {
+        self.target
+    }
+
+    pub fn fault(&self) -> ProxyFault {
+        *self.fault.borrow()
+    }
+
+    pub fn set_fault(&self, fault: ProxyFault) {
+        self.fault.send_replace(fault);
+    }
+
+    pub fn delay(&self, delay: Duration) {
+        self.set_fault(ProxyFault::Delay(delay));
+    }
+
+    pub fn drop_traffic(&self) {
+        self.set_fault(ProxyFault::Drop);
+    }
+
+    pub fn reset(&self) {
+        self.set_fault(ProxyFault::Reset);
+    }
+
+    /// Back to forwarding untouched; connections reset meanwhile stay closed.
+    pub fn heal(&self) {
+        self.set_fault(ProxyFault::None);
+    }
+}
+
+impl Drop for NodeProxy {
+    fn drop(&mut self) {
+        // Open connections end once the fault sender is gone.
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    target: SocketAddr,
+    faults: watch::Receiver<ProxyFault>,
+) {
+    while let Ok((client, _)) = listener.accept().await {
+        tokio::spawn(forward(client, target, faults.clone()));
+    }
+}
+
+async fn forward(mut client: TcpStream, target: SocketAddr, faults: watch::Receiver<ProxyFault>) {
+    if *faults.borrow() == ProxyFault::Reset {
+        client.set_zero_linger().ok();
+        return;
+    }
+    let Ok(mut server) = TcpStream::connect(target).await else {
+        client.set_zero_linger().ok();
+        return;
+    };
+    let (client_read, client_write) = client.split();
+    let (server_read, server_write) = server.split();
+    tokio::join!(
+        pump(client_read, server_write, faults.clone()),
+        pump(server_read, client_write, faults.clone()),
+    );
+    if *faults.borrow() == ProxyFault::Reset {
+        client.set_zero_linger().ok();
+        server.set_zero_linger().ok();
+    }
+}
+
+/// Copies `from` to `to` under the current fault until either side closes or the
+/// connection is reset.
+async fn pump(
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin,
+    mut faults: watch::Receiver<ProxyFault>,
+) {
+    let mut buffer = vec![0; 16 * 1024];
+    loop {
+        if *faults.borrow_and_update() == ProxyFault::Reset {
+            return;
+        }
+        let read = tokio::select! {
+            changed = faults.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                continue;
+            }
+            read = from.read(&mut buffer) => read,
+        };
+        let length = match read {
+            Ok(0) | Err(_) => {
+                to.shutdown().await.ok();
+                return;
+            }
+            Ok(length) => length,
+        };
+        let fault = *faults.borrow();
+        match fault {
+            ProxyFault::None => {}
+            ProxyFault::Delay(delay) => tokio::time::sleep(delay).await,
+            ProxyFault::Drop => continue,
+            ProxyFault::Reset => return,
+        }
+        if to.write_all(&buffer[..length]).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut read, mut write) = stream.split();
+                    tokio::io::copy(&mut read, &mut write).await.ok();
+                });
+            }
+        });
+        address
+    }
+
+    async fn echo(stream: &mut TcpStream, timeout: Duration) -> Result<Vec<u8>, IoError> {
+        stream.write_all(b"ping").await?;
+        let mut reply = [0; 4];
+        tokio::time::timeout(timeout, stream.read_exact(&mut reply)).await??;
+        Ok(reply.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_faults() {
+        let proxy = NodeProxy::start("127.0.0.1:0".parse().unwrap(), echo_server().await)
+            .await
+            .unwrap();
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        let second = Duration::from_secs(1);
+        assert_eq!(echo(&mut stream, second).await.unwrap(), b"ping");
+
+        proxy.delay(Duration::from_millis(100));
+        let started = tokio::time::Instant::now();
+        assert_eq!(echo(&mut stream, second).await.unwrap(), b"ping");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        proxy.drop_traffic();
+        let err = echo(&mut stream, Duration::from_millis(100)).await;
+        assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+
+        proxy.reset();
+        let err = echo(&mut stream, second).await.unwrap_err();
+        assert_ne!(err.kind(), std::io::ErrorKind::TimedOut);
+
+        proxy.heal();
+        let mut stream = TcpStream::connect(proxy.address()).await.unwrap();
+        assert_eq!(echo(&mut stream, second).await.unwrap(), b"ping");
+    }
+}
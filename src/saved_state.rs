@@ -0,0 +1,136 @@
+use crate::auth::{Credentials, RoleSpec};
+use crate::cluster::{IpLayout, NodeStartOption, Partitioner, ReplicationMode};
+use crate::cluster_config::ScyllaConfig;
+use crate::ports::NodePorts;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// Contents of the state file written by
+/// [`Cluster::save_state`](crate::cluster::Cluster::save_state). Fields missing from files
+/// written by older versions take their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedCluster {
+    pub name: String,
+    pub version: String,
+    #[serde(default = "default_scylla")]
+    pub scylla: bool,
+    pub install_directory: String,
+    #[serde(default)]
+    pub config_dir: Option<String>,
+    pub ip_prefix: String,
+    #[serde(default)]
+    pub ip_layout: IpLayout,
+    #[serde(default)]
+    pub ipv6_prefix: Option<String>,
+    #[serde(default)]
+    pub host_addresses: Vec<IpAddr>,
+    #[serde(default)]
+    pub hostname_domain: Option<String>,
+    #[serde(default)]
+    pub hosts_file: Option<PathBuf>,
+    #[serde(default)]
+    pub default_node_smp: Option<i32>,
+    #[serde(default)]
+    pub default_node_memory: Option<i32>,
+    #[serde(default)]
+    pub default_start_options: Vec<NodeStartOption>,
+    #[serde(default)]
+    pub partitioner: Option<Partitioner>,
+    #[serde(default)]
+    pub replication_mode: Option<ReplicationMode>,
+    #[serde(default)]
+    pub password_auth: bool,
+    #[serde(default)]
+    pub auth_test_role: Option<Credentials>,
+    #[serde(default)]
+    pub auth_roles: Vec<RoleSpec>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Seeds per datacenter id, see
+    /// [`Cluster::set_dc_seeds`](crate::cluster::Cluster::set_dc_seeds).
+    #[serde(default)]
+    pub dc_seeds: HashMap<i32, usize>,
+    #[serde(default)]
+    pub nodes: Vec<SavedNode>,
+}
+
+/// One node of a [`SavedCluster`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct SavedNode {
+    pub name: String,
+    #[serde(default)]
+    pub datacenter_id: Option<i32>,
+    #[serde(default)]
+    pub node_id: Option<i32>,
+    #[serde(default)]
+    pub rack: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub seed: bool,
+    #[serde(default)]
+    pub smp: Option<i32>,
+    #[serde(default)]
+    pub memory: Option<i32>,
+    #[serde(default, deserialize_with = "config_or_flat_string")]
+    pub config: ScyllaConfig,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub address_suffix: Option<u32>,
+    #[serde(default)]
+    pub ipv6_address: Option<String>,
+    #[serde(default)]
+    pub hostname: Option<String>,
+    #[serde(default)]
+    pub ports: NodePorts,
+}
+
+fn default_scylla() -> bool {
+    true
+}
+
+/// Node config as saved now, or in the flat `key:value` form older versions saved.
+fn config_or_flat_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ScyllaConfig, D::Error> {
+    match ScyllaConfig::deserialize(deserializer)? {
+        ScyllaConfig::String(flat) => {
+            ScyllaConfig::from_flat_string(&flat).map_err(serde::de::Error::custom)
+        }
+        config => Ok(config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_flat_string_configs() {
+        let state: SavedNode = serde_json::from_str(
+            r#"{"name": "node_1_1", "config": "num_tokens:8 a.b:[1, 2]", "ports": {"jmx": 7101}}"#,
+        )
+        .unwrap();
+        assert_eq!(state.config.to_flat_string(), "a.b:[1,2] num_tokens:8");
+        assert_eq!(state.ports.jmx, Some(7101));
+        assert!(!state.seed);
+
+        let state: SavedNode = serde_json::from_str(
+            r#"{"name": "node_1_1", "config": {"num_tokens": 8, "ratio": 0.5, "list": ["a"]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            state.config.to_flat_string(),
+            "list:[a] num_tokens:8 ratio:0.5"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Environment variable overriding the scylla-bench executable, `scylla-bench` from `PATH`
+/// by default.
+pub const SCYLLA_BENCH_ENV: &str = "CCM_BINDING_SCYLLA_BENCH";
+
+/// What scylla-bench does to the partitions (`-mode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchMode {
+    Write,
+    Read,
+    CounterUpdate,
+    CounterRead,
+    Scan,
+}
+
+impl BenchMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BenchMode::Write => "write",
+            BenchMode::Read => "read",
+            BenchMode::CounterUpdate => "counter_update",
+            BenchMode::CounterRead => "counter_read",
+            BenchMode::Scan => "scan",
+        }
+    }
+}
+
+/// scylla-bench run for [`Cluster::scylla_bench`](crate::cluster::Cluster::scylla_bench).
+#[derive(Debug, Clone)]
+pub struct BenchWorkload {
+    /// Executable to run, see [`SCYLLA_BENCH_ENV`].
+    pub program: String,
+    pub mode: BenchMode,
+    /// Which partitions are hit (`-workload`), e.g. `sequential` or `uniform`.
+    pub workload: String,
+    /// Concurrent requests (`-concurrency`).
+    pub concurrency: Option<u32>,
+    /// How long to run (`-duration`); sequential workloads stop once every row was visited.
+    pub duration: Option<Duration>,
+    pub partition_count: Option<u64>,
+    pub clustering_row_count: Option<u64>,
+    /// Requests per second across all workers (`-max-rate`).
+    pub max_rate: Option<u32>,
+    pub consistency: Option<String>,
+    /// Further flags, e.g. `["-replication-factor", "3"]`.
+    pub args: Vec<String>,
+    /// Kills scylla-bench if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Receives every output line while scylla-bench runs.
+    pub output: Option<UnboundedSender<String>>,
+}
+
+impl BenchWorkload {
+    /// Sequential `mode` workload run with the executable from [`SCYLLA_BENCH_ENV`].
+    pub fn new(mode: BenchMode) -> Self {
+        BenchWorkload {
+            program: std::env::var(SCYLLA_BENCH_ENV).unwrap_or_else(|_| "scylla-bench".to_string()),
+            mode,
+            workload: "sequential".to_string(),
+            concurrency: None,
+            duration: None,
+            partition_count: None,
+            clustering_row_count: None,
+            max_rate: None,
+            consistency: None,
+            args: vec![],
+            timeout: None,
+            output: None,
+        }
+    }
+
+    pub fn program(mut self, program: impl Into<String>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    pub fn workload(mut self, workload: impl Into<String>) -> Self {
+        self.workload = workload.into();
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: u32) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn partition_count(mut self, partition_count: u64) -> Self {
+        self.partition_count = Some(partition_count);
+        self
+    }
+
+    pub fn clustering_row_count(mut self, clustering_row_count: u64) -> Self {
+        self.clustering_row_count = Some(clustering_row_count);
+        self
+    }
+
+    pub fn max_rate(mut self, max_rate: u32) -> Self {
+        self.max_rate = Some(max_rate);
+        self
+    }
+
+    pub fn consistency(mut self, consistency: impl Into<String>) -> Self {
+        self.consistency = Some(consistency.into());
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn output(mut self, output: UnboundedSender<String>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// scylla-bench flags, without the nodes to connect to.
+    pub(crate) fn bench_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "-mode".to_string(),
+            self.mode.as_str().to_string(),
+            "-workload".to_string(),
+            self.workload.clone(),
+        ];
+        let mut flag = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                args.extend([name.to_string(), value]);
+            }
+        };
+        flag("-concurrency", self.concurrency.map(|n| n.to_string()));
+        flag(
+            "-duration",
+            self.duration.map(|d| format!("{}s", d.as_secs().max(1))),
+        );
+        flag(
+            "-partition-count",
+            self.partition_count.map(|n| n.to_string()),
+        );
+        flag(
+            "-clustering-row-count",
+            self.clustering_row_count.map(|n| n.to_string()),
+        );
+        flag("-max-rate", self.max_rate.map(|n| n.to_string()));
+        flag("-consistency-level", self.consistency.clone());
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+/// Results scylla-bench prints once done; latencies are those of the first latency
+/// section, the raw ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchSummary {
+    pub time: Duration,
+    pub total_ops: u64,
+    pub total_rows: u64,
+    pub total_errors: u64,
+    pub ops_per_second: f64,
+    pub rows_per_second: f64,
+    pub latency_max: Duration,
+    pub latency_p999: Duration,
+    pub latency_p99: Duration,
+    pub latency_p95: Duration,
+    pub latency_median: Duration,
+    pub latency_mean: Duration,
+}
+
+impl BenchSummary {
+    /// Summary from the `Results` section of scylla-bench output, `None` if there is none.
+    pub fn parse(output: &str) -> Option<Self> {
+        let mut lines = output.lines().skip_while(|line| line.trim() != "Results");
+        lines.next()?;
+        let mut summary = BenchSummary::default();
+        let mut ops = None;
+        let mut latency_sections = 0;
+        for line in lines {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let number = || value.replace(',', "").parse::<f64>().ok();
+            let duration = || parse_go_duration(value).unwrap_or_default();
+            match key.trim() {
+                key if key.to_lowercase().contains("latency") => latency_sections += 1,
+                _ if latency_sections > 1 => {}
+                "Time (avg)" => summary.time = duration(),
+                "Total ops" => ops = number().map(|n| n as u64),
+                "Total rows" => summary.total_rows = number().unwrap_or_default() as u64,
+                "Total errors" => summary.total_errors = number().unwrap_or_default() as u64,
+                "Operations/s" => summary.ops_per_second = number().unwrap_or_default(),
+                "Rows/s" => summary.rows_per_second = number().unwrap_or_default(),
+                "max" => summary.latency_max = duration(),
+                "99.9th" => summary.latency_p999 = duration(),
+                "99th" => summary.latency_p99 = duration(),
+                "95th" => summary.latency_p95 = duration(),
+                "median" => summary.latency_median = duration(),
+                "mean" => summary.latency_mean = duration(),
+                _ => {}
+            }
+        }
+        summary.total_ops = ops?;
+        Some(summary)
+    }
+}
+
+/// Duration printed by Go, e.g. `1m2.5s`, `27.197439ms` or `850µs`.
+fn parse_go_duration(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    let mut nanos = 0.0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|split| *split > 0)?;
+        let (number, tail) = rest.split_at(split);
+        let unit_end = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+        let scale = match unit {
+            "h" => 3600e9,
+            "m" => 60e9,
+            "s" => 1e9,
+            "ms" => 1e6,
+            "us" | "µs" => 1e3,
+            "ns" => 1.0,
+            _ => return None,
+        };
+        nanos += number.parse::<f64>().ok()? * scale;
+        rest = tail;
+    }
+    Some(Duration::from_nanos(nanos.round() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_args() {
+        let workload = BenchWorkload::new(BenchMode::CounterUpdate)
+            .program("/opt/scylla-bench")
+            .workload("uniform")
+            .concurrency(16)
+            .duration(Duration::from_secs(60))
+            .partition_count(1000)
+            .consistency("quorum");
+        assert_eq!(workload.program, "/opt/scylla-bench");
+        assert_eq!(
+            workload.bench_args().join(" "),
+            "-mode counter_update -workload uniform -concurrency 16 -duration 60s \
+             -partition-count 1000 -consistency-level quorum"
+        );
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let output = "\
+Configuration
+Mode:\t\t\t write
+Results
+Time (avg):\t 5.000817523s
+Total ops:\t 91040
+Total rows:\t 91040
+Operations/s:\t 18203.11
+Rows/s:\t\t 18203.11
+raw latency :
+  max:\t\t 27.197439ms
+  99.9th:\t 14.221311ms
+  99th:\t\t 6.356991ms
+  95th:\t\t 2.621439ms
+  90th:\t\t 1.835007ms
+  median:\t 850µs
+  mean:\t\t 1.063803ms
+c-o fixed latency :
+  max:\t\t 1m2.5s
+";
+        let summary = BenchSummary::parse(output).unwrap();
+        assert_eq!(summary.total_ops, 91040);
+        assert_eq!(summary.ops_per_second, 18203.11);
+        assert_eq!(summary.time.as_millis(), 5000);
+        assert_eq!(summary.latency_max, Duration::from_nanos(27197439));
+        assert_eq!(summary.latency_median, Duration::from_micros(850));
+        assert_eq!(
+            parse_go_duration("1m2.5s"),
+            Some(Duration::from_millis(62500))
+        );
+        assert_eq!(BenchSummary::parse("Mode: write"), None);
+    }
+}
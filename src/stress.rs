@@ -0,0 +1,245 @@
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// cassandra-stress run for [`Cluster::stress`](crate::cluster::Cluster::stress): the
+/// command, how much load, and extra options passed through as they are.
+#[derive(Debug, Clone)]
+pub struct StressProfile {
+    pub command: String,
+    /// Number of operations (`n=`); takes precedence over `duration`.
+    pub ops: Option<u64>,
+    /// How long to run (`duration=`).
+    pub duration: Option<Duration>,
+    pub consistency: Option<String>,
+    /// Client threads (`-rate threads=`).
+    pub threads: Option<u32>,
+    /// Operations per second across all threads (`-rate throttle=`).
+    pub throttle: Option<u32>,
+    /// Further options, e.g. `["-schema", "replication(factor=3)"]`.
+    pub args: Vec<String>,
+    /// Kills cassandra-stress if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Receives every output line while cassandra-stress runs.
+    pub output: Option<UnboundedSender<String>>,
+}
+
+impl StressProfile {
+    /// Profile running the cassandra-stress `command`, e.g. `write`, `read` or `mixed`.
+    pub fn new(command: impl Into<String>) -> Self {
+        StressProfile {
+            command: command.into(),
+            ops: None,
+            duration: None,
+            consistency: None,
+            threads: None,
+            throttle: None,
+            args: vec![],
+            timeout: None,
+            output: None,
+        }
+    }
+
+    pub fn write() -> Self {
+        Self::new("write")
+    }
+
+    pub fn read() -> Self {
+        Self::new("read")
+    }
+
+    pub fn ops(mut self, ops: u64) -> Self {
+        self.ops = Some(ops);
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn consistency(mut self, consistency: impl Into<String>) -> Self {
+        self.consistency = Some(consistency.into());
+        self
+    }
+
+    pub fn threads(mut self, threads: u32) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn throttle(mut self, ops_per_second: u32) -> Self {
+        self.throttle = Some(ops_per_second);
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn output(mut self, output: UnboundedSender<String>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// cassandra-stress arguments, without the nodes to connect to.
+    pub(crate) fn stress_args(&self) -> Vec<String> {
+        let mut args = vec![self.command.clone()];
+        if let Some(ops) = self.ops {
+            args.push(format!("n={}", ops));
+        } else if let Some(duration) = self.duration {
+            args.push(format!("duration={}s", duration.as_secs().max(1)));
+        }
+        if let Some(consistency) = &self.consistency {
+            args.push(format!("cl={}", consistency));
+        }
+        if self.threads.is_some() || self.throttle.is_some() {
+            args.push("-rate".to_string());
+            if let Some(threads) = self.threads {
+                args.push(format!("threads={}", threads));
+            }
+            if let Some(throttle) = self.throttle {
+                args.push(format!("throttle={}/s", throttle));
+            }
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+/// Summary cassandra-stress prints once done. Latencies are in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StressSummary {
+    pub op_rate: f64,
+    pub partition_rate: f64,
+    pub row_rate: f64,
+    pub latency_mean: f64,
+    pub latency_median: f64,
+    pub latency_p95: f64,
+    pub latency_p99: f64,
+    pub latency_p999: f64,
+    pub latency_max: f64,
+    pub total_partitions: u64,
+    pub total_errors: u64,
+    pub total_time: Duration,
+}
+
+impl StressSummary {
+    /// Summary from the `Results:` section of cassandra-stress output, `None` if there is
+    /// none, e.g. when the run failed.
+    pub fn parse(output: &str) -> Option<Self> {
+        let results = &output[output.rfind("Results:")?..];
+        let mut summary = StressSummary::default();
+        let mut op_rate = None;
+        for line in results.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            let number = || {
+                value
+                    .split_whitespace()
+                    .next()?
+                    .replace(',', "")
+                    .parse::<f64>()
+                    .ok()
+            };
+            match key.trim() {
+                "Op rate" => op_rate = number(),
+                "Partition rate" => summary.partition_rate = number().unwrap_or_default(),
+                "Row rate" => summary.row_rate = number().unwrap_or_default(),
+                "Latency mean" => summary.latency_mean = number().unwrap_or_default(),
+                "Latency median" => summary.latency_median = number().unwrap_or_default(),
+                "Latency 95th percentile" => summary.latency_p95 = number().unwrap_or_default(),
+                "Latency 99th percentile" => summary.latency_p99 = number().unwrap_or_default(),
+                "Latency 99.9th percentile" => summary.latency_p999 = number().unwrap_or_default(),
+                "Latency max" => summary.latency_max = number().unwrap_or_default(),
+                "Total partitions" => {
+                    summary.total_partitions = number().unwrap_or_default() as u64
+                }
+                "Total errors" => summary.total_errors = number().unwrap_or_default() as u64,
+                "Total operation time" => summary.total_time = parse_hms(value)?,
+                _ => {}
+            }
+        }
+        summary.op_rate = op_rate?;
+        Some(summary)
+    }
+}
+
+/// `HH:MM:SS` as printed for the total operation time.
+fn parse_hms(value: &str) -> Option<Duration> {
+    let mut seconds = 0;
+    for part in value.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stress_args() {
+        let profile = StressProfile::write()
+            .ops(10000)
+            .duration(Duration::from_secs(30))
+            .consistency("QUORUM")
+            .threads(4)
+            .throttle(500)
+            .arg("-schema")
+            .arg("replication(factor=3)");
+        assert_eq!(
+            profile.stress_args(),
+            [
+                "write",
+                "n=10000",
+                "cl=QUORUM",
+                "-rate",
+                "threads=4",
+                "throttle=500/s",
+                "-schema",
+                "replication(factor=3)"
+            ]
+        );
+        let timed = StressProfile::read().duration(Duration::from_secs(30));
+        assert_eq!(timed.stress_args(), ["read", "duration=30s"]);
+    }
+
+    #[test]
+    fn test_parse_summary() {
+        let output = "\
+total, 100000, 12345, 12345, 12345, 0.5, 0.4, 0.9, 1.5, 5.1, 40.2, 8.1, 0.0, 0, 0, 0, 0, 0, 0
+Results:
+Op rate                   :   12,345 op/s  [WRITE: 12,345 op/s]
+Partition rate            :   12,345 pk/s  [WRITE: 12,345 pk/s]
+Row rate                  :   12,345 row/s [WRITE: 12,345 row/s]
+Latency mean              :    0.5 ms [WRITE: 0.5 ms]
+Latency median            :    0.4 ms [WRITE: 0.4 ms]
+Latency 95th percentile   :    0.9 ms [WRITE: 0.9 ms]
+Latency 99th percentile   :    1.5 ms [WRITE: 1.5 ms]
+Latency 99.9th percentile :    5.1 ms [WRITE: 5.1 ms]
+Latency max               :   40.2 ms [WRITE: 40.2 ms]
+Total partitions          :    100,000 [WRITE: 100,000]
+Total errors              :          0 [WRITE: 0]
+Total GC count            : 0
+Total operation time      : 00:01:08
+
+END
+";
+        let summary = StressSummary::parse(output).unwrap();
+        assert_eq!(summary.op_rate, 12345.0);
+        assert_eq!(summary.latency_p99, 1.5);
+        assert_eq!(summary.latency_max, 40.2);
+        assert_eq!(summary.total_partitions, 100000);
+        assert_eq!(summary.total_errors, 0);
+        assert_eq!(summary.total_time, Duration::from_secs(68));
+        assert_eq!(StressSummary::parse("Connection refused"), None);
+    }
+}
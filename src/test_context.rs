@@ -0,0 +1,71 @@
+use std::cell::RefCell;
+
+thread_local! {
+    static TEST_NAME: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Sets the name of the test running on the current thread.
+/// Clusters created afterwards on this thread are labeled with it.
+pub fn set(name: impl Into<String>) {
+    TEST_NAME.with(|current| *current.borrow_mut() = Some(name.into()));
+}
+
+/// Forgets the name previously stored with [`set`].
+pub fn clear() {
+    TEST_NAME.with(|current| *current.borrow_mut() = None);
+}
+
+/// Returns the name of the currently running test.
+/// An explicitly [`set`] name wins; otherwise the libtest harness thread name is used,
+/// which is the full test path (e.g. `cluster::tests::test_lifecycle`).
+pub fn current() -> Option<String> {
+    if let Some(name) = TEST_NAME.with(|current| current.borrow().clone()) {
+        return Some(name);
+    }
+    match std::thread::current().name() {
+        Some(name) if name != "main" && !name.starts_with("tokio-runtime-worker") => {
+            Some(name.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the current test name in a form usable in file and cluster names.
+pub fn label() -> Option<String> {
+    current().map(|name| sanitize(&name))
+}
+
+fn sanitize(name: &str) -> String {
+    name.replace("::", ".")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_uses_harness_thread_name() {
+        clear();
+        assert_eq!(
+            current().as_deref(),
+            Some("test_context::tests::test_current_uses_harness_thread_name")
+        );
+    }
+
+    #[test]
+    fn test_set_overrides_thread_name() {
+        set("custom name/with::chars");
+        assert_eq!(current().as_deref(), Some("custom name/with::chars"));
+        assert_eq!(label().as_deref(), Some("custom_name_with.chars"));
+        clear();
+    }
+}
@@ -0,0 +1,431 @@
+use crate::ccm_cli::LoggedCmd;
+use crate::cluster_config::ScyllaConfig;
+use crate::host_capabilities::{Capability, require_unless_dry_run};
+use std::collections::HashMap;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+const VALIDITY_DAYS: &str = "3650";
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Fresh password for the PKCS12 stores of one cluster. It ends up in clear in the node
+/// configs, so it only has to differ between clusters, not resist guessing.
+fn random_password() -> String {
+    use std::hash::{BuildHasher, RandomState};
+    let now = std::time::SystemTime::now();
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().hash_one(now)))
+        .collect()
+}
+
+/// Self-signed CA used to sign node certificates of a single cluster.
+#[derive(Debug, Clone)]
+pub struct CertificateAuthority {
+    /// PEM certificate drivers should trust.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// PKCS12 truststore containing only the CA certificate, for Cassandra; Scylla reads
+    /// `cert`.
+    pub truststore: Option<PathBuf>,
+    /// Password of the PKCS12 stores of this CA and its node certificates, masked in the
+    /// logs of the logger that generated them.
+    pub password: String,
+}
+
+/// Certificate and key of a single node, in PEM (Scylla) and, for CAs with a truststore,
+/// PKCS12 (Cassandra) form.
+#[derive(Debug, Clone)]
+pub struct NodeCertificate {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub keystore: Option<PathBuf>,
+    pub keystore_password: String,
+}
+
+impl CertificateAuthority {
+    /// Creates a new CA in `directory` using the `openssl` CLI and, with `java_stores`,
+    /// its PKCS12 truststore using `keytool`, which every OpenSSL release can't write.
+    pub async fn generate(
+        logged_cmd: &LoggedCmd,
+        directory: &Path,
+        java_stores: bool,
+    ) -> Result<Self, IoError> {
+        require_unless_dry_run(logged_cmd, Capability::Openssl)?;
+        if java_stores {
+            require_unless_dry_run(logged_cmd, Capability::Keytool)?;
+        }
+        tokio::fs::create_dir_all(directory).await?;
+        let ca = CertificateAuthority {
+            cert: directory.join("ca.crt"),
+            key: directory.join("ca.key"),
+            truststore: java_stores.then(|| directory.join("truststore.p12")),
+            password: random_password(),
+        };
+        logged_cmd.redact(ca.password.clone());
+        let (cert, key) = (path_str(&ca.cert), path_str(&ca.key));
+        logged_cmd
+            .run_command(
+                "openssl",
+                &[
+                    "req",
+                    "-x509",
+                    "-newkey",
+                    "rsa:2048",
+                    "-nodes",
+                    "-keyout",
+                    &key,
+                    "-out",
+                    &cert,
+                    "-days",
+                    VALIDITY_DAYS,
+                    "-subj",
+                    "/CN=ccm-binding test CA",
+                ],
+                None,
+            )
+            .await?;
+        if let Some(truststore) = &ca.truststore {
+            // Java only trusts certificates imported as trusted entries, which OpenSSL
+            // writes from 3.2 on; keytool does on every release.
+            let truststore = path_str(truststore);
+            logged_cmd
+                .run_command(
+                    "keytool",
+                    &[
+                        "-importcert",
+                        "-noprompt",
+                        "-alias",
+                        "ca",
+                        "-file",
+                        &cert,
+                        "-keystore",
+                        &truststore,
+                        "-storetype",
+                        "PKCS12",
+                        "-storepass",
+                        &ca.password,
+                    ],
+                    None,
+                )
+                .await?;
+        }
+        Ok(ca)
+    }
+
+    /// Issues a certificate for `name` valid for `ip` and `hostname`, if any, writing
+    /// `<name>.crt/.key` and, if the CA has a truststore, `<name>.p12` to `directory`.
+    pub async fn issue(
+        &self,
+        logged_cmd: &LoggedCmd,
+        name: &str,
+        ip: &str,
+        hostname: Option<&str>,
+        directory: &Path,
+    ) -> Result<NodeCertificate, IoError> {
+        require_unless_dry_run(logged_cmd, Capability::Openssl)?;
+        tokio::fs::create_dir_all(directory).await?;
+        let certificate = NodeCertificate {
+            cert: directory.join(format!("{}.crt", name)),
+            key: directory.join(format!("{}.key", name)),
+            keystore: self
+                .truststore
+                .is_some()
+                .then(|| directory.join(format!("{}.p12", name))),
+            keystore_password: self.password.clone(),
+        };
+        let csr = directory.join(format!("{}.csr", name));
+        let extensions = directory.join(format!("{}.ext", name));
+        let mut alt_names = format!("IP:{},DNS:{},DNS:localhost", ip, name);
+        if let Some(hostname) = hostname {
+            alt_names.push_str(&format!(",DNS:{}", hostname));
+        }
+        tokio::fs::write(&extensions, format!("subjectAltName={}\n", alt_names)).await?;
+
+        let (cert, key) = (path_str(&certificate.cert), path_str(&certificate.key));
+        let (csr, extensions) = (path_str(&csr), path_str(&extensions));
+        let (ca_cert, ca_key) = (path_str(&self.cert), path_str(&self.key));
+        let subject = format!("/CN={}", name);
+        logged_cmd
+            .run_command(
+                "openssl",
+                &[
+                    "req", "-newkey", "rsa:2048", "-nodes", "-keyout", &key, "-out", &csr, "-subj",
+                    &subject,
+                ],
+                None,
+            )
+            .await?;
+        logged_cmd
+            .run_command(
+                "openssl",
+                &[
+                    "x509",
+                    "-req",
+                    "-in",
+                    &csr,
+                    "-CA",
+                    &ca_cert,
+                    "-CAkey",
+                    &ca_key,
+                    "-CAcreateserial",
+                    "-out",
+                    &cert,
+                    "-days",
+                    VALIDITY_DAYS,
+                    "-extfile",
+                    &extensions,
+                ],
+                None,
+            )
+            .await?;
+        if let Some(keystore) = &certificate.keystore {
+            let (keystore, password) = (path_str(keystore), format!("pass:{}", self.password));
+            logged_cmd
+                .run_command(
+                    "openssl",
+                    &[
+                        "pkcs12", "-export", "-in", &cert, "-inkey", &key, "-name", name, "-out",
+                        &keystore, "-passout", &password,
+                    ],
+                    None,
+                )
+                .await?;
+        }
+        Ok(certificate)
+    }
+}
+
+/// Which internode connections `server_encryption_options` encrypts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InternodeEncryption {
+    All,
+    Dc,
+    Rack,
+    None,
+}
+
+impl InternodeEncryption {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InternodeEncryption::All => "all",
+            InternodeEncryption::Dc => "dc",
+            InternodeEncryption::Rack => "rack",
+            InternodeEncryption::None => "none",
+        }
+    }
+}
+
+/// Builds `client_encryption_options` for a node using `certificate`.
+pub fn client_encryption_options(
+    certificate: &NodeCertificate,
+    ca: &CertificateAuthority,
+    scylla: bool,
+) -> ScyllaConfig {
+    let mut options = encryption_options(certificate, ca, scylla);
+    options.insert("enabled".to_string(), ScyllaConfig::Bool(true));
+    ScyllaConfig::Map(HashMap::from([(
+        "client_encryption_options".to_string(),
+        ScyllaConfig::Map(options),
+    )]))
+}
+
+/// Builds `server_encryption_options` for a node using `certificate`.
+pub fn server_encryption_options(
+    certificate: &NodeCertificate,
+    ca: &CertificateAuthority,
+    scylla: bool,
+    mode: InternodeEncryption,
+) -> ScyllaConfig {
+    let mut options = encryption_options(certificate, ca, scylla);
+    options.insert(
+        "internode_encryption".to_string(),
+        ScyllaConfig::String(mode.as_str().to_string()),
+    );
+    ScyllaConfig::Map(HashMap::from([(
+        "server_encryption_options".to_string(),
+        ScyllaConfig::Map(options),
+    )]))
+}
+
+/// Keys shared by client and server encryption options: Scylla takes PEM files,
+/// Cassandra takes PKCS12 stores.
+fn encryption_options(
+    certificate: &NodeCertificate,
+    ca: &CertificateAuthority,
+    scylla: bool,
+) -> HashMap<String, ScyllaConfig> {
+    let string = |path: &Path| ScyllaConfig::String(path_str(path));
+    let mut options = HashMap::new();
+    if scylla {
+        options.insert("certificate".to_string(), string(&certificate.cert));
+        options.insert("keyfile".to_string(), string(&certificate.key));
+        options.insert("truststore".to_string(), string(&ca.cert));
+    } else {
+        if let Some(keystore) = &certificate.keystore {
+            options.insert("keystore".to_string(), string(keystore));
+        }
+        options.insert(
+            "keystore_password".to_string(),
+            ScyllaConfig::String(certificate.keystore_password.clone()),
+        );
+        if let Some(truststore) = &ca.truststore {
+            options.insert("truststore".to_string(), string(truststore));
+        }
+        options.insert(
+            "truststore_password".to_string(),
+            ScyllaConfig::String(ca.password.clone()),
+        );
+        options.insert(
+            "store_type".to_string(),
+            ScyllaConfig::String("PKCS12".to_string()),
+        );
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> (CertificateAuthority, NodeCertificate) {
+        let ca = CertificateAuthority {
+            cert: PathBuf::from("/tls/ca.crt"),
+            key: PathBuf::from("/tls/ca.key"),
+            truststore: Some(PathBuf::from("/tls/truststore.p12")),
+            password: "secret".to_string(),
+        };
+        let certificate = NodeCertificate {
+            cert: PathBuf::from("/conf/node.crt"),
+            key: PathBuf::from("/conf/node.key"),
+            keystore: Some(PathBuf::from("/conf/node.p12")),
+            keystore_password: "secret".to_string(),
+        };
+        (ca, certificate)
+    }
+
+    #[test]
+    fn test_client_encryption_options() {
+        let (ca, certificate) = fixtures();
+
+        assert_eq!(
+            client_encryption_options(&certificate, &ca, true).to_flat_string(),
+            "client_encryption_options.certificate:/conf/node.crt \
+             client_encryption_options.enabled:true \
+             client_encryption_options.keyfile:/conf/node.key \
+             client_encryption_options.truststore:/tls/ca.crt"
+        );
+        assert_eq!(
+            client_encryption_options(&certificate, &ca, false).to_flat_string(),
+            "client_encryption_options.enabled:true \
+             client_encryption_options.keystore:/conf/node.p12 \
+             client_encryption_options.keystore_password:secret \
+             client_encryption_options.store_type:PKCS12 \
+             client_encryption_options.truststore:/tls/truststore.p12 \
+             client_encryption_options.truststore_password:secret"
+        );
+    }
+
+    #[test]
+    fn test_server_encryption_options() {
+        let (ca, certificate) = fixtures();
+
+        assert_eq!(
+            server_encryption_options(&certificate, &ca, true, InternodeEncryption::Dc)
+                .to_flat_string(),
+            "server_encryption_options.certificate:/conf/node.crt \
+             server_encryption_options.internode_encryption:dc \
+             server_encryption_options.keyfile:/conf/node.key \
+             server_encryption_options.truststore:/tls/ca.crt"
+        );
+    }
+
+    #[test]
+    fn test_random_password() {
+        let password = random_password();
+        assert_eq!(password.len(), 32);
+        assert_ne!(password, random_password());
+    }
+
+    #[tokio::test]
+    async fn test_generate_ca_and_issue_certificate() {
+        crate::require_capability!(Capability::Openssl);
+        let dir = std::env::temp_dir().join("ccm_binding_test_tls");
+        tokio::fs::remove_dir_all(&dir).await.ok();
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let mut logged_cmd = LoggedCmd::new();
+        logged_cmd
+            .set_log_file(path_str(&dir.join("tls.log")))
+            .await
+            .unwrap();
+
+        // Scylla reads PEM files only.
+        let ca = CertificateAuthority::generate(&logged_cmd, &dir.join("ca"), false)
+            .await
+            .expect("Failed to generate CA");
+        let certificate = ca
+            .issue(
+                &logged_cmd,
+                "node_1_1",
+                "127.0.1.1",
+                Some("node-1-1.ccm.test"),
+                &dir.join("node"),
+            )
+            .await
+            .expect("Failed to issue certificate");
+
+        let ca_cert = path_str(&ca.cert);
+        let cert = path_str(&certificate.cert);
+        let verified = logged_cmd
+            .run_command("openssl", &["verify", "-CAfile", &ca_cert, &cert], None)
+            .await;
+        assert!(verified.is_ok(), "certificate does not chain to the CA");
+        assert!(ca.truststore.is_none() && certificate.keystore.is_none());
+        assert!(!dir.join("ca/truststore.p12").exists());
+        assert!(!dir.join("node/node_1_1.p12").exists());
+
+        crate::require_capability!(Capability::Keytool);
+        let ca = CertificateAuthority::generate(&logged_cmd, &dir.join("java_ca"), true)
+            .await
+            .expect("Failed to generate CA with a truststore");
+        let certificate = ca
+            .issue(
+                &logged_cmd,
+                "node_1_1",
+                "127.0.1.1",
+                None,
+                &dir.join("java_node"),
+            )
+            .await
+            .expect("Failed to issue certificate with a keystore");
+        assert!(certificate.keystore.as_ref().unwrap().exists());
+        let truststore = path_str(ca.truststore.as_ref().unwrap());
+        let listed = logged_cmd
+            .run_command(
+                "keytool",
+                &[
+                    "-list",
+                    "-storetype",
+                    "PKCS12",
+                    "-keystore",
+                    &truststore,
+                    "-storepass",
+                    &ca.password,
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(listed.stdout.contains("trustedCertEntry"));
+        let log = tokio::fs::read_to_string(dir.join("tls.log"))
+            .await
+            .unwrap();
+        assert!(log.contains("-storepass ***"));
+        assert!(log.contains("-passout pass:***"));
+        assert!(!log.contains(&ca.password));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use crate::ccm_cli::RunOptions;
+use crate::executor::CommandExecutor;
+use crate::run_options;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Directories of a ccm install dir that hold the Cassandra tools, across Cassandra,
+/// Scylla relocatable packages and Scylla builds with scylla-tools-java next to them.
+const TOOL_DIRS: &[&str] = &[
+    "bin",
+    "share/cassandra/bin",
+    "scylla-tools-java/bin",
+    "scylla-tools/bin",
+    "tools/bin",
+    "../scylla-tools-java/bin",
+];
+
+/// Prints the first of its arguments that is a file.
+const FIRST_FILE_SCRIPT: &str =
+    r#"for candidate; do if [ -f "$candidate" ]; then echo "$candidate"; exit 0; fi; done"#;
+
+/// `tool`, e.g. `sstableloader`, of the version installed in `install_dir`, the
+/// `install_dir` ccm wrote to `cluster.conf`. Looked up through `executor`, on the machine
+/// the tool will run on. Fails with `NotFound` if the install has none, rather than
+/// running a tool of another version from `PATH`.
+pub async fn find_tool(
+    executor: &dyn CommandExecutor,
+    install_dir: &Path,
+    tool: &str,
+) -> Result<PathBuf, IoError> {
+    let candidates: Vec<String> = TOOL_DIRS
+        .iter()
+        .map(|dir| {
+            install_dir
+                .join(dir)
+                .join(tool)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let mut args = vec!["-c", FIRST_FILE_SCRIPT, "find_tool"];
+    args.extend(candidates.iter().map(String::as_str));
+    let result = executor
+        .run_command("sh", &args, run_options!(allow_failure = Some(true)))
+        .await?;
+    match result.stdout.lines().next().map(str::trim) {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(IoError::new(
+            std::io::ErrorKind::NotFound,
+            format!("no {} in {}", tool, install_dir.display()),
+        )),
+    }
+}
+
+/// How [`Cluster::sstableloader`](crate::cluster::Cluster::sstableloader) streams SSTables.
+#[derive(Debug, Clone, Default)]
+pub struct SstableLoaderOptions {
+    /// Executable to run instead of the one of the installed version.
+    pub program: Option<PathBuf>,
+    /// Streaming throughput cap in megabits per second (`--throttle`).
+    pub throttle: Option<u32>,
+    /// Connections per node (`--connections-per-host`).
+    pub connections_per_host: Option<u32>,
+    /// Further options, e.g. `["--verbose"]`.
+    pub args: Vec<String>,
+    /// Kills sstableloader if it runs longer than this.
+    pub timeout: Option<Duration>,
+    /// Receives every output line while sstableloader runs.
+    pub output: Option<UnboundedSender<String>>,
+}
+
+impl SstableLoaderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    pub fn throttle(mut self, megabits_per_second: u32) -> Self {
+        self.throttle = Some(megabits_per_second);
+        self
+    }
+
+    pub fn connections_per_host(mut self, connections: u32) -> Self {
+        self.connections_per_host = Some(connections);
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn output(mut self, output: UnboundedSender<String>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// sstableloader options, without the nodes to stream to.
+    pub(crate) fn loader_args(&self) -> Vec<String> {
+        let mut args = vec!["--no-progress".to_string()];
+        if let Some(throttle) = self.throttle {
+            args.extend(["--throttle".to_string(), throttle.to_string()]);
+        }
+        if let Some(connections) = self.connections_per_host {
+            args.extend([
+                "--connections-per-host".to_string(),
+                connections.to_string(),
+            ]);
+        }
+        args.extend(self.args.iter().cloned());
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_find_tool() {
+        let install_dir = std::env::temp_dir().join("ccm_binding_test_find_tool");
+        std::fs::remove_dir_all(&install_dir).ok();
+        let bin = install_dir.join("share/cassandra/bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("sstableloader"), "").unwrap();
+        let executor = crate::ccm_cli::LoggedCmd::new();
+        assert_eq!(
+            find_tool(&executor, &install_dir, "sstableloader")
+                .await
+                .unwrap(),
+            bin.join("sstableloader")
+        );
+        assert_eq!(
+            find_tool(&executor, &install_dir, "sh")
+                .await
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+        std::fs::remove_dir_all(&install_dir).ok();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::cluster_config::ScyllaConfig;
+use std::collections::HashMap;
+
+/// Release number parsed from a ccm version string such as `release:6.2` or `2024.1.3`.
+/// Enterprise releases keep their year-based numbering, e.g. `2024.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+/// First enterprise major, used to tell year-based releases apart from open source ones.
+const FIRST_ENTERPRISE_MAJOR: u32 = 2017;
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses the version part of a ccm version string. Returns `None` for versions without
+    /// a release number (`unstable/master:...`, local install directories), which callers
+    /// should treat as the newest release.
+    pub fn parse(ccm_version: &str) -> Option<Self> {
+        let version = ccm_version
+            .rsplit_once(':')
+            .map_or(ccm_version, |(_, version)| version);
+        let mut parts = version
+            .split(['.', '-', '~'])
+            .map(|part| part.parse::<u32>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+        Some(Version::new(major, minor, patch))
+    }
+
+    pub fn is_enterprise(&self) -> bool {
+        self.major >= FIRST_ENTERPRISE_MAJOR
+    }
+
+    /// Whether this release is at least `open_source` or `enterprise`, whichever numbering
+    /// it uses.
+    pub fn at_least(&self, open_source: Version, enterprise: Version) -> bool {
+        if self.is_enterprise() {
+            *self >= enterprise
+        } else {
+            *self >= open_source
+        }
+    }
+}
+
+/// `experimental_features` replaced the all-or-nothing `experimental` flag in these releases.
+const EXPERIMENTAL_FEATURES_SINCE: (Version, Version) =
+    (Version::new(4, 0, 0), Version::new(2020, 1, 0));
+
+/// Config enabling the given Scylla experimental features in the form `ccm_version` expects:
+/// `experimental_features: [...]` on newer releases, `experimental: true` (which enables every
+/// experimental feature) on older ones.
+pub fn experimental_config(ccm_version: &str, features: &[&str]) -> ScyllaConfig {
+    let (open_source, enterprise) = EXPERIMENTAL_FEATURES_SINCE;
+    let feature_list =
+        Version::parse(ccm_version).is_none_or(|version| version.at_least(open_source, enterprise));
+    let (key, value) = if feature_list {
+        (
+            "experimental_features",
+            ScyllaConfig::List(
+                features
+                    .iter()
+                    .map(|feature| ScyllaConfig::String(feature.to_string()))
+                    .collect(),
+            ),
+        )
+    } else {
+        ("experimental", ScyllaConfig::Bool(true))
+    };
+    ScyllaConfig::Map(HashMap::from([(key.to_string(), value)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Version::parse("release:6.2"), Some(Version::new(6, 2, 0)));
+        assert_eq!(Version::parse("2024.1.3"), Some(Version::new(2024, 1, 3)));
+        assert_eq!(
+            Version::parse("release:5.4.0-rc1"),
+            Some(Version::new(5, 4, 0))
+        );
+        assert_eq!(Version::parse("unstable/master:2024-05-01T10:00:00Z"), None);
+        assert!(Version::new(2023, 1, 0).is_enterprise());
+        assert!(!Version::new(6, 2, 0).is_enterprise());
+    }
+
+    #[test]
+    fn test_experimental_config_depends_on_version() {
+        assert_eq!(
+            experimental_config("release:6.2", &["udf", "alternator-streams"]).to_flat_string(),
+            "experimental_features:[udf,alternator-streams]"
+        );
+        assert_eq!(
+            experimental_config("release:3.3", &["cdc"]).to_flat_string(),
+            "experimental:true"
+        );
+        assert_eq!(
+            experimental_config("release:2019.1", &["cdc"]).to_flat_string(),
+            "experimental:true"
+        );
+        assert_eq!(
+            experimental_config("unstable/master:latest", &["cdc"]).to_flat_string(),
+            "experimental_features:[cdc]"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::ccm_cli::LoggedCmd;
+use crate::cluster::{Node, NodeStatus};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Error as IoError;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{RwLock, mpsc};
+use tokio::task::JoinHandle;
+
+/// Server of a node that went away without being stopped through the crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeCrash {
+    pub node: String,
+    /// When the watchdog noticed, within one poll interval of the crash.
+    pub at: SystemTime,
+    /// `system.log` of the node, usually telling why it died.
+    pub log: PathBuf,
+}
+
+impl fmt::Display for NodeCrash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self
+            .at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+            % 86400;
+        write!(
+            f,
+            "{} died at {:02}:{:02}:{:02} UTC, see {}",
+            self.node,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            self.log.display()
+        )
+    }
+}
+
+impl std::error::Error for NodeCrash {}
+
+/// Background check of node server processes, see
+/// [`Cluster::watch_crashes`](crate::cluster::Cluster::watch_crashes). Every crash is
+/// reported once, also as a `crashed` event in the cluster log. Watching stops when this
+/// value is dropped.
+pub struct CrashWatchdog {
+    receiver: mpsc::Receiver<NodeCrash>,
+    task: JoinHandle<()>,
+}
+
+impl CrashWatchdog {
+    pub(crate) fn watch(
+        nodes: Vec<Arc<RwLock<Node>>>,
+        logged_cmd: Arc<LoggedCmd>,
+        interval: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(64);
+        let task = tokio::spawn(watch_nodes(nodes, logged_cmd, interval, sender));
+        CrashWatchdog { receiver, task }
+    }
+
+    /// Waits for the next crash; `None` if the watchdog task ended.
+    pub async fn next_crash(&mut self) -> Option<NodeCrash> {
+        self.receiver.recv().await
+    }
+
+    /// Fails with the first crash not reported yet, e.g. between test steps to fail fast
+    /// instead of timing out on queries.
+    pub fn check(&mut self) -> Result<(), IoError> {
+        match self.receiver.try_recv() {
+            Ok(crash) => Err(IoError::other(crash)),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl Drop for CrashWatchdog {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Reports nodes whose server was seen running and is gone, unless a stop was requested.
+/// Nodes that are not running when watching starts are picked up once they are started.
+async fn watch_nodes(
+    nodes: Vec<Arc<RwLock<Node>>>,
+    logged_cmd: Arc<LoggedCmd>,
+    interval: Duration,
+    sender: mpsc::Sender<NodeCrash>,
+) {
+    let mut running = HashSet::new();
+    loop {
+        for node in nodes.iter() {
+            let node = node.read().await;
+            if !matches!(node.status, NodeStatus::ACTIVE) {
+                running.remove(&node.name);
+                continue;
+            }
+            let directory = node.directory();
+            // A node that can't be looked up right now is checked again next round.
+            let Ok(alive) = node.process_running().await else {
+                continue;
+            };
+            if alive {
+                running.insert(node.name.clone());
+            } else if running.remove(&node.name) && !node.stop_requested() {
+                let crash = NodeCrash {
+                    node: node.name.clone(),
+                    at: SystemTime::now(),
+                    log: directory.join("logs").join("system.log"),
+                };
+                logged_cmd.log_event("crashed", &crash.to_string()).await;
+                if sender.send(crash).await.is_err() {
+                    return;
+                }
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}